@@ -0,0 +1,80 @@
+//! Times the synth's `process` call on the cases that matter most: a held note, a chord, a
+//! note under automation, and silence. Each renders ten seconds at 44.1 kHz in 512-sample
+//! blocks through `OfflineHost`, the same host as the golden renders, so the output writes
+//! are timed too. Run them with
+//!
+//! ```text
+//! cargo bench --features renderer --bench process
+//! ```
+//!
+//! and compare with the baseline in `BENCHMARKS.md`. Criterion can't be fetched in every
+//! build environment this crate has, so each case takes the best of several runs with the
+//! standard library alone.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use vsttest::offline::OfflineHost;
+
+/// The block size the host renders in.
+const BLOCK: usize = 512;
+
+/// Seconds of audio each run renders.
+const SECONDS: usize = 10;
+
+/// How many times each case runs; the fastest is reported.
+const RUNS: usize = 3;
+
+/// Time `SECONDS` of blocks from the host `start` makes, calling `between` with the host and
+/// the block's number before each, and print the cost of the fastest run.
+fn bench(
+    name: &str,
+    start: impl Fn() -> OfflineHost,
+    mut between: impl FnMut(&OfflineHost, usize),
+) {
+    let blocks = SECONDS * 44100 / BLOCK;
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut host = start();
+        let started = Instant::now();
+        for block in 0..blocks {
+            between(&host, block);
+            black_box(host.process(BLOCK));
+        }
+        best = best.min(started.elapsed());
+    }
+    println!(
+        "{}: {:.1} ns a sample, {:.0}x real time",
+        name,
+        best.as_secs_f64() * 1e9 / (blocks * BLOCK) as f64,
+        SECONDS as f64 / best.as_secs_f64()
+    );
+}
+
+/// A host with `notes` held down from the first block.
+fn holding(notes: &[u8]) -> OfflineHost {
+    let mut host = OfflineHost::new(BLOCK, &[]);
+    for &note in notes {
+        host.queue_midi(0, [0x90, note, 100]);
+    }
+    host
+}
+
+fn main() {
+    bench("One note", || holding(&[69]), |_, _| ());
+    let chord = [48, 52, 55, 59, 60, 64, 67, 71];
+    bench("Eight notes", || holding(&chord), |_, _| ());
+    // The cutoff, amplitude and unison spread all move every block, as under a drawn-in
+    // automation curve, so the smoothers and the filter coefficients never settle.
+    bench(
+        "One note under automation",
+        || holding(&[69]),
+        |host, block| {
+            let position = (block % 100) as f32 / 100.0;
+            host.set_parameter(38, position);
+            host.set_parameter(0, 0.5 + 0.25 * position);
+            host.set_parameter(17, position);
+        },
+    );
+    bench("Idle", || holding(&[]), |_, _| ());
+}
@@ -0,0 +1,82 @@
+//! Times a chord of wide, detuned sine unisons with FM, the heaviest load for the voices, to
+//! compare rendering them in lanes with rendering them a sample at a time. Run it both ways:
+//!
+//! ```text
+//! cargo bench --features renderer --bench voices
+//! cargo bench --features renderer,scalar --bench voices
+//! ```
+//!
+//! It times a whole offline render, so everything after the voices is counted as well and the
+//! difference between the two is all theirs. Criterion can't be fetched in every build
+//! environment this crate has, so this takes the best of several runs with the standard
+//! library alone.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use vsttest::offline::{self, Settings};
+
+/// How many times the render runs; the fastest is reported.
+const RUNS: usize = 10;
+
+/// How long the chord is held, in seconds.
+const SECONDS: u32 = 4;
+
+/// A one-track Standard MIDI File at 480 ticks a quarter note and 120 BPM, holding `notes`
+/// for `SECONDS`.
+fn chord(notes: &[u8]) -> Vec<u8> {
+    let mut track = Vec::new();
+    for &note in notes {
+        track.extend_from_slice(&[0, 0x90, note, 100]);
+    }
+    // Two quarter notes to the second, as a variable-length number.
+    let ticks = SECONDS * 960;
+    track.extend_from_slice(&[0x80 | (ticks >> 7) as u8, (ticks & 0x7f) as u8]);
+    for (index, &note) in notes.iter().enumerate() {
+        if index > 0 {
+            track.push(0);
+        }
+        track.extend_from_slice(&[0x80, note, 0]);
+    }
+    track.extend_from_slice(&[0, 0xff, 0x2f, 0]);
+
+    let mut midi = b"MThd\0\0\0\x06\0\0\0\x01\x01\xe0MTrk".to_vec();
+    midi.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    midi.extend(track);
+    midi
+}
+
+fn main() {
+    let midi = chord(&[48, 55, 60, 64, 67, 71, 74, 79]);
+    let settings = Settings {
+        params: [
+            ("Unison Voices", "1"),
+            ("Unison Spread", "0.7"),
+            ("FM Amount", "0.3"),
+            ("Stereo Width", "0.8"),
+        ]
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value.to_string()))
+        .collect(),
+        ..Settings::default()
+    };
+    let mut best = Duration::MAX;
+    let mut samples = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let outputs = offline::render(black_box(&midi), &settings).expect("the chord renders");
+        best = best.min(start.elapsed());
+        samples = black_box(outputs)[0].len();
+    }
+    let rendered = samples as f64 / f64::from(settings.sample_rate);
+    println!(
+        "Seven copies of eight notes, {}: {:.0} ns a sample, {:.0}x real time",
+        if cfg!(feature = "scalar") {
+            "a sample at a time"
+        } else {
+            "in lanes"
+        },
+        best.as_secs_f64() * 1e9 / samples as f64,
+        rendered / best.as_secs_f64()
+    );
+}
@@ -0,0 +1,29 @@
+//! Where aftertouch goes, whether it is pressure on the whole channel or on single keys.
+
+use crate::params::options;
+
+options! {
+    /// What aftertouch modulates.
+    pub enum AftertouchDestination {
+        None => "None",
+        /// Pressing harder swells the level up to full from `1 - amount`.
+        Volume => "Volume",
+        /// Pressure works like the mod wheel, which it adds to.
+        Vibrato => "Vibrato",
+        /// Pressing harder opens the filter.
+        Cutoff => "Cutoff",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AftertouchDestination;
+
+    #[test]
+    fn destinations_round_trip() {
+        for &destination in AftertouchDestination::ALL.iter() {
+            let value = destination.to_normalized();
+            assert_eq!(AftertouchDestination::from_normalized(value), destination);
+        }
+    }
+}
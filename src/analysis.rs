@@ -0,0 +1,54 @@
+//! Signal measurements used by the tests.
+
+use crate::TAU;
+
+/// Amplitude of the `freq` component of `signal`, measured with a Hann-windowed DFT bin.
+pub fn magnitude_at(signal: &[f32], freq: f64, sample_rate: f64) -> f64 {
+    let len = signal.len() as f64;
+    let (mut re, mut im, mut window_sum) = (0.0, 0.0, 0.0);
+    for (i, &s) in signal.iter().enumerate() {
+        let window = 0.5 - 0.5 * (TAU * i as f64 / len).cos();
+        let w = TAU * freq * i as f64 / sample_rate;
+        re += f64::from(s) * window * w.cos();
+        im -= f64::from(s) * window * w.sin();
+        window_sum += window;
+    }
+    2.0 * (re * re + im * im).sqrt() / window_sum
+}
+
+/// The average power of the DFT bins across the octave starting at `low`.
+///
+/// Only every fourth bin is measured, which is plenty for an average of a noisy spectrum.
+pub fn octave_power(signal: &[f32], low: f64, sample_rate: f64) -> f64 {
+    let bin = sample_rate / signal.len() as f64;
+    let bins = ((low / bin) as usize..(2.0 * low / bin) as usize).step_by(4);
+    let count = bins.len() as f64;
+    bins.map(|i| magnitude_at(signal, i as f64 * bin, sample_rate).powi(2))
+        .sum::<f64>()
+        / count
+}
+
+pub fn rms(signal: &[f32]) -> f64 {
+    (signal.iter().map(|&s| f64::from(s).powi(2)).sum::<f64>() / signal.len() as f64).sqrt()
+}
+
+/// The RMS level of each successive `window` samples.
+pub fn rms_envelope(signal: &[f32], window: usize) -> Vec<f64> {
+    signal.chunks(window).map(rms).collect()
+}
+
+/// Estimate the frequency of a steady tone from its rising zero crossings.
+pub fn measure_frequency(signal: &[f32], sample_rate: f64) -> f64 {
+    let crossings: Vec<f64> = signal
+        .windows(2)
+        .enumerate()
+        .filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0)
+        .map(|(i, w)| i as f64 + f64::from(w[0] / (w[0] - w[1])))
+        .collect();
+    let cycles = (crossings.len() - 1) as f64;
+    cycles * sample_rate / (crossings[crossings.len() - 1] - crossings[0])
+}
+
+pub fn cents_between(a: f64, b: f64) -> f64 {
+    1200.0 * (a / b).log2()
+}
@@ -0,0 +1,410 @@
+//! The arpeggiator, which plays the held keys one at a time in step with the tempo.
+//!
+//! It sits between incoming notes and the voices: while it's on, keys go to it instead of
+//! starting voices, and it starts and releases notes itself at the step boundaries. Steps are
+//! counted in beats from a position it's given every sample. That is the host's song position
+//! while the transport runs, so steps stay on the host's grid and come back in the same
+//! places when a loop jumps back. Otherwise it's a free-running clock, and the steps are
+//! counted from the first key.
+
+use crate::lfo::{SyncDivision, SYNC_DIVISIONS};
+use crate::noise::Noise;
+use crate::params::options;
+
+options! {
+    /// The order the held keys are played in.
+    pub enum ArpMode {
+        Up => "Up",
+        Down => "Down",
+        /// Up and back down, without playing the top and bottom keys twice.
+        UpDown => "Up/Down",
+        /// Any held key each step, drawn from a generator that always starts from the same seed.
+        Random => "Random",
+    }
+}
+
+/// Where the step lengths start in `SYNC_DIVISIONS`, at a quarter note.
+const FIRST_DIVISION: usize = 7;
+
+/// The step lengths "Arp Rate" steps through, from a quarter note down to a 1/32 triplet.
+pub fn arp_divisions() -> &'static [SyncDivision] {
+    &SYNC_DIVISIONS[FIRST_DIVISION..]
+}
+
+/// A key as the arpeggiator plays it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArpNote {
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+}
+
+/// How the arpeggiator plays, read from the parameters every block.
+#[derive(Clone, Copy, Debug)]
+pub struct ArpSettings {
+    pub mode: ArpMode,
+    /// The length of a step in beats.
+    pub beats: f64,
+    /// The part of each step a note sounds for, from 0.0 to 1.0.
+    pub gate: f64,
+    /// Whether the keys keep playing after they're let go, until new ones are pressed.
+    pub hold: bool,
+}
+
+/// What to do on one sample: release the last note, then start the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArpStep {
+    pub release: Option<ArpNote>,
+    pub play: Option<ArpNote>,
+}
+
+/// The number of MIDI notes, and so the most keys there can be down or playing at once.
+const NOTES: usize = 128;
+
+/// The keys the arpeggiator knows about. They're kept in fixed arrays, like `NoteStack`'s, so
+/// pressing keys never allocates on the audio thread.
+pub struct Arpeggiator {
+    /// The keys that are physically down, the first `down_len` of them.
+    down: [u8; NOTES],
+    down_len: usize,
+    /// The keys being played, the first `notes_len` of them, from lowest to highest. With hold
+    /// on, these can outlast `down`.
+    notes: [ArpNote; NOTES],
+    notes_len: usize,
+    /// The note started on the latest step, until its gate closes.
+    sounding: Option<ArpNote>,
+    /// How many steps have been played since the keys were first pressed.
+    count: usize,
+    /// The step the latest position was in, or `None` while nothing is held.
+    last_step: Option<i64>,
+    /// The position steps are counted from.
+    origin: f64,
+    random: Noise,
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Arpeggiator {
+        let silent = ArpNote {
+            channel: 0,
+            note: 0,
+            velocity: 0,
+        };
+        Arpeggiator {
+            down: [0; NOTES],
+            down_len: 0,
+            notes: [silent; NOTES],
+            notes_len: 0,
+            sounding: None,
+            count: 0,
+            last_step: None,
+            origin: 0.0,
+            random: Noise::default(),
+        }
+    }
+}
+
+/// Keep only the first `len` of `items` that `keep` accepts, in order, and count them in `len`.
+fn retain<T: Copy>(items: &mut [T], len: &mut usize, keep: impl Fn(&T) -> bool) {
+    let mut kept = 0;
+    for index in 0..*len {
+        if keep(&items[index]) {
+            items[kept] = items[index];
+            kept += 1;
+        }
+    }
+    *len = kept;
+}
+
+impl Arpeggiator {
+    fn down(&self) -> &[u8] {
+        &self.down[..self.down_len]
+    }
+
+    fn notes(&self) -> &[ArpNote] {
+        &self.notes[..self.notes_len]
+    }
+
+    /// Take in a key going down. The first key after every key was let go starts a new set
+    /// of keys, replacing any that hold kept.
+    pub fn key_down(&mut self, channel: u8, note: u8, velocity: u8) {
+        if self.down_len == 0 {
+            self.notes_len = 0;
+            self.count = 0;
+        }
+        // Only notes outside MIDI's range could fill the arrays.
+        if !self.down().contains(&note) && self.down_len < NOTES {
+            self.down[self.down_len] = note;
+            self.down_len += 1;
+        }
+        let played = ArpNote {
+            channel,
+            note,
+            velocity,
+        };
+        match self.notes().binary_search_by_key(&note, |held| held.note) {
+            Ok(index) => self.notes[index] = played,
+            Err(index) if self.notes_len < NOTES => {
+                self.notes.copy_within(index..self.notes_len, index + 1);
+                self.notes[index] = played;
+                self.notes_len += 1;
+            }
+            Err(_) => (),
+        }
+    }
+
+    /// Take in a key being let go. Returns whether it was a key the arpeggiator had, which a
+    /// key pressed before it was switched on isn't.
+    pub fn key_up(&mut self, note: u8, hold: bool) -> bool {
+        let known = self.down().contains(&note);
+        retain(&mut self.down, &mut self.down_len, |&down| down != note);
+        if !hold {
+            retain(&mut self.notes, &mut self.notes_len, |held| {
+                held.note != note
+            });
+        }
+        known
+    }
+
+    /// Forget every key, returning the note that was sounding, if there was one.
+    pub fn stop(&mut self) -> Option<ArpNote> {
+        self.down_len = 0;
+        self.notes_len = 0;
+        self.last_step = None;
+        self.sounding.take()
+    }
+
+    /// Advance to `position` in beats. `free` says the position is the arpeggiator's own
+    /// clock rather than the song position, so steps start from the first key instead of
+    /// from the next step boundary.
+    pub fn tick(&mut self, position: f64, free: bool, settings: &ArpSettings) -> ArpStep {
+        if !settings.hold {
+            // Hold has been switched off since the keys were let go.
+            let down = &self.down[..self.down_len];
+            retain(&mut self.notes, &mut self.notes_len, |held| {
+                down.contains(&held.note)
+            });
+        }
+        if self.notes_len == 0 {
+            self.last_step = None;
+            self.count = 0;
+            return ArpStep {
+                release: self.sounding.take(),
+                play: None,
+            };
+        }
+        if self.last_step.is_none() {
+            self.origin = if free { position } else { 0.0 };
+        }
+        let steps = (position - self.origin) / settings.beats;
+        let step = steps.floor() as i64;
+        let into_step = steps - steps.floor();
+        let last_step =
+            *self
+                .last_step
+                .get_or_insert(if into_step == 0.0 { step - 1 } else { step });
+
+        let mut out = ArpStep::default();
+        if step != last_step {
+            // A new step, including after a jump back to the start of a loop.
+            out.release = self.sounding.take();
+            let note = self.pick(settings.mode);
+            self.sounding = Some(note);
+            out.play = Some(note);
+            self.last_step = Some(step);
+            self.count += 1;
+        } else if into_step >= settings.gate {
+            out.release = self.sounding.take();
+        }
+        out
+    }
+
+    /// The key to play on the next step.
+    fn pick(&mut self, mode: ArpMode) -> ArpNote {
+        let len = self.notes_len;
+        let index = match mode {
+            ArpMode::Up => self.count % len,
+            ArpMode::Down => len - 1 - self.count % len,
+            ArpMode::UpDown if len == 1 => 0,
+            ArpMode::UpDown => {
+                let period = 2 * (len - 1);
+                let place = self.count % period;
+                place.min(period - place)
+            }
+            ArpMode::Random => ((self.random.unit() * len as f64) as usize).min(len - 1),
+        };
+        self.notes[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{arp_divisions, ArpMode, ArpNote, ArpSettings, Arpeggiator};
+
+    /// Sixteenth-note steps, half of each sounding.
+    fn settings(mode: ArpMode) -> ArpSettings {
+        ArpSettings {
+            mode,
+            beats: 0.25,
+            gate: 0.5,
+            hold: false,
+        }
+    }
+
+    fn chord(notes: &[u8]) -> Arpeggiator {
+        let mut arp = Arpeggiator::default();
+        for &note in notes {
+            arp.key_down(0, note, 100);
+        }
+        arp
+    }
+
+    /// Each note played and released over `positions`, with the position it happened at.
+    fn run(
+        arp: &mut Arpeggiator,
+        settings: &ArpSettings,
+        positions: impl Iterator<Item = f64>,
+    ) -> Vec<(f64, char, u8)> {
+        let mut events = Vec::new();
+        for position in positions {
+            let step = arp.tick(position, false, settings);
+            if let Some(ArpNote { note, .. }) = step.release {
+                events.push((position, '-', note));
+            }
+            if let Some(ArpNote { note, .. }) = step.play {
+                events.push((position, '+', note));
+            }
+        }
+        events
+    }
+
+    /// Positions 1/96 of a beat apart, like samples of a host timeline, from `start` beats
+    /// for `beats` beats.
+    fn timeline(start: f64, beats: f64) -> impl Iterator<Item = f64> {
+        (0..(beats * 96.0) as usize).map(move |tick| start + tick as f64 / 96.0)
+    }
+
+    /// The notes started over `beats` beats from the start of the song.
+    fn order(notes: &[u8], mode: ArpMode, beats: f64) -> Vec<u8> {
+        let mut arp = chord(notes);
+        run(&mut arp, &settings(mode), timeline(0.0, beats))
+            .into_iter()
+            .filter(|&(_, kind, _)| kind == '+')
+            .map(|(_, _, note)| note)
+            .collect()
+    }
+
+    #[test]
+    fn steps_land_on_the_grid_and_close_at_the_gate() {
+        let mut arp = chord(&[64, 60, 67]);
+        let events = run(&mut arp, &settings(ArpMode::Up), timeline(0.0, 0.75));
+        assert_eq!(
+            events,
+            [
+                (0.0, '+', 60),
+                (0.125, '-', 60),
+                (0.25, '+', 64),
+                (0.375, '-', 64),
+                (0.5, '+', 67),
+                (0.625, '-', 67),
+            ]
+        );
+    }
+
+    #[test]
+    fn modes_play_in_their_order() {
+        let notes = [60, 64, 67, 72];
+        assert_eq!(
+            order(&notes, ArpMode::Up, 2.0),
+            [60, 64, 67, 72, 60, 64, 67, 72]
+        );
+        assert_eq!(
+            order(&notes, ArpMode::Down, 2.0),
+            [72, 67, 64, 60, 72, 67, 64, 60]
+        );
+        assert_eq!(
+            order(&notes, ArpMode::UpDown, 2.0),
+            [60, 64, 67, 72, 67, 64, 60, 64]
+        );
+        assert_eq!(order(&[60], ArpMode::UpDown, 0.5), [60, 60]);
+        let random = order(&notes, ArpMode::Random, 8.0);
+        assert_eq!(random, order(&notes, ArpMode::Random, 8.0));
+        for note in &notes {
+            assert!(random.contains(note), "{:?}", random);
+        }
+    }
+
+    #[test]
+    fn every_key_can_be_held() {
+        let notes: Vec<u8> = (0..128).rev().collect();
+        let up = order(&notes, ArpMode::Up, 33.0);
+        assert_eq!(up[..128], (0..128).collect::<Vec<u8>>()[..]);
+        assert_eq!(up[128..], [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn keys_pressed_mid_step_wait_for_the_grid() {
+        let mut arp = chord(&[60]);
+        let events = run(
+            &mut arp,
+            &settings(ArpMode::Up),
+            timeline(1.0, 0.5).skip(10),
+        );
+        assert_eq!(events[0], (1.25, '+', 60));
+        // On its own clock it starts straight away.
+        let mut arp = chord(&[60]);
+        let step = arp.tick(1.1, true, &settings(ArpMode::Up));
+        assert_eq!(step.play.map(|played| played.note), Some(60));
+    }
+
+    #[test]
+    fn loops_jump_back_onto_the_grid() {
+        let mut arp = chord(&[60, 64]);
+        let settings = settings(ArpMode::Up);
+        let positions = timeline(3.5, 0.5).chain(timeline(0.0, 0.25));
+        let events = run(&mut arp, &settings, positions);
+        let starts: Vec<f64> = events
+            .iter()
+            .filter(|&&(_, kind, _)| kind == '+')
+            .map(|&(position, _, _)| position)
+            .collect();
+        assert_eq!(starts, [3.5, 3.75, 0.0]);
+    }
+
+    #[test]
+    fn letting_go_stops_unless_held() {
+        let mut arp = chord(&[60, 64]);
+        let free = settings(ArpMode::Up);
+        run(&mut arp, &free, timeline(0.0, 0.1));
+        arp.key_up(60, false);
+        arp.key_up(64, false);
+        let events = run(&mut arp, &free, timeline(0.1, 1.0));
+        assert_eq!(events, [(0.1, '-', 60)]);
+
+        let held = ArpSettings { hold: true, ..free };
+        let mut arp = chord(&[60, 64]);
+        arp.key_up(60, true);
+        arp.key_up(64, true);
+        assert_eq!(order_with(&mut arp, &held), [60, 64, 60, 64]);
+        // A new key after they were all let go starts a new set.
+        arp.key_down(0, 72, 100);
+        assert_eq!(order_with(&mut arp, &held), [72, 72, 72, 72]);
+        // Switching hold off lets go of the keys that aren't down.
+        arp.key_up(72, true);
+        assert_eq!(order_with(&mut arp, &free), []);
+    }
+
+    /// The notes started over the next beat, on a free clock.
+    fn order_with(arp: &mut Arpeggiator, settings: &ArpSettings) -> Vec<u8> {
+        (0..96)
+            .filter_map(|tick| arp.tick(f64::from(tick) / 96.0, true, settings).play)
+            .map(|played| played.note)
+            .collect()
+    }
+
+    #[test]
+    fn rates_run_from_a_quarter_to_a_thirty_second_triplet() {
+        let divisions = arp_divisions();
+        assert_eq!(divisions[0].name, "1/4");
+        assert_eq!(divisions[divisions.len() - 1].name, "1/32T");
+    }
+}
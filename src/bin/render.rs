@@ -0,0 +1,75 @@
+//! Render a MIDI file to a WAV file with the synth, without a host.
+//!
+//! ```text
+//! cargo run --features renderer --bin render -- song.mid song.wav --param amplitude=0.8
+//! ```
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process;
+
+use vsttest::offline::{self, Settings};
+
+const USAGE: &str = "usage: render <in.mid> <out.wav> [--sample-rate HZ] [--block-size SAMPLES] \
+                     [--param NAME=VALUE]...";
+
+/// The files to read and write, and how to render.
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<(String, String, Settings), String> {
+    let mut settings = Settings::default();
+    let mut files = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--sample-rate" => {
+                let rate = value()?;
+                settings.sample_rate = rate
+                    .parse()
+                    .map_err(|_| format!("bad sample rate {}", rate))?;
+            }
+            "--block-size" => {
+                let size = value()?;
+                settings.block_size = size
+                    .parse()
+                    .map_err(|_| format!("bad block size {}", size))?;
+            }
+            "--param" => {
+                let param = value()?;
+                let (name, value) = param
+                    .split_once('=')
+                    .ok_or(format!("--param takes NAME=VALUE, not {}", param))?;
+                settings.params.push((name.to_string(), value.to_string()));
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => files.push(arg),
+        }
+    }
+    match <[String; 2]>::try_from(files) {
+        Ok([input, output]) => Ok((input, output, settings)),
+        Err(_) => Err(USAGE.to_string()),
+    }
+}
+
+fn run() -> Result<(), String> {
+    let (input, output, settings) = parse_args(std::env::args().skip(1))?;
+    let midi = std::fs::read(&input).map_err(|error| format!("{}: {}", input, error))?;
+    let outputs =
+        offline::render(&midi, &settings).map_err(|error| format!("{}: {}", input, error))?;
+    let mut file = File::create(&output)
+        .map(BufWriter::new)
+        .map_err(|error| format!("{}: {}", output, error))?;
+    // Dropping the writer would flush it too, but throw away any error doing so.
+    offline::write_wav(&mut file, settings.sample_rate as u32, &outputs)
+        .and_then(|()| file.flush())
+        .map_err(|error| format!("{}: {}", output, error))
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
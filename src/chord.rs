@@ -0,0 +1,198 @@
+//! Chord memory: every key plays a whole chord, built up from the key at fixed intervals.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::params::options;
+
+/// The most notes a chord can have, including the key itself.
+pub const MAX_CHORD_NOTES: usize = 8;
+
+options! {
+    /// The chord the "Chord" parameter plays on every key.
+    pub enum ChordType {
+        /// Just the key.
+        Off => "Off",
+        Octave => "Octave",
+        Fifth => "Fifth",
+        Major => "Major",
+        Minor => "Minor",
+        Sus4 => "Sus4",
+        /// The chord last learned with "Chord Learn".
+        User => "User",
+    }
+}
+
+impl ChordType {
+    /// The chord this type plays, given the learned one.
+    pub fn chord(self, user: Chord) -> Chord {
+        match self {
+            ChordType::Off => Chord::default(),
+            ChordType::Octave => Chord::new(&[0, 12]),
+            ChordType::Fifth => Chord::new(&[0, 7]),
+            ChordType::Major => Chord::new(&[0, 4, 7]),
+            ChordType::Minor => Chord::new(&[0, 3, 7]),
+            ChordType::Sus4 => Chord::new(&[0, 5, 7]),
+            ChordType::User => user,
+        }
+    }
+}
+
+/// The intervals of a chord in semitones above the key that plays it, from lowest to highest.
+/// The key itself, at 0, is always one of them.
+///
+/// A chord is a fixed size so playing one never allocates on the audio thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chord {
+    intervals: [u8; MAX_CHORD_NOTES],
+    len: usize,
+}
+
+impl Default for Chord {
+    /// Just the key.
+    fn default() -> Chord {
+        Chord::new(&[0])
+    }
+}
+
+impl Chord {
+    /// The chord of `intervals`, which can come in any order. Repeats are dropped, and so are
+    /// the highest intervals past `MAX_CHORD_NOTES`.
+    pub fn new(intervals: &[u8]) -> Chord {
+        let mut chord = Chord {
+            intervals: [0; MAX_CHORD_NOTES],
+            len: 1,
+        };
+        for &interval in intervals {
+            chord.insert(interval);
+        }
+        chord
+    }
+
+    /// The chord `keys` make, taken from the lowest of them, or `None` with no keys.
+    pub fn learn(keys: &[u8]) -> Option<Chord> {
+        let lowest = *keys.iter().min()?;
+        let mut chord = Chord::default();
+        for &key in keys {
+            chord.insert(key - lowest);
+        }
+        Some(chord)
+    }
+
+    /// Add `interval` in its place, dropping the highest interval if that's one too many.
+    fn insert(&mut self, interval: u8) {
+        let index = match self.intervals().binary_search(&interval) {
+            Ok(_) => return,
+            Err(index) => index,
+        };
+        if index == MAX_CHORD_NOTES {
+            return;
+        }
+        if self.len == MAX_CHORD_NOTES {
+            self.len -= 1;
+        }
+        self.intervals.copy_within(index..self.len, index + 1);
+        self.intervals[index] = interval;
+        self.len += 1;
+    }
+
+    pub fn intervals(&self) -> &[u8] {
+        &self.intervals[..self.len]
+    }
+
+    /// The notes `key` plays, leaving out any above the top of the MIDI range.
+    pub fn notes(self, key: u8) -> impl Iterator<Item = u8> {
+        (0..self.len)
+            .map(move |index| u16::from(key) + u16::from(self.intervals[index]))
+            .filter(|&note| note <= 127)
+            .map(|note| note as u8)
+    }
+
+    /// Whether `note` is one of the notes `key` plays.
+    pub fn plays(&self, key: u8, note: u8) -> bool {
+        note >= key && self.intervals().contains(&(note - key))
+    }
+}
+
+/// A chord one thread can learn while another plays it, without either waiting.
+///
+/// It's packed into a single atomic word, a byte for each interval. The first interval is
+/// always the key itself at 0, so its byte holds how many there are instead.
+#[derive(Debug)]
+pub struct SharedChord(AtomicU64);
+
+impl Default for SharedChord {
+    fn default() -> SharedChord {
+        SharedChord(AtomicU64::new(pack(Chord::default())))
+    }
+}
+
+impl SharedChord {
+    pub fn get(&self) -> Chord {
+        let bytes = self.0.load(Ordering::Relaxed).to_le_bytes();
+        let mut intervals = bytes;
+        intervals[0] = 0;
+        Chord {
+            intervals,
+            len: usize::from(bytes[0]),
+        }
+    }
+
+    pub fn set(&self, chord: Chord) {
+        self.0.store(pack(chord), Ordering::Relaxed);
+    }
+}
+
+fn pack(chord: Chord) -> u64 {
+    let mut bytes = chord.intervals;
+    bytes[0] = chord.len as u8;
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chord, ChordType, SharedChord, MAX_CHORD_NOTES};
+
+    #[test]
+    fn chords_are_built_up_from_the_key() {
+        let major = ChordType::Major.chord(Chord::default());
+        assert_eq!(major.notes(48).collect::<Vec<_>>(), [48, 52, 55]);
+        assert!(major.plays(48, 55));
+        assert!(!major.plays(48, 53));
+        assert_eq!(
+            ChordType::Off.chord(major).notes(48).collect::<Vec<_>>(),
+            [48]
+        );
+        assert_eq!(ChordType::User.chord(major), major);
+        // Notes past the top of the range are left out.
+        assert_eq!(major.notes(122).collect::<Vec<_>>(), [122, 126]);
+    }
+
+    #[test]
+    fn learned_chords_start_from_the_lowest_key() {
+        let chord = Chord::learn(&[67, 60, 64, 72, 60]).unwrap();
+        assert_eq!(chord.intervals(), [0, 4, 7, 12]);
+        assert_eq!(Chord::learn(&[]), None);
+        let wide: Vec<u8> = (0..20).map(|key| key * 2).collect();
+        assert_eq!(
+            Chord::learn(&wide).unwrap().intervals().len(),
+            MAX_CHORD_NOTES
+        );
+    }
+
+    #[test]
+    fn shared_chords_come_back_as_they_went_in() {
+        let shared = SharedChord::default();
+        assert_eq!(shared.get(), Chord::default());
+        for intervals in &[&[0, 4, 7][..], &[0, 1, 2, 3, 4, 5, 6, 127], &[0, 12]] {
+            shared.set(Chord::new(intervals));
+            assert_eq!(shared.get(), Chord::new(intervals));
+        }
+    }
+
+    #[test]
+    fn types_round_trip() {
+        for &chord in ChordType::ALL.iter() {
+            assert_eq!(ChordType::from_normalized(chord.to_normalized()), chord);
+        }
+    }
+}
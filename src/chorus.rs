@@ -0,0 +1,231 @@
+//! A chorus of modulated delay taps on the summed voices.
+
+use crate::delay_line::DelayLine;
+use crate::sine::{Lookup, Osc};
+
+/// The delay each tap swings around, in seconds.
+const CENTRE: f64 = 0.0175;
+
+/// How far either way of `CENTRE` a tap swings at full depth, so the delays stay within
+/// 5 to 30 ms.
+const SWING: f64 = 0.0125;
+
+/// Where in the LFO's cycle each tap is on the left channel, spread evenly.
+const TAPS: [f64; 3] = [0.0, 1.0 / 3.0, 2.0 / 3.0];
+
+/// How far ahead in the cycle the right channel's taps are, which is what makes the chorus
+/// wide: the two sides are never delayed by the same amount at once.
+const RIGHT_OFFSET: f64 = 0.25;
+
+/// Two delay lines, one per channel, read by taps whose delays an LFO sweeps.
+///
+/// The lines are allocated for the longest delay at the sample rate they're made for, so
+/// processing never allocates.
+pub struct Chorus {
+    lines: [DelayLine; 2],
+    sample_rate: f64,
+    /// Position of the LFO within its cycle, kept in `[0, 1)`.
+    phase: f64,
+    /// The taps' positions in the cycle, normally `TAPS`.
+    taps: &'static [f64],
+}
+
+impl Chorus {
+    /// A chorus with empty delay lines long enough for `sample_rate`.
+    pub fn new(sample_rate: f64) -> Chorus {
+        let line = DelayLine::new(CENTRE + SWING, sample_rate);
+        Chorus {
+            lines: [line.clone(), line],
+            sample_rate,
+            phase: 0.0,
+            taps: &TAPS,
+        }
+    }
+
+    /// Whether the lines hold nothing but silence, so the chorus adds nothing to silence.
+    pub fn is_silent(&self) -> bool {
+        self.lines.iter().all(DelayLine::is_silent)
+    }
+
+    /// Empty the lines and start the LFO from the top of its cycle, without reallocating.
+    pub fn reset(&mut self) {
+        self.lines.iter_mut().for_each(DelayLine::clear);
+        self.phase = 0.0;
+    }
+
+    /// Move the LFO on by `samples` at `rate` Hz, for a block skipped as silence.
+    pub fn skip(&mut self, rate: f64, samples: usize) {
+        self.phase = (self.phase + rate * samples as f64 / self.sample_rate).fract();
+    }
+
+    /// One stereo frame through the chorus, with the LFO at `rate` Hz, `depth` of the full
+    /// swing and `mix` of the wet signal in place of the dry.
+    ///
+    /// With no mix the chorus is out of the signal, and what was last swept through the taps
+    /// is let go so it can't be heard again once the mix comes back up.
+    pub fn process(&mut self, frame: (f64, f64), rate: f64, depth: f64, mix: f64) -> (f64, f64) {
+        let phase = self.phase;
+        self.phase = (phase + rate / self.sample_rate).fract();
+        if mix == 0.0 {
+            self.lines.iter_mut().for_each(DelayLine::clear);
+            return frame;
+        }
+        self.lines[0].push(frame.0);
+        self.lines[1].push(frame.1);
+
+        let swing = SWING * depth.clamp(0.0, 1.0);
+        let mut wet = [0.0; 2];
+        for (channel, offset) in [0.0, RIGHT_OFFSET].iter().enumerate() {
+            for tap in self.taps {
+                let sine = Lookup.sine(phase + tap + offset);
+                let delay = (CENTRE + swing * sine) * self.sample_rate;
+                wet[channel] += self.lines[channel].read(delay);
+            }
+        }
+        let taps = self.taps.len() as f64;
+        let (left, right) = (wet[0] / taps, wet[1] / taps);
+        (
+            frame.0 + (left - frame.0) * mix,
+            frame.1 + (right - frame.1) * mix,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chorus, CENTRE, SWING};
+    use crate::noise::{Noise, NoiseColor};
+
+    const RATE: f64 = 44100.0;
+
+    /// How often `measured_delays` measures, in seconds.
+    const HOP: f64 = 0.02;
+
+    /// Where the wet signal's delay is, in seconds, every `HOP` through four seconds of noise
+    /// through a single tap: the lag at which each stretch of output best matches the input.
+    fn measured_delays(rate: f64, depth: f64) -> [Vec<f64>; 2] {
+        let mut chorus = Chorus::new(RATE);
+        chorus.taps = &[0.0];
+        // Taking the highs off the noise widens its correlation peak, so the delay moving
+        // within a stretch doesn't smear the peak away.
+        let mut noise = Noise::default();
+        let mut lowpassed = 0.0;
+        let input: Vec<f64> = (0..4 * RATE as usize)
+            .map(|_| {
+                lowpassed += (noise.next(NoiseColor::White) - lowpassed) * 0.25;
+                lowpassed
+            })
+            .collect();
+        let output: Vec<(f64, f64)> = input
+            .iter()
+            .map(|&sample| chorus.process((sample, sample), rate, depth, 1.0))
+            .collect();
+
+        let (window, hop) = (256, (HOP * RATE) as usize);
+        let swing = SWING * depth + 0.001;
+        let lags = ((CENTRE - swing) * RATE) as usize..((CENTRE + swing) * RATE) as usize;
+        let mut delays = [Vec::new(), Vec::new()];
+        for start in (lags.end..output.len() - window).step_by(hop) {
+            for (channel, delays) in delays.iter_mut().enumerate() {
+                let correlation = |lag: usize| -> f64 {
+                    (start..start + window)
+                        .map(|n| {
+                            let out = if channel == 0 {
+                                output[n].0
+                            } else {
+                                output[n].1
+                            };
+                            out * input[n - lag]
+                        })
+                        .sum()
+                };
+                let (best, _) = lags
+                    .clone()
+                    .map(|lag| (lag, correlation(lag)))
+                    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                    .unwrap();
+                delays.push(best as f64 / RATE);
+            }
+        }
+        delays
+    }
+
+    /// When, in seconds from the first measurement, the delays rise through the centre.
+    fn rising_crossings(delays: &[f64]) -> Vec<f64> {
+        delays
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < CENTRE && pair[1] >= CENTRE)
+            .map(|(i, pair)| {
+                let between = (CENTRE - pair[0]) / (pair[1] - pair[0]);
+                (i as f64 + between) * HOP
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wet_delay_sweeps_at_the_set_rate() {
+        let (rate, depth) = (1.0, 0.5);
+        let [left, right] = measured_delays(rate, depth);
+        let (shortest, longest) = left
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(lo, hi), &d| (lo.min(d), hi.max(d)));
+        assert!(
+            (shortest - (CENTRE - SWING * depth)).abs() < 0.0005,
+            "{}",
+            shortest
+        );
+        assert!(
+            (longest - (CENTRE + SWING * depth)).abs() < 0.0005,
+            "{}",
+            longest
+        );
+
+        let crossings = rising_crossings(&left);
+        assert!(crossings.len() >= 3, "{:?}", crossings);
+        for pair in crossings.windows(2) {
+            assert!(
+                (pair[1] - pair[0] - 1.0 / rate).abs() < 0.02,
+                "{:?}",
+                crossings
+            );
+        }
+        // The right side runs a quarter of a cycle ahead.
+        let right_crossings = rising_crossings(&right);
+        let lead = (crossings[1] - right_crossings[1]).rem_euclid(1.0 / rate);
+        assert!((lead - 0.25 / rate).abs() < 0.02, "{}", lead);
+    }
+
+    #[test]
+    fn no_mix_passes_the_input_through_exactly() {
+        let mut chorus = Chorus::new(RATE);
+        let mut noise = Noise::default();
+        for _ in 0..4410 {
+            let frame = (noise.next(NoiseColor::White), noise.next(NoiseColor::Pink));
+            assert_eq!(chorus.process(frame, 1.0, 1.0, 0.0), frame);
+        }
+        assert!(chorus.is_silent());
+
+        // After being up, turning it back down leaves nothing behind either.
+        for _ in 0..4410 {
+            let frame = (noise.next(NoiseColor::White), 0.0);
+            assert_ne!(chorus.process(frame, 1.0, 1.0, 0.5), frame);
+        }
+        assert!(!chorus.is_silent());
+        let frame = (0.25, -0.5);
+        assert_eq!(chorus.process(frame, 1.0, 1.0, 0.0), frame);
+        assert!(chorus.is_silent());
+    }
+
+    #[test]
+    fn silence_lets_the_lines_drain() {
+        let mut chorus = Chorus::new(RATE);
+        chorus.process((1.0, 1.0), 1.0, 0.5, 1.0);
+        let mut tail = 0.0;
+        while !chorus.is_silent() {
+            tail += chorus.process((0.0, 0.0), 1.0, 0.5, 1.0).0.abs();
+        }
+        assert!(tail > 0.0);
+        assert_eq!(chorus.process((0.0, 0.0), 1.0, 0.5, 1.0), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,540 @@
+//! The plugin's state as a chunk of bytes, which hosts save with a project in place of the
+//! raw list of parameter values.
+//!
+//! Everything is little-endian, and a string is a `u32` byte length followed by UTF-8. A chunk
+//! starts with `MAGIC` and the format version. A program chunk then holds one program; a bank
+//! chunk holds the selected program's index, the number of programs, and the programs.
+//!
+//! A program is its name, the number of entries, and the entries. Each entry is a key (the
+//! parameter's name), the length of its payload in bytes, and the payload (the parameter's
+//! normalized value as an `f32`). Keys this build doesn't know are skipped, so new parameters
+//! and fields are added as entries and the version only changes when the layout itself does,
+//! or when a parameter's values change meaning.
+//!
+//! Whatever else is saved with the plugin's state goes with the only program of a program
+//! chunk and the selected program of a bank chunk, as more entries:
+//!
+//! - A loaded scale file is keyed `TUNING_KEY`, with the text of the file as its payload.
+//! - Controllers bound by MIDI learn are keyed `LEARNED_KEY`. The payload is each
+//!   controller's number as a byte, followed by the name of the parameter it moves.
+//! - A learned chord is keyed `CHORD_KEY`, with its intervals in semitones as the payload, a
+//!   byte each.
+//!
+//! Version 1 had no magic and stored each program's values by position, as a count followed by
+//! that many `f32`s in parameter order. Parameters are only ever added at the end of the list,
+//! so those positions still name the same parameters.
+//!
+//! Version 2 had the same layout as version 3, but its "Bend Range" values covered up to
+//! `VERSION_2_MAX_BEND_RANGE` semitones. Older chunks have theirs rescaled as they're read, so
+//! they keep the range they were saved with.
+
+use crate::presets;
+use crate::program::{Program, PROGRAM_COUNT};
+use crate::MAX_BEND_RANGE;
+
+/// Marks a chunk from version 2 onwards. Version 1 chunks start with the version instead.
+const MAGIC: &[u8; 4] = b"SBDS";
+
+/// The layout written by this build.
+pub const VERSION: u32 = 3;
+
+/// The widest bend range before version 3, in semitones.
+const VERSION_2_MAX_BEND_RANGE: f32 = 24.0;
+
+/// The key of the entry holding the scale file.
+const TUNING_KEY: &str = "Tuning";
+
+/// The key of the entry holding the MIDI learn bindings.
+const LEARNED_KEY: &str = "Learned Controllers";
+
+/// The key of the entry holding the learned chord.
+const CHORD_KEY: &str = "User Chord";
+
+/// What's saved with the selected program besides its parameter values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Extras {
+    /// The scale file loaded, if there is one.
+    pub tuning: Option<String>,
+    /// Controllers bound by MIDI learn, and the names of the parameters they move.
+    pub learned: Vec<(u8, String)>,
+    /// The intervals of the learned chord, or none if no chord was learned.
+    pub user_chord: Vec<u8>,
+}
+
+impl Extras {
+    fn entries(&self) -> usize {
+        usize::from(self.tuning.is_some())
+            + usize::from(!self.learned.is_empty())
+            + usize::from(!self.user_chord.is_empty())
+    }
+}
+
+/// A program as read from a chunk of any version, before it meets this build's parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamSnapshot {
+    pub name: String,
+    /// Values by parameter name, in the order the chunk had them.
+    pub values: Vec<(String, f32)>,
+    /// What was saved with this program besides its values.
+    pub extras: Extras,
+}
+
+impl ParamSnapshot {
+    /// The program this snapshot makes, given each parameter's name and default value in index
+    /// order.
+    ///
+    /// Parameters the snapshot has no value for, because it was saved before they existed,
+    /// keep their defaults. Values for parameters that don't exist any more are dropped, as
+    /// are values out of range, which can only have come from a damaged chunk.
+    pub fn into_program(self, params: &[(&str, f32)]) -> Program {
+        let mut values: Vec<f32> = params.iter().map(|&(_, default)| default).collect();
+        for (name, value) in self.values {
+            let index = params.iter().position(|&(param, _)| param == name);
+            if let Some(index) = index.filter(|_| (0.0..=1.0).contains(&value)) {
+                values[index] = value;
+            }
+        }
+        Program {
+            name: self.name,
+            values,
+        }
+    }
+}
+
+/// A program chunk, given each parameter's name and default value in index order, and what's
+/// saved with it.
+pub fn encode_program(program: &Program, params: &[(&str, f32)], extras: &Extras) -> Vec<u8> {
+    let mut data = header();
+    write_program(&mut data, program, params, Some(extras));
+    data
+}
+
+pub fn encode_bank(
+    programs: &[Program],
+    current: usize,
+    params: &[(&str, f32)],
+    extras: &Extras,
+) -> Vec<u8> {
+    let mut data = header();
+    write_u32(&mut data, current as u32);
+    write_u32(&mut data, programs.len() as u32);
+    for (index, program) in programs.iter().enumerate() {
+        let extras = Some(extras).filter(|_| index == current);
+        write_program(&mut data, program, params, extras);
+    }
+    data
+}
+
+/// The program in a program chunk of any version and what was saved with it, or `None` if the
+/// chunk is damaged or from a newer layout.
+pub fn decode_program(data: &[u8], params: &[(&str, f32)]) -> Option<(Program, Extras)> {
+    let mut reader = Reader { data };
+    let version = reader.version()?;
+    let mut snapshot = migrate(version, reader.data, params)?;
+    let extras = std::mem::take(&mut snapshot.extras);
+    Some((snapshot.into_program(params), extras))
+}
+
+/// The programs in a bank chunk of any version, which is selected, and what was saved with
+/// them, or `None` if the chunk is damaged or from a newer layout.
+///
+/// A bank of fewer than `PROGRAM_COUNT` programs is filled out with the factory programs from
+/// the slots it doesn't reach, and any past that are dropped. A bank of no programs at all is
+/// how to reset to the factory bank.
+pub fn decode_bank(data: &[u8], params: &[(&str, f32)]) -> Option<(Vec<Program>, usize, Extras)> {
+    let mut reader = Reader { data };
+    let version = reader.version()?;
+    let current = reader.u32()? as usize;
+    let count = reader.u32()? as usize;
+    let mut programs = Vec::new();
+    let mut extras = Extras::default();
+    for index in 0..count {
+        let mut snapshot = reader.snapshot(version, params)?;
+        if index == current {
+            extras = std::mem::take(&mut snapshot.extras);
+        }
+        programs.push(snapshot.into_program(params));
+    }
+    programs.truncate(PROGRAM_COUNT);
+    let factory = presets::factory_bank(params);
+    programs.extend(factory.into_iter().skip(count));
+    Some((programs, current.min(PROGRAM_COUNT - 1), extras))
+}
+
+/// Read one program written in the layout of `version`, from just after the chunk's header.
+///
+/// Version 1 values are stored by position, so the names in `params` say what they are.
+pub fn migrate(version: u32, data: &[u8], params: &[(&str, f32)]) -> Option<ParamSnapshot> {
+    Reader { data }.snapshot(version, params)
+}
+
+fn header() -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    write_u32(&mut data, VERSION);
+    data
+}
+
+fn write_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_string(data: &mut Vec<u8>, string: &str) {
+    write_u32(data, string.len() as u32);
+    data.extend_from_slice(string.as_bytes());
+}
+
+fn write_program(
+    data: &mut Vec<u8>,
+    program: &Program,
+    params: &[(&str, f32)],
+    extras: Option<&Extras>,
+) {
+    write_string(data, &program.name);
+    let entries = program.values.len() + extras.map_or(0, Extras::entries);
+    write_u32(data, entries as u32);
+    for (&(name, _), value) in params.iter().zip(&program.values) {
+        write_string(data, name);
+        write_u32(data, 4);
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    let extras = match extras {
+        Some(extras) => extras,
+        None => return,
+    };
+    if let Some(tuning) = &extras.tuning {
+        write_string(data, TUNING_KEY);
+        write_string(data, tuning);
+    }
+    if !extras.learned.is_empty() {
+        let mut payload = Vec::new();
+        for (controller, name) in &extras.learned {
+            payload.push(*controller);
+            write_string(&mut payload, name);
+        }
+        write_string(data, LEARNED_KEY);
+        write_u32(data, payload.len() as u32);
+        data.extend_from_slice(&payload);
+    }
+    if !extras.user_chord.is_empty() {
+        write_string(data, CHORD_KEY);
+        write_u32(data, extras.user_chord.len() as u32);
+        data.extend_from_slice(&extras.user_chord);
+    }
+}
+
+/// Reads a chunk from the front, returning `None` from every read that runs off the end.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = self.u32()? as usize;
+        Some(String::from_utf8_lossy(self.bytes(length)?).into_owned())
+    }
+
+    /// The format version from the chunk's header, if it's one this build can read.
+    fn version(&mut self) -> Option<u32> {
+        if self.data.starts_with(MAGIC) {
+            self.bytes(MAGIC.len())?;
+            self.u32()
+                .filter(|&version| (2..=VERSION).contains(&version))
+        } else {
+            self.u32().filter(|&version| version == 1)
+        }
+    }
+
+    fn snapshot(&mut self, version: u32, params: &[(&str, f32)]) -> Option<ParamSnapshot> {
+        let name = self.string()?;
+        let count = self.u32()? as usize;
+        let mut values = Vec::new();
+        let mut extras = Extras::default();
+        for index in 0..count {
+            if version == 1 {
+                let value = self.f32()?;
+                if let Some(&(param, _)) = params.get(index) {
+                    values.push((param.to_string(), value));
+                }
+            } else {
+                let key = self.string()?;
+                let length = self.u32()? as usize;
+                let payload = self.bytes(length)?;
+                if key == TUNING_KEY {
+                    extras.tuning = Some(String::from_utf8_lossy(payload).into_owned());
+                } else if key == LEARNED_KEY {
+                    extras.learned = learned(payload);
+                } else if key == CHORD_KEY {
+                    extras.user_chord = payload.to_vec();
+                } else if payload.len() == 4 {
+                    // A payload of another size is a field from a later build, not a value.
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(payload);
+                    values.push((key, f32::from_le_bytes(bytes)));
+                }
+            }
+        }
+        if version < 3 {
+            for (key, value) in values.iter_mut() {
+                if key == "Bend Range" {
+                    *value = upgrade_bend_range(*value);
+                }
+            }
+        }
+        Some(ParamSnapshot {
+            name,
+            values,
+            extras,
+        })
+    }
+}
+
+/// The bindings in a `LEARNED_KEY` payload, up to any damage.
+fn learned(payload: &[u8]) -> Vec<(u8, String)> {
+    let mut reader = Reader { data: payload };
+    let mut bindings = Vec::new();
+    while let Some(&[controller]) = reader.bytes(1) {
+        match reader.string() {
+            Some(name) => bindings.push((controller, name)),
+            None => break,
+        }
+    }
+    bindings
+}
+
+/// The "Bend Range" value for the same number of semitones as `value` was before version 3.
+fn upgrade_bend_range(value: f32) -> f32 {
+    let semitones = 1.0 + (value * (VERSION_2_MAX_BEND_RANGE - 1.0)).round();
+    (semitones - 1.0) / (MAX_BEND_RANGE - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_bank, decode_program, encode_bank, encode_program, learned, migrate, Extras,
+        ParamSnapshot, MAGIC, TUNING_KEY, VERSION,
+    };
+    use crate::noise::Noise;
+    use crate::presets::factory_bank;
+    use crate::program::{Program, PROGRAM_COUNT};
+
+    const PARAMS: &[(&str, f32)] = &[("Amplitude", 0.5), ("Attack", 0.6), ("Decay", 0.7)];
+
+    fn program(name: &str, values: &[f32]) -> Program {
+        Program {
+            name: name.to_string(),
+            values: values.to_vec(),
+        }
+    }
+
+    fn snapshot(name: &str, values: &[(&str, f32)]) -> ParamSnapshot {
+        ParamSnapshot {
+            name: name.to_string(),
+            values: values.iter().map(|&(k, v)| (k.to_string(), v)).collect(),
+            extras: Extras::default(),
+        }
+    }
+
+    /// A version 1 program: the name, then the values by position.
+    fn version_1_program(name: &str, values: &[f32]) -> Vec<u8> {
+        let mut data = (name.len() as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn program_round_trips() {
+        let saved = program("Bright Lead", &[0.1, 0.9, 1.0]);
+        let data = encode_program(&saved, PARAMS, &Extras::default());
+        assert!(data.starts_with(MAGIC));
+        assert_eq!(
+            decode_program(&data, PARAMS),
+            Some((saved, Extras::default()))
+        );
+    }
+
+    #[test]
+    fn bank_round_trips() {
+        let saved: Vec<Program> = (0..PROGRAM_COUNT)
+            .map(|i| program(&format!("P{}", i), &[i as f32 / 16.0, 0.5, 0.0]))
+            .collect();
+        let data = encode_bank(&saved, 5, PARAMS, &Extras::default());
+        assert_eq!(
+            decode_bank(&data, PARAMS),
+            Some((saved, 5, Extras::default()))
+        );
+    }
+
+    #[test]
+    fn tuning_round_trips_with_the_selected_program() {
+        let scale = "Quarter tones\n1\n50.0\n";
+        let extras = Extras {
+            tuning: Some(scale.to_string()),
+            ..Extras::default()
+        };
+        let saved = program("Lead", &[0.1, 0.9, 1.0]);
+        let data = encode_program(&saved, PARAMS, &extras);
+        assert_eq!(decode_program(&data, PARAMS), Some((saved, extras.clone())));
+
+        let saved: Vec<Program> = (0..PROGRAM_COUNT)
+            .map(|i| program(&format!("P{}", i), &[i as f32 / 16.0, 0.5, 0.0]))
+            .collect();
+        let data = encode_bank(&saved, 5, PARAMS, &extras);
+        // Saved once, not with every program.
+        let plain = encode_bank(&saved, 5, PARAMS, &Extras::default());
+        assert_eq!(data.len(), plain.len() + 8 + TUNING_KEY.len() + scale.len());
+        assert_eq!(decode_bank(&data, PARAMS), Some((saved, 5, extras)));
+    }
+
+    #[test]
+    fn learned_controllers_round_trip() {
+        let extras = Extras {
+            tuning: Some("Even\n1\n2/1\n".to_string()),
+            learned: vec![(1, "Decay".to_string()), (74, "Amplitude".to_string())],
+            user_chord: vec![0, 4, 7, 12],
+        };
+        let saved = program("Lead", &[0.1, 0.9, 1.0]);
+        let data = encode_program(&saved, PARAMS, &extras);
+        assert_eq!(
+            decode_program(&data, PARAMS),
+            Some((saved.clone(), extras.clone()))
+        );
+
+        // A cut-off payload keeps the bindings before the damage.
+        let mut payload = vec![1, 5, 0, 0, 0];
+        payload.extend_from_slice(b"Decay");
+        payload.extend_from_slice(&[74, 9, 0, 0, 0, b'A']);
+        assert_eq!(learned(&payload), [(1, "Decay".to_string())]);
+    }
+
+    #[test]
+    fn short_banks_keep_the_factory_programs() {
+        let factory = factory_bank(PARAMS);
+        let saved = [program("Mine", &[0.1, 0.2, 0.3])];
+        let (programs, _, _) =
+            decode_bank(&encode_bank(&saved, 0, PARAMS, &Extras::default()), PARAMS).unwrap();
+        assert_eq!(programs[0], saved[0]);
+        assert_eq!(programs[1..], factory[1..]);
+
+        let (programs, current, _) =
+            decode_bank(&encode_bank(&[], 3, PARAMS, &Extras::default()), PARAMS).unwrap();
+        assert_eq!((programs, current), (factory, 3));
+    }
+
+    #[test]
+    fn version_1_values_are_named_by_position() {
+        let data = version_1_program("Old", &[0.1, 0.2]);
+        assert_eq!(
+            migrate(1, &data, PARAMS),
+            Some(snapshot("Old", &[("Amplitude", 0.1), ("Attack", 0.2)]))
+        );
+        // Positions past the end of this build's parameters have no name and are dropped.
+        let data = version_1_program("Old", &[0.1, 0.2, 0.3, 0.4]);
+        assert_eq!(migrate(1, &data, PARAMS).unwrap().values.len(), 3);
+    }
+
+    #[test]
+    fn version_2_values_are_named_by_key() {
+        let data = encode_program(
+            &program("New", &[0.1, 0.2, 0.3]),
+            PARAMS,
+            &Extras::default(),
+        );
+        assert_eq!(
+            migrate(2, &data[8..], PARAMS),
+            Some(snapshot(
+                "New",
+                &[("Amplitude", 0.1), ("Attack", 0.2), ("Decay", 0.3)]
+            ))
+        );
+    }
+
+    #[test]
+    fn older_bend_ranges_keep_their_semitones() {
+        let params = &[("Bend Range", 0.0)];
+        let data = encode_program(&program("Wide", &[1.0]), params, &Extras::default());
+        let twenty_four = snapshot("Wide", &[("Bend Range", 23.0 / 47.0)]);
+        assert_eq!(migrate(2, &data[8..], params), Some(twenty_four));
+        assert_eq!(
+            migrate(1, &version_1_program("Wide", &[1.0 / 23.0]), params),
+            Some(snapshot("Wide", &[("Bend Range", 1.0 / 47.0)]))
+        );
+        // This version's values are already on the new scale.
+        let forty_eight = snapshot("Wide", &[("Bend Range", 1.0)]);
+        assert_eq!(migrate(VERSION, &data[8..], params), Some(forty_eight));
+    }
+
+    #[test]
+    fn snapshots_fill_in_defaults_and_drop_the_unknown() {
+        let old = snapshot("Old", &[("Attack", 0.2), ("Gone", 0.9), ("Amplitude", 7.0)]);
+        assert_eq!(old.into_program(PARAMS), program("Old", &[0.5, 0.2, 0.7]));
+    }
+
+    #[test]
+    fn damaged_and_future_chunks_are_rejected() {
+        let data = encode_program(
+            &program("Lead", &[0.1, 0.9, 0.5]),
+            PARAMS,
+            &Extras::default(),
+        );
+        for length in 0..data.len() {
+            assert_eq!(decode_program(&data[..length], PARAMS), None);
+        }
+
+        let mut future = data.clone();
+        future[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(decode_program(&future, PARAMS), None);
+        // Without the magic only version 1 makes sense.
+        assert_eq!(decode_program(&data[4..], PARAMS), None);
+    }
+
+    #[test]
+    fn random_bytes_never_panic() {
+        let mut noise = Noise::with_seed(7);
+        for length in 0..2000 {
+            let mut data: Vec<u8> = (0..length % 200)
+                .map(|_| (noise.unit() * 256.0) as u8)
+                .collect();
+            // Start most of them with a valid header so the rest of the reader gets a workout.
+            if data.len() >= 8 {
+                match length % 3 {
+                    0 => data[..4].copy_from_slice(&1u32.to_le_bytes()),
+                    1 => {
+                        data[..4].copy_from_slice(MAGIC);
+                        data[4..8].copy_from_slice(&VERSION.to_le_bytes());
+                    }
+                    _ => (),
+                }
+            }
+            if let Some((program, _)) = decode_program(&data, PARAMS) {
+                assert!(program.values.iter().all(|v| (0.0..=1.0).contains(v)));
+            }
+            if let Some((programs, current, _)) = decode_bank(&data, PARAMS) {
+                assert_eq!(programs.len(), PROGRAM_COUNT);
+                assert!(current < PROGRAM_COUNT);
+            }
+        }
+    }
+}
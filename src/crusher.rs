@@ -0,0 +1,179 @@
+//! A lo-fi stage: fewer bits per sample, and samples held for several in a row to bring the
+//! rate down.
+//!
+//! Truncating leaves every sample up to a step lower, which is an offset, so the synth runs
+//! this stage before its DC blocker rather than after.
+
+use crate::params::options;
+
+/// The bit depth the "Bit Depth" parameter starts from, where the stage is left out.
+pub const MAX_BITS: f64 = 16.0;
+
+/// The lowest bit depth.
+pub const MIN_BITS: f64 = 1.0;
+
+/// The longest the downsampler holds a sample for, in samples.
+pub const MAX_HOLD: usize = 32;
+
+options! {
+    /// How a sample is brought onto the nearest step of the reduced bit depth.
+    pub enum Quantize {
+        /// To the nearest step.
+        Round => "Round",
+        /// To the step below, as dropping the low bits of a two's complement sample does.
+        Truncate => "Truncate",
+    }
+}
+
+/// What the stage does, read from the parameters every block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrushSettings {
+    /// From `MIN_BITS` to `MAX_BITS`, and not necessarily whole.
+    pub bits: f64,
+    pub quantize: Quantize,
+    /// How many samples each one taken is held for, from 1 to `MAX_HOLD`.
+    pub hold: usize,
+}
+
+impl CrushSettings {
+    /// Whether the settings leave the sound exactly as it is.
+    pub fn is_neutral(&self) -> bool {
+        self.bits >= MAX_BITS && self.hold <= 1
+    }
+}
+
+/// The downsampler's held frame, and how far through holding it it is. Both carry on from
+/// one block to the next, so the hold pattern doesn't start over at every block.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Crusher {
+    held: (f64, f64),
+    /// Samples since the held frame was taken.
+    count: usize,
+}
+
+impl Crusher {
+    /// Whether the stage holds nothing but silence.
+    pub fn is_silent(&self) -> bool {
+        self.held == (0.0, 0.0)
+    }
+
+    /// Move the hold pattern on by `samples` of silence, for a block skipped as silence.
+    pub fn skip(&mut self, settings: &CrushSettings, samples: usize) {
+        let hold = settings.hold.clamp(1, MAX_HOLD);
+        self.count = (self.count + samples) % hold;
+    }
+
+    pub fn process(&mut self, frame: (f64, f64), settings: &CrushSettings) -> (f64, f64) {
+        if settings.is_neutral() {
+            self.held = frame;
+            self.count = 0;
+            return frame;
+        }
+        let hold = settings.hold.clamp(1, MAX_HOLD);
+        // Shortening the hold part way through one ends it at once.
+        if self.count == 0 || self.count >= hold {
+            self.count = 0;
+            self.held = (quantize(frame.0, settings), quantize(frame.1, settings));
+        }
+        self.count = (self.count + 1) % hold;
+        self.held
+    }
+}
+
+/// `sample` on the nearest step, or the one below, with `bits` of depth, where full scale is
+/// `2^(bits - 1)` steps each way. At `MAX_BITS` it is left as it is.
+fn quantize(sample: f64, settings: &CrushSettings) -> f64 {
+    if settings.bits >= MAX_BITS {
+        return sample;
+    }
+    let steps = (settings.bits.max(MIN_BITS) - 1.0).exp2();
+    let scaled = sample * steps;
+    match settings.quantize {
+        Quantize::Round => scaled.round() / steps,
+        Quantize::Truncate => scaled.floor() / steps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CrushSettings, Crusher, Quantize, MAX_BITS};
+    use crate::noise::{Noise, NoiseColor};
+
+    fn settings(bits: f64, quantize: Quantize, hold: usize) -> CrushSettings {
+        CrushSettings {
+            bits,
+            quantize,
+            hold,
+        }
+    }
+
+    /// A second of noise through a crusher with `settings`.
+    fn crushed(settings: &CrushSettings) -> Vec<(f64, f64)> {
+        let mut crusher = Crusher::default();
+        let mut noise = Noise::default();
+        (0..44100)
+            .map(|_| {
+                let frame = (noise.next(NoiseColor::White), noise.next(NoiseColor::Pink));
+                crusher.process(frame, settings)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn two_bits_leaves_a_handful_of_levels() {
+        for &quantize in Quantize::ALL.iter() {
+            let output = crushed(&settings(2.0, quantize, 1));
+            let mut levels: Vec<f64> = output.iter().map(|frame| frame.0).collect();
+            levels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            levels.dedup();
+            let positive = levels.iter().filter(|&&level| level > 0.0).count();
+            let negative = levels.iter().filter(|&&level| level < 0.0).count();
+            assert!(positive <= 4 && negative <= 4, "{:?}", levels);
+            assert!(levels.len() > 1);
+        }
+    }
+
+    #[test]
+    fn downsampling_holds_runs_across_blocks() {
+        let mut crusher = Crusher::default();
+        let hold = settings(MAX_BITS, Quantize::Round, 8);
+        let mut output = Vec::new();
+        // Blocks that don't divide by the hold carry its pattern on regardless.
+        for block in 0..20 {
+            for i in 0..37 {
+                let sample = f64::from(block * 37 + i) / 1000.0;
+                output.push(crusher.process((sample, -sample), &hold).0);
+            }
+        }
+        for run in output.chunks(8) {
+            assert!(run.iter().all(|&sample| sample == run[0]), "{:?}", run);
+        }
+        assert!(output
+            .chunks(8)
+            .zip(output.chunks(8).skip(1))
+            .all(|(a, b)| a[0] != b[0]));
+    }
+
+    #[test]
+    fn neutral_settings_are_transparent() {
+        let neutral = settings(MAX_BITS, Quantize::Truncate, 1);
+        let mut crusher = Crusher::default();
+        let mut noise = Noise::default();
+        for _ in 0..4410 {
+            let frame = (noise.next(NoiseColor::White), noise.next(NoiseColor::White));
+            assert_eq!(crusher.process(frame, &neutral), frame);
+        }
+    }
+
+    #[test]
+    fn truncating_only_ever_goes_down() {
+        let mut noise = Noise::default();
+        let mut crusher = Crusher::default();
+        let truncate = settings(4.5, Quantize::Truncate, 1);
+        for _ in 0..4410 {
+            let sample = noise.next(NoiseColor::White);
+            let crushed = crusher.process((sample, sample), &truncate).0;
+            assert!(crushed <= sample && sample - crushed < 0.125);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! A high-pass filter that removes DC offset from the output.
+
+use crate::denormal::flush;
+use crate::PI;
+
+/// The blocker's cutoff in Hz, low enough to leave a 20 Hz fundamental within 0.3 dB.
+const CUTOFF: f64 = 5.0;
+
+/// How many samples of exact silence in before the blocker lets go of what it holds. Real
+/// signals cross zero, but never sit on it.
+const SETTLE_AFTER: u32 = 2;
+
+/// How fast in units per second the output ramps to zero once the input is silent: what a
+/// release leaves is gone within a sample or two, and even the offset a narrow pulse leaves
+/// within a dozen milliseconds, too gently to click.
+const SETTLE_SLEW: f64 = 40.0;
+
+/// A one-pole, one-zero high-pass for a single channel: `y[n] = x[n] - x[n-1] + r·y[n-1]`.
+#[derive(Clone, Copy, Debug)]
+pub struct DcBlocker {
+    /// The pole, just inside the unit circle.
+    pole: f64,
+    /// `SETTLE_SLEW` per sample.
+    settle_step: f64,
+    last_input: f64,
+    last_output: f64,
+    /// How many samples in a row the input has been exactly zero, up to `SETTLE_AFTER`.
+    silent_for: u32,
+}
+
+impl DcBlocker {
+    /// A blocker with no history.
+    pub fn new(sample_rate: f64) -> DcBlocker {
+        DcBlocker {
+            pole: (-2.0 * PI * CUTOFF / sample_rate).exp(),
+            settle_step: SETTLE_SLEW / sample_rate,
+            last_input: 0.0,
+            last_output: 0.0,
+            silent_for: 0,
+        }
+    }
+
+    /// Whether the blocker has nothing left to put out while its input stays at zero.
+    pub fn is_settled(&self) -> bool {
+        self.last_input == 0.0 && self.last_output == 0.0
+    }
+
+    pub fn process(&mut self, input: f64) -> f64 {
+        if input == 0.0 {
+            self.silent_for = (self.silent_for + 1).min(SETTLE_AFTER);
+        } else {
+            self.silent_for = 0;
+        }
+        // Left to the pole, what the blocker holds when the notes stop would take seconds to
+        // reach silence; once the input has stayed silent there's nothing left to pass, so it
+        // is let go quickly instead.
+        if self.silent_for == SETTLE_AFTER {
+            let output = self.last_output
+                - self
+                    .last_output
+                    .max(-self.settle_step)
+                    .min(self.settle_step);
+            self.last_input = 0.0;
+            self.last_output = output;
+            return output;
+        }
+        // Flushed so the blocker never falls into slow denormal arithmetic once the input
+        // stops.
+        let output = flush(input - self.last_input + self.pole * self.last_output);
+        self.last_input = input;
+        self.last_output = output;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DcBlocker;
+    use crate::analysis::rms;
+    use crate::TAU;
+
+    /// The gain in dB for a sine at `freq`, once the blocker has settled.
+    fn gain_db(freq: f64) -> f64 {
+        let mut blocker = DcBlocker::new(44100.0);
+        let sine = |i: usize| (freq * TAU * i as f64 / 44100.0).sin();
+        let (input, output): (Vec<f32>, Vec<f32>) = (0..441_000)
+            .map(|i| (sine(i) as f32, blocker.process(sine(i)) as f32))
+            .skip(220_500)
+            .unzip();
+        20.0 * (rms(&output) / rms(&input)).log10()
+    }
+
+    #[test]
+    fn low_fundamentals_pass() {
+        assert!(gain_db(20.0) > -0.3, "{} dB", gain_db(20.0));
+        assert!(gain_db(1000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn constant_input_decays_to_zero() {
+        let mut blocker = DcBlocker::new(44100.0);
+        assert_eq!(blocker.process(0.5), 0.5);
+        let mut output = 0.5;
+        for _ in 0..44100 {
+            output = blocker.process(0.5);
+        }
+        assert!(output.abs() < 1e-6, "{}", output);
+    }
+
+    #[test]
+    fn silence_settles_quickly_and_smoothly() {
+        let mut blocker = DcBlocker::new(44100.0);
+        for _ in 0..441 {
+            blocker.process(0.5);
+        }
+        let mut last = blocker.process(0.0);
+        for _ in 0..2048 {
+            let output = blocker.process(0.0);
+            assert!((output - last).abs() < 0.01, "{} after {}", output, last);
+            last = output;
+        }
+        assert!(blocker.is_settled());
+
+        // A lone zero crossing in a signal leaves the blocker as it was.
+        let mut blocker = DcBlocker::new(44100.0);
+        let pole = blocker.pole;
+        blocker.process(1.0);
+        assert_eq!(blocker.process(0.0), pole - 1.0);
+    }
+}
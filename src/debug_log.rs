@@ -0,0 +1,440 @@
+//! A log of what the synth decided, written from the audio thread without locking or
+//! allocating, for tracking down stuck notes and the like inside a host.
+//!
+//! It only exists with the `debug-log` feature. The audio thread pushes small fixed-size
+//! records into a ring, and a background thread drains them to the file named by the
+//! `VSTTEST_LOG` environment variable, or to stderr. Pushing never waits: when the drain falls
+//! behind, the oldest records are written over and the drain reports how many it missed.
+//!
+//! Each slot of the ring is a sequence lock. The writer marks the slot odd while it writes
+//! and even once it's done, and the reader keeps a record only if the slot held the same even
+//! mark, the one for the record it wanted, before and after reading it.
+
+// Without the feature only the tests use this module.
+#![cfg_attr(not(feature = "debug-log"), allow(dead_code))]
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::params::PARAM_COUNT;
+
+/// How many records the ring holds, enough for a few seconds of busy playing between drains.
+const CAPACITY: usize = 4096;
+
+/// How often the drain wakes up to write out what has been pushed.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Something the synth did.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Record {
+    /// A MIDI message arrived, to be applied `frame` samples into the block.
+    Midi { data: [u8; 3], frame: u32 },
+    /// A voice started a note.
+    NoteOn { voice: u8, channel: u8, note: u8 },
+    /// A key was let go, with its notes held on if the sustain pedal is down.
+    NoteOff { note: u8, pedal: bool },
+    /// A voice playing `stolen` was faded out to make room for `note`.
+    Steal { voice: u8, stolen: u8, note: u8 },
+    /// A parameter has changed since the block before.
+    Parameter { index: u16, value: f32 },
+}
+
+/// A record and the block it was pushed in, counting from the first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Entry {
+    pub block: u32,
+    pub record: Record,
+}
+
+impl Entry {
+    /// The entry packed into the two words a slot holds: the block, a kind and three bytes in
+    /// the first, and 32 more bits in the second.
+    fn encode(self) -> [u64; 2] {
+        let (kind, bytes, extra) = match self.record {
+            Record::Midi { data, frame } => (0, data, frame),
+            Record::NoteOn {
+                voice,
+                channel,
+                note,
+            } => (1, [voice, channel, note], 0),
+            Record::NoteOff { note, pedal } => (2, [note, pedal as u8, 0], 0),
+            Record::Steal {
+                voice,
+                stolen,
+                note,
+            } => (3, [voice, stolen, note], 0),
+            Record::Parameter { index, value } => {
+                let [high, low] = index.to_be_bytes();
+                (4, [high, low, 0], value.to_bits())
+            }
+        };
+        let first = u64::from(self.block) << 32
+            | u64::from(u32::from_be_bytes([kind, bytes[0], bytes[1], bytes[2]]));
+        [first, u64::from(extra)]
+    }
+
+    fn decode(words: [u64; 2]) -> Option<Entry> {
+        let [kind, a, b, c] = (words[0] as u32).to_be_bytes();
+        let extra = words[1] as u32;
+        let record = match kind {
+            0 => Record::Midi {
+                data: [a, b, c],
+                frame: extra,
+            },
+            1 => Record::NoteOn {
+                voice: a,
+                channel: b,
+                note: c,
+            },
+            2 => Record::NoteOff {
+                note: a,
+                pedal: b != 0,
+            },
+            3 => Record::Steal {
+                voice: a,
+                stolen: b,
+                note: c,
+            },
+            4 => Record::Parameter {
+                index: u16::from_be_bytes([a, b]),
+                value: f32::from_bits(extra),
+            },
+            _ => return None,
+        };
+        Some(Entry {
+            block: (words[0] >> 32) as u32,
+            record,
+        })
+    }
+}
+
+struct Slot {
+    /// `2n + 1` while record `n` is being written here, `2n + 2` once it has been.
+    sequence: AtomicU64,
+    words: [AtomicU64; 2],
+}
+
+/// The records shared between the writer and the drain.
+struct Ring {
+    slots: Box<[Slot]>,
+    /// How many records have ever been pushed.
+    written: AtomicU64,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Ring {
+        Ring {
+            slots: (0..capacity)
+                .map(|_| Slot {
+                    sequence: AtomicU64::new(0),
+                    words: [AtomicU64::new(0), AtomicU64::new(0)],
+                })
+                .collect(),
+            written: AtomicU64::new(0),
+        }
+    }
+
+    fn slot(&self, position: u64) -> &Slot {
+        &self.slots[(position % self.slots.len() as u64) as usize]
+    }
+}
+
+/// The one end that pushes. There is only ever one, which is what lets a push go without
+/// any compare-and-swap.
+pub struct Writer {
+    ring: Arc<Ring>,
+}
+
+impl Writer {
+    pub fn push(&mut self, entry: Entry) {
+        let position = self.ring.written.load(Ordering::Relaxed);
+        let slot = self.ring.slot(position);
+        slot.sequence.store(2 * position + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        for (word, value) in slot.words.iter().zip(&entry.encode()) {
+            word.store(*value, Ordering::Relaxed);
+        }
+        slot.sequence.store(2 * position + 2, Ordering::Release);
+        self.ring.written.store(position + 1, Ordering::Release);
+    }
+}
+
+/// The end that drains.
+pub struct Reader {
+    ring: Arc<Ring>,
+    /// The next record to read.
+    next: u64,
+}
+
+impl Reader {
+    /// Hand every record pushed since the last drain to `read`, oldest first, returning how
+    /// many were written over before they could be read.
+    pub fn drain(&mut self, mut read: impl FnMut(Entry)) -> u64 {
+        let written = self.ring.written.load(Ordering::Acquire);
+        let oldest = written.saturating_sub(self.ring.slots.len() as u64);
+        let mut dropped = oldest.saturating_sub(self.next);
+        self.next = self.next.max(oldest);
+        while self.next < written {
+            let slot = self.ring.slot(self.next);
+            let wanted = 2 * self.next + 2;
+            let before = slot.sequence.load(Ordering::Acquire);
+            let words = [
+                slot.words[0].load(Ordering::Relaxed),
+                slot.words[1].load(Ordering::Relaxed),
+            ];
+            fence(Ordering::Acquire);
+            let after = slot.sequence.load(Ordering::Relaxed);
+            match Entry::decode(words) {
+                Some(entry) if before == wanted && after == wanted => read(entry),
+                // The writer lapped the drain while it was reading.
+                _ => dropped += 1,
+            }
+            self.next += 1;
+        }
+        dropped
+    }
+}
+
+/// A ring holding `capacity` records, and its two ends.
+fn ring(capacity: usize) -> (Writer, Reader) {
+    let ring = Arc::new(Ring::new(capacity));
+    let reader = Reader {
+        ring: Arc::clone(&ring),
+        next: 0,
+    };
+    (Writer { ring }, reader)
+}
+
+/// A line of the log.
+fn describe(entry: Entry) -> String {
+    let what = match entry.record {
+        Record::Midi { data, frame } => format!(
+            "midi {:02X} {:02X} {:02X} at frame {}",
+            data[0], data[1], data[2], frame
+        ),
+        Record::NoteOn {
+            voice,
+            channel,
+            note,
+        } => format!(
+            "note on {} channel {} on voice {}",
+            note,
+            channel + 1,
+            voice
+        ),
+        Record::NoteOff { note, pedal: false } => format!("note off {}", note),
+        Record::NoteOff { note, pedal: true } => format!("note off {}, held by the pedal", note),
+        Record::Steal {
+            voice,
+            stolen,
+            note,
+        } => format!("voice {} stolen from {} for {}", voice, stolen, note),
+        Record::Parameter { index, value } => format!("parameter {} = {}", index, value),
+    };
+    format!("block {}: {}", entry.block, what)
+}
+
+/// The synth's end of the log: the writer, the block count and the parameter values last
+/// logged. Dropping it stops the drain once it has written out what's left.
+pub struct DebugLog {
+    writer: Writer,
+    block: u32,
+    params: [f32; PARAM_COUNT],
+    stop: Arc<AtomicBool>,
+    drain: Option<JoinHandle<()>>,
+}
+
+impl DebugLog {
+    /// Start a log and the thread that drains it.
+    pub fn start() -> DebugLog {
+        let (writer, mut reader) = ring(CAPACITY);
+        let stop = Arc::new(AtomicBool::new(false));
+        let drain = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let mut out: Box<dyn Write> = match std::env::var_os("VSTTEST_LOG")
+                    .and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok())
+                {
+                    Some(file) => Box::new(file),
+                    None => Box::new(io::stderr()),
+                };
+                loop {
+                    // Read the flag first, so the last drain sees everything pushed before it
+                    // was set.
+                    let stopping = stop.load(Ordering::Acquire);
+                    let mut lines = String::new();
+                    let dropped = reader.drain(|entry| {
+                        lines.push_str(&describe(entry));
+                        lines.push('\n');
+                    });
+                    if dropped > 0 {
+                        lines.push_str(&format!("{} records dropped\n", dropped));
+                    }
+                    // There's nowhere to report a failed write, and the synth plays on.
+                    let _ = out.write_all(lines.as_bytes()).and_then(|_| out.flush());
+                    if stopping {
+                        break;
+                    }
+                    thread::sleep(DRAIN_INTERVAL);
+                }
+            })
+        };
+        DebugLog {
+            writer,
+            block: 0,
+            params: [f32::NAN; PARAM_COUNT],
+            stop,
+            drain: Some(drain),
+        }
+    }
+
+    pub fn push(&mut self, record: Record) {
+        self.writer.push(Entry {
+            block: self.block,
+            record,
+        });
+    }
+
+    /// Start the next block, logging every parameter that changed since the last one, or all
+    /// of them the first time.
+    pub fn begin_block(&mut self, params: impl Iterator<Item = f32>) {
+        self.block = self.block.wrapping_add(1);
+        for (index, value) in params.enumerate().take(PARAM_COUNT) {
+            if value.to_bits() != self.params[index].to_bits() {
+                self.params[index] = value;
+                self.push(Record::Parameter {
+                    index: index as u16,
+                    value,
+                });
+            }
+        }
+    }
+}
+
+impl Drop for DebugLog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(drain) = self.drain.take() {
+            let _ = drain.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ring, Entry, Record};
+    use std::thread;
+
+    fn entry(block: u32) -> Entry {
+        Entry {
+            block,
+            record: Record::Midi {
+                data: [0x90, 60, 100],
+                frame: block,
+            },
+        }
+    }
+
+    fn blocks(reader: &mut super::Reader) -> (Vec<u32>, u64) {
+        let mut read = Vec::new();
+        let dropped = reader.drain(|entry| {
+            // A torn read would mix two records.
+            assert_eq!(entry, self::entry(entry.block));
+            read.push(entry.block)
+        });
+        (read, dropped)
+    }
+
+    #[test]
+    fn records_come_back_as_they_went_in() {
+        let (mut writer, mut reader) = ring(8);
+        let records = [
+            Record::Midi {
+                data: [0xB3, 64, 127],
+                frame: 511,
+            },
+            Record::NoteOn {
+                voice: 15,
+                channel: 9,
+                note: 127,
+            },
+            Record::NoteOff {
+                note: 0,
+                pedal: true,
+            },
+            Record::Steal {
+                voice: 3,
+                stolen: 60,
+                note: 61,
+            },
+            Record::Parameter {
+                index: 93,
+                value: -0.25,
+            },
+        ];
+        for (block, &record) in records.iter().enumerate() {
+            writer.push(Entry {
+                block: u32::MAX - block as u32,
+                record,
+            });
+        }
+        let mut read = Vec::new();
+        assert_eq!(reader.drain(|entry| read.push(entry)), 0);
+        let expected: Vec<Entry> = records
+            .iter()
+            .enumerate()
+            .map(|(block, &record)| Entry {
+                block: u32::MAX - block as u32,
+                record,
+            })
+            .collect();
+        assert_eq!(read, expected);
+        assert_eq!(blocks(&mut reader), (vec![], 0));
+    }
+
+    #[test]
+    fn overflow_drops_the_oldest_records() {
+        let (mut writer, mut reader) = ring(4);
+        for block in 0..10 {
+            writer.push(entry(block));
+        }
+        assert_eq!(blocks(&mut reader), (vec![6, 7, 8, 9], 6));
+        writer.push(entry(10));
+        assert_eq!(blocks(&mut reader), (vec![10], 0));
+        // Exactly a ringful since the last drain loses nothing.
+        for block in 11..15 {
+            writer.push(entry(block));
+        }
+        assert_eq!(blocks(&mut reader), (vec![11, 12, 13, 14], 0));
+    }
+
+    #[test]
+    fn a_writer_racing_the_drain_never_waits_or_duplicates() {
+        const PUSHES: u32 = 200_000;
+        let (mut writer, mut reader) = ring(64);
+        let writing = thread::spawn(move || {
+            for block in 0..PUSHES {
+                writer.push(entry(block));
+            }
+        });
+        let (mut read, mut dropped) = (Vec::new(), 0);
+        loop {
+            let finished = writing.is_finished();
+            let (more, lost) = blocks(&mut reader);
+            read.extend(more);
+            dropped += lost;
+            if finished {
+                break;
+            }
+        }
+        writing.join().unwrap();
+        // Whatever got through is in order, and every push is either read or counted lost.
+        assert!(read.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(read.len() as u64 + dropped, u64::from(PUSHES));
+        assert_eq!(read.last(), Some(&(PUSHES - 1)));
+    }
+}
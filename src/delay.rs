@@ -0,0 +1,267 @@
+//! A stereo echo with feedback, optionally bouncing between the channels.
+
+use crate::delay_line::DelayLine;
+use crate::denormal::flush;
+use crate::lfo::{SyncDivision, SYNC_DIVISIONS};
+use crate::smoother::SmoothedParam;
+
+/// The longest delay, in seconds. Synced times longer than this at slow tempos are held here.
+pub const MAX_DELAY: f64 = 2.0;
+
+/// The shortest delay, in seconds.
+pub const MIN_DELAY: f64 = 0.001;
+
+/// The most feedback, below the point where the echoes would stop dying away.
+pub const MAX_FEEDBACK: f64 = 0.95;
+
+/// Where the synced times start in `SYNC_DIVISIONS`, at one bar.
+const FIRST_DIVISION: usize = 2;
+
+/// The times "Delay Note" steps through, from a bar down to a 1/32 triplet.
+pub fn delay_divisions() -> &'static [SyncDivision] {
+    &SYNC_DIVISIONS[FIRST_DIVISION..]
+}
+
+/// Fed-back signal below this level goes round unchanged; above it the clipper bends it
+/// towards full scale, so even a runaway loop stays bounded.
+const KNEE: f64 = 0.5;
+
+/// How long the read head takes to follow a change of delay time, in seconds. Sliding it
+/// bends the pitch of what's in the line for a moment instead of jumping and crackling.
+const TIME_SLEW: f64 = 0.1;
+
+/// What the delay does, read from the parameters every block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DelaySettings {
+    /// In seconds, from `MIN_DELAY` to `MAX_DELAY`.
+    pub time: f64,
+    /// How much of each echo comes round again, up to `MAX_FEEDBACK`.
+    pub feedback: f64,
+    /// How much of the echoes replaces the dry sound, with none leaving it untouched.
+    pub mix: f64,
+    /// Whether the echoes alternate between left and right.
+    pub ping_pong: bool,
+}
+
+/// Soft-clip the signal going round the loop: straight up to `KNEE`, then curving towards
+/// but never past full scale.
+fn soft_clip(sample: f64) -> f64 {
+    let level = sample.abs();
+    if level <= KNEE {
+        return sample;
+    }
+    let over = (level - KNEE) / (1.0 - KNEE);
+    (KNEE + (1.0 - KNEE) * over.tanh()).copysign(sample)
+}
+
+/// A delay line per channel, long enough for `MAX_DELAY` at the sample rate it was made for,
+/// so processing never allocates.
+pub struct Delay {
+    lines: [DelayLine; 2],
+    sample_rate: f64,
+    /// The delay time in samples, slewed.
+    time: SmoothedParam,
+}
+
+impl Delay {
+    /// A delay with empty lines long enough for `sample_rate`.
+    pub fn new(sample_rate: f64) -> Delay {
+        let line = DelayLine::new(MAX_DELAY, sample_rate);
+        Delay {
+            lines: [line.clone(), line],
+            sample_rate,
+            time: SmoothedParam::new(TIME_SLEW, sample_rate),
+        }
+    }
+
+    /// Whether the lines hold nothing but silence, so there are no echoes left to come.
+    pub fn is_silent(&self) -> bool {
+        self.lines.iter().all(DelayLine::is_silent)
+    }
+
+    /// Empty the lines, without reallocating, and have the next delay time taken at once.
+    pub fn reset(&mut self) {
+        self.lines.iter_mut().for_each(DelayLine::clear);
+        self.time = SmoothedParam::new(TIME_SLEW, self.sample_rate);
+    }
+
+    /// Move the read head straight to `time` seconds, for when there's nothing to hear it
+    /// slide.
+    pub fn snap(&mut self, time: f64) {
+        self.time.snap(self.samples(time));
+    }
+
+    fn samples(&self, time: f64) -> f64 {
+        time.clamp(MIN_DELAY, MAX_DELAY) * self.sample_rate
+    }
+
+    /// One stereo frame through the delay.
+    ///
+    /// A mix of zero bypasses the delay, and echoes still on their way round are dropped
+    /// rather than left waiting for it to be turned up.
+    pub fn process(&mut self, frame: (f64, f64), settings: &DelaySettings) -> (f64, f64) {
+        let time = self.time.next(self.samples(settings.time));
+        if settings.mix == 0.0 {
+            self.lines.iter_mut().for_each(DelayLine::clear);
+            return frame;
+        }
+        // The lines hold up to the previous sample, which is one closer than this one.
+        let echo = (
+            self.lines[0].read(time - 1.0),
+            self.lines[1].read(time - 1.0),
+        );
+        let feedback = settings.feedback.clamp(0.0, MAX_FEEDBACK);
+        let back = |echo: f64| flush(soft_clip(feedback * echo));
+        let into = if settings.ping_pong {
+            // Everything starts on the left, and each echo crosses to the other side.
+            (0.5 * (frame.0 + frame.1) + back(echo.1), back(echo.0))
+        } else {
+            (frame.0 + back(echo.0), frame.1 + back(echo.1))
+        };
+        self.lines[0].push(into.0);
+        self.lines[1].push(into.1);
+        let mix = settings.mix;
+        (
+            frame.0 + (echo.0 - frame.0) * mix,
+            frame.1 + (echo.1 - frame.1) * mix,
+        )
+    }
+}
+
+/// How long the echoes of `settings` last after the input stops, in seconds: until they have
+/// died away by 60 dB, or none at all with the delay turned off.
+pub fn tail(settings: &DelaySettings) -> f64 {
+    if settings.mix == 0.0 {
+        return 0.0;
+    }
+    let feedback = settings.feedback.clamp(0.0, MAX_FEEDBACK);
+    let repeats = if feedback > 0.0 {
+        1.0 + (0.001f64).ln() / feedback.ln()
+    } else {
+        1.0
+    };
+    settings.time.clamp(MIN_DELAY, MAX_DELAY) * repeats.ceil()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{soft_clip, tail, Delay, DelaySettings};
+
+    const RATE: f64 = 44100.0;
+
+    fn settings(feedback: f64, ping_pong: bool) -> DelaySettings {
+        DelaySettings {
+            time: 0.25,
+            feedback,
+            mix: 1.0,
+            ping_pong,
+        }
+    }
+
+    /// Two seconds of the delay's answer to an impulse on the left.
+    fn impulse_response(settings: &DelaySettings) -> Vec<(f64, f64)> {
+        let mut delay = Delay::new(RATE);
+        (0..2 * RATE as usize)
+            .map(|i| {
+                let input = if i == 0 { 1.0 } else { 0.0 };
+                delay.process((input, 0.0), settings)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn echoes_come_on_time_and_die_away_geometrically() {
+        let response = impulse_response(&settings(0.5, false));
+        let spacing = (0.25 * RATE) as usize;
+        for (i, &(left, right)) in response.iter().enumerate() {
+            assert_eq!(right, 0.0);
+            if i > 0 && i % spacing == 0 {
+                let expected = 0.5f64.powi(i as i32 / spacing as i32 - 1);
+                assert!((left - expected).abs() < 1e-12, "{} at {}", left, i);
+            } else {
+                assert_eq!(left, 0.0, "at {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn ping_pong_alternates_sides() {
+        let response = impulse_response(&settings(0.5, true));
+        let spacing = (0.25 * RATE) as usize;
+        for echo in 1..8 {
+            let (left, right) = response[echo * spacing];
+            let level = 0.5 * 0.5f64.powi(echo as i32 - 1);
+            let expected = if echo % 2 == 1 {
+                (level, 0.0)
+            } else {
+                (0.0, level)
+            };
+            assert!((left - expected.0).abs() < 1e-12, "{} {}", echo, left);
+            assert!((right - expected.1).abs() < 1e-12, "{} {}", echo, right);
+        }
+    }
+
+    #[test]
+    fn full_feedback_stays_bounded() {
+        let mut delay = Delay::new(RATE);
+        let settings = DelaySettings {
+            time: 0.01,
+            feedback: 10.0,
+            mix: 1.0,
+            ping_pong: false,
+        };
+        let peak = (0..RATE as usize)
+            .map(|_| delay.process((1.0, -1.0), &settings).0.abs())
+            .fold(0.0, f64::max);
+        // The input, and at most full scale coming back round.
+        assert!(peak <= 2.0, "{}", peak);
+        assert!(soft_clip(100.0) <= 1.0);
+        assert_eq!(soft_clip(-0.25), -0.25);
+    }
+
+    #[test]
+    fn no_mix_passes_the_input_through_exactly() {
+        let mut delay = Delay::new(RATE);
+        let mut settings = settings(0.5, false);
+        for i in 0..4410 {
+            let frame = ((i as f64).sin(), (i as f64).cos());
+            delay.process(frame, &settings);
+        }
+        assert!(!delay.is_silent());
+        settings.mix = 0.0;
+        let frame = (0.25, -0.5);
+        assert_eq!(delay.process(frame, &settings), frame);
+        assert!(delay.is_silent());
+        assert_eq!(tail(&settings), 0.0);
+    }
+
+    #[test]
+    fn changing_the_time_slides_the_echo_without_a_jump() {
+        let mut delay = Delay::new(RATE);
+        let mut settings = settings(0.0, false);
+        let sine = |i: usize| (i as f64 * 0.01).sin();
+        for i in 0..RATE as usize {
+            delay.process((sine(i), 0.0), &settings);
+        }
+        settings.time = 0.5;
+        let mut last = delay.process((sine(44100), 0.0), &settings).0;
+        for i in 44101..2 * RATE as usize {
+            let output = delay.process((sine(i), 0.0), &settings).0;
+            assert!(
+                (output - last).abs() < 0.05,
+                "{} after {} at {}",
+                output,
+                last,
+                i
+            );
+            last = output;
+        }
+    }
+
+    #[test]
+    fn tail_lasts_until_the_echoes_are_60_db_down() {
+        assert_eq!(tail(&settings(0.0, false)), 0.25);
+        // The eleventh echo, at 0.5 to the tenth, is the first below 0.001.
+        assert_eq!(tail(&settings(0.5, false)), 0.25 * 11.0);
+    }
+}
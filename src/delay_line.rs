@@ -0,0 +1,102 @@
+//! A fixed-length line of past samples, read back at fractional delays.
+
+/// The samples most recently pushed, up to a length set when the line is made.
+#[derive(Clone, Debug)]
+pub struct DelayLine {
+    /// A power of two long, so positions wrap with a mask.
+    samples: Vec<f64>,
+    /// Where the latest sample is.
+    write: usize,
+    /// How many silent samples in a row have been pushed, up to the line's length.
+    quiet_for: usize,
+}
+
+impl DelayLine {
+    /// An empty line long enough to read `longest` seconds back at `sample_rate`.
+    pub fn new(longest: f64, sample_rate: f64) -> DelayLine {
+        // The cubic reads one sample past the delay asked for.
+        let len = (longest * sample_rate).ceil() as usize + 2;
+        let len = len.next_power_of_two();
+        DelayLine {
+            samples: vec![0.0; len],
+            write: 0,
+            quiet_for: len,
+        }
+    }
+
+    pub fn push(&mut self, sample: f64) {
+        self.write = (self.write + 1) & (self.samples.len() - 1);
+        self.samples[self.write] = sample;
+        if sample == 0.0 {
+            self.quiet_for = (self.quiet_for + 1).min(self.samples.len());
+        } else {
+            self.quiet_for = 0;
+        }
+    }
+
+    /// Whether every sample in the line is silence.
+    pub fn is_silent(&self) -> bool {
+        self.quiet_for >= self.samples.len()
+    }
+
+    /// Fill the line with silence, without reallocating it.
+    pub fn clear(&mut self) {
+        if !self.is_silent() {
+            self.samples.fill(0.0);
+            self.quiet_for = self.samples.len();
+        }
+    }
+
+    /// The sample `delay` samples before the latest one, between samples by a cubic
+    /// (Catmull-Rom) curve through the four nearest, so a moving delay doesn't zipper.
+    ///
+    /// `delay` must be at least 1 and leave room for one sample more within the line.
+    pub fn read(&self, delay: f64) -> f64 {
+        let mask = self.samples.len() - 1;
+        let whole = delay.floor();
+        let t = delay - whole;
+        let at = |back: usize| self.samples[self.write.wrapping_sub(back) & mask];
+        let back = whole as usize;
+        let (y0, y1, y2, y3) = (at(back - 1), at(back), at(back + 1), at(back + 2));
+        let a = -0.5 * y0 + 1.5 * y1 - 1.5 * y2 + 0.5 * y3;
+        let b = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+        let c = -0.5 * y0 + 0.5 * y2;
+        ((a * t + b) * t + c) * t + y1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DelayLine;
+
+    #[test]
+    fn whole_delays_are_exact_and_fractions_in_between() {
+        let mut line = DelayLine::new(0.01, 1000.0);
+        for i in 0..20 {
+            line.push(f64::from(i));
+        }
+        assert_eq!(line.read(1.0), 18.0);
+        assert_eq!(line.read(7.0), 12.0);
+        // A ramp is a curve the cubic follows exactly.
+        assert!((line.read(3.25) - 15.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn silent_once_the_last_sound_has_gone_through() {
+        // Ten samples, and two for the cubic, round up to sixteen.
+        let mut line = DelayLine::new(0.01, 1000.0);
+        assert!(line.is_silent());
+        line.push(1.0);
+        for _ in 1..16 {
+            line.push(0.0);
+            assert!(!line.is_silent());
+        }
+        line.push(0.0);
+        assert!(line.is_silent());
+
+        line.push(1.0);
+        line.clear();
+        assert!(line.is_silent());
+        assert_eq!(line.read(1.0), 0.0);
+    }
+}
@@ -0,0 +1,169 @@
+//! Keeping denormal numbers out of the audio path.
+//!
+//! A recursive state left to decay after its input stops, such as a filter ringing out or a
+//! smoother easing to zero, eventually shrinks into the denormal range, where arithmetic can
+//! be a hundred times slower. That's when the synth is quietest, so the CPU spikes just as
+//! nothing seems to be happening.
+//!
+//! `DenormalGuard` has the CPU flush them to zero itself while the synth renders, where it
+//! can. `flush` does the same for a single state in plain code, which works everywhere and
+//! makes every CPU render the same samples.
+
+use std::marker::PhantomData;
+
+/// State values smaller than this are flushed to zero. It is far below anything audible, but
+/// far above the denormal range, so even a product of two small states doesn't reach it.
+const DENORMAL_LIMIT: f64 = 1e-20;
+
+/// `value`, or zero once it is too small to matter.
+pub fn flush(value: f64) -> f64 {
+    if value.abs() < DENORMAL_LIMIT {
+        0.0
+    } else {
+        value
+    }
+}
+
+/// The SSE control register's flush-to-zero and denormals-are-zero bits.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+const FTZ_DAZ: u32 = 0x8040;
+
+/// Flushes denormal results and inputs to zero in hardware for as long as it is held, and
+/// puts back the thread's previous setting when dropped.
+///
+/// The setting belongs to the thread, so the guard can't be sent to another one. On CPUs
+/// without the setting it does nothing.
+pub struct DenormalGuard {
+    /// The control register as it was before, if it was changed.
+    #[cfg_attr(
+        not(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse"
+        )),
+        allow(dead_code)
+    )]
+    saved: Option<u32>,
+    not_send: PhantomData<*const ()>,
+}
+
+impl DenormalGuard {
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    ))]
+    pub fn new() -> DenormalGuard {
+        let saved = read_csr();
+        write_csr(saved | FTZ_DAZ);
+        DenormalGuard {
+            saved: Some(saved),
+            not_send: PhantomData,
+        }
+    }
+
+    #[cfg(not(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    )))]
+    pub fn new() -> DenormalGuard {
+        DenormalGuard {
+            saved: None,
+            not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for DenormalGuard {
+    fn drop(&mut self) {
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse"
+        ))]
+        if let Some(saved) = self.saved {
+            write_csr(saved);
+        }
+    }
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+fn read_csr() -> u32 {
+    let mut csr = 0u32;
+    // SAFETY: `stmxcsr` only stores the control register into `csr`.
+    unsafe {
+        std::arch::asm!("stmxcsr [{}]", in(reg) &mut csr, options(nostack, preserves_flags));
+    }
+    csr
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+fn write_csr(csr: u32) {
+    // SAFETY: `ldmxcsr` only loads the control register from `csr`; the callers only change
+    // how denormals are treated, which no code relies on for memory safety.
+    unsafe {
+        std::arch::asm!("ldmxcsr [{}]", in(reg) &csr, options(nostack, readonly, preserves_flags));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flush, DenormalGuard};
+    use std::hint::black_box;
+
+    #[test]
+    fn decaying_states_reach_exactly_zero() {
+        let mut state = 1.0f64;
+        let mut unflushed = 1.0f64;
+        for _ in 0..14_000 {
+            state = flush(state * 0.95);
+            unflushed *= 0.95;
+        }
+        assert_eq!(state, 0.0);
+        // Left alone, the same decay is still lingering in the denormal range.
+        assert!(unflushed > 0.0 && !unflushed.is_normal());
+        assert_eq!(flush(-1e-30), 0.0);
+        assert_eq!(flush(1e-6), 1e-6);
+    }
+
+    #[test]
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    ))]
+    fn guard_flushes_until_dropped() {
+        let tiny = || black_box(f64::MIN_POSITIVE) / black_box(4.0);
+        assert!(tiny() > 0.0);
+        {
+            let _guard = DenormalGuard::new();
+            assert_eq!(tiny(), 0.0);
+            // Denormal inputs count as zero too.
+            assert_eq!(black_box(f64::MIN_POSITIVE / 4.0) * black_box(4.0), 0.0);
+        }
+        assert!(tiny() > 0.0);
+    }
+
+    #[test]
+    fn guards_nest() {
+        let tiny = || black_box(f64::MIN_POSITIVE) / black_box(4.0);
+        let outer = DenormalGuard::new();
+        let inner = DenormalGuard::new();
+        drop(inner);
+        // The inner guard puts back the outer one's setting, not the thread's original.
+        let flushed = tiny() == 0.0;
+        drop(outer);
+        assert!(tiny() > 0.0);
+        if cfg!(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse"
+        )) {
+            assert!(flushed);
+        }
+    }
+}
@@ -0,0 +1,494 @@
+//! The plugin's own editor: a slider for every parameter, with its name and its value as the
+//! host would show it.
+//!
+//! It only exists with the `editor` feature, and only on Linux, where the pixels are shown in
+//! an X11 window inside the one the host gives; elsewhere the plugin offers the host no
+//! editor. The panel is drawn into a plain array of pixels with a built-in bitmap font, so it
+//! needs no toolkit. The sliders read and write the same atomics the host
+//! automates, and the panel is redrawn whenever any of them has moved, so automation shows
+//! while the editor is open.
+
+// Without the feature or a window system only the tests use this module.
+#![cfg_attr(not(all(feature = "editor", target_os = "linux")), allow(dead_code))]
+
+use std::convert::TryFrom;
+use std::os::raw::c_void;
+use std::sync::Arc;
+
+use vst::editor::Editor;
+use vst::plugin::PluginParameters;
+
+use crate::params::PARAM_COUNT;
+use crate::GainEffectParameters;
+
+#[cfg(all(feature = "editor", target_os = "linux"))]
+use crate::x11::{Pointer, Window};
+
+/// How many columns of sliders there are.
+const COLUMNS: usize = 3;
+
+/// The size of one slider's cell, name and value included, in pixels.
+const CELL_WIDTH: usize = 300;
+const CELL_HEIGHT: usize = 18;
+
+/// The space around the whole panel.
+const MARGIN: usize = 8;
+
+/// Where the slider's bar sits within its cell, after the name and before the value.
+const BAR_LEFT: usize = 92;
+const BAR_WIDTH: usize = 108;
+const BAR_HEIGHT: usize = 10;
+const VALUE_LEFT: usize = BAR_LEFT + BAR_WIDTH + 6;
+
+/// The rows the sliders take up, filling each column before the next.
+const ROWS: usize = PARAM_COUNT.div_ceil(COLUMNS);
+
+/// The editor's size, in pixels.
+pub const WIDTH: usize = COLUMNS * CELL_WIDTH + 2 * MARGIN;
+pub const HEIGHT: usize = ROWS * CELL_HEIGHT + 2 * MARGIN;
+
+const BACKGROUND: u32 = 0x20_20_20;
+const TEXT: u32 = 0xE0_E0_E0;
+const BAR: u32 = 0x40_40_40;
+const FILL: u32 = 0x4A_90_D9;
+/// The fill of the slider being dragged.
+const ACTIVE_FILL: u32 = 0x7A_B8_F0;
+
+/// Glyphs are five columns of seven pixels, with a column between letters.
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+
+/// The printable ASCII characters from the space to the tilde, a byte per column with the top
+/// pixel in the lowest bit.
+#[rustfmt::skip]
+const FONT: [[u8; GLYPH_WIDTH]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E],
+    [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x01, 0x01], [0x3E, 0x41, 0x41, 0x51, 0x32],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x04, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F],
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x03, 0x04, 0x78, 0x04, 0x03],
+    [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7E, 0x09, 0x01, 0x02], [0x08, 0x14, 0x54, 0x54, 0x3C],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3D, 0x00], [0x00, 0x7F, 0x10, 0x28, 0x44],
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C],
+    [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C],
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+/// The glyph for `c`, with a question mark standing in for anything outside ASCII.
+fn glyph(c: char) -> &'static [u8; GLYPH_WIDTH] {
+    match c {
+        ' '..='~' => &FONT[c as usize - ' ' as usize],
+        _ => &FONT[usize::from(b'?' - b' ')],
+    }
+}
+
+/// The top left corner of parameter `index`'s cell.
+fn cell(index: usize) -> (usize, usize) {
+    (
+        MARGIN + index / ROWS * CELL_WIDTH,
+        MARGIN + index % ROWS * CELL_HEIGHT,
+    )
+}
+
+/// The parameter whose bar is at `(x, y)`, if any.
+fn bar_at(x: i32, y: i32) -> Option<usize> {
+    let x = usize::try_from(x).ok()?.checked_sub(MARGIN)?;
+    let y = usize::try_from(y).ok()?.checked_sub(MARGIN)?;
+    let (column, row) = (x / CELL_WIDTH, y / CELL_HEIGHT);
+    let index = column * ROWS + row;
+    let within = x % CELL_WIDTH;
+    let in_bar = (BAR_LEFT..BAR_LEFT + BAR_WIDTH).contains(&within);
+    Some(index).filter(|&index| column < COLUMNS && row < ROWS && index < PARAM_COUNT && in_bar)
+}
+
+/// The value a slider is set to by the pointer at `x`, clamped to the ends of the bar.
+fn value_at(index: usize, x: i32) -> f32 {
+    let left = (cell(index).0 + BAR_LEFT) as f32;
+    ((x as f32 - left) / (BAR_WIDTH - 1) as f32).clamp(0.0, 1.0)
+}
+
+/// The panel's pixels, `0xRRGGBB` a row at a time.
+struct Canvas {
+    pixels: Vec<u32>,
+}
+
+impl Canvas {
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, colour: u32) {
+        for row in y..(y + height).min(HEIGHT) {
+            let start = row * WIDTH;
+            self.pixels[start + x.min(WIDTH)..start + (x + width).min(WIDTH)].fill(colour);
+        }
+    }
+
+    /// Draw `text` from `(x, y)`, cut short rather than running past `width`.
+    fn text(&mut self, x: usize, y: usize, width: usize, text: &str) {
+        for (n, c) in text.chars().take(width / ADVANCE).enumerate() {
+            let left = x + n * ADVANCE;
+            for (column, bits) in glyph(c).iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits >> row & 1 != 0 {
+                        self.pixels[(y + row) * WIDTH + left + column] = TEXT;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The sliders, drawn and dragged without any window, which the editor shows.
+pub struct Panel {
+    params: Arc<GainEffectParameters>,
+    canvas: Canvas,
+    /// The values the canvas shows, to tell when the host has moved any.
+    shown: [f32; PARAM_COUNT],
+    /// The slider being dragged.
+    dragging: Option<usize>,
+}
+
+impl Panel {
+    pub fn new(params: Arc<GainEffectParameters>) -> Panel {
+        let mut panel = Panel {
+            params,
+            canvas: Canvas {
+                pixels: vec![BACKGROUND; WIDTH * HEIGHT],
+            },
+            shown: [f32::NAN; PARAM_COUNT],
+            dragging: None,
+        };
+        panel.refresh();
+        panel
+    }
+
+    pub fn pixels(&self) -> &[u32] {
+        &self.canvas.pixels
+    }
+
+    /// Draw the panel again if any parameter has changed since it was last drawn, returning
+    /// whether it did. A value's text can depend on the others, so every cell is redrawn.
+    pub fn refresh(&mut self) -> bool {
+        let values: Vec<f32> = (0..PARAM_COUNT)
+            .map(|index| self.params.get_parameter(index as i32))
+            .collect();
+        if values
+            .iter()
+            .zip(&self.shown)
+            .all(|(value, shown)| value.to_bits() == shown.to_bits())
+        {
+            return false;
+        }
+        self.shown.copy_from_slice(&values);
+        self.draw();
+        true
+    }
+
+    fn draw(&mut self) {
+        self.canvas.pixels.fill(BACKGROUND);
+        for index in 0..PARAM_COUNT {
+            let (x, y) = cell(index);
+            let text_y = y + (CELL_HEIGHT - GLYPH_HEIGHT) / 2;
+            let name = self.params.get_parameter_name(index as i32);
+            self.canvas.text(x + 4, text_y, BAR_LEFT - 8, &name);
+
+            let bar_y = y + (CELL_HEIGHT - BAR_HEIGHT) / 2;
+            let filled = (self.shown[index].clamp(0.0, 1.0) * BAR_WIDTH as f32).round() as usize;
+            let fill = if self.dragging == Some(index) {
+                ACTIVE_FILL
+            } else {
+                FILL
+            };
+            self.canvas
+                .fill(x + BAR_LEFT, bar_y, BAR_WIDTH, BAR_HEIGHT, BAR);
+            self.canvas
+                .fill(x + BAR_LEFT, bar_y, filled, BAR_HEIGHT, fill);
+
+            let value = self.params.display_text(index as i32);
+            self.canvas
+                .text(x + VALUE_LEFT, text_y, CELL_WIDTH - VALUE_LEFT, &value);
+        }
+    }
+
+    /// Start dragging the slider under `(x, y)`, setting it to where it was pressed. Returns
+    /// the parameter grabbed.
+    pub fn press(&mut self, x: i32, y: i32) -> Option<(usize, f32)> {
+        let index = bar_at(x, y)?;
+        self.dragging = Some(index);
+        let value = value_at(index, x);
+        let handle = self.params.handle(index);
+        handle.begin();
+        handle.set(value);
+        self.refresh();
+        Some((index, value))
+    }
+
+    /// Move the slider being dragged to follow the pointer at `x`, wherever it has got to.
+    pub fn drag(&mut self, x: i32) -> Option<(usize, f32)> {
+        let index = self.dragging?;
+        let value = value_at(index, x);
+        self.params.handle(index).set(value);
+        self.refresh();
+        Some((index, value))
+    }
+
+    /// Let go of the slider being dragged, returning which it was.
+    pub fn release(&mut self) -> Option<usize> {
+        let index = self.dragging.take()?;
+        self.params.handle(index).end();
+        // Drawn again to take the highlight off.
+        self.draw();
+        Some(index)
+    }
+}
+
+/// Where there's no window system to show the panel in, the plugin has no editor, and this
+/// stands in for the window so the tests can drive the panel.
+#[cfg(not(all(feature = "editor", target_os = "linux")))]
+struct Window;
+
+#[cfg(not(all(feature = "editor", target_os = "linux")))]
+impl Window {
+    fn open(_parent: usize, _width: usize, _height: usize) -> Option<Window> {
+        None
+    }
+}
+
+/// The editor the host opens. Each drag is a gesture the host is told about, so it can
+/// record it as automation.
+pub struct ParamEditor {
+    panel: Panel,
+    window: Option<Window>,
+}
+
+impl ParamEditor {
+    pub fn new(params: Arc<GainEffectParameters>) -> ParamEditor {
+        ParamEditor {
+            panel: Panel::new(params),
+            window: None,
+        }
+    }
+
+    #[cfg(all(feature = "editor", target_os = "linux"))]
+    fn pointer(&mut self, pointer: Pointer) {
+        match pointer {
+            Pointer::Press(x, y) => {
+                self.panel.press(x, y);
+            }
+            Pointer::Drag(x, _) => {
+                self.panel.drag(x);
+            }
+            Pointer::Release => {
+                self.panel.release();
+            }
+        }
+    }
+}
+
+impl Editor for ParamEditor {
+    fn size(&self) -> (i32, i32) {
+        (WIDTH as i32, HEIGHT as i32)
+    }
+
+    fn position(&self) -> (i32, i32) {
+        (0, 0)
+    }
+
+    /// Poll the window, and draw the panel again if the host or a drag has moved anything.
+    fn idle(&mut self) {
+        #[cfg(all(feature = "editor", target_os = "linux"))]
+        {
+            let mut window = match self.window.take() {
+                Some(window) => window,
+                None => return,
+            };
+            let mut pointers = Vec::new();
+            let exposed = window.poll(|pointer| pointers.push(pointer));
+            for pointer in pointers {
+                self.pointer(pointer);
+            }
+            if self.panel.refresh() || exposed {
+                window.show(self.panel.pixels());
+            }
+            self.window = Some(window);
+        }
+    }
+
+    /// Closing drops the window, which is safe to do however many times the host asks. A drag
+    /// cut short by closing is ended for the host.
+    fn close(&mut self) {
+        self.window = None;
+        self.panel.release();
+    }
+
+    /// Open inside the host's window `parent`, moving there from any window already open. A
+    /// null parent, or no window system to open on, leaves the editor closed.
+    fn open(&mut self, parent: *mut c_void) -> bool {
+        self.close();
+        if parent.is_null() {
+            return false;
+        }
+        self.window = Window::open(parent as usize, WIDTH, HEIGHT);
+        #[cfg(all(feature = "editor", target_os = "linux"))]
+        if let Some(window) = &mut self.window {
+            self.panel.refresh();
+            window.show(self.panel.pixels());
+        }
+        self.window.is_some()
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.window.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    fn editor() -> ParamEditor {
+        ParamEditor::new(Arc::new(GainEffectParameters::default()))
+    }
+
+    /// The middle of parameter `index`'s bar, `along` of the way from its left end.
+    fn on_bar(index: usize, along: f32) -> (i32, i32) {
+        let (x, y) = cell(index);
+        (
+            (x + BAR_LEFT) as i32 + (along * (BAR_WIDTH - 1) as f32).round() as i32,
+            (y + CELL_HEIGHT / 2) as i32,
+        )
+    }
+
+    fn cell_pixels(panel: &Panel, index: usize) -> Vec<u32> {
+        let (x, y) = cell(index);
+        (y..y + CELL_HEIGHT)
+            .flat_map(|row| panel.pixels()[row * WIDTH + x..row * WIDTH + x + CELL_WIDTH].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn every_slider_fits_in_the_reported_size() {
+        let mut editor = editor();
+        assert_eq!(editor.size(), (WIDTH as i32, HEIGHT as i32));
+        assert_eq!(editor.panel.pixels().len(), WIDTH * HEIGHT);
+        let (x, y) = cell(PARAM_COUNT - 1);
+        assert!(x + CELL_WIDTH <= WIDTH - MARGIN && y + CELL_HEIGHT <= HEIGHT - MARGIN);
+        for index in 0..PARAM_COUNT {
+            let (x, y) = on_bar(index, 0.5);
+            assert_eq!(bar_at(x, y), Some(index));
+        }
+        assert_eq!(bar_at(0, 0), None);
+        assert_eq!(bar_at(-1, 20), None);
+        assert!(!editor.is_open());
+    }
+
+    #[test]
+    fn opening_on_no_window_and_closing_are_safe_to_repeat() {
+        let mut editor = editor();
+        for _ in 0..3 {
+            assert!(!editor.open(ptr::null_mut()));
+            assert!(!editor.is_open());
+            editor.close();
+            editor.close();
+            assert!(!editor.is_open());
+        }
+        // Idling while closed does nothing.
+        editor.idle();
+    }
+
+    #[test]
+    fn dragging_a_slider_sets_its_parameter() {
+        let params = Arc::new(GainEffectParameters::default());
+        let mut panel = Panel::new(Arc::clone(&params));
+        let (x, y) = on_bar(0, 0.25);
+        let (index, value) = panel.press(x, y).unwrap();
+        assert_eq!(index, 0);
+        assert!((value - 0.25).abs() < 0.01, "{}", value);
+        assert_eq!(params.get_parameter(0), value);
+        // Dragging off either end pins it there.
+        assert_eq!(panel.drag(x + 1000), Some((0, 1.0)));
+        assert_eq!(panel.drag(x - 1000), Some((0, 0.0)));
+        assert_eq!(params.get_parameter(0), 0.0);
+        assert_eq!(panel.release(), Some(0));
+        assert_eq!(panel.drag(x), None);
+        assert_eq!(panel.release(), None);
+        // Pressing the name or the value text grabs nothing.
+        let (x, y) = cell(1);
+        assert_eq!(panel.press(x as i32 + 4, y as i32 + 4), None);
+    }
+
+    #[test]
+    fn automation_shows_while_open() {
+        let params = Arc::new(GainEffectParameters::default());
+        let mut panel = Panel::new(Arc::clone(&params));
+        assert!(!panel.refresh());
+        let before = cell_pixels(&panel, 38);
+        params.set_parameter(38, 0.1);
+        assert!(panel.refresh());
+        assert_ne!(cell_pixels(&panel, 38), before);
+        // Nothing moved since.
+        assert!(!panel.refresh());
+    }
+
+    #[test]
+    fn names_and_values_are_drawn() {
+        let panel = Panel::new(Arc::new(GainEffectParameters::default()));
+        let text_in = |index: usize, left: usize, width: usize| {
+            let (x, y) = cell(index);
+            (y..y + CELL_HEIGHT).any(|row| {
+                panel.pixels()[row * WIDTH + x + left..row * WIDTH + x + left + width]
+                    .contains(&TEXT)
+            })
+        };
+        for index in 0..PARAM_COUNT {
+            assert!(text_in(index, 0, BAR_LEFT), "name {}", index);
+            assert!(
+                text_in(index, VALUE_LEFT, CELL_WIDTH - VALUE_LEFT),
+                "value {}",
+                index
+            );
+        }
+        // Every printable character has something to draw but the space.
+        assert!(FONT[1..]
+            .iter()
+            .all(|columns| columns.iter().any(|&c| c != 0)));
+        assert_eq!(glyph('µ'), glyph('?'));
+    }
+}
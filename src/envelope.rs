@@ -0,0 +1,349 @@
+//! Per-voice ADSR amplitude envelope.
+
+use crate::params::options;
+
+/// How long `Envelope::kill` takes to fade to silence, in seconds.
+const KILL_TIME: f64 = 0.005;
+
+/// How many time constants an exponential segment lasts. Each segment is scaled to reach
+/// its target exactly at the end, rather than only closing in on it.
+const EXP_TIME_CONSTANTS: f64 = 5.0;
+
+/// The envelope shape. Times are in seconds and `sustain` is a level between 0 and 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdsrSettings {
+    pub attack: f64,
+    pub decay: f64,
+    pub sustain: f64,
+    pub release: f64,
+    pub curve: EnvCurve,
+}
+
+options! {
+    /// How each segment moves from its starting level to its target.
+    pub enum EnvCurve {
+        /// A straight line.
+        Linear => "Linear",
+        /// Fast at first and slowing as it closes in, like a capacitor charging: a concave attack
+        /// and a convex decay and release.
+        Exponential => "Exp",
+        /// Easing out of the starting level and into the target.
+        SCurve => "S-Curve",
+    }
+}
+
+impl EnvCurve {
+    /// How far through its change in level a segment is at `progress` through its time,
+    /// both from 0.0 to 1.0. Every curve starts at 0.0 and ends at exactly 1.0, so the
+    /// segments last as long as they're set to.
+    pub fn shape(self, progress: f64) -> f64 {
+        match self {
+            EnvCurve::Linear => progress,
+            EnvCurve::Exponential => {
+                let k = EXP_TIME_CONSTANTS;
+                (1.0 - (-k * progress).exp()) / (1.0 - (-k).exp())
+            }
+            EnvCurve::SCurve => progress * progress * (3.0 - 2.0 * progress),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// An ADSR envelope, stepped once per sample, with segments shaped by an `EnvCurve`.
+///
+/// Every stage starts from whatever level the envelope was at, so retriggering or releasing
+/// part way through a stage never makes the output jump.
+#[derive(Clone, Copy, Debug)]
+pub struct Envelope {
+    stage: Stage,
+    /// Samples elapsed in the current stage.
+    position: u64,
+    /// The level the current stage started from.
+    start_level: f64,
+    level: f64,
+    /// The current release was started by `kill` and ignores the release setting.
+    killed: bool,
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope {
+            stage: Stage::Idle,
+            position: 0,
+            start_level: 0.0,
+            level: 0.0,
+            killed: false,
+        }
+    }
+}
+
+impl Envelope {
+    /// Start (or restart) the attack from the current level.
+    pub fn trigger(&mut self) {
+        self.enter(Stage::Attack);
+    }
+
+    /// Move to the release stage from wherever the envelope currently is.
+    pub fn release(&mut self) {
+        if self.stage != Stage::Idle && self.stage != Stage::Release {
+            self.enter(Stage::Release);
+        }
+    }
+
+    /// Fade out over a few milliseconds, just long enough to avoid a click.
+    pub fn kill(&mut self) {
+        if self.stage != Stage::Idle {
+            self.enter(Stage::Release);
+            self.killed = true;
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// The level returned by the latest call to `next`.
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    /// Whether the envelope is still producing sound.
+    pub fn is_active(&self) -> bool {
+        self.stage != Stage::Idle
+    }
+
+    /// Return the level for the current sample and advance by one sample.
+    pub fn next(&mut self, settings: &AdsrSettings, sample_rate: f64) -> f64 {
+        self.settle(settings, sample_rate);
+
+        let progress = match self.stage_length(settings, sample_rate) {
+            Some(length) => self.position as f64 / length as f64,
+            None => 0.0,
+        };
+        // A kill is only there to avoid a click, so it fades the same whatever the curve.
+        let progress = if self.killed {
+            progress
+        } else {
+            settings.curve.shape(progress)
+        };
+        self.level = match self.stage {
+            Stage::Idle => 0.0,
+            Stage::Attack => self.start_level + (1.0 - self.start_level) * progress,
+            Stage::Decay => self.start_level + (settings.sustain - self.start_level) * progress,
+            Stage::Sustain => settings.sustain,
+            Stage::Release => self.start_level * (1.0 - progress),
+        };
+        self.position += 1;
+
+        self.settle(settings, sample_rate);
+        self.level
+    }
+
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.position = 0;
+        self.start_level = self.level;
+        self.killed = false;
+    }
+
+    /// The length in samples of the current stage, or `None` if it lasts until the next event.
+    fn stage_length(&self, settings: &AdsrSettings, sample_rate: f64) -> Option<u64> {
+        let samples = |seconds: f64| (seconds.max(0.0) * sample_rate).round() as u64;
+        match self.stage {
+            // A retriggered attack keeps the same slope, so it is shorter when starting higher.
+            Stage::Attack => Some(samples(settings.attack * (1.0 - self.start_level))),
+            Stage::Decay => Some(samples(settings.decay)),
+            Stage::Release if self.killed => Some(samples(KILL_TIME)),
+            Stage::Release => Some(samples(settings.release)),
+            Stage::Idle | Stage::Sustain => None,
+        }
+    }
+
+    /// Move on from every stage that has run its full length.
+    fn settle(&mut self, settings: &AdsrSettings, sample_rate: f64) {
+        while let Some(length) = self.stage_length(settings, sample_rate) {
+            if self.position < length {
+                break;
+            }
+            match self.stage {
+                Stage::Attack => {
+                    self.level = 1.0;
+                    self.enter(Stage::Decay);
+                }
+                Stage::Decay => {
+                    self.level = settings.sustain;
+                    self.enter(Stage::Sustain);
+                }
+                _ => {
+                    self.level = 0.0;
+                    self.enter(Stage::Idle);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AdsrSettings, EnvCurve, Envelope, Stage, EXP_TIME_CONSTANTS};
+
+    const SAMPLE_RATE: f64 = 1000.0;
+    const SETTINGS: AdsrSettings = AdsrSettings {
+        attack: 0.01,
+        decay: 0.02,
+        sustain: 0.5,
+        release: 0.03,
+        curve: EnvCurve::Linear,
+    };
+
+    fn step(envelope: &mut Envelope, samples: usize) -> Vec<f64> {
+        (0..samples)
+            .map(|_| envelope.next(&SETTINGS, SAMPLE_RATE))
+            .collect()
+    }
+
+    #[test]
+    fn stage_boundaries_land_on_expected_samples() {
+        let mut envelope = Envelope::default();
+        envelope.trigger();
+
+        let attack = step(&mut envelope, 9);
+        assert_eq!(attack[0], 0.0);
+        assert!((attack[5] - 0.5).abs() < 1e-12);
+        assert_eq!(envelope.stage(), Stage::Attack);
+        step(&mut envelope, 1);
+        assert_eq!(envelope.stage(), Stage::Decay);
+
+        let decay = step(&mut envelope, 20);
+        assert_eq!(decay[0], 1.0);
+        assert!((decay[10] - 0.75).abs() < 1e-12);
+        assert_eq!(envelope.stage(), Stage::Sustain);
+
+        assert!(step(&mut envelope, 100).iter().all(|&l| l == 0.5));
+
+        envelope.release();
+        let release = step(&mut envelope, 29);
+        assert_eq!(release[0], 0.5);
+        assert_eq!(envelope.stage(), Stage::Release);
+        step(&mut envelope, 1);
+        assert_eq!(envelope.stage(), Stage::Idle);
+        assert!(!envelope.is_active());
+    }
+
+    #[test]
+    fn release_during_attack_starts_from_current_level() {
+        let mut envelope = Envelope::default();
+        envelope.trigger();
+        let attack = step(&mut envelope, 5);
+        envelope.release();
+        let release = step(&mut envelope, 30);
+
+        assert_eq!(release[0], attack[4]);
+        assert!(release.windows(2).all(|w| w[1] <= w[0]));
+        assert_eq!(envelope.stage(), Stage::Idle);
+    }
+
+    #[test]
+    fn retrigger_continues_from_current_level() {
+        let mut envelope = Envelope::default();
+        envelope.trigger();
+        step(&mut envelope, 50);
+        envelope.release();
+        let release = step(&mut envelope, 15);
+        envelope.trigger();
+        let attack = step(&mut envelope, 10);
+
+        assert_eq!(attack[0], release[14]);
+        assert_eq!(envelope.stage(), Stage::Decay);
+    }
+
+    #[test]
+    fn kill_ignores_release_setting() {
+        let mut envelope = Envelope::default();
+        envelope.trigger();
+        step(&mut envelope, 50);
+        envelope.kill();
+        let fade = step(&mut envelope, 5);
+        assert_eq!(fade[0], 0.5);
+        assert!(!envelope.is_active());
+    }
+
+    #[test]
+    fn zero_length_stages_are_skipped() {
+        let settings = AdsrSettings {
+            attack: 0.0,
+            decay: 0.0,
+            sustain: 0.25,
+            release: 0.0,
+            curve: EnvCurve::Linear,
+        };
+        let mut envelope = Envelope::default();
+        envelope.trigger();
+        assert_eq!(envelope.next(&settings, SAMPLE_RATE), 0.25);
+        envelope.release();
+        assert_eq!(envelope.next(&settings, SAMPLE_RATE), 0.0);
+        assert!(!envelope.is_active());
+    }
+
+    #[test]
+    fn curves_pass_their_analytic_midpoints() {
+        let k = EXP_TIME_CONSTANTS;
+        for &(curve, half, quarter) in &[
+            (EnvCurve::Linear, 0.5, 0.25),
+            (
+                EnvCurve::Exponential,
+                (1.0 - (-k / 2.0).exp()) / (1.0 - (-k).exp()),
+                (1.0 - (-k / 4.0).exp()) / (1.0 - (-k).exp()),
+            ),
+            (EnvCurve::SCurve, 0.5, 0.156_25),
+        ] {
+            let settings = AdsrSettings {
+                attack: 0.1,
+                curve,
+                ..SETTINGS
+            };
+            let mut envelope = Envelope::default();
+            envelope.trigger();
+            let attack: Vec<f64> = (0..100)
+                .map(|_| envelope.next(&settings, SAMPLE_RATE))
+                .collect();
+            assert!((attack[50] - half).abs() < 1e-12, "{:?}", curve);
+            assert!((attack[25] - quarter).abs() < 1e-12, "{:?}", curve);
+            assert!(attack.windows(2).all(|w| w[1] > w[0]), "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn curved_releases_end_in_silence_on_time() {
+        for &curve in EnvCurve::ALL.iter() {
+            let settings = AdsrSettings { curve, ..SETTINGS };
+            let mut envelope = Envelope::default();
+            envelope.trigger();
+            for _ in 0..100 {
+                envelope.next(&settings, SAMPLE_RATE);
+            }
+            envelope.release();
+            let release: Vec<f64> = (0..30)
+                .map(|_| envelope.next(&settings, SAMPLE_RATE))
+                .collect();
+            assert!(release.windows(2).all(|w| w[1] < w[0]), "{:?}", curve);
+            assert_eq!(envelope.next(&settings, SAMPLE_RATE), 0.0);
+            assert!(!envelope.is_active(), "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn curves_round_trip() {
+        for &curve in EnvCurve::ALL.iter() {
+            assert_eq!(EnvCurve::from_normalized(curve.to_normalized()), curve);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Fixed-capacity queue of MIDI events waiting for their sample position in the next block.
+
+/// The most events that can wait for a single block.
+pub const CAPACITY: usize = 512;
+
+/// A MIDI event and the sample within the block at which it takes effect.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub offset: usize,
+    pub data: [u8; 3],
+}
+
+/// A ring buffer of pending events, stored inline so queueing never allocates.
+pub struct EventQueue {
+    events: [TimedEvent; CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl Default for EventQueue {
+    fn default() -> EventQueue {
+        EventQueue {
+            events: [TimedEvent::default(); CAPACITY],
+            head: 0,
+            len: 0,
+        }
+    }
+}
+
+impl EventQueue {
+    /// Add an event to the back of the queue, handing it back if the queue is full.
+    pub fn push(&mut self, event: TimedEvent) -> Result<(), TimedEvent> {
+        if self.len == CAPACITY {
+            return Err(event);
+        }
+        self.events[(self.head + self.len) % CAPACITY] = event;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Remove the event at the front of the queue if it is due at or before `offset`.
+    pub fn pop_due(&mut self, offset: usize) -> Option<TimedEvent> {
+        if self.len == 0 || self.events[self.head].offset > offset {
+            return None;
+        }
+        let event = self.events[self.head];
+        self.head = (self.head + 1) % CAPACITY;
+        self.len -= 1;
+        Some(event)
+    }
+
+    /// Whether the event at the front of the queue is due at or before `offset`.
+    pub fn has_due(&self, offset: usize) -> bool {
+        self.len > 0 && self.events[self.head].offset <= offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drop every waiting event.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventQueue, TimedEvent, CAPACITY};
+
+    fn event(offset: usize) -> TimedEvent {
+        TimedEvent {
+            offset,
+            data: [0x90, 60, 100],
+        }
+    }
+
+    #[test]
+    fn pops_events_once_due() {
+        let mut queue = EventQueue::default();
+        queue.push(event(0)).unwrap();
+        queue.push(event(10)).unwrap();
+
+        assert_eq!(queue.pop_due(0), Some(event(0)));
+        assert_eq!(queue.pop_due(9), None);
+        assert_eq!(queue.pop_due(10), Some(event(10)));
+        assert_eq!(queue.pop_due(usize::MAX), None);
+    }
+
+    #[test]
+    fn refuses_events_when_full() {
+        let mut queue = EventQueue::default();
+        for i in 0..CAPACITY {
+            queue.push(event(i)).unwrap();
+        }
+        assert_eq!(queue.push(event(0)), Err(event(0)));
+
+        // Space is reused once events are taken out.
+        assert_eq!(queue.pop_due(0), Some(event(0)));
+        queue.push(event(CAPACITY)).unwrap();
+        for i in 1..=CAPACITY {
+            assert_eq!(queue.pop_due(usize::MAX), Some(event(i)));
+        }
+
+        queue.push(event(0)).unwrap();
+        queue.clear();
+        assert_eq!(queue.pop_due(usize::MAX), None);
+    }
+}
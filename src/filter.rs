@@ -0,0 +1,319 @@
+//! Resonant state-variable filter with low-pass, high-pass, band-pass and notch outputs.
+
+use crate::denormal::flush;
+use crate::params::options;
+use crate::PI;
+
+/// The lowest and highest cutoff frequencies, in Hz.
+pub const MIN_CUTOFF: f64 = 20.0;
+pub const MAX_CUTOFF: f64 = 20_000.0;
+
+/// The filter's Q with no resonance, which gives the flattest passband.
+const MIN_Q: f64 = 0.707;
+
+/// The filter's Q with full resonance. Keeping it finite keeps the filter from ringing forever.
+const MAX_Q: f64 = 20.0;
+
+/// How long a change of mode takes to crossfade to the new output, in seconds.
+const MODE_FADE: f64 = 0.005;
+
+options! {
+    /// Which of the filter's outputs is heard.
+    pub enum FilterMode {
+        LowPass => "Low-pass",
+        HighPass => "High-pass",
+        BandPass => "Band-pass",
+        Notch => "Notch",
+    }
+}
+
+impl FilterMode {
+    /// How much of the low, band and high outputs make up this mode.
+    fn mix(self) -> [f64; 3] {
+        match self {
+            FilterMode::LowPass => [1.0, 0.0, 0.0],
+            FilterMode::HighPass => [0.0, 0.0, 1.0],
+            FilterMode::BandPass => [0.0, 1.0, 0.0],
+            FilterMode::Notch => [1.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// Coefficients for a cutoff, resonance and sample rate.
+///
+/// Working these out needs a `tan`, so they are only recomputed when one of the three changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coefficients {
+    a1: f64,
+    a2: f64,
+    a3: f64,
+    /// Damping, the inverse of Q.
+    k: f64,
+    /// How far a mode crossfade moves in one sample.
+    fade_step: f64,
+}
+
+impl Coefficients {
+    /// `resonance` goes from 0.0 (none) to 1.0 (strong, but still decaying).
+    pub fn new(cutoff: f64, resonance: f64, sample_rate: f64) -> Coefficients {
+        // Stay clear of Nyquist, where the prewarping below goes to infinity.
+        let cutoff = cutoff.clamp(MIN_CUTOFF, 0.49 * sample_rate);
+        let q = MIN_Q * (MAX_Q / MIN_Q).powf(resonance.clamp(0.0, 1.0));
+        let g = (PI * cutoff / sample_rate).tan();
+        let k = 1.0 / q;
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        Coefficients {
+            a1,
+            a2,
+            a3: g * a2,
+            k,
+            fade_step: 1.0 / (MODE_FADE * sample_rate),
+        }
+    }
+}
+
+/// One voice's filter memory.
+///
+/// This is the trapezoidal state-variable form, which stays stable however quickly its
+/// coefficients change. All four outputs come from the same two integrators, so switching
+/// mode never leaves the filter in a state that doesn't match the new output; the switch is
+/// still crossfaded, since the outputs themselves differ.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StateVariable {
+    ic1eq: f64,
+    ic2eq: f64,
+    /// The current weights of the low, band and high outputs, or `None` straight after a
+    /// reset, when the first sample jumps to the mode's own.
+    mix: Option<[f64; 3]>,
+}
+
+impl StateVariable {
+    /// Filter one sample, in the given mode.
+    pub fn process(&mut self, input: f64, coefficients: &Coefficients, mode: FilterMode) -> f64 {
+        let Coefficients {
+            a1,
+            a2,
+            a3,
+            k,
+            fade_step,
+        } = *coefficients;
+        let v3 = input - self.ic2eq;
+        let v1 = a1 * self.ic1eq + a2 * v3;
+        let v2 = self.ic2eq + a2 * self.ic1eq + a3 * v3;
+        // Flushed so a decaying filter never falls into slow denormal arithmetic.
+        self.ic1eq = flush(2.0 * v1 - self.ic1eq);
+        self.ic2eq = flush(2.0 * v2 - self.ic2eq);
+
+        let target = mode.mix();
+        let mix = self.mix.get_or_insert(target);
+        for (weight, target) in mix.iter_mut().zip(target.iter()) {
+            *weight += (target - *weight).clamp(-fade_step, fade_step);
+        }
+        // Scaling the band output by the damping keeps its peak at unity whatever the
+        // resonance.
+        let (low, band) = (v2, k * v1);
+        let high = input - band - low;
+        mix[0] * low + mix[1] * band + mix[2] * high
+    }
+
+    /// Forget any previous input, so the next note doesn't start with the tail of the last one.
+    pub fn reset(&mut self) {
+        *self = StateVariable::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Coefficients, FilterMode, StateVariable};
+    use crate::analysis::magnitude_at;
+    use crate::noise::{Noise, NoiseColor};
+    use crate::PI;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    const CUTOFF: f64 = 1000.0;
+
+    /// Damping with no resonance.
+    const K: f64 = 1.0 / 0.707;
+
+    /// White noise, and the same noise through a filter at `CUTOFF` in `mode`.
+    fn noise_through(mode: FilterMode) -> (Vec<f32>, Vec<f32>) {
+        let coefficients = Coefficients::new(CUTOFF, 0.0, SAMPLE_RATE);
+        let mut filter = StateVariable::default();
+        let mut noise = Noise::default();
+        (0..16384)
+            .map(|_| {
+                let input = noise.next(NoiseColor::White);
+                (
+                    input as f32,
+                    filter.process(input, &coefficients, mode) as f32,
+                )
+            })
+            .unzip()
+    }
+
+    /// The gain of a mode at `freq`, in dB.
+    ///
+    /// Frequencies are warped the way the trapezoidal integrators warp them, which turns the
+    /// filter into the analog prototype.
+    fn expected(mode: FilterMode, freq: f64) -> f64 {
+        let warp = |f: f64| (PI * f / SAMPLE_RATE).tan();
+        let w = warp(freq) / warp(CUTOFF);
+        let denominator = ((1.0 - w * w).powi(2) + (K * w).powi(2)).sqrt();
+        let numerator = match mode {
+            FilterMode::LowPass => 1.0,
+            FilterMode::HighPass => w * w,
+            FilterMode::BandPass => K * w,
+            FilterMode::Notch => (1.0 - w * w).abs(),
+        };
+        20.0 * (numerator / denominator).log10()
+    }
+
+    /// Check a mode's measured response against `expected` at each of `freqs`.
+    fn check_response(mode: FilterMode, freqs: &[f64]) {
+        let (input, output) = noise_through(mode);
+        // Comparing against the same noise unfiltered cancels out its random ups and downs.
+        let gain = |freq: f64| {
+            20.0 * (magnitude_at(&output, freq, SAMPLE_RATE)
+                / magnitude_at(&input, freq, SAMPLE_RATE))
+            .log10()
+        };
+        for &freq in freqs {
+            let error = gain(freq) - expected(mode, freq);
+            assert!(
+                error.abs() < 1.0,
+                "{:?} at {} Hz is off by {} dB",
+                mode,
+                freq,
+                error
+            );
+        }
+    }
+
+    /// The band edges of the band-pass and notch, as multiples of the warped cutoff, where
+    /// their responses cross at -3 dB.
+    fn band_edges() -> (f64, f64) {
+        let root = (K * K + 4.0).sqrt();
+        ((root - K) / 2.0, (root + K) / 2.0)
+    }
+
+    /// The frequency at a multiple of the warped cutoff.
+    fn unwarp(w: f64) -> f64 {
+        (w * (PI * CUTOFF / SAMPLE_RATE).tan()).atan() * SAMPLE_RATE / PI
+    }
+
+    #[test]
+    fn low_pass_matches_butterworth() {
+        // A two-pole Butterworth is 3 dB down at the cutoff and falls 12 dB per octave above.
+        check_response(
+            FilterMode::LowPass,
+            &[250.0, 1000.0, 2000.0, 4000.0, 8000.0],
+        );
+        assert!((expected(FilterMode::LowPass, CUTOFF) + 3.0).abs() < 0.1);
+        let slope = expected(FilterMode::LowPass, 4000.0) - expected(FilterMode::LowPass, 2000.0);
+        assert!((slope + 12.0).abs() < 1.0, "{} dB", slope);
+    }
+
+    #[test]
+    fn high_pass_mirrors_low_pass() {
+        check_response(FilterMode::HighPass, &[125.0, 250.0, 500.0, 1000.0, 4000.0]);
+        assert!((expected(FilterMode::HighPass, CUTOFF) + 3.0).abs() < 0.1);
+        let slope = expected(FilterMode::HighPass, 250.0) - expected(FilterMode::HighPass, 125.0);
+        assert!((slope - 12.0).abs() < 1.0, "{} dB", slope);
+    }
+
+    #[test]
+    fn band_pass_peaks_at_cutoff() {
+        let (low, high) = band_edges();
+        let edges = [unwarp(low), unwarp(high)];
+        check_response(
+            FilterMode::BandPass,
+            &[125.0, edges[0], 1000.0, edges[1], 8000.0],
+        );
+        assert!(expected(FilterMode::BandPass, CUTOFF).abs() < 0.01);
+        for &edge in &edges {
+            assert!((expected(FilterMode::BandPass, edge) + 3.0).abs() < 0.1);
+        }
+        // One pole on each side, so 6 dB per octave.
+        let slope = expected(FilterMode::BandPass, 125.0) - expected(FilterMode::BandPass, 62.5);
+        assert!((slope - 6.0).abs() < 0.5, "{} dB", slope);
+    }
+
+    #[test]
+    fn notch_removes_cutoff() {
+        let (low, high) = band_edges();
+        let edges = [unwarp(low), unwarp(high)];
+        check_response(FilterMode::Notch, &[125.0, edges[0], edges[1], 8000.0]);
+        for &edge in &edges {
+            assert!((expected(FilterMode::Notch, edge) + 3.0).abs() < 0.1);
+        }
+        let (input, output) = noise_through(FilterMode::Notch);
+        let depth =
+            magnitude_at(&output, CUTOFF, SAMPLE_RATE) / magnitude_at(&input, CUTOFF, SAMPLE_RATE);
+        assert!(depth < 0.05, "{}", depth);
+    }
+
+    #[test]
+    fn switching_mode_crossfades() {
+        let coefficients = Coefficients::new(CUTOFF, 0.0, SAMPLE_RATE);
+        let mut filter = StateVariable::default();
+        let mut noise = Noise::default();
+        let mut last = 0.0;
+        for i in 0..4000 {
+            let mode = if i < 2000 {
+                FilterMode::LowPass
+            } else {
+                FilterMode::HighPass
+            };
+            let out = filter.process(
+                0.5 + 0.02 * noise.next(NoiseColor::White),
+                &coefficients,
+                mode,
+            );
+            // Low-pass passes the offset and high-pass blocks it, so a hard switch would jump
+            // by 0.5 in a single sample.
+            if i > 100 {
+                assert!((out - last).abs() < 0.1, "jump of {} at {}", out - last, i);
+            }
+            last = out;
+        }
+        assert!(last.abs() < 0.1);
+    }
+
+    #[test]
+    fn modes_round_trip() {
+        for &mode in FilterMode::ALL.iter() {
+            assert_eq!(FilterMode::from_normalized(mode.to_normalized()), mode);
+        }
+    }
+
+    #[test]
+    fn full_resonance_decays_to_silence() {
+        let coefficients = Coefficients::new(5000.0, 1.0, SAMPLE_RATE);
+        for &mode in FilterMode::ALL.iter() {
+            let mut filter = StateVariable::default();
+            let mut out = vec![filter.process(1.0, &coefficients, mode)];
+            out.extend((0..100_000).map(|_| filter.process(0.0, &coefficients, mode)));
+
+            assert!(out.iter().all(|s| s.is_finite()));
+            assert_eq!(out[out.len() - 1], 0.0, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn cutoff_near_nyquist_is_stable() {
+        for &sample_rate in &[22050.0, 44100.0, 192_000.0] {
+            let coefficients = Coefficients::new(20_000.0, 1.0, sample_rate);
+            for &mode in FilterMode::ALL.iter() {
+                let mut filter = StateVariable::default();
+                let mut noise = Noise::default();
+                for _ in 0..10_000 {
+                    let input = noise.next(NoiseColor::White);
+                    let out = filter.process(input, &coefficients, mode);
+                    assert!(out.is_finite() && out.abs() < 100.0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Handing the latest of a value from one thread to another without locking or allocating.
+//!
+//! It's a triple buffer. The writer fills one slot while the reader holds another, and the
+//! third sits between them with the latest value published. Publishing swaps the writer's
+//! slot for the middle one, and taking swaps the reader's for it, so neither side ever waits
+//! for the other and no value is copied between slots. A value published over one that
+//! hasn't been taken replaces it; only the latest matters.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// Marks the middle slot as published since the reader last took one.
+const FRESH: u8 = 4;
+
+/// The slots shared between the two ends.
+struct Slots<T> {
+    slots: [UnsafeCell<T>; 3],
+    /// The index of the middle slot, with `FRESH` set if it hasn't been taken.
+    middle: AtomicU8,
+}
+
+// Each slot is only ever reached through the one end that holds its index, and the swaps on
+// `middle` pass a slot from one end to the other along with what was written to it.
+unsafe impl<T: Send> Sync for Slots<T> {}
+
+impl<T> Slots<T> {
+    /// The slot at `index`.
+    ///
+    /// # Safety
+    ///
+    /// Only the end holding `index` may call this, and only while it holds it.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn slot(&self, index: u8) -> &mut T {
+        &mut *self.slots[usize::from(index)].get()
+    }
+}
+
+/// The end that publishes.
+pub struct Writer<T> {
+    slots: Arc<Slots<T>>,
+    /// The slot being filled.
+    back: u8,
+}
+
+impl<T> Writer<T> {
+    /// The slot to fill before publishing. It still holds whatever was last in it, which may
+    /// be from several publishes ago.
+    pub fn slot(&mut self) -> &mut T {
+        // The writer holds `back` until it publishes it, which needs `&mut self`.
+        unsafe { self.slots.slot(self.back) }
+    }
+
+    /// Hand over what's in the slot, in place of anything published but not yet taken.
+    pub fn publish(&mut self) {
+        let middle = self.slots.middle.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = middle & !FRESH;
+    }
+}
+
+/// The end that takes.
+pub struct Reader<T> {
+    slots: Arc<Slots<T>>,
+    /// The slot last taken.
+    front: u8,
+}
+
+impl<T> Reader<T> {
+    /// The latest value published, or `None` if nothing has been since the last take.
+    pub fn take(&mut self) -> Option<&mut T> {
+        if self.slots.middle.load(Ordering::Acquire) & FRESH == 0 {
+            return None;
+        }
+        let middle = self.slots.middle.swap(self.front, Ordering::AcqRel);
+        self.front = middle & !FRESH;
+        // The reader holds `front` until it takes another, which needs `&mut self`.
+        Some(unsafe { self.slots.slot(self.front) })
+    }
+}
+
+/// The two ends of a handoff, with every slot starting out as `initial`.
+pub fn handoff<T: Clone>(initial: T) -> (Writer<T>, Reader<T>) {
+    let slots = Arc::new(Slots {
+        slots: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        middle: AtomicU8::new(1),
+    });
+    let reader = Reader {
+        slots: Arc::clone(&slots),
+        front: 2,
+    };
+    (Writer { slots, back: 0 }, reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::handoff;
+    use std::thread;
+
+    #[test]
+    fn the_latest_value_is_taken_once() {
+        let (mut writer, mut reader) = handoff(0);
+        assert_eq!(reader.take(), None);
+        *writer.slot() = 1;
+        writer.publish();
+        *writer.slot() = 2;
+        writer.publish();
+        assert_eq!(reader.take().copied(), Some(2));
+        assert_eq!(reader.take(), None);
+        *writer.slot() = 3;
+        writer.publish();
+        assert_eq!(reader.take().copied(), Some(3));
+    }
+
+    #[test]
+    fn values_arrive_whole_across_threads() {
+        let (mut writer, mut reader) = handoff([0u32; 64]);
+        let writing = thread::spawn(move || {
+            for value in 1..=10_000 {
+                *writer.slot() = [value; 64];
+                writer.publish();
+            }
+        });
+        let mut last = 0;
+        while last < 10_000 {
+            if let Some(values) = reader.take() {
+                // A torn value would mix two publishes, and values only ever go up.
+                assert!(values.iter().all(|&value| value == values[0]));
+                assert!(values[0] > last);
+                last = values[0];
+            }
+        }
+        writing.join().unwrap();
+    }
+}
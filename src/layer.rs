@@ -0,0 +1,40 @@
+//! The octave layer: a copy of the first oscillator an octave above or below each note.
+
+use crate::params::options;
+
+options! {
+    /// Which octaves the layer adds.
+    pub enum OctaveLayer {
+        Off => "Off",
+        Up => "+1 oct",
+        Down => "-1 oct",
+        /// An octave above and an octave below.
+        Both => "Both",
+    }
+}
+
+impl OctaveLayer {
+    /// Whether the layer has a copy an octave up.
+    pub fn up(self) -> bool {
+        self == OctaveLayer::Up || self == OctaveLayer::Both
+    }
+
+    /// Whether the layer has a copy an octave down.
+    pub fn down(self) -> bool {
+        self == OctaveLayer::Down || self == OctaveLayer::Both
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OctaveLayer;
+
+    #[test]
+    fn layers_round_trip() {
+        for &layer in OctaveLayer::ALL.iter() {
+            assert_eq!(OctaveLayer::from_normalized(layer.to_normalized()), layer);
+        }
+        assert!(OctaveLayer::Both.up() && OctaveLayer::Both.down());
+        assert!(!OctaveLayer::Off.up() && !OctaveLayer::Off.down());
+    }
+}
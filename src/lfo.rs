@@ -0,0 +1,327 @@
+//! Low-frequency oscillator for modulating pitch, level or cutoff.
+
+use crate::noise::{Noise, NoiseColor};
+use crate::params::options;
+use crate::{PI, TAU};
+
+options! {
+    pub enum LfoShape {
+        Sine => "Sine",
+        Triangle => "Triangle",
+        /// A new random level at the start of every cycle, held until the next.
+        SampleAndHold => "S&H",
+        /// Glides from one random level to the next over each cycle.
+        SmoothRandom => "Smooth Random",
+    }
+}
+
+options! {
+    /// What the LFO modulates.
+    pub enum LfoDestination {
+        Pitch => "Pitch",
+        Amplitude => "Amplitude",
+        Cutoff => "Cutoff",
+    }
+}
+
+/// A tempo-synced cycle length.
+pub struct SyncDivision {
+    pub name: &'static str,
+    /// The length of one LFO cycle in quarter notes. Bars are taken to be four beats long.
+    pub beats: f64,
+}
+
+/// The divisions the "Sync Rate" parameter steps through, from the slowest to the fastest.
+pub const SYNC_DIVISIONS: [SyncDivision; 18] = [
+    SyncDivision {
+        name: "4 bars",
+        beats: 16.0,
+    },
+    SyncDivision {
+        name: "2 bars",
+        beats: 8.0,
+    },
+    SyncDivision {
+        name: "1 bar",
+        beats: 4.0,
+    },
+    SyncDivision {
+        name: "1/2D",
+        beats: 3.0,
+    },
+    SyncDivision {
+        name: "1/2",
+        beats: 2.0,
+    },
+    SyncDivision {
+        name: "1/4D",
+        beats: 1.5,
+    },
+    SyncDivision {
+        name: "1/2T",
+        beats: 4.0 / 3.0,
+    },
+    SyncDivision {
+        name: "1/4",
+        beats: 1.0,
+    },
+    SyncDivision {
+        name: "1/8D",
+        beats: 0.75,
+    },
+    SyncDivision {
+        name: "1/4T",
+        beats: 2.0 / 3.0,
+    },
+    SyncDivision {
+        name: "1/8",
+        beats: 0.5,
+    },
+    SyncDivision {
+        name: "1/16D",
+        beats: 0.375,
+    },
+    SyncDivision {
+        name: "1/8T",
+        beats: 1.0 / 3.0,
+    },
+    SyncDivision {
+        name: "1/16",
+        beats: 0.25,
+    },
+    SyncDivision {
+        name: "1/32D",
+        beats: 0.1875,
+    },
+    SyncDivision {
+        name: "1/16T",
+        beats: 1.0 / 6.0,
+    },
+    SyncDivision {
+        name: "1/32",
+        beats: 0.125,
+    },
+    SyncDivision {
+        name: "1/32T",
+        beats: 1.0 / 12.0,
+    },
+];
+
+/// Where in its cycle an LFO `beats` long should be at the host position `ppq_pos`.
+///
+/// Cycles are counted from the start of the song, so the phase only depends on the position;
+/// jumping back to the start of a loop puts the LFO back where it was the first time round.
+/// Positions before zero, in a pre-roll, carry on the same pattern backwards.
+pub fn synced_phase(ppq_pos: f64, beats: f64) -> f64 {
+    let phase = (ppq_pos / beats).rem_euclid(1.0);
+    // A tiny negative position rounds up to a whole cycle.
+    if phase < 1.0 {
+        phase
+    } else {
+        0.0
+    }
+}
+
+/// An oscillator stepped once per sample, giving values between -1.0 and 1.0.
+///
+/// The sine and triangle start at zero and rise, so a retriggered LFO always starts the same
+/// way. The random shapes draw from their own generator, which starts from the same seed every
+/// time the plugin is created, so renders repeat exactly.
+#[derive(Default)]
+pub struct Lfo {
+    /// Position within the cycle, kept in `[0, 1)`.
+    phase: f64,
+    /// The random level the current cycle started from and the one it is heading for. Sample
+    /// and hold jumps straight to the second.
+    random: [f64; 2],
+    generator: Noise,
+}
+
+impl Lfo {
+    /// The current value, then advance by one sample at `rate` Hz.
+    pub fn next(&mut self, shape: LfoShape, rate: f64, sample_rate: f64) -> f64 {
+        let value = match shape {
+            LfoShape::Sine => (self.phase * TAU).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * ((self.phase + 0.25).fract() - 0.5).abs(),
+            LfoShape::SampleAndHold => self.random[1],
+            LfoShape::SmoothRandom => {
+                let [from, to] = self.random;
+                from + (to - from) * (0.5 - 0.5 * (self.phase * PI).cos())
+            }
+        };
+        let phase = self.phase + rate / sample_rate;
+        if phase >= 1.0 {
+            self.next_random();
+        }
+        self.phase = phase.fract();
+        value
+    }
+
+    /// Advance by `samples` at `rate` Hz without giving any values, ending up where `next`
+    /// would have. The random levels move on once for each of the last two cycles passed,
+    /// which are all a cycle's levels depend on.
+    pub fn skip(&mut self, rate: f64, sample_rate: f64, samples: usize) {
+        let phase = self.phase + rate / sample_rate * samples as f64;
+        for _ in 0..(phase as usize).min(2) {
+            self.next_random();
+        }
+        self.phase = phase.fract();
+    }
+
+    /// Go back to the start of the cycle, which starts a new random level too.
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.next_random();
+    }
+
+    /// Jump to `phase`, which must be in `[0, 1)`.
+    ///
+    /// A jump more than half a cycle backwards is taken as the cycle having wrapped round
+    /// since the last sample, and moves the random shapes on.
+    pub fn set_phase(&mut self, phase: f64) {
+        if phase < self.phase - 0.5 {
+            self.next_random();
+        }
+        self.phase = phase;
+    }
+
+    fn next_random(&mut self) {
+        self.random = [self.random[1], self.generator.next(NoiseColor::White)];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{synced_phase, Lfo, LfoDestination, LfoShape, SYNC_DIVISIONS};
+
+    #[test]
+    fn triangle_hits_its_corners() {
+        let mut lfo = Lfo::default();
+        // A 1 Hz LFO at 4 samples a second steps through a quarter of the cycle at a time.
+        let values: Vec<f64> = (0..5)
+            .map(|_| lfo.next(LfoShape::Triangle, 1.0, 4.0))
+            .collect();
+        assert_eq!(values, [0.0, 1.0, 0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn reset_restarts_the_cycle() {
+        let mut lfo = Lfo::default();
+        let first: Vec<f64> = (0..100)
+            .map(|_| lfo.next(LfoShape::Sine, 3.0, 1000.0))
+            .collect();
+        lfo.reset();
+        let second: Vec<f64> = (0..100)
+            .map(|_| lfo.next(LfoShape::Sine, 3.0, 1000.0))
+            .collect();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|v| v.abs() <= 1.0));
+    }
+
+    #[test]
+    fn sample_and_hold_steps_once_per_cycle() {
+        let mut lfo = Lfo::default();
+        // A 10 Hz LFO at 1000 samples a second lasts 100 samples.
+        let values: Vec<f64> = (0..1000)
+            .map(|_| lfo.next(LfoShape::SampleAndHold, 10.0, 1000.0))
+            .collect();
+        let changes: Vec<usize> = (1..values.len())
+            .filter(|&i| values[i] != values[i - 1])
+            .collect();
+        assert_eq!(changes, (1..10).map(|c| c * 100).collect::<Vec<_>>());
+        assert!(values.iter().all(|v| v.abs() <= 1.0));
+    }
+
+    #[test]
+    fn smooth_random_joins_the_held_levels() {
+        let mut held = Lfo::default();
+        let mut smooth = Lfo::default();
+        let mut previous = 0.0;
+        for i in 0..1000 {
+            let level = held.next(LfoShape::SampleAndHold, 10.0, 1000.0);
+            let value = smooth.next(LfoShape::SmoothRandom, 10.0, 1000.0);
+            // Each cycle of the smooth shape starts where the last stepped level was.
+            if i % 100 == 0 {
+                assert!((value - previous).abs() < 1e-12, "{}", i);
+            } else {
+                assert!((value - previous).abs() < 0.05, "{}", i);
+            }
+            assert!(value.abs() <= 1.0);
+            previous = if i % 100 == 99 { level } else { value };
+        }
+    }
+
+    #[test]
+    fn random_shapes_repeat_from_a_fresh_lfo() {
+        let run = || {
+            let mut lfo = Lfo::default();
+            (0..1000)
+                .map(|_| lfo.next(LfoShape::SmoothRandom, 7.0, 1000.0))
+                .collect::<Vec<f64>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn shapes_round_trip() {
+        for &shape in LfoShape::ALL.iter() {
+            assert_eq!(LfoShape::from_normalized(shape.to_normalized()), shape);
+        }
+    }
+
+    #[test]
+    fn synced_phase_follows_the_bar() {
+        // A one bar LFO is a quarter of the way round on each beat.
+        assert_eq!(synced_phase(0.0, 4.0), 0.0);
+        assert_eq!(synced_phase(1.0, 4.0), 0.25);
+        assert_eq!(synced_phase(6.0, 4.0), 0.5);
+        // A dotted eighth lines up again every three beats.
+        assert!(synced_phase(3.0, 0.75).abs() < 1e-12);
+        assert!((synced_phase(3.375, 0.75) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn synced_phase_repeats_across_a_loop() {
+        // A two bar loop from bar 3, with a half note LFO.
+        let (loop_start, loop_end) = (8.0, 16.0);
+        let beats = 2.0;
+        let before_wrap = synced_phase(loop_end - 0.25, beats);
+        let after_wrap = synced_phase(loop_start, beats);
+        assert!((before_wrap - 0.875).abs() < 1e-12);
+        assert_eq!(after_wrap, synced_phase(loop_end, beats));
+        // Every pass round the loop sees the same phases at the same points.
+        for step in 0..32 {
+            let offset = f64::from(step) * 0.25;
+            let first = synced_phase(loop_start + offset, beats);
+            let second = synced_phase(loop_end + offset, beats);
+            assert!((first - second).abs() < 1e-12, "{}", offset);
+        }
+    }
+
+    #[test]
+    fn synced_phase_handles_pre_roll() {
+        assert_eq!(synced_phase(-1.0, 4.0), 0.75);
+        assert_eq!(synced_phase(-1e-20, 4.0), 0.0);
+        for &ppq_pos in &[-3.7, -0.001, 0.0, 123.456, 1e9] {
+            let phase = synced_phase(ppq_pos, 0.75);
+            assert!((0.0..1.0).contains(&phase), "{}", ppq_pos);
+        }
+    }
+
+    #[test]
+    fn sync_divisions_get_faster() {
+        for pair in SYNC_DIVISIONS.windows(2) {
+            assert!(pair[0].beats > pair[1].beats, "{}", pair[1].name);
+        }
+    }
+
+    #[test]
+    fn destinations_round_trip() {
+        for &destination in LfoDestination::ALL.iter() {
+            assert_eq!(
+                LfoDestination::from_normalized(destination.to_normalized()),
+                destination
+            );
+        }
+    }
+}
@@ -2,229 +2,6325 @@
 extern crate vst;
 
 use vst::plugin::PluginParameters;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use vst::util::AtomicFloat;
-use vst::api::{Events, Supported};
+use vst::api::{Events, Supported, TimeInfoFlags};
 use vst::buffer::AudioBuffer;
 use vst::event::Event;
-use vst::plugin::{CanDo, Category, Info, Plugin};
+use vst::host::Host;
+use vst::plugin::{CanDo, Category, HostCallback, Info, Plugin};
 
-use std::f64::consts::PI;
+use std::convert::TryFrom;
+use std::f64::consts::{FRAC_PI_2, PI};
 
-/// Convert the midi note's pitch into the equivalent frequency.
+mod aftertouch;
+#[cfg(test)]
+mod analysis;
+mod arp;
+mod chord;
+mod chorus;
+mod chunk;
+mod crusher;
+mod dc_blocker;
+#[cfg(any(test, feature = "debug-log"))]
+mod debug_log;
+mod delay;
+mod delay_line;
+mod denormal;
+#[cfg(any(test, all(feature = "editor", target_os = "linux")))]
+mod editor;
+mod envelope;
+mod event_queue;
+mod filter;
+mod handoff;
+mod layer;
+mod lfo;
+mod limiter;
+mod midi;
+mod midi_learn;
+mod mpe;
+mod noise;
+mod note_stack;
+#[cfg(any(test, feature = "renderer"))]
+pub mod offline;
+mod oscillator;
+mod oversampling;
+mod param_handle;
+mod params;
+mod pluck;
+mod presets;
+mod program;
+mod reverb;
+mod saturation;
+mod sine;
+#[cfg(any(test, feature = "renderer"))]
+mod smf;
+mod smoother;
+#[cfg(test)]
+mod testing;
+mod tilt;
+mod tuning;
+mod voice;
+mod voice_steal;
+mod wavetable;
+#[cfg(all(feature = "editor", target_os = "linux"))]
+mod x11;
+
+use crate::aftertouch::AftertouchDestination;
+use crate::arp::{arp_divisions, ArpMode, ArpSettings, ArpStep, Arpeggiator};
+use crate::chord::{Chord, ChordType, SharedChord};
+use crate::chorus::Chorus;
+use crate::chunk::Extras;
+use crate::crusher::{CrushSettings, Crusher, Quantize, MAX_BITS, MAX_HOLD, MIN_BITS};
+use crate::dc_blocker::DcBlocker;
+#[cfg(feature = "debug-log")]
+use crate::debug_log::{DebugLog, Record};
+use crate::delay::{delay_divisions, Delay, DelaySettings, MAX_DELAY, MAX_FEEDBACK, MIN_DELAY};
+use crate::denormal::{flush, DenormalGuard};
+use crate::envelope::{AdsrSettings, EnvCurve};
+use crate::event_queue::{EventQueue, TimedEvent};
+use crate::filter::{Coefficients, FilterMode, MAX_CUTOFF, MIN_CUTOFF};
+use crate::layer::OctaveLayer;
+use crate::lfo::{synced_phase, Lfo, LfoDestination, LfoShape, SyncDivision, SYNC_DIVISIONS};
+use crate::limiter::Limiter;
+use crate::midi::{bend_position, MidiMessage, RpnState, PITCH_BEND_SENSITIVITY, SENDS_MIDI};
+use crate::midi_learn::{control_value, learnable, ControlMap, LearnMode, TARGET_SLOTS};
+use crate::mpe::{is_master_channel, MpeChannels};
+use crate::noise::{Noise, NoiseColor};
+use crate::note_stack::{NotePriority, NoteStack};
+use crate::oscillator::{Waveform, MIN_PULSE_WIDTH};
+use crate::oversampling::{Decimator, Oversampling, MAX_FACTOR};
+use crate::param_handle::{from_host, HostLink, ParamHandle};
+use crate::params::{ParamId, PARAMS, PARAM_COUNT};
+use crate::pluck::PluckedString;
+use crate::program::{Program, ProgramBank, PROGRAM_COUNT};
+use crate::reverb::Reverb;
+use crate::saturation::Saturator;
+use crate::smoother::SmoothedParam;
+use crate::tilt::Tilt;
+use crate::tuning::{sysex_scale, tuning_link, AudioTuning, HostTuning, Tuning, SYSEX_TUNING};
+use crate::voice::{Modulation, RenderContext, Voice, VoiceSettings};
+use crate::voice_steal::{pick_victim, Candidate, StealPolicy};
+use crate::wavetable::{TableMorph, TABLES};
+
+/// Record what the synth did in its debug log. Without the `debug-log` feature this is
+/// nothing at all, arguments included.
+macro_rules! debug_log {
+    ($synth:expr, $record:expr) => {
+        #[cfg(feature = "debug-log")]
+        $synth.log.push($record);
+    };
+}
+
+/// The usual frequency of A4 in Hz, which "Master Tune" centres on.
+const A4_FREQ: f64 = 440.0;
+
+/// The MIDI note A4 is.
+const A4_PITCH: f64 = 69.0;
+
+/// The frequency ratio of an interval of `semitones` in equal temperament.
+///
+/// Whole octaves are split off and applied as exact powers of two, so notes an octave apart
+/// are exactly 2:1 whatever rounding the fraction of an octave left over takes.
+fn semitones_to_ratio(semitones: f64) -> f64 {
+    let octaves = (semitones / 12.0).floor();
+    let rest = semitones - 12.0 * octaves;
+    (rest / 12.0).exp2() * 2f64.powi(octaves as i32)
+}
+
+/// The frequency of a fractional MIDI `pitch`, with A4 tuned to `a4` Hz.
+///
+/// Note 0 is about 8.1758 Hz and note 127 about 12543.8540 Hz at the usual 440 Hz.
+fn pitch_to_freq(pitch: f64, a4: f64) -> f64 {
+    semitones_to_ratio(pitch - A4_PITCH) * a4
+}
+
+/// The frequency of MIDI note `pitch`, with A4 tuned to `a4` Hz.
+#[inline]
+fn midi_pitch_to_freq(pitch: u8, a4: f64) -> f64 {
+    pitch_to_freq(f64::from(pitch), a4)
+}
+
+/// The number of notes that can sound at the same time.
+const MAX_VOICES: usize = 16;
+
+/// The most oscillator copies a single note can be rendered with.
+const MAX_UNISON: usize = 7;
+
+/// The number of harmonics in the additive waveform.
+const HARMONICS: usize = 8;
+
+/// The parameters that glide to new values instead of jumping when the host changes them.
 ///
-/// This function assumes A4 is 440hz.
-fn midi_pitch_to_freq(pitch: u8) -> f64 {
-    const A4_PITCH: i8 = 69;
-    const A4_FREQ: f64 = 440.0;
+/// Each is smoothed in the same units as its parameter, so the cutoff moves evenly through
+/// octaves.
+struct Smoothers {
+    amplitude: SmoothedParam,
+    attack: SmoothedParam,
+    cutoff: SmoothedParam,
+    master_tune: SmoothedParam,
+    transpose: SmoothedParam,
+    pan_left: SmoothedParam,
+    pan_right: SmoothedParam,
+    chorus_depth: SmoothedParam,
+    chorus_mix: SmoothedParam,
+    delay_mix: SmoothedParam,
+    reverb_mix: SmoothedParam,
+}
 
-    // Midi notes can be 0-127
-    ((f64::from(pitch as i8 - A4_PITCH)) / 12.).exp2() * A4_FREQ
+impl Smoothers {
+    /// Smoothers with no values yet, so each starts at its parameter's current setting.
+    fn new(sample_rate: f64) -> Smoothers {
+        // Time constants of 5 ms settle within about 20 ms.
+        Smoothers {
+            amplitude: SmoothedParam::new(0.005, sample_rate),
+            attack: SmoothedParam::new(0.005, sample_rate),
+            cutoff: SmoothedParam::new(0.005, sample_rate),
+            master_tune: SmoothedParam::new(0.005, sample_rate),
+            transpose: SmoothedParam::new(0.005, sample_rate),
+            pan_left: SmoothedParam::new(0.005, sample_rate),
+            pan_right: SmoothedParam::new(0.005, sample_rate),
+            chorus_depth: SmoothedParam::new(0.005, sample_rate),
+            chorus_mix: SmoothedParam::new(0.005, sample_rate),
+            delay_mix: SmoothedParam::new(0.005, sample_rate),
+            reverb_mix: SmoothedParam::new(0.005, sample_rate),
+        }
+    }
 }
 
 struct SineSynth {
     sample_rate: f64,
-    time: f64,
-    note_duration: f64,
-    note: Option<u8>,
+    /// The longest block the host has said it will send, or `None` if it hasn't said.
+    /// Rendering keeps nothing sized by the block, so this is only checked against.
+    max_block_size: Option<usize>,
+    /// Whether voices may render a sine in lanes, for checking them against the scalar path.
+    #[cfg(test)]
+    lanes: bool,
+    voices: [Voice; MAX_VOICES],
+    /// Gain applied to the sum of all voices so that stacked notes don't clip.
+    voice_gain: f64,
+    /// The output's level on its way out of or back from bypass, from 0.0 to 1.0.
+    bypass_gain: f64,
+    /// Pitch wheel position from -1.0 (full down) to 1.0 (full up).
+    pitch_bend: f64,
+    /// Mod wheel position from 0.0 to 1.0, as last received.
+    mod_wheel: f64,
+    /// The mod wheel position eased towards `mod_wheel` so jumps don't step the vibrato depth.
+    mod_wheel_smoothed: f64,
+    /// Channel pressure from 0.0 to 1.0, as last received.
+    pressure: f64,
+    /// `pressure` eased over `PRESSURE_SMOOTHING_TIME`, since keyboards send it in coarse
+    /// steps.
+    pressure_smoothed: f64,
+    /// Position of the vibrato LFO within its cycle, kept in `[0, 1)`.
+    vibrato_phase: f64,
+    /// Position of the tremolo LFO within its cycle, kept in `[0, 1)`. It runs freely from
+    /// block to block and note to note.
+    tremolo_phase: f64,
+    /// The voice the latest note went to, whose pitch the next note glides from. In mono mode
+    /// this is the one voice that plays.
+    last_voice: Option<usize>,
+    /// Whether another key was still down when the latest key went down.
+    legato: bool,
+    /// The keys that are down, whichever mode the synth is in.
+    held_notes: NoteStack,
+    /// Draws the random start phases, and the seed it was started from.
+    phase_random: (Noise, u32),
+    /// How many notes have been started, used to order voices by age.
+    notes_started: u64,
+    /// The general-purpose LFO, shared by every voice.
+    lfo: Lfo,
+    /// The LFO's output after slew limiting, so sample and hold steps don't click.
+    lfo_slewed: f64,
+    /// Asked for the transport position when the LFO is synced to tempo.
+    host: HostCallback,
+    /// The pulse width eased towards the parameter value, so sweeps don't step.
+    pulse_width: f64,
+    smoothers: Smoothers,
+    /// The oversampling the current block renders at, held so the voices' rate can't change
+    /// part way through one.
+    oversampling: Oversampling,
+    /// Bring the left and right channels back down from the voices' rate.
+    decimators: [Decimator; 2],
+    /// The lo-fi stage, whose hold pattern carries on from block to block.
+    crusher: Crusher,
+    /// Takes any DC offset out of the left and right outputs.
+    dc_blockers: [DcBlocker; 2],
+    /// Leans the sound darker or brighter, when "Brightness" is off centre.
+    tilt: Tilt,
+    /// Thickens the summed voices, when the "Chorus Mix" parameter is up.
+    chorus: Chorus,
+    /// Echoes the panned output, when the "Delay Mix" parameter is up.
+    delay: Delay,
+    /// Puts the output in a room, when the "Reverb Mix" parameter is up.
+    reverb: Reverb,
+    /// The last stage before the outputs, when the "Limiter" parameter is on.
+    limiter: Limiter,
+    /// Shared by every voice; each one shapes it with its own envelope.
+    noise: Noise,
+    /// One string per voice, at the same index, for the `Pluck` waveform.
+    strings: Vec<PluckedString>,
+    /// The filter coefficients, and the cutoff, resonance and sample rate they were made for.
+    filter: (Coefficients, [f64; 3]),
+    sustain_pedal: bool,
+    /// The channel filter currently applied to incoming messages; `None` is omni.
+    midi_channel: Option<u8>,
+    /// The program a MIDI Program Change asked for, selected once the current block is done.
+    pending_program: Option<usize>,
+    /// Which registered parameter Data Entry controllers currently set.
+    rpn: RpnState,
+    /// Each channel's pitch bend, pressure and slide, which shape its notes in MPE mode.
+    mpe: MpeChannels,
+    /// The chord each key started, so letting go of the key releases all of it.
+    chords: [Chord; 128],
+    arp: Arpeggiator,
+    /// The arpeggiator's position in beats while the host's transport isn't giving one.
+    arp_clock: f64,
+    /// Events received for the next block, applied at their sample offsets during `process`.
+    events: EventQueue,
     params: Arc<GainEffectParameters>,
+    /// The table notes are tuned from, kept here so notes never wait on the host for it.
+    tuning: AudioTuning,
+    /// Where the audio thread records its decisions, with the `debug-log` feature.
+    #[cfg(feature = "debug-log")]
+    log: DebugLog,
 }
 
 struct GainEffectParameters {
-    // The plugin's state consists of a single parameter: amplitude.
-    amplitude: AtomicFloat,
-    attack: AtomicFloat,
+    /// Every parameter's normalized value, indexed by `ParamId`. The accessors below map them
+    /// onto what the synth uses.
+    values: [AtomicFloat; PARAM_COUNT],
+    /// The programs the host can switch between. The live values above are the selected
+    /// program's, as edited since it was selected.
+    programs: ProgramBank,
+    /// The program the host last selected while the audio thread was running, for it to
+    /// switch to at the start of its next block, or `NO_PROGRAM`.
+    requested_program: AtomicUsize,
+    /// Whether the host has resumed the plugin, so the audio thread may be in a block.
+    processing: AtomicBool,
+    /// The scale notes are tuned to, as last loaded or reported by the audio thread. It belongs
+    /// to the plugin rather than to a program.
+    tuning: Mutex<HostTuning>,
+    /// The controllers bound by MIDI learn, which belong to the plugin like the tuning.
+    learned: ControlMap,
+    /// The chord last learned with "Chord Learn", which the "User" chord plays.
+    user_chord: SharedChord,
+    /// Told about the changes the plugin makes itself, through `handle`.
+    host: HostLink,
 }
-impl Default for GainEffectParameters {
-    fn default() -> GainEffectParameters {
-        GainEffectParameters {
-            amplitude: AtomicFloat::new(0.5),
-            attack: AtomicFloat::new(0.5),
-        }
-    }
+
+/// What `requested_program` holds when the host hasn't asked for a program.
+const NO_PROGRAM: usize = usize::MAX;
+
+/// How far full channel pressure moves the cutoff at full "Aftertouch Amount", in octaves.
+const MAX_AFTERTOUCH_CUTOFF: f64 = 4.0;
+
+/// The time constant channel pressure is smoothed with, in seconds.
+const PRESSURE_SMOOTHING_TIME: f64 = 0.03;
+
+/// The level from 0.0 to 1.0 that `velocity` plays at on the velocity curve `steps` from
+/// linear.
+///
+/// Velocities 1 and 127 keep their levels on every curve; soft curves raise the ones between
+/// and hard curves lower them.
+fn curved_velocity(velocity: u8, steps: i32) -> f64 {
+    let exponent = (f64::from(steps) / 2.0).exp2();
+    let position = f64::from(velocity.max(1) - 1) / 126.0;
+    (1.0 + 126.0 * position.powf(exponent)) / 127.0
 }
 
-impl SineSynth {
-    fn time_per_sample(&self) -> f64 {
-        1.0 / self.sample_rate
+/// The widest pitch bend range, in semitones either way.
+const MAX_BEND_RANGE: f32 = 48.0;
+
+/// How far "Octave" and "Semitone" transpose either way, in whole octaves and semitones.
+const MAX_OCTAVE_SHIFT: i32 = 4;
+const MAX_SEMITONE_SHIFT: i32 = 12;
+
+/// How far "Fine" tunes either way, in cents.
+const MAX_FINE_TUNE: f64 = 100.0;
+
+/// The range of "Arp BPM", in beats per minute.
+const MIN_ARP_TEMPO: f64 = 40.0;
+const MAX_ARP_TEMPO: f64 = 300.0;
+
+/// Whether anything follows the host's tempo and song position, as the arpeggiator, the
+/// synced LFO and the synced delay do.
+const HOST_TIME: bool = true;
+
+/// Whether the host's own bypass switch reaches the plugin. This version of the vst crate
+/// drops the soft bypass call before it gets here, so hosts are told no and bypass by
+/// themselves; the "Bypass" parameter is there for hosts that can map their switch to it.
+const SOFT_BYPASS: bool = false;
+
+/// How long "Bypass" takes to fade the output out or back in, in seconds.
+const BYPASS_FADE_TIME: f64 = 0.01;
+
+/// The most of the host's samples the voices render at a time. A block is rendered in
+/// stretches of up to this many, each ending early wherever an event or arpeggiator step
+/// lands, so a note never changes part way through one.
+const SUB_BLOCK: usize = 16;
+
+/// How many steps "Vel Curve" goes either way from linear. Each step multiplies or divides
+/// the curve's exponent by √2, so the curves reach from an exponent of 0.25 to 4.
+const VEL_CURVE_STEPS: i32 = 4;
+
+/// The largest second oscillator detune, in cents either way.
+const MAX_DETUNE: f64 = 100.0;
+
+/// How far above the first oscillator "Detune" can take the second when hard sync is on, in
+/// semitones.
+const MAX_SYNC_OFFSET: f64 = 24.0;
+
+/// The deepest vibrato, in cents either way.
+const MAX_VIBRATO_DEPTH: f32 = 100.0;
+
+/// The widest unison spread, in cents either way.
+const MAX_UNISON_SPREAD: f64 = 50.0;
+
+/// How far full stereo width detunes each channel, in cents; left goes flat and right sharp.
+const MAX_STEREO_DETUNE: f64 = 4.0;
+
+/// The coarse FM ratios the "FM Ratio" parameter steps through.
+const FM_RATIOS: [f64; 9] = [0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+
+/// The widest FM fine tuning, in cents either way.
+const MAX_FM_FINE: f64 = 50.0;
+
+/// The FM modulation index with "FM Amount" fully up.
+const MAX_FM_INDEX: f64 = 10.0;
+
+/// The furthest the filter envelope can move the cutoff, in octaves either way.
+const MAX_FILTER_ENV_AMOUNT: f64 = 6.0;
+
+/// The range of "Master Tune", in Hz for A4.
+const MIN_MASTER_TUNE: f64 = 400.0;
+const MAX_MASTER_TUNE: f64 = 480.0;
+
+/// The note whose cutoff and level key tracking leave unchanged, middle C.
+const KEY_TRACK_CENTRE: u8 = 60;
+
+/// How much "Level Key Track" changes the level per octave at either end, in dB.
+const MAX_LEVEL_KEY_TRACK: f64 = 6.0;
+
+/// How far the LFO moves the pitch at full depth, in semitones either way.
+const MAX_LFO_PITCH: f64 = 12.0;
+
+/// How far the LFO moves the cutoff at full depth, in octaves either way.
+const MAX_LFO_CUTOFF: f64 = 4.0;
+
+/// The range of attack times above zero, in seconds.
+const MIN_ATTACK: f64 = 0.0001;
+const MAX_ATTACK: f64 = 5.0;
+
+/// The shortest attack, in seconds, while notes start at random phases.
+const MIN_RANDOM_PHASE_ATTACK: f64 = 0.002;
+
+/// The output level range in dB, above the muted bottom of the knob.
+const MIN_AMPLITUDE_DB: f64 = -60.0;
+const MAX_AMPLITUDE_DB: f64 = 6.0;
+/// The output level at the centre of the knob, where it starts: exactly half gain.
+const CENTRE_AMPLITUDE_DB: f64 = -6.020_599_913_279_624;
+
+/// The range of glide times, in seconds.
+const MIN_GLIDE: f64 = 0.001;
+const MAX_GLIDE: f64 = 2.0;
+
+/// The longest plucked string decay, with "Damping" at zero, in seconds.
+const MAX_PLUCK_DECAY: f64 = 10.0;
+
+/// The range of fixed second oscillator frequencies, in Hz.
+const MIN_OSC2_FIXED: f64 = 20.0;
+const MAX_OSC2_FIXED: f64 = 5000.0;
+
+impl GainEffectParameters {
+    /// The factory programs, with the first selected, reporting changes to `host`.
+    fn new(host: HostCallback) -> GainEffectParameters {
+        GainEffectParameters::linked(host).0
     }
 
-    /// Process an incoming midi event.
-    ///
-    /// The midi data is split up like so:
-    ///
-    /// `data[0]`: Contains the status and the channel. Source: [source]
-    /// `data[1]`: Contains the supplemental data for the message - so, if this was a NoteOn then
-    ///            this would contain the note.
-    /// `data[2]`: Further supplemental data. Would be velocity in the case of a NoteOn message.
-    ///
-    /// [source]: http://www.midimountain.com/midi/midi_status.htm
-    fn process_midi_event(&mut self, data: [u8; 3]) {
-        match data[0] {
-            128 => self.note_off(data[1]),
-            144 => self.note_on(data[1]),
-            _ => (),
+    /// As `new`, along with the audio thread's side of the tuning.
+    fn linked(host: HostCallback) -> (GainEffectParameters, AudioTuning) {
+        let (tuning, audio_tuning) = tuning_link();
+        let params = GainEffectParameters {
+            values: std::array::from_fn(|index| AtomicFloat::new(PARAMS[index].default)),
+            programs: ProgramBank::new(PARAMS.iter().map(|param| param.default).collect()),
+            requested_program: AtomicUsize::new(NO_PROGRAM),
+            processing: AtomicBool::new(false),
+            tuning: Mutex::new(tuning),
+            learned: ControlMap::default(),
+            user_chord: SharedChord::default(),
+            host: HostLink::new(host),
+        };
+        // The first factory program is the defaults, so the live values already match it.
+        let factory = presets::factory_bank(&chunk_layout());
+        params.programs.replace(factory, 0);
+        (params, audio_tuning)
+    }
+
+    /// Parameter `index`, for the plugin to change and the host to hear about.
+    fn handle(&self, index: usize) -> ParamHandle<'_> {
+        ParamHandle::new(self, index)
+    }
+
+    /// Set a parameter, whoever asked.
+    fn store(&self, index: i32, val: f32) {
+        // Hosts should only send values from 0.0 to 1.0, but one that doesn't mustn't reach
+        // the sound, and a value that isn't a number has nothing to keep.
+        if !val.is_finite() {
+            return;
+        }
+        if let Some(param) = self.param(index) {
+            param.set(val.clamp(0.0, 1.0));
+        }
+        // Clearing happens once, and leaves MIDI learn off rather than clearing again.
+        if index == ParamId::MidiLearn as i32 && self.learn_mode() == LearnMode::Clear {
+            self.learned.clear();
+            self.values[ParamId::MidiLearn as usize].set(LearnMode::Off.to_normalized());
         }
     }
 
-    fn note_on(&mut self, note: u8) {
-        self.note_duration = 0.0;
-        self.note = Some(note)
+    /// Select program `preset`, keeping the edits to the one selected before, and ask the host
+    /// to show the new program. That one request covers every parameter the switch changed,
+    /// which reporting each as an edit would record as automation.
+    ///
+    /// Nothing here waits or allocates, so the audio thread can switch between blocks. The
+    /// host only switches itself while the audio thread isn't running; see `change_preset`.
+    fn switch_program(&self, preset: usize) {
+        let values = match self.programs.switch(preset, &self.values) {
+            Some(values) => values,
+            None => return,
+        };
+        let kept = values.iter().enumerate().take(PARAM_COUNT);
+        for (index, value) in kept.filter(|&(index, _)| PARAMS[index].id.in_programs()) {
+            self.store(index as i32, value.get());
+        }
+        self.host.update_display();
     }
 
-    fn note_off(&mut self, note: u8) {
-        if self.note == Some(note) {
-            self.note = None
+    /// Switch to the program the host selected while the audio thread was running, if it did.
+    fn switch_requested_program(&self) {
+        let preset = self.requested_program.swap(NO_PROGRAM, Ordering::AcqRel);
+        if preset != NO_PROGRAM {
+            // The host asked for the switch, but it may have shown the values from before, so
+            // it is still asked to show them again.
+            self.switch_program(preset);
         }
     }
-}
 
-pub const TAU: f64 = PI * 2.0;
+    /// The stored value behind a parameter index.
+    fn param(&self, index: i32) -> Option<&AtomicFloat> {
+        self.values.get(index as usize)
+    }
 
-impl Default for SineSynth {
-    fn default() -> SineSynth {
-        SineSynth {
-            sample_rate: 44100.0,
-            note_duration: 0.0,
-            time: 0.0,
-            note: None,
-            params: Arc::new(GainEffectParameters::default()),
+    /// A parameter's value as shown in full, unit and all, or nothing for an unknown index.
+    fn display_text(&self, index: i32) -> String {
+        match PARAMS.get(index as usize) {
+            Some(param) => (param.format)(&Shown {
+                params: self,
+                id: param.id,
+                value: self.get(param.id),
+            }),
+            None => "".to_string(),
         }
     }
-}
 
-impl Plugin for SineSynth {
-    fn get_info(&self) -> Info {
-        Info {
-            name: "SobudoSynth".to_string(),
-            vendor: "d34dmeat".to_string(),
-            unique_id: 6667,
-            category: Category::Synth,
-            inputs: 2,
-            outputs: 2,
-            parameters: 2,
-            initial_delay: 0,
-            ..Info::default()
+    /// A parameter's value split into the number hosts show as its text and the unit they show
+    /// as its label, which they put after it themselves.
+    fn split_unit(&self, index: i32) -> (String, &'static str) {
+        let text = self.display_text(index);
+        let units = PARAMS
+            .get(index as usize)
+            .map_or(&[][..], |param| param.units);
+        // The text ends in whichever of the parameter's units it's shown in at the moment,
+        // unless it's a word like "Off" that has none.
+        let split = units.iter().find_map(|&unit| {
+            let value = text.strip_suffix(unit)?;
+            let number = value.ends_with(|c: char| c == ' ' || c.is_ascii_digit());
+            Some((value.trim_end().to_string(), unit)).filter(|_| number)
+        });
+        split.unwrap_or((text, ""))
+    }
+
+    /// The tuning to save, and the way to load another. The audio thread never waits on it.
+    fn tuning(&self) -> MutexGuard<'_, HostTuning> {
+        self.tuning.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Tune to the scale file `source`, or back to equal temperament for `None`. A file that
+    /// can't be read changes nothing.
+    fn load_tuning(&self, source: Option<&str>) {
+        let tuning = match source {
+            Some(text) => match Tuning::from_scl(text) {
+                Some(tuning) => tuning,
+                None => return,
+            },
+            None => Tuning::default(),
+        };
+        self.tuning().load(tuning, source.map(str::to_string));
+    }
+
+    /// What's saved with the selected program besides its values.
+    fn extras(&self) -> Extras {
+        let user_chord = self.user_chord.get();
+        Extras {
+            tuning: self.tuning().source().map(str::to_string),
+            learned: self
+                .learned
+                .bindings()
+                .map(|(controller, target)| (controller, PARAMS[target].name.to_string()))
+                .collect(),
+            // Just the key is no chord to keep.
+            user_chord: if user_chord == Chord::default() {
+                Vec::new()
+            } else {
+                user_chord.intervals().to_vec()
+            },
         }
     }
 
-    #[allow(unused_variables)]
-    #[allow(clippy::single_match)]
-    fn process_events(&mut self, events: &Events) {
-        for event in events.events() {
-            match event {
-                Event::Midi(ev) => self.process_midi_event(ev.data),
-                // More events can be handled here.
-                _ => (),
+    /// Every program as it's saved, and which is selected. Parameters that aren't part of a
+    /// program are saved at their defaults, whatever they're at now.
+    fn saved_programs(&self) -> (Vec<Program>, usize) {
+        let (mut programs, current) = self.programs.programs(&self.values);
+        for program in &mut programs {
+            for (value, param) in program.values.iter_mut().zip(PARAMS.iter()) {
+                if !param.id.in_programs() {
+                    *value = param.default;
+                }
             }
         }
+        (programs, current)
     }
 
-    fn set_sample_rate(&mut self, rate: f32) {
-        self.sample_rate = f64::from(rate);
+    /// Make a loaded program's values live, leaving the parameters that aren't part of one as
+    /// they are.
+    fn load_values(&self, values: &[f32]) {
+        for (index, &value) in values.iter().enumerate() {
+            if PARAMS
+                .get(index)
+                .is_some_and(|param| param.id.in_programs())
+            {
+                self.set_parameter(index as i32, value);
+            }
+        }
     }
 
-    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
-        let samples = buffer.samples();
-        let amplitude = self.params.amplitude.get();
-        let (_, mut outputs) = buffer.split();
-        let output_count = outputs.len();
-        let per_sample = self.time_per_sample();
-        let mut output_sample;
-        for sample_idx in 0..samples {
-            let time = self.time;
-            let note_duration = self.note_duration;
-            if let Some(current_note) = self.note {
-                let signal = (time * midi_pitch_to_freq(current_note) * TAU).sin();
-
-                // Apply a quick envelope to the attack of the signal to avoid popping.
-                let attack = self.params.attack.get() as f64;
-                //let attack = 0.5;
-                let alpha = if note_duration < attack {
-                    note_duration / attack
-                } else {
-                    1.0
-                };
+    /// Take up the tuning and bindings saved with a program. Bindings to parameters this
+    /// build doesn't have are dropped.
+    fn load_extras(&self, extras: Extras) {
+        self.load_tuning(extras.tuning.as_deref());
+        self.user_chord.set(Chord::new(&extras.user_chord));
+        self.learned.clear();
+        for (controller, name) in extras.learned {
+            if let Some(target) = PARAMS.iter().position(|param| param.name == name) {
+                self.learned.bind(controller, target);
+            }
+        }
+    }
 
-                output_sample = ((signal * alpha)*amplitude as f64) as f32;
+    /// Set the pitch bend range to the nearest whole number of semitones it can be. The
+    /// parameter holds it, so whichever of the host and the MIDI input set it last wins.
+    fn set_bend_range(&self, semitones: f32) {
+        let semitones = semitones.round().clamp(1.0, MAX_BEND_RANGE);
+        self.handle(ParamId::BendRange as usize)
+            .change((semitones - 1.0) / (MAX_BEND_RANGE - 1.0));
+    }
 
-                self.time += per_sample;
-                self.note_duration += per_sample;
-            } else {
-                output_sample = 0.0;
-            }
-            for buf_idx in 0..output_count {
-                let buff = outputs.get_mut(buf_idx);
-                buff[sample_idx] = output_sample;
+    /// The harmonic levels, scaled down if needed so that they sum to no more than 1.0 and the
+    /// additive waveform can't exceed ±1.0.
+    fn harmonics(&self) -> [f64; HARMONICS] {
+        let mut levels = [0.0; HARMONICS];
+        let first = ParamId::Harmonic1 as usize;
+        for (level, param) in levels.iter_mut().zip(&self.values[first..]) {
+            *level = f64::from(param.get()).clamp(0.0, 1.0);
+        }
+        let total: f64 = levels.iter().sum();
+        if total > 1.0 {
+            for level in levels.iter_mut() {
+                *level /= total;
             }
         }
+        levels
     }
 
-    fn can_do(&self, can_do: CanDo) -> Supported {
-        match can_do {
-            CanDo::ReceiveMidiEvent => Supported::Yes,
-            _ => Supported::Maybe,
+    /// The chord every key plays.
+    fn chord(&self) -> Chord {
+        self.chord_type().chord(self.user_chord.get())
+    }
+
+    fn voice_settings(&self, sample_rate: f64) -> VoiceSettings {
+        // The two oscillators are crossfaded with a constant-power law, so the mix doesn't dip
+        // in the middle.
+        let mix_angle = f64::from(self.get(ParamId::OscMix)).clamp(0.0, 1.0) * PI / 2.0;
+        let unison = self.unison_voices();
+        VoiceSettings {
+            sample_rate,
+            // The same time constant as the smoothed parameters.
+            retune: SmoothedParam::new(0.005, sample_rate),
+            waveform: Waveform::from_normalized(self.get(ParamId::Waveform)),
+            table: TableMorph {
+                table: self.table(),
+                position: f64::from(self.get(ParamId::TablePosition)).clamp(0.0, 1.0),
+            },
+            harmonics: self.harmonics(),
+            adsr: self.adsr(),
+            velocity_sens: f64::from(self.get(ParamId::VelocitySens)),
+            sub_level: f64::from(self.get(ParamId::SubLevel)),
+            sub_shape: self.sub_shape(),
+            octave_layer: self.octave_layer(),
+            layer_level: f64::from(self.get(ParamId::LayerLevel)),
+            osc2_ratio: self.osc2_ratio(),
+            osc1_gain: mix_angle.cos(),
+            osc2_gain: mix_angle.sin(),
+            osc2_fixed: self.osc2_fixed(),
+            ring_mod: f64::from(self.get(ParamId::RingMod)).clamp(0.0, 1.0),
+            sync: self.sync(),
+            unison,
+            unison_ratios: self.unison_ratios(),
+            unison_gain: 1.0 / unison as f64,
+            #[cfg(test)]
+            lanes: true,
+            stereo_ratio: self.stereo_ratio(),
+            noise_level: f64::from(self.get(ParamId::NoiseLevel)),
+            pluck_decay: self.pluck_decay(),
+            fm_ratio: self.fm_ratio(),
+            fm_index: self.fm_index(),
+            filter_mode: self.filter_mode(),
+            filter_resonance: f64::from(self.get(ParamId::Resonance)),
+            filter_adsr: self.filter_adsr(),
+            filter_env_amount: self.filter_env_amount(),
+            key_track: self.key_track(),
+            level_key_track: self.level_key_track(),
+            vibrato_depth: f64::from(self.vibrato_depth()),
+            aftertouch: self.aftertouch_destination(),
+            aftertouch_amount: self.aftertouch_amount(),
+            pressure_smoothing: 1.0 - (-1.0 / (PRESSURE_SMOOTHING_TIME * sample_rate)).exp(),
+            // The same time constant as the smoothed parameters.
+            expression_smoothing: 1.0 - (-1.0 / (0.01 * sample_rate)).exp(),
         }
     }
-    // Return the parameter object. This method can be omitted if the
-    // plugin has no parameters.
-    fn get_parameter_object(&mut self) -> Arc<dyn PluginParameters> {
-        Arc::clone(&self.params) as Arc<dyn PluginParameters>
+
+    /// The normalized value a parameter's text stands for, undoing `get_parameter_text`.
+    ///
+    /// Numbers can be typed with or without a unit; a bare number is in the unit the
+    /// parameter is shown in. `None` if the text doesn't make sense for the parameter.
+    fn parse_parameter(&self, index: i32, text: &str) -> Option<f64> {
+        let param = PARAMS.get(index as usize)?;
+        (param.parse)(self, text).filter(|value| !value.is_nan())
     }
 }
 
-impl PluginParameters for GainEffectParameters {
-    // the `get_parameter` function reads the value of a parameter.
-    fn get_parameter(&self, index: i32) -> f32 {
-        match index {
-            0 => self.amplitude.get(),
-            1 => self.attack.get(),
-            _ => 0.0,
+/// What the synth makes of the parameters' normalized values.
+///
+/// The live parameters map their own values, and `Shown` maps them with one set to a value
+/// being shown as text, so a parameter's text comes from the very mapping that's heard.
+trait ParamValues {
+    /// The normalized value of parameter `id`.
+    fn get(&self, id: ParamId) -> f32;
+
+    /// The output level as a linear gain.
+    fn amplitude(&self) -> f64 {
+        db_to_gain(amplitude_db(self.get(ParamId::Amplitude)))
+    }
+
+    /// The octave transposition, a whole number from `-MAX_OCTAVE_SHIFT` to `MAX_OCTAVE_SHIFT`.
+    fn octave(&self) -> i32 {
+        let steps = self.get(ParamId::Octave).clamp(0.0, 1.0) * (2 * MAX_OCTAVE_SHIFT) as f32;
+        steps.round() as i32 - MAX_OCTAVE_SHIFT
+    }
+
+    /// The semitone transposition, a whole number from `-MAX_SEMITONE_SHIFT` to
+    /// `MAX_SEMITONE_SHIFT`.
+    fn semitone(&self) -> i32 {
+        let steps = self.get(ParamId::Semitone).clamp(0.0, 1.0) * (2 * MAX_SEMITONE_SHIFT) as f32;
+        steps.round() as i32 - MAX_SEMITONE_SHIFT
+    }
+
+    /// How many steps "Vel Curve" is from linear, negative for soft and positive for hard.
+    fn velocity_curve(&self) -> i32 {
+        let steps = self.get(ParamId::VelocityCurve).clamp(0.0, 1.0) * (2 * VEL_CURVE_STEPS) as f32;
+        steps.round() as i32 - VEL_CURVE_STEPS
+    }
+
+    /// The fine tuning in cents.
+    fn fine_tune(&self) -> f64 {
+        (f64::from(self.get(ParamId::Fine)).clamp(0.0, 1.0) * 2.0 - 1.0) * MAX_FINE_TUNE
+    }
+
+    /// How far "Octave", "Semitone" and "Fine" move the pitch together, in semitones.
+    fn transpose(&self) -> f64 {
+        f64::from(12 * self.octave() + self.semitone()) + self.fine_tune() / 100.0
+    }
+
+    /// The pitch bend range in whole semitones, from 1 to `MAX_BEND_RANGE`.
+    fn bend_range(&self) -> f32 {
+        1.0 + (self.get(ParamId::BendRange) * (MAX_BEND_RANGE - 1.0)).round()
+    }
+
+    /// The vibrato rate in Hz, mapped exponentially from 0.1 to 10.
+    fn vibrato_rate(&self) -> f32 {
+        0.1 * 100f32.powf(self.get(ParamId::VibratoRate))
+    }
+
+    /// The channel to listen to, numbered from 0 as on the wire, or `None` for omni.
+    fn midi_channel(&self) -> Option<u8> {
+        match (self.get(ParamId::MidiChannel).clamp(0.0, 1.0) * 16.0).round() as u8 {
+            0 => None,
+            channel => Some(channel - 1),
         }
     }
 
-    // the `set_parameter` function sets the value of a parameter.
-    fn set_parameter(&self, index: i32, val: f32) {
-        #[allow(clippy::single_match)]
-        match index {
-            0 => self.amplitude.set(val),
-            1 => self.attack.set(val),
-            _ => (),
+    /// The vibrato depth in cents with the mod wheel fully up.
+    fn vibrato_depth(&self) -> f32 {
+        self.get(ParamId::VibratoDepth) * MAX_VIBRATO_DEPTH
+    }
+
+    /// The fraction of the cycle the square wave spends high, from 5% to 95%.
+    fn pulse_width(&self) -> f64 {
+        let range = 1.0 - 2.0 * MIN_PULSE_WIDTH;
+        MIN_PULSE_WIDTH + f64::from(self.get(ParamId::PulseWidth)).clamp(0.0, 1.0) * range
+    }
+
+    fn sub_shape(&self) -> Waveform {
+        if self.get(ParamId::SubShape) < 0.5 {
+            Waveform::Sine
+        } else {
+            Waveform::Square
         }
     }
 
-    // This is what will display underneath our control.  We can
-    // format it into a string that makes the most since.
-    fn get_parameter_text(&self, index: i32) -> String {
-        match index {
-            0 => format!("{:.2}", (self.amplitude.get() - 0.5) * 2f32),
-            1 => format!("{:.2}", (self.attack.get() - 0.5) * 2f32),
-            _ => "".to_string(),
+    fn octave_layer(&self) -> OctaveLayer {
+        OctaveLayer::from_normalized(self.get(ParamId::OctaveLayer))
+    }
+
+    fn noise_color(&self) -> NoiseColor {
+        if self.get(ParamId::NoiseColor) < 0.5 {
+            NoiseColor::White
+        } else {
+            NoiseColor::Pink
         }
     }
 
-    // This shows the control's name.
-    fn get_parameter_name(&self, index: i32) -> String {
-        match index {
-            0 => "Amplitude",
-            1 => "Attack",
-            _ => "",
+    /// The index of the selected table in `TABLES`.
+    fn table(&self) -> usize {
+        let last = (TABLES.len() - 1) as f32;
+        (self.get(ParamId::Table).clamp(0.0, 1.0) * last).round() as usize
+    }
+
+    /// The coarse FM ratio, one of `FM_RATIOS`.
+    fn fm_coarse(&self) -> f64 {
+        let last = (FM_RATIOS.len() - 1) as f32;
+        FM_RATIOS[(self.get(ParamId::FmRatio).clamp(0.0, 1.0) * last).round() as usize]
+    }
+
+    /// The FM fine tuning in cents.
+    fn fm_fine(&self) -> f64 {
+        (f64::from(self.get(ParamId::FmFine)).clamp(0.0, 1.0) * 2.0 - 1.0) * MAX_FM_FINE
+    }
+
+    /// The frequency ratio of the FM modulator to the note, including fine tuning.
+    fn fm_ratio(&self) -> f64 {
+        self.fm_coarse() * (self.fm_fine() / 1200.0).exp2()
+    }
+
+    fn fm_index(&self) -> f64 {
+        f64::from(self.get(ParamId::FmAmount)).clamp(0.0, 1.0) * MAX_FM_INDEX
+    }
+
+    /// The fixed second oscillator frequency in Hz, mapped exponentially, or `None` when it
+    /// follows the keyboard.
+    fn osc2_fixed(&self) -> Option<f64> {
+        let value = f64::from(self.get(ParamId::Osc2Fixed)).clamp(0.0, 1.0);
+        if value == 0.0 {
+            None
+        } else {
+            Some(MIN_OSC2_FIXED * (MAX_OSC2_FIXED / MIN_OSC2_FIXED).powf(value))
         }
-        .to_string()
     }
-}
 
-plugin_main!(SineSynth);
+    /// The second oscillator's detune in cents.
+    fn detune(&self) -> f64 {
+        (f64::from(self.get(ParamId::Detune)).clamp(0.0, 1.0) * 2.0 - 1.0) * MAX_DETUNE
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::midi_pitch_to_freq;
+    fn sync(&self) -> bool {
+        self.get(ParamId::Sync) >= 0.5
+    }
 
-    #[test]
-    fn test_midi_pitch_to_freq() {
-        for i in 0..127 {
-            // expect no panics
-            midi_pitch_to_freq(i);
+    /// With hard sync on, the second oscillator's pitch above the first in semitones.
+    ///
+    /// A synced oscillator's pitch sets its timbre rather than the note, so "Detune" sweeps over
+    /// a much wider range.
+    fn sync_offset(&self) -> f64 {
+        f64::from(self.get(ParamId::Detune)).clamp(0.0, 1.0) * MAX_SYNC_OFFSET
+    }
+
+    /// The frequency ratio of the second oscillator to the first.
+    fn osc2_ratio(&self) -> f64 {
+        if self.sync() {
+            semitones_to_ratio(self.sync_offset())
+        } else {
+            (self.detune() / 1200.0).exp2()
+        }
+    }
+
+    /// The number of unison copies, from 1 to `MAX_UNISON`.
+    fn unison_voices(&self) -> usize {
+        let extra = (MAX_UNISON - 1) as f32;
+        1 + (self.get(ParamId::UnisonVoices).clamp(0.0, 1.0) * extra).round() as usize
+    }
+
+    /// The detune of the outermost unison copies in cents.
+    fn unison_spread(&self) -> f64 {
+        f64::from(self.get(ParamId::UnisonSpread)).clamp(0.0, 1.0) * MAX_UNISON_SPREAD
+    }
+
+    /// The frequency ratio of each unison copy, spaced evenly from `-spread` to `+spread`.
+    fn unison_ratios(&self) -> [f64; MAX_UNISON] {
+        let voices = self.unison_voices();
+        let spread = self.unison_spread();
+        let mut ratios = [1.0; MAX_UNISON];
+        if voices > 1 {
+            for (copy, ratio) in ratios[..voices].iter_mut().enumerate() {
+                let position = 2.0 * copy as f64 / (voices - 1) as f64 - 1.0;
+                *ratio = (position * spread / 1200.0).exp2();
+            }
+        }
+        ratios
+    }
+
+    /// The frequency ratio of the right channel to the note, the left being its reciprocal.
+    fn stereo_ratio(&self) -> f64 {
+        let width = f64::from(self.get(ParamId::StereoWidth)).clamp(0.0, 1.0);
+        (width * MAX_STEREO_DETUNE / 1200.0).exp2()
+    }
+
+    /// How long a plucked string takes to decay by 60 dB, mapped exponentially from
+    /// `MAX_PLUCK_DECAY` down to a hundredth of it.
+    fn pluck_decay(&self) -> f64 {
+        MAX_PLUCK_DECAY * 0.01f64.powf(f64::from(self.get(ParamId::Damping)).clamp(0.0, 1.0))
+    }
+
+    /// The smoothing applied to the noise that plucks a string; see `PluckedString::excite`.
+    fn excite_brightness(&self) -> f64 {
+        0.05 + 0.95 * f64::from(self.get(ParamId::ExciteBrightness)).clamp(0.0, 1.0)
+    }
+
+    /// The filter cutoff in Hz, mapped exponentially from `MIN_CUTOFF` to `MAX_CUTOFF`.
+    fn cutoff(&self) -> f64 {
+        cutoff_frequency(f64::from(self.get(ParamId::Cutoff)))
+    }
+
+    /// Whether the cutoff is fully up, which takes the filter out of the signal altogether.
+    ///
+    /// An envelope or key tracking moving the cutoff keeps the filter in, so they can bring it
+    /// down. Only the low-pass passes everything when fully up, so the other modes stay in too.
+    fn filter_open(&self) -> bool {
+        self.get(ParamId::Cutoff) >= 1.0
+            && self.filter_env_amount() == 0.0
+            && self.key_track() == 0.0
+            && self.filter_mode() == FilterMode::LowPass
+            && !(self.lfo_destination() == LfoDestination::Cutoff && self.lfo_depth() > 0.0)
+    }
+
+    fn filter_mode(&self) -> FilterMode {
+        FilterMode::from_normalized(self.get(ParamId::FilterMode))
+    }
+
+    fn key_track(&self) -> f64 {
+        f64::from(self.get(ParamId::KeyTrack)).clamp(0.0, 1.0)
+    }
+
+    /// The LFO rate in Hz, mapped exponentially from 0.1 to 20.
+    fn lfo_rate(&self) -> f64 {
+        0.1 * 200f64.powf(f64::from(self.get(ParamId::LfoRate)).clamp(0.0, 1.0))
+    }
+
+    fn arp(&self) -> bool {
+        self.get(ParamId::Arp) >= 0.5
+    }
+
+    fn arp_mode(&self) -> ArpMode {
+        ArpMode::from_normalized(self.get(ParamId::ArpMode))
+    }
+
+    fn arp_division(&self) -> &'static SyncDivision {
+        let divisions = arp_divisions();
+        let last = (divisions.len() - 1) as f32;
+        &divisions[(self.get(ParamId::ArpRate).clamp(0.0, 1.0) * last).round() as usize]
+    }
+
+    /// The arpeggiator tempo in beats per minute, for when the host has none.
+    fn arp_tempo(&self) -> f64 {
+        let value = f64::from(self.get(ParamId::ArpTempo)).clamp(0.0, 1.0);
+        MIN_ARP_TEMPO + value * (MAX_ARP_TEMPO - MIN_ARP_TEMPO)
+    }
+
+    fn arp_settings(&self) -> ArpSettings {
+        ArpSettings {
+            mode: self.arp_mode(),
+            beats: self.arp_division().beats,
+            gate: f64::from(self.get(ParamId::ArpGate)).clamp(0.0, 1.0),
+            hold: self.get(ParamId::ArpHold) >= 0.5,
+        }
+    }
+
+    /// The tremolo rate in Hz, mapped exponentially from 0.1 to 20.
+    fn tremolo_rate(&self) -> f64 {
+        0.1 * 200f64.powf(f64::from(self.get(ParamId::TremRate)).clamp(0.0, 1.0))
+    }
+
+    fn tremolo_depth(&self) -> f64 {
+        f64::from(self.get(ParamId::TremDepth)).clamp(0.0, 1.0)
+    }
+
+    fn lfo_depth(&self) -> f64 {
+        f64::from(self.get(ParamId::LfoDepth)).clamp(0.0, 1.0)
+    }
+
+    fn lfo_destination(&self) -> LfoDestination {
+        LfoDestination::from_normalized(self.get(ParamId::LfoDestination))
+    }
+
+    fn lfo_shape(&self) -> LfoShape {
+        LfoShape::from_normalized(self.get(ParamId::LfoShape))
+    }
+
+    /// Whether every note restarts the LFO, rather than it running freely.
+    fn lfo_retrigger(&self) -> bool {
+        self.get(ParamId::LfoRetrigger) >= 0.5
+    }
+
+    /// Whether the LFO follows the host's tempo and position rather than "LFO Rate".
+    fn lfo_sync(&self) -> bool {
+        self.get(ParamId::LfoSync) >= 0.5
+    }
+
+    /// The glide time in seconds, mapped exponentially from 1ms to `MAX_GLIDE`, or zero with
+    /// the parameter fully down.
+    fn glide(&self) -> f64 {
+        let value = f64::from(self.get(ParamId::Glide)).clamp(0.0, 1.0);
+        if value == 0.0 {
+            0.0
+        } else {
+            MIN_GLIDE * (MAX_GLIDE / MIN_GLIDE).powf(value)
         }
     }
-}
\ No newline at end of file
+
+    /// Whether glide only slides to a note played while another key is still down.
+    fn legato_glide(&self) -> bool {
+        self.get(ParamId::GlideMode) >= 0.5
+    }
+
+    fn bypass(&self) -> bool {
+        self.get(ParamId::Bypass) >= 0.5
+    }
+
+    /// Whether only one note plays at a time.
+    fn mono(&self) -> bool {
+        self.get(ParamId::VoiceMode) >= 0.5
+    }
+
+    fn note_priority(&self) -> NotePriority {
+        NotePriority::from_normalized(self.get(ParamId::NotePriority))
+    }
+
+    fn voice_steal(&self) -> StealPolicy {
+        StealPolicy::from_normalized(self.get(ParamId::VoiceSteal))
+    }
+
+    /// Whether a voice's oscillators start from the beginning of their cycles on every new
+    /// note, rather than carrying on from wherever the voice's last note left them.
+    fn phase_reset(&self) -> bool {
+        self.get(ParamId::PhaseReset) >= 0.5
+    }
+
+    /// Whether each new note starts its oscillator at a random point in the cycle.
+    fn random_phase(&self) -> bool {
+        self.get(ParamId::RandomPhase) >= 0.5
+    }
+
+    /// The seed for the random start phases.
+    fn random_seed(&self) -> u32 {
+        (f64::from(self.get(ParamId::RandomSeed).clamp(0.0, 1.0)) * f64::from(u32::MAX)) as u32
+    }
+
+    /// Whether MIDI Program Change messages switch programs. Some hosts send them on
+    /// transport start, which this lets users ignore.
+    fn program_change(&self) -> bool {
+        self.get(ParamId::ProgramChange) >= 0.5
+    }
+
+    fn aftertouch_destination(&self) -> AftertouchDestination {
+        AftertouchDestination::from_normalized(self.get(ParamId::AftertouchDest))
+    }
+
+    fn aftertouch_amount(&self) -> f64 {
+        f64::from(self.get(ParamId::AftertouchAmount)).clamp(0.0, 1.0)
+    }
+
+    /// Whether MIDI Polyphonic Expression is on. It listens on every channel, whatever "MIDI
+    /// Channel" is set to.
+    fn mpe(&self) -> bool {
+        self.get(ParamId::Mpe) >= 0.5
+    }
+
+    fn chord_type(&self) -> ChordType {
+        ChordType::from_normalized(self.get(ParamId::Chord))
+    }
+
+    fn chord_learn(&self) -> bool {
+        self.get(ParamId::ChordLearn) >= 0.5
+    }
+
+    fn learn_mode(&self) -> LearnMode {
+        LearnMode::from_normalized(self.get(ParamId::MidiLearn))
+    }
+
+    /// The index of the parameter MIDI learn binds to.
+    fn learn_target(&self) -> usize {
+        let slot = self.get(ParamId::LearnTarget).clamp(0.0, 1.0) * (TARGET_SLOTS - 1) as f32;
+        (slot.round() as usize).min(PARAM_COUNT - 1)
+    }
+
+    /// Whether MIDI Tuning Standard SysEx messages are applied. A sequencer can send them
+    /// ahead of a song, which this lets users ignore.
+    fn tuning_messages(&self) -> bool {
+        self.get(ParamId::TuningMessages) >= 0.5
+    }
+
+    fn limiter(&self) -> bool {
+        self.get(ParamId::Limiter) >= 0.5
+    }
+
+    /// The frequency of A4 in Hz, from 400 to 480.
+    fn master_tune(&self) -> f64 {
+        let range = MAX_MASTER_TUNE - MIN_MASTER_TUNE;
+        MIN_MASTER_TUNE + f64::from(self.get(ParamId::MasterTune)).clamp(0.0, 1.0) * range
+    }
+
+    /// How many voices can sound at once, from 1 to `MAX_VOICES`.
+    fn polyphony(&self) -> usize {
+        1 + (self.get(ParamId::Polyphony).clamp(0.0, 1.0) * (MAX_VOICES - 1) as f32).round()
+            as usize
+    }
+
+    fn sync_division(&self) -> &'static SyncDivision {
+        let last = (SYNC_DIVISIONS.len() - 1) as f32;
+        &SYNC_DIVISIONS[(self.get(ParamId::SyncRate).clamp(0.0, 1.0) * last).round() as usize]
+    }
+
+    /// How far the filter envelope moves the cutoff at full level, in octaves.
+    fn filter_env_amount(&self) -> f64 {
+        (f64::from(self.get(ParamId::FilterEnvAmount)).clamp(0.0, 1.0) * 2.0 - 1.0)
+            * MAX_FILTER_ENV_AMOUNT
+    }
+
+    fn filter_adsr(&self) -> AdsrSettings {
+        AdsrSettings {
+            attack: f64::from(self.get(ParamId::FilterAttack)),
+            decay: f64::from(self.get(ParamId::FilterDecay)),
+            sustain: f64::from(self.get(ParamId::FilterSustain)),
+            release: f64::from(self.get(ParamId::FilterRelease)),
+            curve: EnvCurve::Linear,
+        }
+    }
+
+    /// The attack time used for an attack parameter of `attack` seconds.
+    fn attack_time(&self, attack: f64) -> f64 {
+        // A random start phase can land on a peak of the wave, which would click without a
+        // ramp in.
+        if self.random_phase() {
+            attack.max(MIN_RANDOM_PHASE_ATTACK)
+        } else {
+            attack
+        }
+    }
+
+    fn adsr(&self) -> AdsrSettings {
+        AdsrSettings {
+            attack: self.attack_time(attack_seconds(f64::from(self.get(ParamId::Attack)))),
+            decay: f64::from(self.get(ParamId::Decay)),
+            sustain: f64::from(self.get(ParamId::Sustain)),
+            release: f64::from(self.get(ParamId::Release)),
+            curve: self.env_curve(),
+        }
+    }
+
+    fn env_curve(&self) -> EnvCurve {
+        EnvCurve::from_normalized(self.get(ParamId::EnvCurve))
+    }
+
+    fn oversampling(&self) -> Oversampling {
+        Oversampling::from_normalized(self.get(ParamId::Oversampling))
+    }
+
+    /// The chorus rate in Hz, mapped exponentially from 0.1 to 5.
+    fn chorus_rate(&self) -> f64 {
+        0.1 * 50f64.powf(f64::from(self.get(ParamId::ChorusRate)).clamp(0.0, 1.0))
+    }
+
+    fn chorus_depth(&self) -> f64 {
+        f64::from(self.get(ParamId::ChorusDepth)).clamp(0.0, 1.0)
+    }
+
+    fn chorus_mix(&self) -> f64 {
+        f64::from(self.get(ParamId::ChorusMix)).clamp(0.0, 1.0)
+    }
+
+    /// The unsynced delay time in seconds, mapped exponentially from `MIN_DELAY` to
+    /// `MAX_DELAY`.
+    fn free_delay_time(&self) -> f64 {
+        let value = f64::from(self.get(ParamId::DelayTime)).clamp(0.0, 1.0);
+        MIN_DELAY * (MAX_DELAY / MIN_DELAY).powf(value)
+    }
+
+    fn delay_sync(&self) -> bool {
+        self.get(ParamId::DelaySync) >= 0.5
+    }
+
+    fn delay_division(&self) -> &'static SyncDivision {
+        let divisions = delay_divisions();
+        let last = (divisions.len() - 1) as f32;
+        &divisions[(self.get(ParamId::DelayNote).clamp(0.0, 1.0) * last).round() as usize]
+    }
+
+    fn feedback(&self) -> f64 {
+        f64::from(self.get(ParamId::Feedback)).clamp(0.0, 1.0) * MAX_FEEDBACK
+    }
+
+    fn delay_mix(&self) -> f64 {
+        f64::from(self.get(ParamId::DelayMix)).clamp(0.0, 1.0)
+    }
+
+    fn ping_pong(&self) -> bool {
+        self.get(ParamId::PingPong) >= 0.5
+    }
+
+    fn reverb_size(&self) -> f64 {
+        f64::from(self.get(ParamId::ReverbSize)).clamp(0.0, 1.0)
+    }
+
+    fn reverb_damping(&self) -> f64 {
+        f64::from(self.get(ParamId::ReverbDamping)).clamp(0.0, 1.0)
+    }
+
+    fn reverb_mix(&self) -> f64 {
+        f64::from(self.get(ParamId::ReverbMix)).clamp(0.0, 1.0)
+    }
+
+    /// The lo-fi stage's bit depth, from `MIN_BITS` to `MAX_BITS`.
+    fn bit_depth(&self) -> f64 {
+        let value = f64::from(self.get(ParamId::BitDepth)).clamp(0.0, 1.0);
+        MIN_BITS + value * (MAX_BITS - MIN_BITS)
+    }
+
+    /// How many samples the lo-fi stage holds each one for, from 1 to `MAX_HOLD`.
+    fn downsample(&self) -> usize {
+        1 + (self.get(ParamId::Downsample).clamp(0.0, 1.0) * (MAX_HOLD - 1) as f32).round() as usize
+    }
+
+    fn quantize(&self) -> Quantize {
+        Quantize::from_normalized(self.get(ParamId::Quantize))
+    }
+
+    /// How far the tilt EQ leans, from -1 for darkest to 1 for brightest.
+    fn brightness(&self) -> f64 {
+        f64::from(self.get(ParamId::Brightness)).clamp(0.0, 1.0) * 2.0 - 1.0
+    }
+
+    /// How many dB louder each octave above `KEY_TRACK_CENTRE` plays, negative for quieter.
+    fn level_key_track(&self) -> f64 {
+        (f64::from(self.get(ParamId::LevelKeyTrack)).clamp(0.0, 1.0) * 2.0 - 1.0)
+            * MAX_LEVEL_KEY_TRACK
+    }
+
+    fn crush_settings(&self) -> CrushSettings {
+        CrushSettings {
+            bits: self.bit_depth(),
+            quantize: self.quantize(),
+            hold: self.downsample(),
+        }
+    }
+}
+
+impl ParamValues for GainEffectParameters {
+    fn get(&self, id: ParamId) -> f32 {
+        self.values[id as usize].get()
+    }
+}
+
+/// The parameters with one of them at a value being shown as text, which needn't be its live
+/// one. The others are live, for the parameters whose text depends on them.
+struct Shown<'a> {
+    params: &'a GainEffectParameters,
+    id: ParamId,
+    /// The normalized value being shown.
+    value: f32,
+}
+
+impl ParamValues for Shown<'_> {
+    fn get(&self, id: ParamId) -> f32 {
+        if id == self.id {
+            self.value
+        } else {
+            self.params.get(id)
+        }
+    }
+}
+
+impl Default for GainEffectParameters {
+    fn default() -> GainEffectParameters {
+        GainEffectParameters::new(HostCallback::default())
+    }
+}
+
+impl SineSynth {
+    fn time_per_sample(&self) -> f64 {
+        1.0 / self.sample_rate
+    }
+
+    /// The sample rate the voices render at, a multiple of the host's when oversampling.
+    fn voice_rate(&self) -> f64 {
+        self.sample_rate * self.oversampling.factor() as f64
+    }
+
+    /// Queue an event to be applied `delta_frames` samples into the next block.
+    ///
+    /// If the queue is full the event is applied straight away rather than lost.
+    fn queue_midi_event(&mut self, delta_frames: i32, data: [u8; 3]) {
+        debug_log!(
+            self,
+            Record::Midi {
+                data,
+                frame: delta_frames.max(0) as u32,
+            }
+        );
+        let event = TimedEvent {
+            offset: delta_frames.max(0) as usize,
+            data,
+        };
+        if let Err(event) = self.events.push(event) {
+            self.process_midi_event(event.data);
+        }
+    }
+
+    /// Apply every queued event due at or before `offset`, returning whether there were any.
+    fn apply_due_events(&mut self, offset: usize) -> bool {
+        let mut applied = false;
+        while let Some(event) = self.events.pop_due(offset) {
+            self.process_midi_event(event.data);
+            applied = true;
+        }
+        applied
+    }
+
+    /// The shared filter coefficients for a normalized `cutoff`, or `None` if the filter is
+    /// out of the signal.
+    ///
+    /// `filter_open` is the parameters' verdict; the filter also stays in until a smoothed
+    /// cutoff has finished opening, so it doesn't drop out part way through a sweep.
+    fn filter_coefficients(&mut self, filter_open: bool, cutoff: f64) -> Option<Coefficients> {
+        if filter_open && cutoff >= 1.0 {
+            return None;
+        }
+        let inputs = [
+            cutoff_frequency(cutoff),
+            f64::from(self.params.get(ParamId::Resonance)),
+            self.voice_rate(),
+        ];
+        if inputs != self.filter.1 {
+            self.filter = (Coefficients::new(inputs[0], inputs[1], inputs[2]), inputs);
+        }
+        Some(self.filter.0)
+    }
+
+    /// The host's tempo, and its position in quarter notes if the transport is playing.
+    ///
+    /// `None` if there's no host to ask or it doesn't know the tempo.
+    fn host_time(&self) -> Option<(f64, Option<f64>)> {
+        self.host.raw_callback()?;
+        let mask = TimeInfoFlags::TEMPO_VALID | TimeInfoFlags::PPQ_POS_VALID;
+        let info = self.host.get_time_info(mask.bits())?;
+        let flags = TimeInfoFlags::from_bits_truncate(info.flags);
+        if !flags.contains(TimeInfoFlags::TEMPO_VALID) || info.tempo <= 0.0 {
+            return None;
+        }
+        let playing = TimeInfoFlags::TRANSPORT_PLAYING | TimeInfoFlags::PPQ_POS_VALID;
+        let ppq_pos = Some(info.ppq_pos).filter(|_| flags.contains(playing));
+        Some((info.tempo, ppq_pos))
+    }
+
+    /// The LFO rate in Hz for this block, locking the LFO's phase to the song position when
+    /// it is synced and the transport is running.
+    fn lfo_rate(&mut self) -> f64 {
+        if !self.params.lfo_sync() {
+            return self.params.lfo_rate();
+        }
+        match self.host_time() {
+            Some((tempo, ppq_pos)) => {
+                let beats = self.params.sync_division().beats;
+                if let Some(ppq_pos) = ppq_pos {
+                    self.lfo.set_phase(synced_phase(ppq_pos, beats));
+                }
+                tempo / 60.0 / beats
+            }
+            None => self.params.lfo_rate(),
+        }
+    }
+
+    /// How the delay runs this block, with a synced time worked out from the host's tempo.
+    /// Without a tempo to follow, it falls back on the free time.
+    fn delay_settings(&self) -> DelaySettings {
+        let synced = if self.params.delay_sync() {
+            self.host_time()
+                .map(|(tempo, _)| self.params.delay_division().beats * 60.0 / tempo)
+        } else {
+            None
+        };
+        DelaySettings {
+            time: synced
+                .unwrap_or_else(|| self.params.free_delay_time())
+                .min(MAX_DELAY),
+            feedback: self.params.feedback(),
+            mix: self.params.delay_mix(),
+            ping_pong: self.params.ping_pong(),
+        }
+    }
+
+    /// Where the arpeggiator is in beats at the start of this block, how many beats each
+    /// sample moves it on, and whether that's its own clock rather than the song position.
+    fn arp_timing(&mut self, samples: usize) -> (f64, f64, bool) {
+        let (tempo, position) = match self.host_time() {
+            Some((tempo, Some(ppq_pos))) => {
+                return (ppq_pos, tempo / 60.0 / self.sample_rate, false)
+            }
+            Some((tempo, None)) => (tempo, self.arp_clock),
+            None => (self.params.arp_tempo(), self.arp_clock),
+        };
+        let per_sample = tempo / 60.0 / self.sample_rate;
+        self.arp_clock += per_sample * samples as f64;
+        (position, per_sample, true)
+    }
+
+    fn bend_ratio(&self, bend_range: f64) -> f64 {
+        semitones_to_ratio(self.pitch_bend * bend_range)
+    }
+
+    /// Process an incoming SysEx message. One carrying a scale file or MTS tuning changes
+    /// retunes the notes it covers from the next block, and those already sounding glide to
+    /// their new pitches.
+    fn process_sysex_event(&mut self, payload: &[u8]) {
+        // Changed on top of whatever the host loaded last.
+        self.tuning.update();
+        if let Some(text) = sysex_scale(payload) {
+            self.tuning.load_scale(text);
+        } else if self.params.tuning_messages() {
+            self.tuning.apply_mts(payload);
+        }
+    }
+
+    /// Process an incoming midi event.
+    ///
+    /// Channel messages are dropped unless they match the "MIDI Channel" parameter, which MPE
+    /// mode ignores.
+    fn process_midi_event(&mut self, data: [u8; 3]) {
+        self.update_midi_channel();
+        let message = match midi::parse(data) {
+            Some(message) => message,
+            None => return,
+        };
+        if let (Some(wanted), Some(channel)) = (self.midi_channel, message.channel()) {
+            if channel != wanted {
+                return;
+            }
+        }
+
+        if let MidiMessage::ControlChange {
+            controller, value, ..
+        } = message
+        {
+            if self.learn_control(controller, value) {
+                return;
+            }
+        }
+
+        let mpe = self.params.mpe();
+        match message {
+            MidiMessage::PitchBend { channel, value } if mpe && !is_master_channel(channel) => {
+                self.mpe.set_bend(channel, bend_position(value));
+            }
+            MidiMessage::ChannelPressure { channel, pressure } if mpe => {
+                self.mpe.set_pressure(channel, pressure);
+            }
+            MidiMessage::ControlChange {
+                channel,
+                controller: 74,
+                value,
+            } if mpe => self.mpe.set_slide(channel, value),
+            // A note channel's other controllers are for its note, and none of them are used.
+            MidiMessage::ControlChange { channel, .. } if mpe && !is_master_channel(channel) => (),
+            MidiMessage::NoteOff { note, .. } => self.key_up(note),
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => self.key_down(channel, note, velocity),
+            MidiMessage::ControlChange {
+                controller, value, ..
+            } => self.control_change(controller, value),
+            MidiMessage::ProgramChange { program, .. } if self.params.program_change() => {
+                self.pending_program = Some(usize::from(program).min(PROGRAM_COUNT - 1));
+            }
+            MidiMessage::PitchBend { value, .. } => self.set_pitch_bend(value),
+            MidiMessage::ChannelPressure { pressure, .. } => {
+                self.pressure = f64::from(pressure) / 127.0;
+            }
+            MidiMessage::PolyPressure { note, pressure, .. } => self.poly_pressure(note, pressure),
+            MidiMessage::ProgramChange { .. } | MidiMessage::Unknown => (),
+        }
+    }
+
+    /// Keep the held keys as the user chord, and stop learning. With no keys held the user
+    /// chord stays as it was.
+    fn learn_chord(&mut self) {
+        if let Some(chord) = Chord::learn(self.held_notes.held()) {
+            self.params.user_chord.set(chord);
+        }
+        self.params.handle(ParamId::ChordLearn as usize).change(0.0);
+    }
+
+    /// Render a block of `samples` samples into `outputs`, writing each sample through
+    /// `convert`. Single and double precision hosts both come through here, so the two can't
+    /// sound any different.
+    ///
+    /// The summed voices go through the drive and the decimators, then the lo-fi stage, the DC
+    /// blockers, the brightness tilt, the chorus, the amplitude, tremolo and pan, the delay,
+    /// the reverb, the limiter and the bypass fade. The lo-fi stage is ahead of the DC
+    /// blockers so they take out the offset truncating leaves, and the limiter is last so
+    /// nothing after it can push the output past full scale.
+    fn render<'a, T: 'a>(
+        &mut self,
+        samples: usize,
+        outputs: impl IntoIterator<Item = &'a mut [T]>,
+        convert: impl Fn(f64) -> T,
+    ) {
+        let _denormals = DenormalGuard::new();
+        // A longer block renders as well as any other, but means the host broke its word.
+        debug_assert!(
+            self.max_block_size.is_none_or(|max| samples <= max),
+            "{} samples in a block of at most {:?}",
+            samples,
+            self.max_block_size
+        );
+        #[cfg(feature = "debug-log")]
+        self.log
+            .begin_block(self.params.values.iter().map(AtomicFloat::get));
+        let bypass = self.params.bypass();
+        if bypass && self.bypass_gain == 0.0 {
+            for output in outputs {
+                output.iter_mut().for_each(|sample| *sample = convert(0.0));
+            }
+            self.process_bypassed();
+            return;
+        }
+        // A program switch always lands between blocks, never part way through one, and so
+        // does a tuning loaded with the plugin's state.
+        self.params.switch_requested_program();
+        self.tuning.update();
+        self.update_midi_channel();
+        if self.params.chord_learn() {
+            self.learn_chord();
+        }
+        let oversampling = self.params.oversampling();
+        if oversampling != self.oversampling {
+            // Whatever the decimators hold is at the old rate.
+            self.oversampling = oversampling;
+            self.decimators = [Decimator::default(); 2];
+            self.params.host.set_latency(oversampling.latency() as i32);
+        }
+        let factor = oversampling.factor();
+        let amplitude = self.params.amplitude();
+        let attack = f64::from(self.params.get(ParamId::Attack));
+        let cutoff = f64::from(self.params.get(ParamId::Cutoff)).clamp(0.0, 1.0);
+        let filter_open = self.params.filter_open();
+        let master_tune = self.params.master_tune();
+        let transpose = self.params.transpose();
+        let settings = self.params.voice_settings(self.voice_rate());
+        #[cfg(test)]
+        let settings = VoiceSettings {
+            lanes: self.lanes,
+            ..settings
+        };
+        let pulse_width = self.params.pulse_width();
+        let noise_color = self.params.noise_color();
+        let bend_range = f64::from(self.params.bend_range());
+        let mut bend_ratio = self.bend_ratio(bend_range);
+        let vibrato_rate = f64::from(self.params.vibrato_rate());
+        let vibrato_depth = f64::from(self.params.vibrato_depth());
+        let tremolo_rate = self.params.tremolo_rate();
+        let tremolo_depth = self.params.tremolo_depth();
+        let saturator = Saturator::new(f64::from(self.params.get(ParamId::Drive)));
+        let chorus_rate = self.params.chorus_rate();
+        let chorus_depth = self.params.chorus_depth();
+        let chorus_mix = self.params.chorus_mix();
+        let mut delay_settings = self.delay_settings();
+        let delay_mix = delay_settings.mix;
+        let crush = self.params.crush_settings();
+        self.tilt.set(self.params.brightness(), self.sample_rate);
+        let reverb_mix = self.params.reverb_mix();
+        self.reverb
+            .set(self.params.reverb_size(), self.params.reverb_damping());
+        let limiter = self.params.limiter();
+        let lfo_rate = self.lfo_rate();
+        let lfo_depth = self.params.lfo_depth();
+        let lfo_destination = self.params.lfo_destination();
+        let lfo_shape = self.params.lfo_shape();
+        let mpe = self.params.mpe();
+        let arp_settings = self.params.arp_settings();
+        let arp_timing = if self.params.arp() {
+            Some(self.arp_timing(samples))
+        } else {
+            if let Some(sounding) = self.arp.stop() {
+                self.note_off(sounding.note);
+            }
+            None
+        };
+        let mut channels = outputs.into_iter();
+        let mut left_out = channels.next();
+        let mut right_out = channels.next();
+        // Outputs past the stereo pair have nothing to play.
+        for extra in channels {
+            extra.iter_mut().for_each(|sample| *sample = convert(0.0));
+        }
+        // With only one output connected there is nowhere to pan to, and it plays both
+        // channels summed.
+        let mono = right_out.is_none();
+        let (pan_left, pan_right) = if mono {
+            (1.0, 1.0)
+        } else {
+            pan_gains(self.params.get(ParamId::Pan))
+        };
+        // Echoes sent to the right would never be heard.
+        delay_settings.ping_pong &= !mono;
+        if arp_timing.is_none() && self.is_quiet() {
+            // The block is silence, so nothing smoothed can click and it all arrives at once;
+            // only what runs freely has to be moved on to where it would have got to.
+            for out in left_out.into_iter().chain(right_out) {
+                out.iter_mut().for_each(|sample| *sample = convert(0.0));
+            }
+            let seconds = samples as f64 * self.time_per_sample();
+            self.vibrato_phase = (self.vibrato_phase + vibrato_rate * seconds).fract();
+            self.tremolo_phase = (self.tremolo_phase + tremolo_rate * seconds).fract();
+            self.lfo.skip(lfo_rate, self.sample_rate, samples);
+            self.crusher.skip(&crush, samples);
+            self.chorus.skip(chorus_rate, samples);
+            self.mod_wheel_smoothed = self.mod_wheel;
+            self.pressure_smoothed = self.pressure;
+            self.pulse_width = pulse_width;
+            self.mpe.ease(1.0);
+            let smoothers = &mut self.smoothers;
+            smoothers.amplitude.snap(amplitude);
+            smoothers.attack.snap(attack);
+            smoothers.cutoff.snap(cutoff);
+            smoothers.master_tune.snap(master_tune);
+            smoothers.transpose.snap(transpose);
+            smoothers.pan_left.snap(pan_left);
+            smoothers.pan_right.snap(pan_right);
+            smoothers.chorus_depth.snap(chorus_depth);
+            smoothers.chorus_mix.snap(chorus_mix);
+            smoothers.delay_mix.snap(delay_mix);
+            smoothers.reverb_mix.snap(reverb_mix);
+            self.delay.snap(delay_settings.time);
+            self.bypass_gain = if bypass { 0.0 } else { 1.0 };
+            if let Some(program) = self.pending_program.take() {
+                self.params.switch_program(program);
+            }
+            return;
+        }
+        let per_sample = self.time_per_sample();
+        // One-pole coefficient for values that should settle over roughly 10ms.
+        let smoothing = 1.0 - (-per_sample / 0.01).exp();
+        let pressure_smoothing = 1.0 - (-per_sample / PRESSURE_SMOOTHING_TIME).exp();
+        // The same again for what changes at the voices' rate.
+        let per_voice_sample = per_sample / factor as f64;
+        let voice_smoothing = 1.0 - (-per_voice_sample / 0.01).exp();
+        // The most the LFO can move in one sample, so it takes 1ms to cross its full range.
+        let lfo_slew = 2.0 * per_sample / 0.001;
+        let bypass_step = per_sample / BYPASS_FADE_TIME;
+        // An arpeggiator step due where a stretch was cut short, waiting to start the next.
+        let mut arp_step = None;
+        let mut start = 0;
+        while start < samples {
+            // The modulation for each sample up to the next event or arpeggiator step, so the
+            // voices can render the whole stretch between them at once.
+            let mut modulation = [Modulation::default(); SUB_BLOCK];
+            let mut lfo_gains = [1.0; SUB_BLOCK];
+            let mut end = start;
+            while end < samples && end - start < SUB_BLOCK {
+                // Events timed past the end of the block are applied on its last sample.
+                let due = if end + 1 == samples { usize::MAX } else { end };
+                if end > start {
+                    if self.events.has_due(due) {
+                        break;
+                    }
+                } else if self.apply_due_events(due) {
+                    bend_ratio = self.bend_ratio(bend_range);
+                }
+                if let Some((position, beats_per_sample, free)) = arp_timing {
+                    let position = position + end as f64 * beats_per_sample;
+                    let step = arp_step
+                        .take()
+                        .unwrap_or_else(|| self.arp.tick(position, free, &arp_settings));
+                    if end > start && step != ArpStep::default() {
+                        arp_step = Some(step);
+                        break;
+                    }
+                    if let Some(released) = step.release {
+                        self.note_off(released.note);
+                    }
+                    if let Some(played) = step.play {
+                        self.note_on(played.channel, played.note, played.velocity);
+                    }
+                }
+
+                self.mod_wheel_smoothed = flush(
+                    self.mod_wheel_smoothed
+                        + (self.mod_wheel - self.mod_wheel_smoothed) * smoothing,
+                );
+                self.pressure_smoothed = flush(
+                    self.pressure_smoothed
+                        + (self.pressure - self.pressure_smoothed) * pressure_smoothing,
+                );
+                let mod_wheel = self.mod_wheel_smoothed;
+                let vibrato_sine = (self.vibrato_phase * TAU).sin();
+                let vibrato = mod_wheel * vibrato_depth * vibrato_sine;
+                self.vibrato_phase = (self.vibrato_phase + vibrato_rate * per_sample).fract();
+                let target = lfo_depth * self.lfo.next(lfo_shape, lfo_rate, self.sample_rate);
+                // Only the jumps of the stepped shape are fast enough to be slowed down;
+                // anything smoother passes through unchanged.
+                if (target - self.lfo_slewed).abs() <= lfo_slew {
+                    self.lfo_slewed = target;
+                } else {
+                    self.lfo_slewed += lfo_slew.copysign(target - self.lfo_slewed);
+                }
+                let lfo = self.lfo_slewed;
+                // At zero depth every destination's modulation is exactly neutral.
+                let mut lfo_pitch = 1.0;
+                let mut lfo_cutoff = 0.0;
+                match lfo_destination {
+                    LfoDestination::Pitch => lfo_pitch = semitones_to_ratio(lfo * MAX_LFO_PITCH),
+                    // Tremolo dips down from full level, so it never makes notes louder.
+                    LfoDestination::Amplitude => {
+                        lfo_gains[end - start] = 1.0 - 0.5 * (lfo_depth - lfo);
+                    }
+                    LfoDestination::Cutoff => lfo_cutoff = lfo * MAX_LFO_CUTOFF,
+                }
+                // Smoothing the transposition in semitones makes a change glide evenly; once
+                // it settles, whole octaves and semitones are exact ratios.
+                let transpose_ratio = semitones_to_ratio(self.smoothers.transpose.next(transpose));
+                let smoothed_cutoff = self.smoothers.cutoff.next(cutoff);
+                self.pulse_width += (pulse_width - self.pulse_width) * smoothing;
+                modulation[end - start] = Modulation {
+                    pitch_ratio: bend_ratio
+                        * (vibrato / 1200.0).exp2()
+                        * lfo_pitch
+                        * transpose_ratio,
+                    mod_wheel,
+                    vibrato_sine,
+                    pressure: self.pressure_smoothed,
+                    a4: self.smoothers.master_tune.next(master_tune),
+                    attack: self
+                        .params
+                        .attack_time(attack_seconds(self.smoothers.attack.next(attack))),
+                    filter: self.filter_coefficients(filter_open, smoothed_cutoff),
+                    filter_cutoff: cutoff_frequency(smoothed_cutoff),
+                    lfo_cutoff,
+                    pulse_width: self.pulse_width,
+                    // The noise is coloured for the host's rate, so it is drawn at that rate
+                    // and held while oversampling.
+                    noise: self.noise.next(noise_color),
+                };
+                end += 1;
+            }
+
+            // Everything up to and including the drive runs at the voices' rate.
+            let voice_samples = (end - start) * factor;
+            let mut mixed = [(0.0, 0.0); SUB_BLOCK * MAX_FACTOR];
+            let mut sounding = [0u32; SUB_BLOCK * MAX_FACTOR];
+            let context = RenderContext {
+                settings: &settings,
+                tuning: self.tuning.tuning(),
+                modulation: &modulation[..end - start],
+                oversampling: factor,
+                mpe: Some(&self.mpe).filter(|_| mpe),
+            };
+            let voices = self.voices.iter_mut().zip(self.strings.iter_mut());
+            for (voice, string) in voices.filter(|(v, _)| v.active) {
+                let mut out = [(0.0, 0.0); SUB_BLOCK * MAX_FACTOR];
+                let rendered = voice.render(&mut out[..voice_samples], &context, string);
+                let sum = mixed
+                    .iter_mut()
+                    .zip(sounding.iter_mut())
+                    .zip(&out[..rendered]);
+                for ((mixed, sounding), &(left, right)) in sum {
+                    mixed.0 += left;
+                    mixed.1 += right;
+                    *sounding += 1;
+                }
+            }
+            if mpe {
+                for _ in 0..voice_samples {
+                    self.mpe.ease(voice_smoothing);
+                }
+            }
+            // Notes waiting on a stolen voice start with the next stretch.
+            self.start_stolen_voices();
+
+            for (offset, lfo_gain) in lfo_gains[..end - start].iter().enumerate() {
+                let sample_idx = start + offset;
+                let (mut driven_left, mut driven_right) = ([0.0; MAX_FACTOR], [0.0; MAX_FACTOR]);
+                let rendered = offset * factor..(offset + 1) * factor;
+                let driven = driven_left.iter_mut().zip(driven_right.iter_mut());
+                for ((driven_left, driven_right), index) in driven.zip(rendered) {
+                    let (mut signal_left, signal_right) = mixed[index];
+                    // Drop the gain immediately when voices are added so the sum can never
+                    // exceed a single voice, but raise it slowly when they end to avoid a jump
+                    // in level. With nothing sounding there is no level to jump, so the next
+                    // note starts exactly as the first one did.
+                    let active = sounding[index];
+                    let target_gain = 1.0 / f64::from(active.max(1));
+                    if target_gain < self.voice_gain || active == 0 {
+                        self.voice_gain = target_gain;
+                    } else {
+                        self.voice_gain += (target_gain - self.voice_gain) * voice_smoothing;
+                    }
+
+                    if mono {
+                        // At zero width this is just the left channel.
+                        signal_left = 0.5 * (signal_left + signal_right);
+                    }
+                    let drive = |signal: f64| {
+                        let signal = signal * self.voice_gain * lfo_gain;
+                        // Drive works on the voices' level, so the master amplitude sets how
+                        // loud the saturated sound is rather than how hard it clips.
+                        match &saturator {
+                            Some(saturator) => saturator.process(signal),
+                            None => signal,
+                        }
+                    };
+                    *driven_left = drive(signal_left);
+                    *driven_right = drive(signal_right);
+                }
+                let signal_left = self.decimators[0].process(oversampling, &driven_left[..factor]);
+                let signal_right =
+                    self.decimators[1].process(oversampling, &driven_right[..factor]);
+                let (signal_left, signal_right) =
+                    self.crusher.process((signal_left, signal_right), &crush);
+                // Offset comes from the waveforms and the drive; the gains after this can't add
+                // any, and blocking it here keeps it from riding on the amplitude and tremolo.
+                let signal_left = self.dc_blockers[0].process(signal_left);
+                let signal_right = self.dc_blockers[1].process(signal_right);
+                let (signal_left, signal_right) = self.tilt.process((signal_left, signal_right));
+                let (signal_left, signal_right) = self.chorus.process(
+                    (signal_left, signal_right),
+                    chorus_rate,
+                    self.smoothers.chorus_depth.next(chorus_depth),
+                    self.smoothers.chorus_mix.next(chorus_mix),
+                );
+                // The tremolo swings between full level and `1 - depth`, so at zero depth it is
+                // exactly 1.0 and at full depth it touches silence without going negative.
+                let sine = 0.5 + 0.5 * (self.tremolo_phase * TAU).sin();
+                let tremolo = (1.0 - tremolo_depth) + tremolo_depth * sine;
+                self.tremolo_phase = (self.tremolo_phase + tremolo_rate * per_sample).fract();
+                let gain = self.smoothers.amplitude.next(amplitude) * tremolo;
+                let left = self.smoothers.pan_left.next(pan_left);
+                let right = self.smoothers.pan_right.next(pan_right);
+                let mut frame = (signal_left * gain * left, signal_right * gain * right);
+                delay_settings.mix = self.smoothers.delay_mix.next(delay_mix);
+                frame = self.delay.process(frame, &delay_settings);
+                frame = self
+                    .reverb
+                    .process(frame, self.smoothers.reverb_mix.next(reverb_mix));
+                if limiter {
+                    frame = self.limiter.process(frame.0, frame.1);
+                }
+                self.bypass_gain = if bypass {
+                    (self.bypass_gain - bypass_step).max(0.0)
+                } else {
+                    (self.bypass_gain + bypass_step).min(1.0)
+                };
+                frame = (frame.0 * self.bypass_gain, frame.1 * self.bypass_gain);
+                if let Some(out) = left_out.as_deref_mut() {
+                    out[sample_idx] = convert(frame.0);
+                }
+                if let Some(out) = right_out.as_deref_mut() {
+                    out[sample_idx] = convert(frame.1);
+                }
+            }
+            start = end;
+        }
+
+        // An empty block still consumes its events.
+        self.apply_due_events(usize::MAX);
+
+        // Notes carry on from the new values, which the smoothed parameters glide to rather
+        // than jump.
+        if let Some(program) = self.pending_program.take() {
+            self.params.switch_program(program);
+        }
+    }
+
+    /// Whether a block would render nothing but silence: no note is sounding or waiting to
+    /// start, no event is waiting, and nothing is left ringing in the output stages.
+    fn is_quiet(&self) -> bool {
+        self.events.is_empty()
+            && self
+                .voices
+                .iter()
+                .all(|v| !v.active && v.stolen_by.is_none())
+            && self.decimators.iter().all(Decimator::is_silent)
+            && self.crusher.is_silent()
+            && self.dc_blockers.iter().all(DcBlocker::is_settled)
+            && self.tilt.is_settled()
+            && self.chorus.is_silent()
+            && self.delay.is_silent()
+            && self.reverb.is_silent()
+            && self.limiter.is_settled()
+    }
+
+    /// Keep up with the notes while bypassed and faded out, without rendering anything.
+    ///
+    /// Every event is still taken in, so no NoteOff is missed, but nothing sounds: the voices
+    /// and the arpeggiator are stopped. Coming out of bypass starts from silence, and keys
+    /// held all along don't sound again until they're played again.
+    fn process_bypassed(&mut self) {
+        self.params.switch_requested_program();
+        self.tuning.update();
+        self.update_midi_channel();
+        self.apply_due_events(usize::MAX);
+        if let Some(sounding) = self.arp.stop() {
+            self.note_off(sounding.note);
+        }
+        for voice in self.voices.iter_mut() {
+            voice.active = false;
+            voice.held_by_pedal = false;
+            voice.stolen_by = None;
+        }
+        // What's left in the output stages from before the fade would leak out afterwards.
+        self.decimators = [Decimator::default(); 2];
+        self.crusher = Crusher::default();
+        self.dc_blockers = [DcBlocker::new(self.sample_rate); 2];
+        self.tilt.reset();
+        self.chorus.reset();
+        self.delay.reset();
+        self.reverb.reset();
+        self.limiter = Limiter::new(self.sample_rate);
+        if let Some(program) = self.pending_program.take() {
+            self.params.switch_program(program);
+        }
+    }
+
+    /// Take in a key going down, which the arpeggiator plays if it's on.
+    fn key_down(&mut self, channel: u8, note: u8, velocity: u8) {
+        if self.params.arp() {
+            self.arp.key_down(channel, note, velocity);
+        } else {
+            self.note_on(channel, note, velocity);
+        }
+    }
+
+    /// Take in a key being let go. A key pressed before the arpeggiator was switched on is
+    /// still sounding, and is released as usual.
+    fn key_up(&mut self, note: u8) {
+        let hold = self.params.arp_settings().hold;
+        if !(self.params.arp() && self.arp.key_up(note, hold)) {
+            self.note_off(note);
+        }
+    }
+
+    /// Start the chord for a key, or in mono mode the key alone on the one voice.
+    fn note_on(&mut self, channel: u8, key: u8, velocity: u8) {
+        self.legato = !self.held_notes.is_empty();
+        self.held_notes.push(key);
+        let velocity = curved_velocity(velocity, self.params.velocity_curve());
+        if self.params.mono() {
+            self.mono_note_on(channel, velocity);
+            return;
+        }
+        let chord = self.params.chord();
+        self.chords[usize::from(key)] = chord;
+        for note in chord.notes(key) {
+            self.start_note(channel, key, note, velocity);
+        }
+    }
+
+    /// Start a note on a free voice.
+    ///
+    /// Pressing a pitch that is already sounding retriggers the existing voice instead of
+    /// allocating a second one, so a pitch is never doubled; this includes notes that are only
+    /// still sounding because of the sustain pedal. When "Polyphony" voices are already busy
+    /// the "Voice Steal" policy picks one to take over.
+    ///
+    /// Lowering "Polyphony" leaves any extra voices to finish by themselves; it only stops new
+    /// ones starting.
+    fn start_note(&mut self, channel: u8, key: u8, note: u8, velocity: f64) {
+        let busy = self.voices.iter().filter(|v| v.active).count();
+        let index = self
+            .voices
+            .iter()
+            .position(|v| v.active && v.note == note && v.stolen_by.is_none())
+            .or_else(|| {
+                self.voices
+                    .iter()
+                    .position(|v| !v.active)
+                    .filter(|_| busy < self.params.polyphony())
+            });
+        match index {
+            Some(index) => self.start_voice(index, channel, key, note, velocity),
+            None => self.steal_voice(channel, key, note, velocity),
+        }
+    }
+
+    /// Fade out the voice the steal policy picks, to start the new note on once it is silent.
+    ///
+    /// Voices already being stolen are only taken again if every voice is, in which case the
+    /// note waiting for it is dropped. With a single voice, a policy of None still lets each new
+    /// note take over, as dropping every note while one sounds would leave the synth unplayable.
+    fn steal_voice(&mut self, channel: u8, key: u8, note: u8, velocity: f64) {
+        let policy = match self.params.voice_steal() {
+            StealPolicy::Never if self.params.polyphony() == 1 => StealPolicy::Oldest,
+            policy => policy,
+        };
+        let candidates = |stealing: bool| {
+            self.voices
+                .iter()
+                .enumerate()
+                .filter(move |(_, v)| v.stolen_by.is_some() == stealing)
+                .map(|(index, v)| Candidate {
+                    index,
+                    started: v.started,
+                    level: v.envelope.level(),
+                })
+        };
+        let victim = pick_victim(policy, candidates(false))
+            .or_else(|| pick_victim(policy, candidates(true)));
+        if let Some(index) = victim {
+            debug_log!(
+                self,
+                Record::Steal {
+                    voice: index as u8,
+                    stolen: self.voices[index].note,
+                    note,
+                }
+            );
+            let voice = &mut self.voices[index];
+            voice.kill();
+            voice.held_by_pedal = false;
+            voice.key = key;
+            voice.stolen_by = Some((channel, note, velocity));
+        }
+    }
+
+    /// Start the notes waiting on stolen voices that have finished fading out.
+    fn start_stolen_voices(&mut self) {
+        for index in 0..MAX_VOICES {
+            if self.voices[index].active {
+                continue;
+            }
+            if let Some((channel, note, velocity)) = self.voices[index].stolen_by {
+                let key = self.voices[index].key;
+                self.start_voice(index, channel, key, note, velocity);
+            }
+        }
+    }
+
+    /// Play the key the note priority picks on the mono voice.
+    ///
+    /// While an earlier key is still down the new pitch is played legato, without restarting
+    /// the envelopes.
+    fn mono_note_on(&mut self, channel: u8, velocity: f64) {
+        let note = match self.held_notes.pick(self.params.note_priority()) {
+            Some(note) => note,
+            None => return,
+        };
+        match self.legato_voice() {
+            Some(index) => self.legato_to(index, note),
+            // Reusing the same voice even once it is released keeps a single voice sounding.
+            None => {
+                let index = self.last_voice.unwrap_or(0);
+                self.start_voice(index, channel, note, note, velocity);
+            }
+        }
+    }
+
+    /// The mono voice, if it is playing a note that hasn't been let go.
+    fn legato_voice(&self) -> Option<usize> {
+        self.last_voice
+            .filter(|&i| self.voices[i].active && !self.voices[i].is_released())
+    }
+
+    /// Move a sounding voice to a new pitch, gliding if glide is on.
+    fn legato_to(&mut self, index: usize, note: u8) {
+        let glide_samples = self.glide_samples();
+        let voice = &mut self.voices[index];
+        if voice.note != note {
+            let from = voice.pitch();
+            voice.note = note;
+            voice.start_glide(Some(from), glide_samples);
+        }
+        voice.held_by_pedal = false;
+    }
+
+    /// How long a note starting now takes to slide from the last one, in samples. In legato
+    /// mode a key played after the others were let go jumps straight to its pitch.
+    fn glide_samples(&self) -> f64 {
+        if self.params.legato_glide() && !self.legato {
+            0.0
+        } else {
+            self.params.glide() * self.voice_rate()
+        }
+    }
+
+    /// Start `note`, part of the chord `key` plays, from the beginning of its envelopes on the
+    /// voice at `index`.
+    fn start_voice(&mut self, index: usize, channel: u8, key: u8, note: u8, velocity: f64) {
+        debug_log!(
+            self,
+            Record::NoteOn {
+                voice: index as u8,
+                channel,
+                note,
+            }
+        );
+        if Waveform::from_normalized(self.params.get(ParamId::Waveform)) == Waveform::Pluck {
+            // A repeated note plucks its own string again instead of starting a second one.
+            let freq = self
+                .tuning
+                .tuning()
+                .frequency(note, self.params.master_tune());
+            let period = self.voice_rate() / (freq * semitones_to_ratio(self.params.transpose()));
+            let brightness = self.params.excite_brightness();
+            self.strings[index].excite(period, brightness, &mut self.noise);
+        }
+        // A new note slides from wherever the latest note has got to, even mid-glide or once
+        // it has been let go, as long as it's still sounding. Otherwise it slides from the
+        // latest key still held, and with neither it has nothing to slide from.
+        let glide_from = self
+            .last_voice
+            .filter(|&i| self.voices[i].active)
+            .map(|i| self.voices[i].pitch())
+            .or_else(|| {
+                let held = self.held_notes.held().iter().rev();
+                held.copied().find(|&held| held != key).map(f64::from)
+            });
+        let glide_samples = self.glide_samples();
+        self.last_voice = Some(index);
+        // A synced LFO belongs to the song position, not to the notes.
+        if self.params.lfo_retrigger() && !self.params.lfo_sync() {
+            self.lfo.reset();
+        }
+        let phase_reset = self.params.phase_reset();
+        let new_note = !(self.voices[index].active && self.voices[index].note == note);
+        // Only drawn when it's used, so turning random phase on always starts the same
+        // sequence.
+        let start_phase = if new_note && self.params.random_phase() {
+            Some(self.random_start_phase())
+        } else {
+            None
+        };
+        let voice = &mut self.voices[index];
+        // A retriggered note carries on from where it is, since restarting a sounding
+        // oscillator would click.
+        if new_note {
+            if phase_reset || start_phase.is_some() {
+                voice.reset_phases();
+            }
+            if let Some(start) = start_phase {
+                voice.offset_phases(start);
+            }
+            voice.filter.reset();
+            voice.right_filter.reset();
+            voice.pressure_smoothed = 0.0;
+        }
+        // The key has only just gone down, so it isn't being pressed into yet.
+        voice.pressure = 0.0;
+        voice.level_track = None;
+        voice.channel = channel;
+        voice.key = key;
+        voice.note = note;
+        voice.start_glide(glide_from, glide_samples);
+        voice.velocity = velocity;
+        voice.started = self.notes_started;
+        voice.stolen_by = None;
+        voice.active = true;
+        voice.held_by_pedal = false;
+        voice.trigger();
+        self.notes_started += 1;
+    }
+
+    /// The next random start phase, restarting the sequence first if "Random Seed" has
+    /// changed.
+    fn random_start_phase(&mut self) -> f64 {
+        let seed = self.params.random_seed();
+        if seed != self.phase_random.1 {
+            self.reseed_phases();
+        }
+        self.phase_random.0.unit()
+    }
+
+    /// Restart the random start phases from the beginning of the sequence for the current
+    /// seed.
+    fn reseed_phases(&mut self) {
+        let seed = self.params.random_seed();
+        self.phase_random = (Noise::with_seed(seed), seed);
+    }
+
+    /// Take in poly aftertouch for `note`. Pressure on a key that isn't sounding has nothing
+    /// to modulate, so it's dropped.
+    fn poly_pressure(&mut self, note: u8, pressure: u8) {
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|v| v.active && v.stolen_by.is_none() && v.note == note)
+        {
+            voice.pressure = f64::from(pressure) / 127.0;
+        }
+    }
+
+    /// Release a note, or leave it to the sustain pedal if that is down.
+    ///
+    /// In mono mode the voice carries on with another held key if there is one.
+    fn note_off(&mut self, key: u8) {
+        debug_log!(
+            self,
+            Record::NoteOff {
+                note: key,
+                pedal: self.sustain_pedal,
+            }
+        );
+        self.held_notes.remove(key);
+        let chord = std::mem::take(&mut self.chords[usize::from(key)]);
+        // Nothing is being pressed once every key is up, and the next note shouldn't start
+        // with the last one's pressure.
+        if self.held_notes.is_empty() {
+            self.pressure = 0.0;
+        }
+        // A note still waiting for its stolen voice starts now, cutting the fade short, so it
+        // is heard and released like any other.
+        for index in 0..MAX_VOICES {
+            if let Some((channel, waiting, velocity)) = self.voices[index].stolen_by {
+                if self.voices[index].key == key && chord.plays(key, waiting) {
+                    self.start_voice(index, channel, key, waiting, velocity);
+                }
+            }
+        }
+        if self.params.mono() {
+            if let Some(index) = self.legato_voice() {
+                match self.held_notes.pick(self.params.note_priority()) {
+                    Some(held) => self.legato_to(index, held),
+                    None if self.sustain_pedal => self.voices[index].held_by_pedal = true,
+                    None => self.voices[index].release(),
+                }
+            }
+            return;
+        }
+        let sustain_pedal = self.sustain_pedal;
+        for voice in self
+            .voices
+            .iter_mut()
+            // A note another key's chord shares was taken over by that key, and is left to it.
+            .filter(|v| v.active && !v.is_released() && v.key == key && chord.plays(key, v.note))
+        {
+            if sustain_pedal {
+                voice.held_by_pedal = true;
+            } else {
+                voice.release();
+            }
+        }
+    }
+
+    /// Pick up changes to the channel filter, releasing notes from channels no longer listened to
+    /// so they can't get stuck without their NoteOff.
+    fn update_midi_channel(&mut self) {
+        let midi_channel = self.params.midi_channel().filter(|_| !self.params.mpe());
+        if midi_channel == self.midi_channel {
+            return;
+        }
+        self.midi_channel = midi_channel;
+        if let Some(wanted) = midi_channel {
+            // The held keys don't record their channel, so none are kept.
+            self.held_notes.clear();
+            for voice in self
+                .voices
+                .iter_mut()
+                .filter(|v| v.active && v.channel != wanted)
+            {
+                voice.held_by_pedal = false;
+                voice.release();
+            }
+            for voice in self.voices.iter_mut() {
+                if matches!(voice.stolen_by, Some((channel, _, _)) if channel != wanted) {
+                    voice.stolen_by = None;
+                }
+            }
+        }
+    }
+
+    /// Release every note, including those held by the sustain pedal.
+    ///
+    /// Notes still fade out over the release time.
+    fn all_notes_off(&mut self) {
+        self.sustain_pedal = false;
+        self.held_notes.clear();
+        // The arpeggiator forgets its keys too, held or latched, so it starts nothing new.
+        self.arp.stop();
+        for voice in self.voices.iter_mut().filter(|v| v.active) {
+            voice.held_by_pedal = false;
+            voice.stolen_by = None;
+            voice.release();
+        }
+    }
+
+    /// Silence every note almost immediately, ignoring the release time.
+    fn all_sound_off(&mut self) {
+        self.sustain_pedal = false;
+        self.held_notes.clear();
+        // No key is left to be pressing.
+        self.pressure = 0.0;
+        self.arp.stop();
+        for voice in self.voices.iter_mut().filter(|v| v.active) {
+            voice.held_by_pedal = false;
+            voice.stolen_by = None;
+            voice.kill();
+        }
+    }
+
+    fn set_sustain_pedal(&mut self, down: bool) {
+        self.sustain_pedal = down;
+        if !down {
+            // Keys that are still physically down keep sounding.
+            for voice in self.voices.iter_mut().filter(|v| v.held_by_pedal) {
+                voice.held_by_pedal = false;
+                voice.release();
+            }
+        }
+    }
+
+    /// Bind `controller` if MIDI learn is listening, or move the parameter it's bound to.
+    /// Returns whether MIDI learn took the controller, in which case it does nothing else.
+    fn learn_control(&mut self, controller: u8, value: u8) -> bool {
+        if !learnable(controller) {
+            return false;
+        }
+        if self.params.learn_mode() == LearnMode::Learn {
+            let target = self.params.learn_target();
+            self.params.learned.bind(controller, target);
+            let off = LearnMode::Off.to_normalized();
+            self.params.handle(ParamId::MidiLearn as usize).change(off);
+            return true;
+        }
+        let target = self.params.learned.target(controller);
+        match target {
+            Some(target) => {
+                self.params.handle(target).change(control_value(value));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn control_change(&mut self, controller: u8, value: u8) {
+        if let Some(rpn) = self.rpn.control_change(controller, value) {
+            if rpn.parameter == PITCH_BEND_SENSITIVITY {
+                let semitones = f32::from(rpn.msb) + f32::from(rpn.lsb) / 100.0;
+                self.params.set_bend_range(semitones);
+            }
+        }
+        match controller {
+            1 => self.mod_wheel = f64::from(value) / 127.0,
+            64 => self.set_sustain_pedal(value >= 64),
+            120 => self.all_sound_off(),
+            123 => self.all_notes_off(),
+            _ => (),
+        }
+    }
+
+    /// Convert the 14-bit pitch bend value into the wheel position.
+    fn set_pitch_bend(&mut self, value: u16) {
+        self.pitch_bend = bend_position(value);
+    }
+}
+
+pub const TAU: f64 = PI * 2.0;
+
+/// The output level in dB for a normalized amplitude value, or negative infinity (silence)
+/// at zero.
+///
+/// The knob is linear in dB either side of its centre, with the lower half covering the wide
+/// range down to `MIN_AMPLITUDE_DB` and the upper half the finer range around unity gain. The
+/// centre is about the half gain the parameter used to be a plain multiplier for, so projects
+/// saved before it was in dB keep their level there and are at most 6 dB out elsewhere.
+fn amplitude_db(value: f32) -> f64 {
+    let value = f64::from(value).clamp(0.0, 1.0);
+    if value == 0.0 {
+        f64::NEG_INFINITY
+    } else if value < 0.5 {
+        MIN_AMPLITUDE_DB + (CENTRE_AMPLITUDE_DB - MIN_AMPLITUDE_DB) * value * 2.0
+    } else {
+        CENTRE_AMPLITUDE_DB + (MAX_AMPLITUDE_DB - CENTRE_AMPLITUDE_DB) * (value - 0.5) * 2.0
+    }
+}
+
+/// The normalized amplitude value for a level in dB, the inverse of `amplitude_db`. Levels at
+/// or below `MIN_AMPLITUDE_DB` give the muted bottom of the knob.
+fn amplitude_to_normalized(db: f64) -> f32 {
+    let value = if db <= MIN_AMPLITUDE_DB {
+        0.0
+    } else if db < CENTRE_AMPLITUDE_DB {
+        (db - MIN_AMPLITUDE_DB) / (CENTRE_AMPLITUDE_DB - MIN_AMPLITUDE_DB) / 2.0
+    } else {
+        0.5 + (db - CENTRE_AMPLITUDE_DB) / (MAX_AMPLITUDE_DB - CENTRE_AMPLITUDE_DB) / 2.0
+    };
+    value.min(1.0) as f32
+}
+
+/// The linear gain for a level in dB, where negative infinity is silence.
+fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// The attack time in seconds for a normalized attack value.
+///
+/// Times rise exponentially from `MIN_ATTACK` to `MAX_ATTACK`, so most of the knob is spent
+/// on the short attacks that are used most. Zero is no attack at all, which the envelope
+/// handles by starting the decay straight away.
+fn attack_seconds(value: f64) -> f64 {
+    let value = value.clamp(0.0, 1.0);
+    if value == 0.0 {
+        0.0
+    } else {
+        MIN_ATTACK * (MAX_ATTACK / MIN_ATTACK).powf(value)
+    }
+}
+
+/// The normalized attack value for a time in seconds, the inverse of `attack_seconds`.
+fn attack_to_normalized(seconds: f64) -> f32 {
+    if seconds <= 0.0 {
+        0.0
+    } else {
+        ((seconds / MIN_ATTACK).ln() / (MAX_ATTACK / MIN_ATTACK).ln()).clamp(0.0, 1.0) as f32
+    }
+}
+
+/// Each parameter's name and default value in index order, which chunks are written and read
+/// against.
+fn chunk_layout() -> Vec<(&'static str, f32)> {
+    PARAMS
+        .iter()
+        .map(|param| (param.name, param.default))
+        .collect()
+}
+
+/// The left and right gains for a normalized pan value, by an equal-power law.
+///
+/// The two channels' powers always add up to the same, so a sound stays as loud wherever it's
+/// panned. That leaves each channel 3 dB down in the centre, where both play it, and a sound
+/// panned hard to one side at unity gain there and silent on the other.
+fn pan_gains(value: f32) -> (f64, f64) {
+    let angle = f64::from(value).clamp(0.0, 1.0) * FRAC_PI_2;
+    // Both as sines, which are exactly zero at the ends and equal in the centre.
+    ((FRAC_PI_2 - angle).sin(), angle.sin())
+}
+
+/// The cutoff in Hz for a normalized cutoff parameter value, on a logarithmic scale.
+fn cutoff_frequency(value: f64) -> f64 {
+    MIN_CUTOFF * (MAX_CUTOFF / MIN_CUTOFF).powf(value.clamp(0.0, 1.0))
+}
+
+impl Default for SineSynth {
+    fn default() -> SineSynth {
+        let (params, tuning) = GainEffectParameters::linked(HostCallback::default());
+        wavetable::prepare();
+        sine::prepare();
+        SineSynth {
+            sample_rate: 44100.0,
+            max_block_size: None,
+            #[cfg(test)]
+            lanes: true,
+            voices: [Voice::default(); MAX_VOICES],
+            voice_gain: 1.0,
+            bypass_gain: 1.0,
+            pitch_bend: 0.0,
+            mod_wheel: 0.0,
+            mod_wheel_smoothed: 0.0,
+            pressure: 0.0,
+            pressure_smoothed: 0.0,
+            vibrato_phase: 0.0,
+            tremolo_phase: 0.0,
+            last_voice: None,
+            legato: false,
+            held_notes: NoteStack::default(),
+            phase_random: (Noise::default(), 0),
+            notes_started: 0,
+            lfo: Lfo::default(),
+            lfo_slewed: 0.0,
+            host: HostCallback::default(),
+            pulse_width: 0.5,
+            smoothers: Smoothers::new(44100.0),
+            oversampling: Oversampling::Off,
+            decimators: [Decimator::default(); 2],
+            crusher: Crusher::default(),
+            dc_blockers: [DcBlocker::new(44100.0); 2],
+            tilt: Tilt::default(),
+            chorus: Chorus::new(44100.0),
+            delay: Delay::new(44100.0),
+            reverb: Reverb::new(44100.0),
+            limiter: Limiter::new(44100.0),
+            noise: Noise::default(),
+            strings: (0..MAX_VOICES).map(|_| PluckedString::default()).collect(),
+            filter: (Coefficients::new(MAX_CUTOFF, 0.0, 44100.0), [0.0; 3]),
+            sustain_pedal: false,
+            midi_channel: None,
+            pending_program: None,
+            rpn: RpnState::default(),
+            arp: Arpeggiator::default(),
+            arp_clock: 0.0,
+            chords: [Chord::default(); 128],
+            mpe: MpeChannels::default(),
+            events: EventQueue::default(),
+            params: Arc::new(params),
+            tuning,
+            #[cfg(feature = "debug-log")]
+            log: DebugLog::start(),
+        }
+    }
+}
+
+impl Plugin for SineSynth {
+    fn new(host: HostCallback) -> SineSynth {
+        let (params, tuning) = GainEffectParameters::linked(host);
+        SineSynth {
+            host,
+            params: Arc::new(params),
+            tuning,
+            ..SineSynth::default()
+        }
+    }
+
+    fn get_info(&self) -> Info {
+        Info {
+            name: "SobudoSynth".to_string(),
+            vendor: "d34dmeat".to_string(),
+            unique_id: 6667,
+            category: Category::Synth,
+            inputs: 2,
+            outputs: 2,
+            parameters: PARAM_COUNT as i32,
+            presets: PROGRAM_COUNT as i32,
+            preset_chunks: true,
+            // The host reads this when it loads the plugin. A change to the oversampling since
+            // is reported by `process` once it takes effect.
+            initial_delay: self.params.oversampling().latency() as i32,
+            f64_precision: true,
+            ..Info::default()
+        }
+    }
+
+    /// The release time in samples, rounded as the envelope rounds it, which is how long a
+    /// note carries on after its key is let go, plus however long the delay's echoes and then
+    /// the reverb last. With no release and neither effect there's no tail, which the host is
+    /// told as 0.
+    fn get_tail_size(&self) -> isize {
+        let release = (self.params.adsr().release.max(0.0) * self.sample_rate).round();
+        let echoes = (delay::tail(&self.delay_settings()) * self.sample_rate).round();
+        let reverb = if self.params.reverb_mix() > 0.0 {
+            (reverb::tail(self.params.reverb_size()) * self.sample_rate).round()
+        } else {
+            0.0
+        };
+        (release + echoes + reverb) as isize
+    }
+
+    #[allow(unused_variables)]
+    #[allow(clippy::single_match)]
+    fn process_events(&mut self, events: &Events) {
+        for event in events.events() {
+            match event {
+                Event::Midi(ev) => self.queue_midi_event(ev.delta_frames, ev.data),
+                Event::SysEx(ev) => self.process_sysex_event(ev.payload),
+                // More events can be handled here.
+                _ => (),
+            }
+        }
+    }
+
+    /// Hosts resume the plugin before playing or bouncing, so starting the random phases
+    /// over here makes every bounce of the same notes identical.
+    ///
+    /// Smoothed parameters jump straight to their settings, so a project that was just loaded
+    /// doesn't fade in from stale values.
+    ///
+    /// Nothing from before the host stopped carries on: notes, queued events and the
+    /// modulation phases all start over, but the parameters and programs are kept.
+    fn resume(&mut self) {
+        self.params.processing.store(true, Ordering::Release);
+        self.reseed_phases();
+        self.all_sound_off();
+        self.arp.stop();
+        self.events.clear();
+        self.voices = [Voice::default(); MAX_VOICES];
+        self.chords = [Chord::default(); 128];
+        self.last_voice = None;
+        self.legato = false;
+        self.voice_gain = 1.0;
+        self.bypass_gain = if self.params.bypass() { 0.0 } else { 1.0 };
+        self.mod_wheel_smoothed = self.mod_wheel;
+        self.pressure_smoothed = self.pressure;
+        self.vibrato_phase = 0.0;
+        self.tremolo_phase = 0.0;
+        self.lfo.reset();
+        self.lfo_slewed = 0.0;
+        self.arp_clock = 0.0;
+        self.pulse_width = self.params.pulse_width();
+        self.smoothers = Smoothers::new(self.sample_rate);
+        self.decimators = [Decimator::default(); 2];
+        self.crusher = Crusher::default();
+        self.dc_blockers = [DcBlocker::new(self.sample_rate); 2];
+        self.tilt.reset();
+        self.chorus.reset();
+        self.delay.reset();
+        self.reverb.reset();
+        self.limiter = Limiter::new(self.sample_rate);
+    }
+
+    /// Every buffer the render uses is either a fixed size or, like the lines of the chorus,
+    /// the delay and the reverb, sized by the sample rate when it's set, so there's nothing to
+    /// allocate here. A size of zero or less tells nothing, and leaves blocks of any length
+    /// allowed.
+    fn set_block_size(&mut self, size: i64) {
+        self.max_block_size = usize::try_from(size).ok().filter(|&size| size > 0);
+    }
+
+    /// The host may not call `process` again until it resumes, so every note is cut off now
+    /// rather than left hanging.
+    fn suspend(&mut self) {
+        self.all_sound_off();
+        self.arp.stop();
+        self.params.processing.store(false, Ordering::Release);
+        // Nothing is switching programs now but the host.
+        self.params.switch_requested_program();
+    }
+
+    fn set_sample_rate(&mut self, rate: f32) {
+        self.sample_rate = f64::from(rate);
+        self.smoothers = Smoothers::new(self.sample_rate);
+        self.dc_blockers = [DcBlocker::new(self.sample_rate); 2];
+        // The tilt works its coefficients out for the new rate in the next block.
+        self.tilt.reset();
+        self.chorus = Chorus::new(self.sample_rate);
+        self.delay = Delay::new(self.sample_rate);
+        self.reverb = Reverb::new(self.sample_rate);
+        self.limiter = Limiter::new(self.sample_rate);
+        // Filter memory from the old rate would play back wrongly at the new one.
+        for voice in self.voices.iter_mut() {
+            voice.filter.reset();
+            voice.right_filter.reset();
+        }
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
+        let samples = buffer.samples();
+        let (_, mut outputs) = buffer.split();
+        self.render(samples, &mut outputs, |sample| sample as f32);
+    }
+
+    fn process_f64(&mut self, buffer: &mut AudioBuffer<f64>) {
+        let samples = buffer.samples();
+        let (_, mut outputs) = buffer.split();
+        self.render(samples, &mut outputs, |sample| sample);
+    }
+
+    /// Every capability the host can name gets a definite answer, so it doesn't have to
+    /// guess. Only names this version of the API doesn't know are left as `Maybe`.
+    fn can_do(&self, can_do: CanDo) -> Supported {
+        let supported = match can_do {
+            CanDo::ReceiveEvents | CanDo::ReceiveMidiEvent => true,
+            CanDo::ReceiveSysExEvent | CanDo::MidiSingleNoteTuningChange => SYSEX_TUNING,
+            CanDo::ReceiveTimeInfo => HOST_TIME,
+            CanDo::SendEvents | CanDo::SendMidiEvent => SENDS_MIDI,
+            CanDo::Bypass => SOFT_BYPASS,
+            // Bouncing needs nothing special, so there's no offline interface. There are no
+            // MIDI program names and no per-key controllers either.
+            CanDo::Offline | CanDo::MidiProgramNames | CanDo::MidiKeyBasedInstrumentControl => {
+                false
+            }
+            CanDo::Other(_) => return Supported::Maybe,
+        };
+        if supported {
+            Supported::Yes
+        } else {
+            Supported::No
+        }
+    }
+    // Return the parameter object. This method can be omitted if the
+    // plugin has no parameters.
+    fn get_parameter_object(&mut self) -> Arc<dyn PluginParameters> {
+        Arc::clone(&self.params) as Arc<dyn PluginParameters>
+    }
+
+    /// The editor, where there's a window system to show it in. Elsewhere the host is told
+    /// there is none, rather than offered one that never opens.
+    #[cfg(all(feature = "editor", target_os = "linux"))]
+    fn get_editor(&mut self) -> Option<Box<dyn vst::editor::Editor>> {
+        Some(Box::new(editor::ParamEditor::new(Arc::clone(&self.params))))
+    }
+}
+
+impl PluginParameters for GainEffectParameters {
+    // the `get_parameter` function reads the value of a parameter.
+    fn get_parameter(&self, index: i32) -> f32 {
+        self.param(index).map_or(0.0, AtomicFloat::get)
+    }
+
+    // the `set_parameter` function sets the value of a parameter.
+    fn set_parameter(&self, index: i32, val: f32) {
+        from_host(|| self.store(index, val));
+    }
+
+    // This is what will display underneath our control.  We can
+    // format it into a string that makes the most since.
+    fn get_parameter_text(&self, index: i32) -> String {
+        self.split_unit(index).0
+    }
+
+    /// Set a parameter from text typed into the host. Text that doesn't make sense for the
+    /// parameter leaves it as it was.
+    fn string_to_parameter(&self, index: i32, text: String) -> bool {
+        match self.parse_parameter(index, &text) {
+            Some(value) => {
+                self.set_parameter(index, value.clamp(0.0, 1.0) as f32);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// While the audio thread is running, it makes the switch itself at the start of its next
+    /// block, so the host never changes the values in the middle of one and never has to wait
+    /// for one to end.
+    fn change_preset(&self, preset: i32) {
+        let preset = preset as usize;
+        if preset >= PROGRAM_COUNT {
+            return;
+        }
+        if self.processing.load(Ordering::Acquire) {
+            self.requested_program.store(preset, Ordering::Release);
+        } else {
+            from_host(|| self.switch_program(preset));
+        }
+    }
+
+    /// A program the host selected is reported as soon as it's asked for.
+    fn get_preset_num(&self) -> i32 {
+        match self.requested_program.load(Ordering::Acquire) {
+            NO_PROGRAM => self.programs.current() as i32,
+            requested => requested as i32,
+        }
+    }
+
+    fn set_preset_name(&self, name: String) {
+        self.programs.set_name(self.programs.current(), name);
+    }
+
+    fn get_preset_name(&self, preset: i32) -> String {
+        self.programs.name(preset as usize).unwrap_or_default()
+    }
+
+    fn get_preset_data(&self) -> Vec<u8> {
+        let (mut programs, current) = self.saved_programs();
+        let program = programs.swap_remove(current);
+        chunk::encode_program(&program, &chunk_layout(), &self.extras())
+    }
+
+    fn get_bank_data(&self) -> Vec<u8> {
+        let (programs, current) = self.saved_programs();
+        chunk::encode_bank(&programs, current, &chunk_layout(), &self.extras())
+    }
+
+    /// Load a program chunk into the selected program, along with its tuning and MIDI learn
+    /// bindings. A chunk that can't be read changes nothing.
+    fn load_preset_data(&self, data: &[u8]) {
+        if let Some((program, extras)) = chunk::decode_program(data, &chunk_layout()) {
+            self.load_extras(extras);
+            self.load_values(&program.values);
+            self.programs.set(self.programs.current(), program);
+        }
+    }
+
+    /// Load a bank chunk, replacing every program. A chunk that can't be read changes nothing.
+    fn load_bank_data(&self, data: &[u8]) {
+        if let Some((loaded, current, extras)) = chunk::decode_bank(data, &chunk_layout()) {
+            self.load_extras(extras);
+            // The bank's own selection wins over one still waiting to be made.
+            self.requested_program.store(NO_PROGRAM, Ordering::Release);
+            self.load_values(&loaded[current].values);
+            self.programs.replace(loaded, current);
+        }
+    }
+
+    fn can_be_automated(&self, index: i32) -> bool {
+        PARAMS
+            .get(index as usize)
+            .is_some_and(|param| param.automatable)
+    }
+
+    fn get_parameter_label(&self, index: i32) -> String {
+        self.split_unit(index).1.to_string()
+    }
+
+    // This shows the control's name.
+    fn get_parameter_name(&self, index: i32) -> String {
+        // Negative indices wrap to huge ones, which are out of range too.
+        PARAMS
+            .get(index as usize)
+            .map_or("", |param| param.name)
+            .to_string()
+    }
+}
+
+plugin_main!(SineSynth);
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        cents_between, magnitude_at, measure_frequency, octave_power, rms, rms_envelope,
+    };
+    use crate::chord::ChordType;
+    use crate::dc_blocker::DcBlocker;
+    use crate::filter::FilterMode;
+    use crate::layer::OctaveLayer;
+    use crate::lfo::{LfoDestination, LfoShape};
+    use crate::note_stack::NotePriority;
+    use crate::oscillator::Waveform;
+    use crate::sine::{Lookup, Osc};
+    use crate::tuning::Tuning;
+    use crate::voice::Voice;
+    use crate::voice_steal::StealPolicy;
+    use crate::{
+        amplitude_db, amplitude_to_normalized, attack_seconds, attack_to_normalized,
+        curved_velocity, db_to_gain, midi_pitch_to_freq, pan_gains, pitch_to_freq, ParamValues,
+        SineSynth, A4_FREQ, MAX_AMPLITUDE_DB, MAX_ATTACK, MAX_LEVEL_KEY_TRACK, MAX_VOICES,
+        MIN_AMPLITUDE_DB, MIN_ATTACK, VEL_CURVE_STEPS,
+    };
+    use std::f64::consts::FRAC_1_SQRT_2;
+    use std::sync::Arc;
+    use vst::api::Supported;
+    use vst::host::HostBuffer;
+    use vst::plugin::{CanDo, Plugin, PluginParameters};
+
+    /// Render `samples` samples of the first output channel.
+    fn render(synth: &mut SineSynth, samples: usize) -> Vec<f32> {
+        render_outputs(synth, samples, 2).swap_remove(0)
+    }
+
+    /// Render `samples` samples into each of `channels` outputs.
+    fn render_outputs(synth: &mut SineSynth, samples: usize, channels: usize) -> Vec<Vec<f32>> {
+        let mut host_buffer: HostBuffer<f32> = HostBuffer::new(0, channels);
+        let inputs: [Vec<f32>; 0] = [];
+        let mut outputs = vec![vec![0.0; samples]; channels];
+        synth.process(&mut host_buffer.bind(&inputs, &mut outputs));
+        outputs
+    }
+
+    /// A synth with no attack ramp so renders reach full level immediately.
+    fn instant_synth() -> SineSynth {
+        let synth = SineSynth::default();
+        synth.params.set_parameter(1, 0.0);
+        synth
+    }
+
+    #[test]
+    fn test_midi_pitch_to_freq() {
+        for i in 0..=u8::MAX {
+            // expect no panics
+            assert!(midi_pitch_to_freq(i, A4_FREQ).is_finite());
+        }
+        for &a4 in &[415.0, 432.0, 440.0, 442.0, 444.0] {
+            assert_eq!(midi_pitch_to_freq(69, a4), a4);
+            for note in 0..=115 {
+                let octave = midi_pitch_to_freq(note + 12, a4) / midi_pitch_to_freq(note, a4);
+                assert_eq!(octave, 2.0, "{} at A4 = {}", note, a4);
+            }
+        }
+    }
+
+    #[test]
+    fn semitones_step_evenly_and_upwards() {
+        let semitone = (1.0f64 / 12.0).exp2();
+        for note in 0..127 {
+            let ratio = midi_pitch_to_freq(note + 1, A4_FREQ) / midi_pitch_to_freq(note, A4_FREQ);
+            assert!((ratio - semitone).abs() < 1e-9, "{}", note);
+        }
+        // Fractional pitches climb too, across every octave boundary.
+        let pitches = (0..=12700).map(|cents| f64::from(cents) / 100.0);
+        let frequencies: Vec<f64> = pitches.map(|p| pitch_to_freq(p, A4_FREQ)).collect();
+        assert!(frequencies.windows(2).all(|pair| pair[1] > pair[0]));
+        assert_eq!(
+            pitch_to_freq(60.0, A4_FREQ),
+            midi_pitch_to_freq(60, A4_FREQ)
+        );
+    }
+
+    #[test]
+    fn lowest_and_highest_notes_match_the_documented_frequencies() {
+        assert!((midi_pitch_to_freq(0, A4_FREQ) - 8.175_798_915_643_707).abs() < 1e-9);
+        assert!((midi_pitch_to_freq(127, A4_FREQ) - 12_543.853_951_415_975).abs() < 1e-9);
+        assert_eq!(midi_pitch_to_freq(69, A4_FREQ), 440.0);
+        assert_eq!(midi_pitch_to_freq(57, A4_FREQ), 220.0);
+        assert_eq!(
+            pitch_to_freq(-3.0, A4_FREQ),
+            midi_pitch_to_freq(9, A4_FREQ) / 2.0
+        );
+    }
+
+    #[test]
+    fn master_tune_sets_a4() {
+        let mut synth = instant_synth();
+        assert_eq!(synth.params.display_text(69), "440.0 Hz");
+        let value = synth.params.parse_parameter(69, "432 Hz").unwrap();
+        synth.params.set_parameter(69, value as f32);
+        assert_eq!(synth.params.display_text(69), "432.0 Hz");
+        synth.process_midi_event([144, 69, 127]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 44100);
+        assert!(cents_between(measure_frequency(&out, 44100.0), 432.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn master_tune_glides_sounding_notes() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 69, 127]);
+        render(&mut synth, 4410);
+        synth.params.set_parameter(69, 1.0);
+        let mut previous = synth.voices[0].phase;
+        let mut increments = Vec::new();
+        for _ in 0..2205 {
+            render(&mut synth, 1);
+            let phase = synth.voices[0].phase;
+            increments.push((phase - previous).rem_euclid(1.0) * 44100.0);
+            previous = phase;
+        }
+        // From 440 Hz up to 480 Hz over a few ms, without a jump.
+        assert!((increments[0] - 440.0).abs() < 1.0);
+        assert!((increments[2204] - 480.0).abs() < 0.01);
+        assert!(increments
+            .windows(2)
+            .all(|pair| (pair[1] - pair[0]).abs() < 2.0));
+    }
+
+    /// A SysEx message carrying a 24-step equal-tempered scale file.
+    fn quarter_tone_sysex() -> Vec<u8> {
+        let mut scale = String::from("! quarter.scl\n24 tones per octave\n 24\n!\n");
+        for step in 1..24 {
+            scale.push_str(&format!(" {:.1}\n", f64::from(step) * 50.0));
+        }
+        scale.push_str(" 2/1\n");
+        let mut message = vec![0xF0, 0x7D, b'S', b'C', b'L'];
+        message.extend_from_slice(scale.as_bytes());
+        message.push(0xF7);
+        message
+    }
+
+    #[test]
+    fn quarter_tone_scale_maps_note_61_a_quarter_tone_up() {
+        let mut synth = instant_synth();
+        synth.process_sysex_event(&quarter_tone_sysex());
+        synth.process_midi_event([144, 61, 127]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 44100);
+        let expected = midi_pitch_to_freq(60, A4_FREQ) * (50.0f64 / 1200.0).exp2();
+        assert!((expected - 269.29).abs() < 0.01);
+        assert!(cents_between(measure_frequency(&out, 44100.0), expected).abs() < 0.1);
+
+        // Master Tune still shifts the whole table.
+        synth.params.set_parameter(69, 1.0);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 44100);
+        let expected = expected * 480.0 / A4_FREQ;
+        assert!(cents_between(measure_frequency(&out, 44100.0), expected).abs() < 0.1);
+    }
+
+    /// An MTS single-note tuning change sending each note to a pitch in fractional MIDI notes.
+    fn mts_note_change(changes: &[(u8, f64)]) -> Vec<u8> {
+        let mut message = vec![0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, changes.len() as u8];
+        for &(note, pitch) in changes {
+            let fraction = (pitch.fract() * 16384.0).round() as u16;
+            message.extend_from_slice(&[
+                note,
+                pitch as u8,
+                (fraction >> 7) as u8,
+                (fraction & 0x7F) as u8,
+            ]);
+        }
+        message.push(0xF7);
+        message
+    }
+
+    /// The frequency of a fractional MIDI note in equal temperament.
+    fn fractional_note_frequency(pitch: f64) -> f64 {
+        pitch_to_freq(pitch, A4_FREQ)
+    }
+
+    #[test]
+    fn mts_note_changes_tune_the_notes_they_name() {
+        let retuned = [(60, 60.3), (64, 63.86), (67, 67.02)];
+        for &(note, pitch) in &retuned {
+            let mut synth = instant_synth();
+            synth.process_sysex_event(&mts_note_change(&retuned));
+            synth.process_midi_event([144, note, 127]);
+            render(&mut synth, 4410);
+            let out = render(&mut synth, 44100);
+            let expected = fractional_note_frequency(pitch);
+            let measured = measure_frequency(&out, 44100.0);
+            assert!(
+                cents_between(measured, expected).abs() < 0.1,
+                "{}: {}",
+                note,
+                measured
+            );
+        }
+    }
+
+    #[test]
+    fn mts_note_change_glides_a_sounding_note() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 69, 127]);
+        render(&mut synth, 4410);
+        synth.process_sysex_event(&mts_note_change(&[(69, 69.5)]));
+        let mut previous = synth.voices[0].phase;
+        let mut increments = Vec::new();
+        for _ in 0..2205 {
+            render(&mut synth, 1);
+            let phase = synth.voices[0].phase;
+            increments.push((phase - previous).rem_euclid(1.0) * 44100.0);
+            previous = phase;
+        }
+        let expected = fractional_note_frequency(69.5);
+        assert!((increments[0] - 440.0).abs() < 1.0);
+        assert!((increments[2204] - expected).abs() < 0.01);
+        assert!(increments
+            .windows(2)
+            .all(|pair| (pair[1] - pair[0]).abs() < 1.0));
+    }
+
+    #[test]
+    fn mts_receive_off_ignores_tuning_messages() {
+        let mut synth = instant_synth();
+        assert_eq!(synth.params.display_text(73), "On");
+        synth.params.set_parameter(73, 0.0);
+        synth.process_sysex_event(&mts_note_change(&[(69, 70.0)]));
+        synth.process_midi_event([144, 69, 127]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 44100);
+        assert!(cents_between(measure_frequency(&out, 44100.0), 440.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn tuning_is_saved_and_unreadable_scales_are_ignored() {
+        /// The table the synth plays once it has taken up whatever was loaded.
+        fn playing(synth: &mut SineSynth) -> Tuning {
+            synth.tuning.update();
+            *synth.tuning.tuning()
+        }
+
+        let mut synth = instant_synth();
+        synth.params.load_tuning(Some("Broken\n"));
+        assert_eq!(playing(&mut synth), Tuning::default());
+        assert_eq!(synth.params.tuning().source(), None);
+
+        let mut tuned = instant_synth();
+        tuned.process_sysex_event(&quarter_tone_sysex());
+        let preset = tuned.params.get_preset_data();
+        let bank = tuned.params.get_bank_data();
+        synth.params.load_preset_data(&preset);
+        assert_eq!(playing(&mut synth), playing(&mut tuned));
+        // A chunk saved without a scale goes back to equal temperament.
+        synth
+            .params
+            .load_bank_data(&instant_synth().params.get_bank_data());
+        assert_eq!(playing(&mut synth), Tuning::default());
+        synth.params.load_bank_data(&bank);
+        assert_eq!(playing(&mut synth), playing(&mut tuned));
+        assert_eq!(
+            synth.params.tuning().source(),
+            tuned.params.tuning().source()
+        );
+    }
+
+    #[test]
+    fn overlapping_notes_both_sound() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 67, 100]);
+        let out = render(&mut synth, 8192);
+
+        let c4 = magnitude_at(&out, midi_pitch_to_freq(60, A4_FREQ), 44100.0);
+        let g4 = magnitude_at(&out, midi_pitch_to_freq(67, A4_FREQ), 44100.0);
+        let off = magnitude_at(&out, midi_pitch_to_freq(64, A4_FREQ), 44100.0);
+        assert!(c4 > 0.1, "C4 missing: {}", c4);
+        assert!(g4 > 0.1, "G4 missing: {}", g4);
+        assert!(off < 0.01, "unexpected energy: {}", off);
+        assert!(out.iter().all(|s| s.abs() <= 1.0));
+    }
+
+    #[test]
+    fn note_off_only_releases_matching_pitch() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 67, 100]);
+        render(&mut synth, 512);
+        synth.process_midi_event([128, 60, 0]);
+        let out = render(&mut synth, 8192);
+
+        assert!(magnitude_at(&out, midi_pitch_to_freq(60, A4_FREQ), 44100.0) < 0.01);
+        assert!(magnitude_at(&out, midi_pitch_to_freq(67, A4_FREQ), 44100.0) > 0.1);
+    }
+
+    #[test]
+    fn repeated_note_on_retriggers_voice() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 60, 100]);
+        assert_eq!(synth.voices.iter().filter(|v| v.active).count(), 1);
+    }
+
+    #[test]
+    fn velocity_scales_level() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 32]);
+        let soft = render(&mut synth, 4410);
+        synth.process_midi_event([128, 60, 0]);
+        render(&mut synth, 4410);
+        synth.process_midi_event([144, 60, 127]);
+        let loud = render(&mut synth, 4410);
+
+        let ratio = rms(&soft) / rms(&loud);
+        assert!((ratio - 32.0 / 127.0).abs() < 0.01, "ratio {}", ratio);
+    }
+
+    #[test]
+    fn zero_sensitivity_ignores_velocity() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(5, 0.0);
+        synth.process_midi_event([144, 60, 1]);
+        let out = render(&mut synth, 4410);
+        assert!((rms(&out) - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn velocity_curves_keep_their_endpoints() {
+        for steps in -VEL_CURVE_STEPS..=VEL_CURVE_STEPS {
+            assert_eq!(curved_velocity(1, steps), 1.0 / 127.0);
+            assert_eq!(curved_velocity(127, steps), 1.0);
+        }
+        for velocity in 1..=127 {
+            assert_eq!(curved_velocity(velocity, 0), f64::from(velocity) / 127.0);
+        }
+        assert!(curved_velocity(64, VEL_CURVE_STEPS) < 0.1);
+        assert!(curved_velocity(64, -VEL_CURVE_STEPS) > 0.8);
+    }
+
+    #[test]
+    fn velocity_curve_is_set_at_note_on() {
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(77), "Linear");
+        synth.params.set_parameter(77, 0.0);
+        assert_eq!(synth.params.display_text(77), "Soft 4");
+        for text in &["Soft 2", "Hard 1", "Linear"] {
+            let value = synth.params.parse_parameter(77, text).unwrap();
+            synth.params.set_parameter(77, value as f32);
+            assert_eq!(synth.params.display_text(77), *text);
+        }
+
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 64]);
+        synth.params.set_parameter(77, 1.0);
+        synth.process_midi_event([144, 67, 64]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 16384);
+        let level = |note: u8| magnitude_at(&out, midi_pitch_to_freq(note, A4_FREQ), 44100.0);
+        // The curve doesn't reach notes that have already started.
+        let ratio = level(67) / level(60);
+        let expected = curved_velocity(64, VEL_CURVE_STEPS) / (64.0 / 127.0);
+        assert!(
+            (ratio / expected - 1.0).abs() < 0.01,
+            "{} {}",
+            ratio,
+            expected
+        );
+    }
+
+    #[test]
+    fn zero_velocity_note_on_releases() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 60, 0]);
+        render(&mut synth, 4410);
+        assert!(synth.voices.iter().all(|v| !v.active));
+    }
+
+    #[test]
+    fn full_bend_up_reaches_whole_tone() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 69, 127]);
+        synth.process_midi_event([224, 127, 127]);
+        let out = render(&mut synth, 44100);
+
+        let cents = cents_between(
+            measure_frequency(&out, 44100.0),
+            midi_pitch_to_freq(71, A4_FREQ),
+        );
+        assert!(cents.abs() < 1.0, "off by {} cents", cents);
+    }
+
+    #[test]
+    fn bend_applies_to_sounding_note_and_persists() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 69, 127]);
+        render(&mut synth, 1024);
+        synth.process_midi_event([224, 0, 0]);
+        let bent = render(&mut synth, 44100);
+        let cents = cents_between(
+            measure_frequency(&bent, 44100.0),
+            midi_pitch_to_freq(67, A4_FREQ),
+        );
+        assert!(cents.abs() < 1.0, "off by {} cents", cents);
+
+        synth.process_midi_event([144, 72, 127]);
+        assert_eq!(synth.pitch_bend, -1.0);
+        synth.process_midi_event([224, 0, 64]);
+        assert_eq!(synth.pitch_bend, 0.0);
+    }
+
+    #[test]
+    fn bend_range_parameter_is_whole_semitones() {
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.bend_range(), 2.0);
+        synth.params.set_parameter(6, 0.0);
+        assert_eq!(synth.params.bend_range(), 1.0);
+        synth.params.set_parameter(6, 1.0);
+        assert_eq!(synth.params.bend_range(), 48.0);
+    }
+
+    /// The pitch of note 69 held with the wheel fully up, in semitones above it.
+    fn full_bend_semitones(synth: &mut SineSynth) -> f64 {
+        synth.process_midi_event([144, 69, 127]);
+        synth.process_midi_event([224, 127, 127]);
+        render(synth, 4410);
+        let out = render(synth, 44100);
+        synth.process_midi_event([128, 69, 0]);
+        synth.process_midi_event([224, 0, 64]);
+        cents_between(measure_frequency(&out, 44100.0), 440.0) / 100.0
+    }
+
+    #[test]
+    fn bend_range_parameter_scales_the_bend() {
+        for &semitones in &[1, 12, 48] {
+            let mut synth = instant_synth();
+            let value = synth
+                .params
+                .parse_parameter(6, &semitones.to_string())
+                .unwrap();
+            synth.params.set_parameter(6, value as f32);
+            assert_eq!(synth.params.display_text(6), format!("±{} st", semitones));
+            let bent = full_bend_semitones(&mut synth);
+            assert!((bent - f64::from(semitones)).abs() < 0.01, "{}", bent);
+        }
+    }
+
+    #[test]
+    fn rpn_bend_sensitivity_overrides_the_parameter() {
+        let mut synth = instant_synth();
+        // ±12 st, then RPN 0,0 with other controllers mixed in, and 24 semitones of Data
+        // Entry.
+        synth.params.set_parameter(6, 11.0 / 47.0);
+        for &(controller, value) in &[(101, 0), (1, 40), (100, 0), (64, 0), (6, 24), (38, 0)] {
+            synth.process_midi_event([0xB0, controller, value]);
+        }
+        assert_eq!(synth.params.bend_range(), 24.0);
+        assert_eq!(synth.params.display_text(6), "±24 st");
+        assert!((full_bend_semitones(&mut synth) - 24.0).abs() < 0.01);
+
+        // The parameter wins back when it's set after, and the RPN again after that.
+        synth.params.set_parameter(6, 4.0 / 47.0);
+        assert!((full_bend_semitones(&mut synth) - 5.0).abs() < 0.01);
+        synth.process_midi_event([0xB0, 6, 3]);
+        assert!((full_bend_semitones(&mut synth) - 3.0).abs() < 0.01);
+
+        // Ranges are held to whole semitones the parameter can reach.
+        for &(msb, lsb, expected) in &[(2, 60, 3.0), (0, 0, 1.0), (127, 0, 48.0)] {
+            synth.process_midi_event([0xB0, 6, msb]);
+            synth.process_midi_event([0xB0, 38, lsb]);
+            assert_eq!(synth.params.bend_range(), expected);
+        }
+    }
+
+    /// A synth in MPE mode holding C4 on channel 2 and G4 on channel 3.
+    fn mpe_synth() -> SineSynth {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(74, 1.0);
+        synth.process_midi_event([0x91, 60, 100]);
+        synth.process_midi_event([0x92, 67, 100]);
+        render(&mut synth, 4410);
+        synth
+    }
+
+    /// Whether each of `notes` is sounding in `out`.
+    fn notes_sounding(out: &[f32], notes: &[u8]) -> Vec<bool> {
+        notes
+            .iter()
+            .map(|&note| magnitude_at(out, midi_pitch_to_freq(note, A4_FREQ), 44100.0) > 0.05)
+            .collect()
+    }
+
+    #[test]
+    fn mpe_notes_bend_independently() {
+        let mut synth = mpe_synth();
+        let notes = [48, 60, 61, 67];
+        // A quarter of the way down is 12 of the note channels' 48 semitones.
+        synth.process_midi_event([0xE1, 0, 48]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 16384);
+        assert_eq!(notes_sounding(&out, &notes), [true, false, false, true]);
+
+        // An eighth of the way down on the other channel takes G4 down 6 semitones to C#4.
+        synth.process_midi_event([0xE2, 0, 56]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 16384);
+        assert_eq!(notes_sounding(&out, &notes), [true, false, true, false]);
+
+        // The master channel bends both, by "Bend Range".
+        synth.process_midi_event([0xE0, 0, 0]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 16384);
+        assert_eq!(
+            notes_sounding(&out, &[46, 59, 48, 61]),
+            [true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn without_mpe_every_channel_bends_every_note() {
+        let mut synth = mpe_synth();
+        synth.params.set_parameter(74, 0.0);
+        synth.process_midi_event([0xE1, 0, 0]);
+        synth.process_midi_event([0xD1, 127, 0]);
+        synth.process_midi_event([0xB1, 74, 0]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 16384);
+        assert_eq!(
+            notes_sounding(&out, &[58, 65, 60, 67]),
+            [true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn mpe_pressure_brings_in_vibrato_for_its_note() {
+        let sidebands = |pressure: u8| {
+            let mut synth = mpe_synth();
+            synth.params.set_parameter(7, 1.0); // 10 Hz
+            synth.process_midi_event([0xD1, pressure, 0]);
+            render(&mut synth, 4410);
+            let out = render(&mut synth, 16384);
+            let c4 = midi_pitch_to_freq(60, A4_FREQ);
+            let g4 = midi_pitch_to_freq(67, A4_FREQ);
+            (
+                magnitude_at(&out, c4 + 10.0, 44100.0),
+                magnitude_at(&out, g4 + 10.0, 44100.0),
+            )
+        };
+        let (still_c4, still_g4) = sidebands(0);
+        let (pressed_c4, pressed_g4) = sidebands(127);
+        assert!(still_c4 < 0.005 && still_g4 < 0.005);
+        assert!(pressed_c4 > 0.035, "{}", pressed_c4);
+        assert!(pressed_g4 < 0.005, "{}", pressed_g4);
+    }
+
+    #[test]
+    fn mpe_slide_moves_its_notes_cutoff() {
+        let harmonics = |slide: u8| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(74, 1.0);
+            synth
+                .params
+                .set_parameter(10, Waveform::Saw.to_normalized());
+            synth.params.set_parameter(38, 0.4);
+            synth.process_midi_event([0xB1, 74, slide]);
+            synth.process_midi_event([0x91, 48, 100]);
+            synth.process_midi_event([0x92, 55, 100]);
+            render(&mut synth, 4410);
+            let out = render(&mut synth, 16384);
+            // The fifth harmonics, which are clear of each other's notes.
+            let level =
+                |note: u8| magnitude_at(&out, 5.0 * midi_pitch_to_freq(note, A4_FREQ), 44100.0);
+            (level(48), level(55))
+        };
+        let (centred_c3, centred_g3) = harmonics(64);
+        let (bright_c3, bright_g3) = harmonics(127);
+        let (dark_c3, _) = harmonics(0);
+        assert!(bright_c3 > 2.0 * centred_c3, "{} {}", bright_c3, centred_c3);
+        assert!(dark_c3 < 0.5 * centred_c3, "{} {}", dark_c3, centred_c3);
+        assert!((bright_g3 / centred_g3 - 1.0).abs() < 0.01);
+    }
+
+    /// A synth holding A4 with channel pressure going to volume at full amount.
+    fn pressure_volume_synth() -> SineSynth {
+        let mut synth = instant_synth();
+        assert_eq!(synth.params.display_text(75), "None");
+        let volume = synth.params.parse_parameter(75, "volume").unwrap();
+        synth.params.set_parameter(75, volume as f32);
+        synth.params.set_parameter(76, 1.0);
+        synth.process_midi_event([144, 69, 127]);
+        synth
+    }
+
+    #[test]
+    fn pressure_ramp_swells_the_volume_smoothly() {
+        let mut synth = pressure_volume_synth();
+        render(&mut synth, 4410);
+        assert!(rms(&render(&mut synth, 4410)) < 1e-6);
+        // Coarse steps every 10 ms, as a keyboard might send them.
+        let mut out = Vec::new();
+        for step in 0..16u8 {
+            synth.process_midi_event([0xD0, step * 8 + 7, 0]);
+            out.extend(render(&mut synth, 441));
+        }
+        out.extend(render(&mut synth, 8820));
+        // The windows hold a cycle and a half, so a steady tone ripples slightly between
+        // them. An unsmoothed step would jump about 0.02.
+        let envelope = rms_envelope(&out, 147);
+        for pair in envelope.windows(2) {
+            assert!(pair[1] > pair[0] - 2e-3, "{:?}", pair);
+            assert!(pair[1] - pair[0] < 0.01, "{:?}", pair);
+        }
+        // Full pressure is the level there would be without it.
+        let mut unpressed = instant_synth();
+        unpressed.process_midi_event([144, 69, 127]);
+        render(&mut unpressed, 4410);
+        let full = rms(&render(&mut synth, 4410));
+        assert!((full / rms(&render(&mut unpressed, 4410)) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn poly_pressure_swells_only_its_own_key() {
+        let levels = |pressure: &[[u8; 3]]| {
+            let mut synth = pressure_volume_synth();
+            synth.params.set_parameter(76, 0.5);
+            synth.process_midi_event([128, 69, 0]);
+            // Pressure on a key that isn't down yet is dropped.
+            synth.process_midi_event([0xA0, 67, 127]);
+            synth.process_midi_event([144, 60, 100]);
+            synth.process_midi_event([144, 67, 100]);
+            for &message in pressure {
+                synth.process_midi_event(message);
+            }
+            render(&mut synth, 4410);
+            let out = render(&mut synth, 16384);
+            let level = |note: u8| magnitude_at(&out, midi_pitch_to_freq(note, A4_FREQ), 44100.0);
+            (level(60), level(67))
+        };
+        let (still_c4, still_g4) = levels(&[]);
+        let (pressed_c4, pressed_g4) = levels(&[[0xA0, 67, 127]]);
+        assert!(
+            (still_g4 / still_c4 - 1.0).abs() < 0.01,
+            "{} {}",
+            still_c4,
+            still_g4
+        );
+        assert!(
+            (pressed_g4 / still_g4 - 2.0).abs() < 0.01,
+            "{}",
+            pressed_g4 / still_g4
+        );
+        assert!((pressed_c4 / still_c4 - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pressure_is_let_go_with_the_keys() {
+        let mut synth = pressure_volume_synth();
+        synth.process_midi_event([0xD0, 127, 0]);
+        render(&mut synth, 4410);
+        synth.process_midi_event([128, 69, 0]);
+        render(&mut synth, 4410);
+        synth.process_midi_event([144, 69, 127]);
+        render(&mut synth, 4410);
+        // Held pressure would leave this at full level, about 0.35.
+        assert!(rms(&render(&mut synth, 4410)) < 0.01);
+    }
+
+    /// Arm MIDI learn for the parameter called `target`, then send controller `controller`.
+    fn learn(synth: &mut SineSynth, target: &str, controller: u8) {
+        let target = synth.params.parse_parameter(79, target).unwrap();
+        synth.params.set_parameter(79, target as f32);
+        let learn = synth.params.parse_parameter(78, "Learn").unwrap();
+        synth.params.set_parameter(78, learn as f32);
+        synth.process_midi_event([0xB0, controller, 0]);
+    }
+
+    #[test]
+    fn midi_learn_binds_replaces_and_clears() {
+        let mut synth = instant_synth();
+        assert_eq!(synth.params.display_text(78), "Off");
+        assert_eq!(synth.params.display_text(79), "Cutoff");
+        learn(&mut synth, "Resonance", 20);
+        // Learning takes the one controller and stops listening.
+        assert_eq!(synth.params.display_text(78), "Off");
+        for &(value, expected) in &[(127, 1.0), (0, 0.0), (64, 64.0 / 127.0)] {
+            synth.process_midi_event([0xB0, 20, value]);
+            assert_eq!(synth.params.get_parameter(39), expected);
+        }
+        // The wheel moves the cutoff instead of the vibrato once it's bound.
+        learn(&mut synth, "Cutoff", 1);
+        synth.process_midi_event([0xB0, 1, 127]);
+        assert_eq!(synth.params.get_parameter(38), 1.0);
+        assert_eq!(synth.mod_wheel, 0.0);
+
+        // Learning a bound controller again moves it to the new target.
+        learn(&mut synth, "Amplitude", 20);
+        synth.process_midi_event([0xB0, 20, 127]);
+        assert_eq!(synth.params.get_parameter(39), 64.0 / 127.0);
+        assert_eq!(synth.params.get_parameter(0), 1.0);
+
+        let clear = synth.params.parse_parameter(78, "Clear").unwrap();
+        synth.params.set_parameter(78, clear as f32);
+        assert_eq!(synth.params.display_text(78), "Off");
+        synth.process_midi_event([0xB0, 20, 0]);
+        synth.process_midi_event([0xB0, 1, 0]);
+        assert_eq!(synth.params.get_parameter(0), 1.0);
+        assert_eq!(synth.params.get_parameter(38), 1.0);
+        synth.process_midi_event([0xB0, 1, 127]);
+        assert_eq!(synth.mod_wheel, 1.0);
+    }
+
+    #[test]
+    fn channel_mode_messages_are_not_learned() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 100]);
+        // All Notes Off still releases the note rather than being bound to the cutoff.
+        learn(&mut synth, "Cutoff", 123);
+        assert_eq!(synth.params.display_text(78), "Learn");
+        assert_eq!(held_voices(&synth), []);
+    }
+
+    #[test]
+    fn parameters_stay_in_range_whatever_the_host_sends() {
+        let synth = instant_synth();
+        synth.params.set_parameter(38, 2.5);
+        assert_eq!(synth.params.get_parameter(38), 1.0);
+        synth.params.set_parameter(38, -1.0);
+        assert_eq!(synth.params.get_parameter(38), 0.0);
+        for &value in &[f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            synth.params.set_parameter(38, value);
+            assert_eq!(synth.params.get_parameter(38), 0.0);
+        }
+    }
+
+    #[test]
+    fn learned_controllers_are_saved() {
+        let mut learned = instant_synth();
+        learn(&mut learned, "Resonance", 20);
+        let preset = learned.params.get_preset_data();
+        let bank = learned.params.get_bank_data();
+
+        let mut synth = instant_synth();
+        synth.params.load_preset_data(&preset);
+        synth.process_midi_event([0xB0, 20, 127]);
+        assert_eq!(synth.params.get_parameter(39), 1.0);
+        // A chunk saved without bindings leaves none.
+        synth
+            .params
+            .load_preset_data(&instant_synth().params.get_preset_data());
+        assert_eq!(synth.params.learned.bindings().count(), 0);
+        synth.params.load_bank_data(&bank);
+        assert_eq!(synth.params.learned.target(20), Some(39));
+    }
+
+    /// The notes playing and not let go.
+    fn held_voices(synth: &SineSynth) -> Vec<u8> {
+        let mut notes: Vec<u8> = synth
+            .voices
+            .iter()
+            .filter(|v| v.active && !v.is_released())
+            .map(|v| v.note)
+            .collect();
+        notes.sort_unstable();
+        notes
+    }
+
+    #[test]
+    fn arp_without_host_time_follows_its_own_tempo() {
+        let mut synth = instant_synth();
+        assert_eq!(synth.params.display_text(82), "1/16");
+        assert_eq!(synth.params.display_text(85), "120.0 BPM");
+        synth.params.set_parameter(80, 1.0);
+        for &note in &[67, 60, 64] {
+            synth.process_midi_event([144, note, 100]);
+        }
+        // Sixteenths at 120 BPM are 5512.5 samples apart, and the first starts with the keys.
+        // Each step starts on the first sample at or past its boundary.
+        let mut playing = Vec::new();
+        for &at in &[1, 2757, 2758, 5513, 5514, 11025, 11026, 16538, 16539] {
+            let rendered: usize = playing.iter().map(|&(at, _)| at).max().unwrap_or(0);
+            render(&mut synth, at - rendered);
+            playing.push((at, held_voices(&synth)));
+        }
+        let notes: Vec<Vec<u8>> = playing.into_iter().map(|(_, notes)| notes).collect();
+        assert_eq!(
+            notes,
+            [
+                vec![60],
+                vec![60],
+                vec![],
+                vec![],
+                vec![64],
+                vec![],
+                vec![67],
+                vec![],
+                vec![60],
+            ]
+        );
+
+        for &note in &[60, 64, 67] {
+            synth.process_midi_event([128, note, 0]);
+        }
+        render(&mut synth, 22050);
+        assert!(synth.voices.iter().all(|v| !v.active));
+    }
+
+    #[test]
+    fn keys_held_before_the_arp_are_released() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 100]);
+        synth.params.set_parameter(80, 1.0);
+        render(&mut synth, 100);
+        synth.process_midi_event([128, 60, 0]);
+        render(&mut synth, 100);
+        assert_eq!(held_voices(&synth), []);
+    }
+
+    #[test]
+    fn all_notes_off_stops_a_held_arpeggio() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(80, 1.0);
+        synth.params.set_parameter(84, 1.0);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([128, 60, 0]);
+        render(&mut synth, 100);
+        assert_eq!(held_voices(&synth), [60]);
+        synth.process_midi_event([176, 123, 0]);
+        render(&mut synth, 22050);
+        assert!(synth.voices.iter().all(|v| !v.active));
+    }
+
+    #[test]
+    fn major_chord_plays_and_stops_with_its_key() {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(86, ChordType::Major.to_normalized());
+        assert_eq!(synth.params.display_text(86), "Major");
+        synth.process_midi_event([144, 48, 100]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 16384);
+        let notes = [48, 50, 52, 53, 55];
+        assert_eq!(
+            notes_sounding(&out, &notes),
+            [true, false, true, false, true]
+        );
+
+        synth.process_midi_event([128, 48, 0]);
+        assert_eq!(held_voices(&synth), []);
+        render(&mut synth, 44100);
+        assert!(rms(&render(&mut synth, 4410)) < 1e-6);
+    }
+
+    #[test]
+    fn keys_release_only_their_own_chords() {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(86, ChordType::Major.to_normalized());
+        synth.process_midi_event([144, 48, 100]);
+        synth.process_midi_event([144, 52, 100]);
+        assert_eq!(held_voices(&synth), [48, 52, 55, 56, 59]);
+        // E3 is in both chords, and the second key took it over.
+        synth.process_midi_event([128, 48, 0]);
+        assert_eq!(held_voices(&synth), [52, 56, 59]);
+        synth.process_midi_event([128, 52, 0]);
+        assert_eq!(held_voices(&synth), []);
+    }
+
+    #[test]
+    fn chords_leave_out_notes_past_the_top() {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(86, ChordType::Octave.to_normalized());
+        synth.process_midi_event([144, 120, 100]);
+        assert_eq!(held_voices(&synth), [120]);
+        synth.process_midi_event([144, 100, 100]);
+        assert_eq!(held_voices(&synth), [100, 112, 120]);
+        synth.process_midi_event([128, 100, 0]);
+        assert_eq!(held_voices(&synth), [120]);
+    }
+
+    #[test]
+    fn user_chord_is_learned_from_held_keys_and_saved() {
+        let mut learned = instant_synth();
+        for &note in &[67, 60, 70] {
+            learned.process_midi_event([144, note, 100]);
+        }
+        learned.params.set_parameter(87, 1.0);
+        render(&mut learned, 100);
+        assert_eq!(learned.params.get_parameter(87), 0.0);
+        assert_eq!(learned.params.user_chord.get().intervals(), [0, 7, 10]);
+        let preset = learned.params.get_preset_data();
+
+        let mut synth = instant_synth();
+        synth.params.load_preset_data(&preset);
+        synth
+            .params
+            .set_parameter(86, ChordType::User.to_normalized());
+        synth.process_midi_event([144, 50, 100]);
+        assert_eq!(held_voices(&synth), [50, 57, 60]);
+        // Learning with no keys held keeps the chord.
+        synth.process_midi_event([128, 50, 0]);
+        synth.params.set_parameter(87, 1.0);
+        render(&mut synth, 100);
+        assert_eq!(synth.params.user_chord.get().intervals(), [0, 7, 10]);
+    }
+
+    #[test]
+    fn transposition_is_in_whole_steps() {
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(70), "0 oct");
+        assert_eq!(synth.params.display_text(71), "0 st");
+        assert_eq!(synth.params.display_text(72), "+0.0 cents");
+        for semitones in -12..=12 {
+            let value = synth.params.parse_parameter(71, &semitones.to_string());
+            synth.params.set_parameter(71, value.unwrap() as f32);
+            assert_eq!(synth.params.semitone(), semitones);
+        }
+        assert_eq!(synth.params.display_text(71), "+12 st");
+        // Values between the steps go to the nearest one.
+        synth.params.set_parameter(70, 0.37);
+        assert_eq!(synth.params.octave(), -1);
+        assert_eq!(synth.params.display_text(70), "-1 oct");
+        synth.params.set_parameter(72, 0.25);
+        assert_eq!(synth.params.display_text(72), "-50.0 cents");
+    }
+
+    /// The frequency of a sustained A3 with parameters set as `params` pairs.
+    fn transposed_frequency(params: &[(i32, f32)]) -> f64 {
+        let mut synth = instant_synth();
+        for &(index, value) in params {
+            synth.params.set_parameter(index, value);
+        }
+        synth.process_midi_event([144, 57, 127]);
+        render(&mut synth, 4410);
+        measure_frequency(&render(&mut synth, 44100), 44100.0)
+    }
+
+    #[test]
+    fn transposition_is_exact_ratios() {
+        let plain = transposed_frequency(&[]);
+        let octave = transposed_frequency(&[(70, 5.0 / 8.0)]);
+        assert!(cents_between(octave, 2.0 * plain).abs() < 0.01);
+        let fifth = transposed_frequency(&[(71, 19.0 / 24.0)]);
+        assert!(cents_between(fifth, plain * (7.0 / 12.0f64).exp2()).abs() < 0.01);
+        let fine = transposed_frequency(&[(72, 0.75)]);
+        assert!((cents_between(fine, plain) - 50.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn transposition_composes_with_pitch_bend() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(71, 19.0 / 24.0);
+        synth.process_midi_event([144, 57, 127]);
+        synth.process_midi_event([224, 127, 127]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 44100);
+        // A fifth up and a full bend of two semitones make a major sixth.
+        let expected = midi_pitch_to_freq(66, A4_FREQ);
+        let cents = cents_between(measure_frequency(&out, 44100.0), expected);
+        assert!(cents.abs() < 0.1, "{} cents", cents);
+    }
+
+    #[test]
+    fn mod_wheel_at_zero_leaves_output_unchanged() {
+        let mut plain = instant_synth();
+        plain.params.set_parameter(8, 0.0);
+        plain.process_midi_event([144, 69, 127]);
+
+        let mut wheel = instant_synth();
+        wheel.params.set_parameter(8, 1.0);
+        wheel.process_midi_event([144, 69, 127]);
+        wheel.process_midi_event([176, 1, 0]);
+
+        assert_eq!(render(&mut plain, 8192), render(&mut wheel, 8192));
+    }
+
+    #[test]
+    fn mod_wheel_adds_vibrato() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(7, 0.5); // 1 Hz
+        synth.params.set_parameter(8, 1.0); // 100 cents
+        synth.process_midi_event([144, 69, 127]);
+        synth.process_midi_event([176, 1, 127]);
+        render(&mut synth, 4410);
+        let out = render(&mut synth, 44100);
+
+        // Each tenth of a second covers a different part of the vibrato cycle.
+        let pitches: Vec<f64> = out
+            .chunks(4410)
+            .map(|chunk| cents_between(measure_frequency(chunk, 44100.0), 440.0))
+            .collect();
+        let highest = pitches.iter().cloned().fold(f64::MIN, f64::max);
+        let lowest = pitches.iter().cloned().fold(f64::MAX, f64::min);
+        assert!(highest > 50.0 && highest < 100.0, "highest {}", highest);
+        assert!(lowest < -50.0 && lowest > -100.0, "lowest {}", lowest);
+    }
+
+    #[test]
+    fn pitch_lfo_adds_fm_sidebands() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(47, 1.0); // 20 Hz
+        synth.params.set_parameter(48, 0.01); // ±0.12 semitones
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 16384);
+
+        // For a small modulation index each first sideband is half the index times the
+        // carrier, and the index is the peak deviation over the LFO rate.
+        let deviation = 440.0 * ((0.12 / 12.0) * 2f64.ln());
+        let expected = deviation / 20.0 / 2.0;
+        let carrier = magnitude_at(&out, 440.0, 44100.0);
+        for &sideband in &[420.0, 460.0] {
+            let ratio = magnitude_at(&out, sideband, 44100.0) / carrier;
+            assert!(
+                (ratio / expected - 1.0).abs() < 0.1,
+                "{}: {}",
+                sideband,
+                ratio
+            );
+        }
+        assert!(magnitude_at(&out, 450.0, 44100.0) < 0.01 * carrier);
+    }
+
+    #[test]
+    fn amplitude_lfo_modulates_level_at_its_rate() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(47, 50f32.ln() / 200f32.ln()); // 5 Hz
+        synth.params.set_parameter(48, 1.0);
+        synth
+            .params
+            .set_parameter(49, LfoDestination::Amplitude.to_normalized());
+        assert_eq!(synth.params.display_text(47), "5.00 Hz");
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 88200);
+
+        // The level every hundredth of a second, centred on zero.
+        let levels: Vec<f64> = out.chunks(441).map(rms).collect();
+        let mean = levels.iter().sum::<f64>() / levels.len() as f64;
+        let centred: Vec<f32> = levels.iter().map(|l| (l - mean) as f32).collect();
+        let rate = measure_frequency(&centred, 100.0);
+        assert!((rate - 5.0).abs() < 0.1, "{} Hz", rate);
+        let quietest = levels.iter().cloned().fold(f64::MAX, f64::min);
+        let loudest = levels.iter().cloned().fold(f64::MIN, f64::max);
+        assert!(quietest < 0.1 * loudest, "{} vs {}", quietest, loudest);
+    }
+
+    #[test]
+    fn synced_lfo_without_host_time_runs_free() {
+        let render_lfo = |sync: f32| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(48, 0.1);
+            synth.params.set_parameter(52, sync);
+            synth.process_midi_event([144, 69, 127]);
+            render(&mut synth, 4096)
+        };
+        assert_eq!(render_lfo(1.0), render_lfo(0.0));
+    }
+
+    #[test]
+    fn sync_rate_shows_musical_divisions() {
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(53), "1/4");
+        synth.params.set_parameter(53, 8.0 / 17.0);
+        assert_eq!(synth.params.display_text(53), "1/8D");
+        synth.params.set_parameter(53, 1.0);
+        assert_eq!(synth.params.display_text(53), "1/32T");
+        synth.params.set_parameter(53, 0.0);
+        assert_eq!(synth.params.display_text(53), "4 bars");
+    }
+
+    #[test]
+    fn sample_and_hold_pitch_stays_within_depth() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(47, 20f32.ln() / 200f32.ln()); // 2 Hz
+        synth.params.set_parameter(48, 1.0 / 12.0); // ±1 semitone
+        synth
+            .params
+            .set_parameter(50, LfoShape::SampleAndHold.to_normalized());
+        assert_eq!(synth.params.display_text(50), "S&H");
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 88200);
+
+        // The pitch held through the middle of each half-second step.
+        let pitches: Vec<f64> = out
+            .chunks(22050)
+            .map(|step| cents_between(measure_frequency(&step[5000..17000], 44100.0), 440.0))
+            .collect();
+        assert!(pitches.iter().all(|p| p.abs() < 101.0), "{:?}", pitches);
+        assert!(
+            pitches.windows(2).all(|w| (w[0] - w[1]).abs() > 1.0),
+            "{:?}",
+            pitches
+        );
+    }
+
+    #[test]
+    fn sample_and_hold_tremolo_does_not_click() {
+        let mut synth = instant_synth();
+        // Not a divisor of 440 Hz, so the steps don't all land on zero crossings.
+        synth.params.set_parameter(47, 0.9);
+        synth.params.set_parameter(48, 1.0);
+        synth
+            .params
+            .set_parameter(49, LfoDestination::Amplitude.to_normalized());
+        synth
+            .params
+            .set_parameter(50, LfoShape::SampleAndHold.to_normalized());
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 44100);
+
+        // A full-level 440 Hz sine moves at most 2π·440/44100 per sample; the level steps may
+        // only add a little to that.
+        let peak = out.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        let largest_step = out
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0f32, f32::max);
+        assert!(largest_step < 0.1 * peak, "{} vs {}", largest_step, peak);
+    }
+
+    #[test]
+    fn zero_lfo_depth_is_bypassed() {
+        let render_with_lfo = |destination: LfoDestination| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(47, 1.0);
+            synth.params.set_parameter(49, destination.to_normalized());
+            synth.process_midi_event([144, 69, 127]);
+            render(&mut synth, 4096)
+        };
+        let mut plain = instant_synth();
+        plain.process_midi_event([144, 69, 127]);
+        let plain = render(&mut plain, 4096);
+        for &destination in LfoDestination::ALL.iter() {
+            assert_eq!(render_with_lfo(destination), plain, "{:?}", destination);
+        }
+    }
+
+    #[test]
+    fn vibrato_is_continuous_across_blocks() {
+        let start = |synth: &mut SineSynth| {
+            synth.process_midi_event([144, 69, 127]);
+            synth.process_midi_event([176, 1, 127]);
+        };
+        let mut whole = instant_synth();
+        start(&mut whole);
+        let mut split = instant_synth();
+        start(&mut split);
+
+        let mut blocks = render(&mut split, 1000);
+        blocks.extend(render(&mut split, 3000));
+        assert_eq!(render(&mut whole, 4000), blocks);
+    }
+
+    fn is_silent(signal: &[f32]) -> bool {
+        signal.iter().all(|&s| s == 0.0)
+    }
+
+    #[test]
+    fn sustain_pedal_holds_released_notes() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([176, 64, 127]);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([128, 60, 0]);
+        assert!(!is_silent(&render(&mut synth, 4410)[4000..]));
+
+        synth.process_midi_event([176, 64, 0]);
+        assert!(is_silent(&render(&mut synth, 4410)[4000..]));
+    }
+
+    #[test]
+    fn pedal_up_keeps_physically_held_keys() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([176, 64, 127]);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 67, 100]);
+        synth.process_midi_event([128, 60, 0]);
+        synth.process_midi_event([176, 64, 0]);
+        let out = render(&mut synth, 8192);
+
+        assert!(magnitude_at(&out[4096..], midi_pitch_to_freq(60, A4_FREQ), 44100.0) < 0.001);
+        assert!(magnitude_at(&out[4096..], midi_pitch_to_freq(67, A4_FREQ), 44100.0) > 0.1);
+    }
+
+    #[test]
+    fn repressing_pedal_held_key_retriggers_it() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([176, 64, 127]);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([128, 60, 0]);
+        synth.process_midi_event([144, 60, 100]);
+        assert_eq!(synth.voices.iter().filter(|v| v.active).count(), 1);
+
+        // The key is down again, so lifting the pedal must not release it.
+        synth.process_midi_event([176, 64, 0]);
+        assert!(!is_silent(&render(&mut synth, 4410)[4000..]));
+    }
+
+    #[test]
+    fn all_notes_off_overrides_pedal() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([176, 64, 127]);
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([128, 60, 0]);
+        synth.process_midi_event([176, 123, 0]);
+        assert!(is_silent(&render(&mut synth, 4410)[4000..]));
+    }
+
+    #[test]
+    fn all_notes_off_silences_stuck_notes() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([144, 64, 100]);
+        render(&mut synth, 512);
+        synth.process_midi_event([176, 123, 0]);
+        let out = render(&mut synth, 4096);
+
+        assert!(is_silent(&out[4000..]));
+        assert!(synth.voices.iter().all(|v| !v.active));
+
+        // Later notes play and release normally.
+        synth.process_midi_event([144, 60, 100]);
+        synth.process_midi_event([128, 60, 0]);
+        assert!(is_silent(&render(&mut synth, 4096)[4000..]));
+    }
+
+    #[test]
+    fn all_sound_off_fades_quickly() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(4, 1.0);
+        synth.process_midi_event([176, 64, 127]);
+        synth.process_midi_event([144, 48, 100]);
+        render(&mut synth, 512);
+        synth.process_midi_event([176, 120, 0]);
+        let out = render(&mut synth, 512);
+
+        assert!(is_silent(&out[256..]));
+        assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.02));
+        assert!(!synth.sustain_pedal);
+    }
+
+    #[test]
+    fn bypass_fades_out_and_keeps_up_with_notes() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(0, amplitude_to_normalized(0.0));
+        synth.process_midi_event([144, 69, 127]);
+        let before = render(&mut synth, 4410);
+        let peak = before.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        synth.params.set_parameter(92, 1.0);
+        assert_eq!(synth.params.display_text(92), "On");
+        let out = render(&mut synth, 4410);
+        // A straight 10ms fade, then nothing at all.
+        for (i, sample) in out[..441].iter().enumerate() {
+            assert!(sample.abs() <= peak * (1.0 - i as f32 / 441.0) + 1e-6);
+        }
+        assert!(out[441..].iter().all(|&sample| sample == 0.0));
+
+        // Keys going down and up while bypassed are still followed, but nothing sounds from
+        // them once bypass is off.
+        synth.process_midi_event([144, 60, 127]);
+        synth.process_midi_event([128, 69, 0]);
+        render(&mut synth, 512);
+        assert_eq!(synth.held_notes.held(), [60]);
+        assert_eq!(held_voices(&synth), []);
+        synth.params.set_parameter(92, 0.0);
+        assert!(render(&mut synth, 4410).iter().all(|&sample| sample == 0.0));
+        synth.process_midi_event([144, 64, 127]);
+        assert!(rms(&render(&mut synth, 4410)) > 0.1);
+    }
+
+    #[test]
+    fn channel_filter_only_plays_selected_channel() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(9, 2.0 / 16.0);
+        assert_eq!(synth.params.display_text(9), "Ch 2");
+        synth.process_midi_event([0x90, 60, 100]);
+        synth.process_midi_event([0x91, 67, 100]);
+        let out = render(&mut synth, 8192);
+
+        assert!(magnitude_at(&out, midi_pitch_to_freq(60, A4_FREQ), 44100.0) < 0.001);
+        assert!(magnitude_at(&out, midi_pitch_to_freq(67, A4_FREQ), 44100.0) > 0.1);
+    }
+
+    #[test]
+    fn changing_channel_stops_filtered_notes() {
+        let mut synth = instant_synth();
+        assert_eq!(synth.params.display_text(9), "Omni");
+        synth.process_midi_event([0x90, 60, 100]);
+        synth.process_midi_event([0x91, 67, 100]);
+        render(&mut synth, 512);
+        synth.params.set_parameter(9, 2.0 / 16.0);
+        let out = render(&mut synth, 8192);
+
+        assert!(magnitude_at(&out[4096..], midi_pitch_to_freq(60, A4_FREQ), 44100.0) < 0.001);
+        assert!(magnitude_at(&out[4096..], midi_pitch_to_freq(67, A4_FREQ), 44100.0) > 0.1);
+
+        // The NoteOff for the filtered note never arrives, and nothing is left sounding.
+        synth.process_midi_event([0x81, 67, 0]);
+        assert!(is_silent(&render(&mut synth, 4096)[4000..]));
+    }
+
+    #[test]
+    fn events_start_at_their_delta_frame() {
+        // Whether or not the event falls where the voices would have stopped anyway.
+        for &frame in &[512, 517] {
+            let mut synth = instant_synth();
+            synth.queue_midi_event(frame as i32, [144, 60, 100]);
+            let out = render(&mut synth, 1024);
+
+            assert!(is_silent(&out[..frame + 1]), "{}", frame);
+            assert!(out[frame + 1..].iter().all(|&s| s != 0.0), "{}", frame);
+        }
+    }
+
+    #[test]
+    fn late_events_land_on_last_sample() {
+        let mut synth = instant_synth();
+        synth.queue_midi_event(0, [144, 60, 100]);
+        synth.queue_midi_event(5000, [128, 60, 0]);
+        render(&mut synth, 1024);
+        assert!(synth.voices[0].is_released());
+
+        synth.queue_midi_event(10, [144, 64, 100]);
+        render(&mut synth, 0);
+        assert!(synth.voices.iter().any(|v| v.active && v.note == 64));
+    }
+
+    #[test]
+    fn waveform_parameter_round_trips() {
+        let synth = SineSynth::default();
+        for &waveform in Waveform::ALL.iter() {
+            synth.params.set_parameter(10, waveform.to_normalized());
+            let value = synth.params.get_parameter(10);
+            assert_eq!(Waveform::from_normalized(value), waveform);
+            assert_eq!(synth.params.display_text(10), waveform.name());
+        }
+    }
+
+    #[test]
+    fn every_waveform_renders_bounded_audio() {
+        for &waveform in Waveform::ALL.iter() {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(10, waveform.to_normalized());
+            synth.process_midi_event([144, 60, 127]);
+            let out = render(&mut synth, 4096);
+            assert!(rms(&out) > 0.07, "{:?} is silent", waveform);
+            assert!(out.iter().all(|s| s.abs() <= 1.0), "{:?} clips", waveform);
+        }
+    }
+
+    #[test]
+    fn switching_waveform_keeps_phase() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 60, 127]);
+        render(&mut synth, 1000);
+        let phase = synth.voices[0].phase;
+        let mut blocker = synth.dc_blockers[0];
+        synth
+            .params
+            .set_parameter(10, Waveform::Triangle.to_normalized());
+        let out = render(&mut synth, 1);
+        let expected =
+            0.5 * blocker.process(Waveform::Triangle.sample(phase, 0.0, 0.5)) * pan_gains(0.5).0;
+        assert!((f64::from(out[0]) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn narrow_pulse_dc_is_blocked() {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(10, Waveform::Square.to_normalized());
+        synth.params.set_parameter(11, 0.0);
+        assert_eq!(synth.params.display_text(11), "5%");
+        synth.process_midi_event([144, 57, 127]);
+        render(&mut synth, 4410);
+        // 44100 samples hold exactly 220 cycles of A3.
+        let out = render(&mut synth, 44100);
+
+        // The pulse averages 0.5 * (2 * 0.05 - 1) = -0.45, but none of it reaches the output.
+        let average = out.iter().map(|&s| f64::from(s)).sum::<f64>() / out.len() as f64;
+        assert!(average.abs() < 0.005, "{}", average);
+    }
+
+    /// With the sub and second oscillator off, a note renders exactly as a single table sine, but
+    /// for the DC blocker and the centre pan.
+    #[test]
+    fn default_oscillators_render_plain_sine() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(13, 1.0);
+        synth.params.set_parameter(14, 0.5);
+        synth.params.set_parameter(15, 0.0);
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 4096);
+
+        let increment = 440.0 * (1.0 / 44100.0);
+        let mut phase = 0.0f64;
+        let mut blocker = DcBlocker::new(44100.0);
+        let centre = pan_gains(0.5).0;
+        for &sample in out.iter() {
+            assert_eq!(
+                sample,
+                (0.5 * blocker.process(Lookup.sine(phase)) * centre) as f32
+            );
+            phase = (phase + increment).fract();
+        }
+    }
+
+    #[test]
+    fn sub_adds_energy_an_octave_down() {
+        for &shape in &[0.0, 1.0] {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(12, 1.0);
+            synth.params.set_parameter(13, shape);
+            synth.process_midi_event([144, 69, 127]);
+            let out = render(&mut synth, 8192);
+
+            assert!(magnitude_at(&out, 220.0, 44100.0) > 0.1);
+            assert!(magnitude_at(&out, 440.0, 44100.0) > 0.1);
+            assert!(out.iter().all(|s| s.abs() <= 1.0));
+        }
+    }
+
+    #[test]
+    fn octave_layer_adds_its_fundamental() {
+        let layers = [
+            (OctaveLayer::Up, [false, true, true]),
+            (OctaveLayer::Down, [true, true, false]),
+            (OctaveLayer::Both, [true, true, true]),
+        ];
+        for &(layer, expected) in &layers {
+            for &waveform in &[Waveform::Sine, Waveform::Saw] {
+                let mut synth = instant_synth();
+                synth.params.set_parameter(0, amplitude_to_normalized(0.0));
+                synth.params.set_parameter(10, waveform.to_normalized());
+                synth.params.set_parameter(88, layer.to_normalized());
+                synth.params.set_parameter(89, 1.0);
+                assert_eq!(synth.params.display_text(88), layer.name());
+                synth.process_midi_event([144, 69, 127]);
+                let out = render(&mut synth, 8192);
+
+                // A saw has its own harmonic an octave up, so only the sine shows the layer.
+                if waveform == Waveform::Sine {
+                    let sounding: Vec<bool> = [220.0, 440.0, 880.0]
+                        .iter()
+                        .map(|&freq| magnitude_at(&out, freq, 44100.0) > 0.1)
+                        .collect();
+                    assert_eq!(sounding, expected, "{:?}", layer);
+                }
+                assert!(out.iter().all(|s| s.abs() <= 1.0), "{:?} clips", layer);
+            }
+        }
+    }
+
+    #[test]
+    fn octave_layer_off_or_silent_changes_nothing() {
+        let render_with = |params: &[(i32, f32)]| {
+            let mut synth = instant_synth();
+            synth
+                .params
+                .set_parameter(10, Waveform::Saw.to_normalized());
+            for &(index, value) in params {
+                synth.params.set_parameter(index, value);
+            }
+            synth.process_midi_event([144, 57, 100]);
+            synth.process_midi_event([0xE0, 0, 80]);
+            render(&mut synth, 4410)
+        };
+        let plain = render_with(&[]);
+        let both = OctaveLayer::Both.to_normalized();
+        assert_eq!(render_with(&[(88, both), (89, 0.0)]), plain);
+        assert_eq!(render_with(&[(88, 0.0), (89, 1.0)]), plain);
+    }
+
+    #[test]
+    fn osc_mix_crossfades_to_detuned_oscillator() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(14, 1.0);
+        synth.params.set_parameter(15, 1.0);
+        assert_eq!(synth.params.display_text(14), "+100 cents");
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 44100);
+
+        let cents = cents_between(
+            measure_frequency(&out, 44100.0),
+            midi_pitch_to_freq(70, A4_FREQ),
+        );
+        assert!(cents.abs() < 1.0, "off by {} cents", cents);
+    }
+
+    #[test]
+    fn centred_osc_mix_keeps_power() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(14, 0.0);
+        synth.params.set_parameter(15, 0.5);
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 44100);
+
+        // Each oscillator is 3 dB down, so together they carry the power of one, and the centre
+        // pan takes another 3 dB off both.
+        let half_power = 0.25;
+        let osc1 = magnitude_at(&out, 440.0, 44100.0);
+        let osc2 = magnitude_at(&out, midi_pitch_to_freq(68, A4_FREQ), 44100.0);
+        assert!((osc1 - half_power).abs() < 0.01, "{}", osc1);
+        assert!((osc2 - half_power).abs() < 0.01, "{}", osc2);
+        assert!((rms(&out) - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn full_unison_stays_in_range() {
+        let render_unison = || {
+            let mut synth = instant_synth();
+            synth
+                .params
+                .set_parameter(10, Waveform::Saw.to_normalized());
+            synth.params.set_parameter(16, 1.0);
+            synth.params.set_parameter(17, 1.0);
+            assert_eq!(synth.params.display_text(16), "7");
+            assert_eq!(synth.params.display_text(17), "±50 cents");
+            synth.params.set_parameter(0, amplitude_to_normalized(0.0));
+            synth.process_midi_event([144, 60, 127]);
+            synth.process_midi_event([144, 64, 127]);
+            synth.process_midi_event([144, 67, 127]);
+            render(&mut synth, 44100)
+        };
+        let out = render_unison();
+
+        assert!(out.iter().all(|s| s.abs() <= 1.0));
+        assert!(rms(&out) > 0.05);
+        // The detune and starting phases are fixed, so renders repeat exactly.
+        assert_eq!(out, render_unison());
+    }
+
+    #[test]
+    fn unison_detunes_symmetrically() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(16, 2.0 / 6.0);
+        synth.params.set_parameter(17, 0.2);
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 44100);
+
+        // Three copies at -10, 0 and +10 cents.
+        for &cents in &[-10.0, 0.0, 10.0] {
+            let freq = 440.0 * (cents / 1200.0f64).exp2();
+            let level = magnitude_at(&out, freq, 44100.0);
+            assert!(
+                (level - 0.5 / 3.0 * FRAC_1_SQRT_2).abs() < 0.01,
+                "{} cents: {}",
+                cents,
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn noise_follows_the_envelope() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(18, 1.0);
+        synth.params.set_parameter(19, 1.0);
+        assert_eq!(synth.params.display_text(19), "Pink");
+        assert!(is_silent(&render(&mut synth, 1024)));
+
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 8192);
+        // Noise fills the spectrum between the harmonics of the tone.
+        assert!(magnitude_at(&out, 1000.0, 44100.0) > 0.001);
+        assert!(out.iter().all(|s| s.abs() <= 1.0));
+
+        synth.process_midi_event([128, 69, 0]);
+        assert!(is_silent(&render(&mut synth, 4410)[4000..]));
+    }
+
+    #[test]
+    fn sweeping_table_position_stays_in_range() {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(10, Waveform::Wavetable.to_normalized());
+        synth.params.set_parameter(0, amplitude_to_normalized(0.0));
+        for &note in &[36, 72, 108] {
+            synth.process_midi_event([144, note, 127]);
+        }
+        for table in 0..5 {
+            synth.params.set_parameter(20, table as f32 / 4.0);
+            for step in 0..=20 {
+                synth.params.set_parameter(21, step as f32 / 20.0);
+                let out = render(&mut synth, 256);
+                assert!(out.iter().all(|s| s.is_finite() && s.abs() <= 1.0));
+            }
+        }
+        assert_eq!(synth.params.display_text(20), "Vocal");
+    }
+
+    #[test]
+    fn fm_adds_sidebands() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(22, 2.0 / 8.0);
+        synth.params.set_parameter(24, 0.15);
+        assert_eq!(synth.params.display_text(22), "2x");
+        assert_eq!(synth.params.display_text(24), "1.50");
+        synth.process_midi_event([144, 57, 127]);
+        let out = render(&mut synth, 16384);
+
+        // A 220 Hz carrier and 440 Hz modulator give sidebands at 220 ± n·440 Hz, which all
+        // land on odd harmonics.
+        for &freq in &[220.0, 660.0, 1100.0] {
+            let level = magnitude_at(&out, freq, 44100.0);
+            assert!(level > 0.02, "{} Hz: {}", freq, level);
+        }
+        for &freq in &[440.0, 880.0] {
+            let level = magnitude_at(&out, freq, 44100.0);
+            assert!(level < 0.001, "{} Hz: {}", freq, level);
+        }
+    }
+
+    #[test]
+    fn ring_mod_produces_sum_and_difference() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(25, 1.0);
+        synth.params.set_parameter(26, 0.3);
+        let f2 = synth.params.osc2_fixed().unwrap();
+        synth.process_midi_event([144, 69, 127]);
+        let out = render(&mut synth, 16384);
+
+        // Two sines multiplied give half-amplitude sines at the sum and difference.
+        for &freq in &[440.0 + f2, 440.0 - f2] {
+            let level = magnitude_at(&out, freq, 44100.0);
+            assert!(
+                (level - 0.25 * FRAC_1_SQRT_2).abs() < 0.01,
+                "{} Hz: {}",
+                freq,
+                level
+            );
+        }
+        for &freq in &[440.0, f2] {
+            let level = magnitude_at(&out, freq, 44100.0);
+            assert!(level < 0.001, "{} Hz: {}", freq, level);
+        }
+    }
+
+    #[test]
+    fn synced_pitch_follows_first_oscillator() {
+        for &offset in &[0.2, 0.45, 0.7] {
+            let mut synth = instant_synth();
+            synth
+                .params
+                .set_parameter(10, Waveform::Saw.to_normalized());
+            synth.params.set_parameter(27, 1.0);
+            synth.params.set_parameter(14, offset);
+            synth.process_midi_event([144, 57, 127]);
+            let out = render(&mut synth, 16384);
+
+            // The output repeats at the first oscillator's 220 Hz wherever the second oscillator
+            // is tuned, so there is energy at its harmonics and none between them.
+            let harmonics: f64 = (1..=4)
+                .map(|k| magnitude_at(&out, 220.0 * f64::from(k), 44100.0))
+                .sum();
+            let free_running = 220.0 * synth.params.osc2_ratio();
+            assert!(harmonics > 0.3, "{}: {}", offset, harmonics);
+            for &freq in &[free_running, 330.0, 550.0] {
+                let level = magnitude_at(&out, freq, 44100.0);
+                assert!(level < 0.005, "{} at {} Hz: {}", offset, freq, level);
+            }
+            assert!(out.iter().all(|s| s.abs() <= 1.0));
+        }
+    }
+
+    #[test]
+    fn additive_fundamental_matches_sine() {
+        let render_with = |waveform: Waveform| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(10, waveform.to_normalized());
+            synth.process_midi_event([144, 69, 127]);
+            render(&mut synth, 4096)
+        };
+        assert_eq!(render_with(Waveform::Additive), render_with(Waveform::Sine));
+    }
+
+    #[test]
+    fn additive_drops_harmonics_above_nyquist() {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(10, Waveform::Additive.to_normalized());
+        for index in 28..36 {
+            synth.params.set_parameter(index, 1.0);
+        }
+        synth.params.set_parameter(0, amplitude_to_normalized(0.0));
+        synth.process_midi_event([144, 108, 127]);
+        let out = render(&mut synth, 16384);
+        assert!(out.iter().all(|s| s.abs() <= 1.0));
+
+        // At C8 harmonics above the fifth are past Nyquist and would fold back to these
+        // frequencies.
+        let fundamental = midi_pitch_to_freq(108, A4_FREQ);
+        assert!(magnitude_at(&out, fundamental, 44100.0) > 0.05);
+        for harmonic in 6..=8 {
+            let folded = 44100.0 - harmonic as f64 * fundamental;
+            let level = magnitude_at(&out, folded, 44100.0);
+            assert!(
+                level < 1e-4,
+                "harmonic {} at {} Hz: {}",
+                harmonic,
+                folded,
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn parameter_table_matches_info() {
+        let synth = SineSynth::default();
+        let count = synth.get_info().parameters;
+        assert_eq!(count as usize, crate::PARAMS.len());
+        assert_eq!(count as usize, crate::PARAM_COUNT);
+        for index in 0..count {
+            assert!(synth.params.param(index).is_some(), "{}", index);
+            assert!(!synth.params.get_parameter_name(index).is_empty());
+            assert!(!synth.params.display_text(index).is_empty());
+        }
+        assert!(synth.params.param(count).is_none());
+        assert_eq!(synth.params.get_parameter_name(-1), "");
+    }
+
+    #[test]
+    fn labels_match_parameter_text() {
+        let synth = SineSynth::default();
+        let count = synth.get_info().parameters;
+        for index in 0..count {
+            let units = crate::PARAMS[index as usize].units;
+            for step in 0..=20 {
+                synth.params.set_parameter(index, step as f32 / 20.0);
+                let shown = synth.params.display_text(index);
+                let text = synth.params.get_parameter_text(index);
+                let label = synth.params.get_parameter_label(index);
+                assert!(!text.is_empty(), "{}", index);
+                // Hosts show the label after the text, so the unit is in one and not both.
+                assert!(!text.ends_with(&label) || label.is_empty(), "{:?}", shown);
+                let rejoined = [text.clone() + &label, format!("{} {}", text, label)];
+                assert!(
+                    label.is_empty() && text == shown || rejoined.contains(&shown),
+                    "{:?} split into {:?} and {:?}",
+                    shown,
+                    text,
+                    label
+                );
+                // Only words like "Off" go without a unit, never numbers.
+                let numeric = shown.starts_with(|c: char| c.is_ascii_digit() || "+-±".contains(c));
+                assert_eq!(
+                    label.is_empty(),
+                    units.is_empty() || !numeric,
+                    "{:?} with {:?}",
+                    shown,
+                    label
+                );
+            }
+        }
+        for &index in &[-1, count, count + 10] {
+            assert_eq!(synth.params.get_parameter_name(index), "");
+            assert_eq!(synth.params.get_parameter_text(index), "");
+            assert_eq!(synth.params.get_parameter_label(index), "");
+        }
+    }
+
+    #[test]
+    fn only_internal_parameters_are_hidden_from_automation() {
+        let synth = SineSynth::default();
+        let count = synth.get_info().parameters;
+        let hidden: Vec<String> = (0..count)
+            .filter(|&index| !synth.params.can_be_automated(index))
+            .map(|index| synth.params.get_parameter_name(index))
+            .collect();
+        assert_eq!(hidden, ["Random Seed"]);
+        assert!(synth.params.can_be_automated(0));
+        assert!(synth.params.can_be_automated(1));
+        assert!(!synth.params.can_be_automated(-1));
+        assert!(!synth.params.can_be_automated(count));
+    }
+
+    #[test]
+    fn every_named_capability_is_answered() {
+        let synth = SineSynth::default();
+        let answers = [
+            ("sendVstEvents", false),
+            ("sendVstMidiEvent", false),
+            ("receiveVstEvents", true),
+            ("receiveVstMidiEvent", true),
+            ("receiveVstTimeInfo", true),
+            ("offline", false),
+            ("midiProgramNames", false),
+            ("bypass", false),
+            ("receiveVstSysexEvent", true),
+            ("midiSingleNoteTuningChange", true),
+            ("midiKeyBasedInstrumentControl", false),
+        ];
+        for &(name, supported) in &answers {
+            let can_do = CanDo::from_str(name);
+            assert!(!matches!(can_do, CanDo::Other(_)), "{}", name);
+            let expected = if supported {
+                Supported::Yes
+            } else {
+                Supported::No
+            };
+            assert!(synth.can_do(can_do) == expected, "{}", name);
+        }
+        let unknown = CanDo::from_str("plugAsChannelInsert");
+        assert!(synth.can_do(unknown) == Supported::Maybe);
+    }
+
+    #[test]
+    fn tail_size_follows_release_and_sample_rate() {
+        let mut synth = SineSynth::default();
+        assert_eq!(synth.get_tail_size(), 2205);
+        synth.params.set_parameter(4, 0.5);
+        assert_eq!(synth.get_tail_size(), 22050);
+        synth.set_sample_rate(48000.0);
+        assert_eq!(synth.get_tail_size(), 24000);
+        synth.params.set_parameter(4, 0.0);
+        assert_eq!(synth.get_tail_size(), 0);
+    }
+
+    #[test]
+    fn tail_size_includes_the_effects() {
+        let synth = SineSynth::default();
+        synth.params.set_parameter(4, 0.0);
+        assert!(synth.params.string_to_parameter(97, "250 ms".to_string()));
+        assert!(synth.params.string_to_parameter(100, "50%".to_string()));
+        assert_eq!(synth.params.display_text(97), "250 ms");
+        assert_eq!(synth.params.display_text(100), "50%");
+        // Turned off, the delay has no echoes to wait for.
+        assert_eq!(synth.get_tail_size(), 0);
+        synth.params.set_parameter(101, 0.5);
+        // The eleventh echo is the first 60 dB down.
+        let echoes = 0.25 * 11.0 * 44100.0;
+        assert!((synth.get_tail_size() as f64 - echoes).abs() <= 1.0);
+        // The reverb rings on after the last echo.
+        synth.params.set_parameter(105, 0.5);
+        let reverb = (crate::reverb::tail(0.5) * 44100.0).round();
+        assert!((synth.get_tail_size() as f64 - echoes - reverb).abs() <= 1.0);
+    }
+
+    #[test]
+    fn synced_delay_without_host_time_uses_the_free_time() {
+        let render_delay = |sync: f32| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(97, 0.5);
+            synth.params.set_parameter(98, sync);
+            synth.params.set_parameter(101, 0.5);
+            synth.process_midi_event([144, 69, 127]);
+            render(&mut synth, 8192)
+        };
+        assert_eq!(render_delay(1.0), render_delay(0.0));
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(99), "1/8D");
+        synth.params.set_parameter(99, 0.0);
+        assert_eq!(synth.params.display_text(99), "1 bar");
+    }
+
+    #[test]
+    fn programs_keep_their_own_values() {
+        let synth = SineSynth::default();
+        let params = &synth.params;
+        assert_eq!(synth.get_info().presets, 16);
+        params.set_parameter(0, 0.25);
+        params.set_preset_name("Quiet".to_string());
+
+        params.change_preset(3);
+        assert_eq!(params.get_preset_num(), 3);
+        assert_eq!(params.get_parameter(0), 0.5);
+        params.set_parameter(0, 0.9);
+        params.set_parameter(38, 0.4);
+        params.set_preset_name("Dark".to_string());
+
+        params.change_preset(0);
+        assert_eq!(params.get_parameter(0), 0.25);
+        assert_eq!(params.get_parameter(38), 1.0);
+        params.change_preset(3);
+        assert_eq!(params.get_parameter(0), 0.9);
+        assert_eq!(params.get_parameter(38), 0.4);
+
+        assert_eq!(params.get_preset_name(0), "Quiet");
+        assert_eq!(params.get_preset_name(3), "Dark");
+        assert_eq!(params.get_preset_name(1), "Soft Pad");
+        assert_eq!(params.get_preset_name(16), "");
+        params.change_preset(16);
+        params.change_preset(-1);
+        assert_eq!(params.get_preset_num(), 3);
+    }
+
+    #[test]
+    fn program_switch_waits_for_the_next_block() {
+        let mut synth = SineSynth::default();
+        let params = Arc::clone(&synth.params);
+        params.set_parameter(0, 0.25);
+        synth.resume();
+        params.change_preset(1);
+        assert_eq!(params.get_preset_num(), 1);
+        assert_eq!(params.get_parameter(0), 0.25);
+        render(&mut synth, 64);
+        assert_eq!(params.get_parameter(0), 0.5);
+        assert_eq!(params.get_preset_num(), 1);
+
+        // Once the audio thread stops, the host switches for itself.
+        params.change_preset(0);
+        synth.suspend();
+        assert_eq!(params.get_parameter(0), 0.25);
+        params.change_preset(1);
+        assert_eq!(params.get_parameter(0), 0.5);
+    }
+
+    #[test]
+    fn pan_law_is_equal_power_and_3_db_down_in_the_centre() {
+        assert_eq!(pan_gains(0.0), (1.0, 0.0));
+        assert_eq!(pan_gains(1.0), (0.0, 1.0));
+        let (left, right) = pan_gains(0.5);
+        assert_eq!(left, right);
+        assert!((20.0 * left.log10() + 3.01).abs() < 0.01);
+        for step in 0..=10 {
+            let (left, right) = pan_gains(step as f32 / 10.0);
+            assert!((left * left + right * right - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn centre_pan_is_a_single_output_3_db_down() {
+        let mut mono = instant_synth();
+        mono.process_midi_event([144, 69, 127]);
+        let mono = render_outputs(&mut mono, 2048, 1).swap_remove(0);
+
+        let mut stereo = instant_synth();
+        stereo.process_midi_event([144, 69, 127]);
+        let outputs = render_outputs(&mut stereo, 2048, 2);
+        assert_eq!(outputs[0], outputs[1]);
+        for (&side, &mono) in outputs[0].iter().zip(mono.iter()) {
+            assert!((f64::from(side) - f64::from(mono) * FRAC_1_SQRT_2).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn hard_pan_silences_the_other_side() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(63, 0.0);
+        synth.process_midi_event([144, 69, 127]);
+        let outputs = render_outputs(&mut synth, 2048, 2);
+        assert!(rms(&outputs[0]) > 0.1);
+        assert!(outputs[1].iter().all(|&s| s == 0.0));
+
+        // A single output ignores the pan.
+        let mut mono = instant_synth();
+        mono.params.set_parameter(63, 0.0);
+        mono.process_midi_event([144, 69, 127]);
+        let mono = render_outputs(&mut mono, 2048, 1).swap_remove(0);
+        assert!((rms(&outputs[0]) / rms(&mono) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pan_moves_without_steps() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 69, 127]);
+        render_outputs(&mut synth, 2048, 2);
+        synth.params.set_parameter(63, 1.0);
+        let left = render_outputs(&mut synth, 4410, 2).swap_remove(0);
+        // The left channel fades out over a few ms instead of cutting off.
+        assert!(rms(&left[..100]) > 0.1);
+        assert!(rms(&left[4000..]) < 1e-3);
+    }
+
+    #[test]
+    fn tremolo_modulates_the_level_at_its_rate_and_depth() {
+        for &(depth, trough) in &[(1.0, 0.0), (0.5, 0.5)] {
+            let mut synth = instant_synth();
+            // 5 Hz.
+            synth.params.set_parameter(65, 50f32.ln() / 200f32.ln());
+            synth.params.set_parameter(66, depth);
+            synth.process_midi_event([144, 69, 127]);
+            render(&mut synth, 44100);
+            // 10 ms windows over one second.
+            let envelope = rms_envelope(&render(&mut synth, 44100), 441);
+            let peak = envelope.iter().cloned().fold(0.0, f64::max);
+            let low = envelope.iter().cloned().fold(peak, f64::min);
+            assert!(
+                (low / peak - trough).abs() < 0.05,
+                "{}: {}",
+                depth,
+                low / peak
+            );
+
+            // Each cycle crosses the middle level on the way up once.
+            let middle = (peak + low) / 2.0;
+            let rises = envelope
+                .windows(2)
+                .filter(|pair| pair[0] < middle && pair[1] >= middle)
+                .count();
+            assert!((4..=6).contains(&rises), "{} rises", rises);
+        }
+    }
+
+    #[test]
+    fn zero_tremolo_depth_is_a_bypass() {
+        let mut plain = instant_synth();
+        plain.process_midi_event([144, 69, 127]);
+        let mut tremolo = instant_synth();
+        tremolo.params.set_parameter(65, 1.0);
+        tremolo.process_midi_event([144, 69, 127]);
+        assert_eq!(render(&mut plain, 4096), render(&mut tremolo, 4096));
+    }
+
+    #[test]
+    fn full_drive_adds_odd_harmonics() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(67, 1.0);
+        synth.process_midi_event([144, 69, 127]);
+        render(&mut synth, 4096);
+        let output = render(&mut synth, 8192);
+        let fundamental = magnitude_at(&output, 440.0, 44100.0);
+        for &odd in &[1320.0, 2200.0] {
+            assert!(magnitude_at(&output, odd, 44100.0) > 0.05 * fundamental);
+        }
+        // A symmetric clipper leaves the even harmonics out.
+        assert!(magnitude_at(&output, 880.0, 44100.0) < 0.01 * fundamental);
+    }
+
+    #[test]
+    fn drive_never_exceeds_full_scale() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(0, amplitude_to_normalized(0.0));
+        synth.params.set_parameter(67, 1.0);
+        synth
+            .params
+            .set_parameter(10, Waveform::Saw.to_normalized());
+        synth.params.set_parameter(38, 0.4);
+        synth.params.set_parameter(39, 1.0); // full resonance
+        for &note in &[40, 52, 59, 64] {
+            synth.process_midi_event([144, note, 127]);
+        }
+        let outputs = render_outputs(&mut synth, 8192, 2);
+        assert!(outputs.iter().flatten().all(|s| s.abs() <= 1.0));
+        assert!(rms(&outputs[0]) > 0.3);
+    }
+
+    #[test]
+    fn limiter_holds_an_overdriven_patch_to_full_scale() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(0, 1.0); // +6 dB
+        synth
+            .params
+            .set_parameter(10, Waveform::Saw.to_normalized());
+        synth.params.set_parameter(16, 1.0);
+        synth.params.set_parameter(38, 0.4);
+        synth.params.set_parameter(39, 1.0);
+        synth.params.set_parameter(63, 0.0); // hard left, another 3 dB
+        for &note in &[40, 52, 59, 64] {
+            synth.process_midi_event([144, note, 127]);
+        }
+        let outputs = render_outputs(&mut synth, 8192, 2);
+        assert!(outputs.iter().flatten().all(|s| s.abs() <= 1.0));
+        assert!(outputs[0].iter().any(|s| s.abs() > 0.99));
+    }
+
+    #[test]
+    fn limiter_leaves_a_quiet_patch_untouched() {
+        let render_with = |limiter: f32| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(68, limiter);
+            synth.params.set_parameter(64, 1.0);
+            synth.process_midi_event([144, 60, 127]);
+            synth.process_midi_event([144, 64, 100]);
+            render_outputs(&mut synth, 8192, 2)
+        };
+        assert_eq!(render_with(1.0), render_with(0.0));
+    }
+
+    #[test]
+    fn chorus_at_no_mix_leaves_the_output_untouched() {
+        let render_with = |rate: f32, depth: f32, mix: f32| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(94, rate);
+            synth.params.set_parameter(95, depth);
+            synth.params.set_parameter(96, mix);
+            synth.process_midi_event([144, 60, 127]);
+            synth.process_midi_event([144, 64, 100]);
+            render_outputs(&mut synth, 8192, 2)
+        };
+        let dry = render_with(0.5, 0.5, 0.0);
+        assert_eq!(render_with(1.0, 1.0, 0.0), dry);
+        // The first samples of the wet signal are the empty delay lines.
+        let wet = render_with(0.5, 0.5, 1.0);
+        assert_ne!(wet, dry);
+        assert!(wet[0][..200].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn reverb_at_no_mix_is_bypassed_exactly() {
+        let render_with = |size: f32, damping: f32, mix: f32| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(103, size);
+            synth.params.set_parameter(104, damping);
+            synth.params.set_parameter(105, mix);
+            synth.process_midi_event([144, 60, 127]);
+            render_outputs(&mut synth, 8192, 2)
+        };
+        let dry = render_with(0.5, 0.5, 0.0);
+        assert_eq!(render_with(1.0, 0.0, 0.0), dry);
+        let wet = render_with(0.5, 0.5, 0.5);
+        assert_ne!(wet[0], wet[1]);
+    }
+
+    #[test]
+    fn neutral_lo_fi_settings_leave_the_output_untouched() {
+        let render_with = |quantize: f32, crush: bool| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(108, quantize);
+            if crush {
+                assert!(synth.params.string_to_parameter(106, "3 bits".to_string()));
+                assert!(synth.params.string_to_parameter(107, "8x".to_string()));
+            }
+            synth.process_midi_event([144, 60, 127]);
+            render_outputs(&mut synth, 8192, 2)
+        };
+        let clean = render_with(0.0, false);
+        assert_eq!(render_with(1.0, false), clean);
+        assert_ne!(render_with(0.0, true), clean);
+
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(106), "16.0 bits");
+        assert_eq!(synth.params.display_text(107), "1x");
+        assert_eq!(synth.params.display_text(108), "Round");
+        assert!(synth.params.string_to_parameter(107, "8x".to_string()));
+        assert_eq!(synth.params.display_text(107), "8x");
+    }
+
+    #[test]
+    fn brightness_tilts_noise_evenly_both_ways() {
+        let render_noise = |brightness: f32| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(18, 1.0);
+            synth.params.set_parameter(109, brightness);
+            synth.process_midi_event([144, 69, 127]);
+            render(&mut synth, 16384).split_off(4096)
+        };
+        let clean = render_noise(0.5);
+        let gain_db = |rendered: &[f32], low: f64| {
+            10.0 * (octave_power(rendered, low, 44100.0) / octave_power(&clean, low, 44100.0))
+                .log10()
+        };
+        // The octaves centred on 100 Hz and 10 kHz.
+        let (bass, treble) = (100.0 / 2f64.sqrt(), 10000.0 / 2f64.sqrt());
+        let (bright, dark) = (render_noise(1.0), render_noise(0.0));
+        assert!(gain_db(&bright, treble) > 4.0);
+        assert!(gain_db(&bright, bass) < -4.0);
+        assert!((gain_db(&bright, bass) + gain_db(&dark, bass)).abs() < 0.5);
+        assert!((gain_db(&bright, treble) + gain_db(&dark, treble)).abs() < 0.5);
+    }
+
+    #[test]
+    fn brightness_back_at_centre_is_transparent() {
+        let render_with = |detour: bool| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(18, 0.5);
+            if detour {
+                synth.params.set_parameter(109, 1.0);
+            }
+            render(&mut synth, 64);
+            synth.params.set_parameter(109, 0.5);
+            synth.process_midi_event([144, 69, 127]);
+            render_outputs(&mut synth, 4096, 2)
+        };
+        assert_eq!(render_with(true), render_with(false));
+    }
+
+    #[test]
+    fn level_key_tracking_turns_high_notes_down() {
+        let level = |track: f32, note: u8| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(110, track);
+            // Softly, so C2 turned up 12 dB stays clear of the limiter.
+            synth.process_midi_event([144, note, 16]);
+            rms(&render(&mut synth, 4410))
+        };
+        let spread_db = |track: f32| 20.0 * (level(track, 84) / level(track, 36)).log10();
+        // C6 is four octaves above C2.
+        let tracked = spread_db(0.0) - spread_db(0.5);
+        assert!(
+            (tracked + 4.0 * MAX_LEVEL_KEY_TRACK).abs() < 0.1,
+            "{} dB",
+            tracked
+        );
+        // Middle C is where it pivots.
+        assert!((level(0.0, 60) / level(0.5, 60) - 1.0).abs() < 1e-9);
+
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(110), "+0.0 dB/oct");
+        synth.params.set_parameter(110, 0.0);
+        assert_eq!(synth.params.display_text(110), "-6.0 dB/oct");
+        let value = synth.params.parse_parameter(110, "3 dB/oct").unwrap();
+        assert!((value - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn level_key_tracking_follows_a_bend() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(110, 0.0);
+        synth.process_midi_event([144, 60, 16]);
+        render(&mut synth, 4410);
+        let straight = rms(&render(&mut synth, 4410));
+        // All the way down, which a small vibrato on top doesn't move the level from.
+        synth.process_midi_event([0xE0, 0, 0]);
+        render(&mut synth, 4410);
+        let bent = rms(&render(&mut synth, 4410));
+        let octaves = f64::from(synth.params.bend_range()) / 12.0;
+        let louder = 20.0 * (bent / straight).log10();
+        assert!(
+            (louder - octaves * MAX_LEVEL_KEY_TRACK).abs() < 0.05,
+            "{} dB",
+            louder
+        );
+    }
+
+    #[test]
+    fn every_output_layout_gets_finite_audio() {
+        let mut rendered = Vec::new();
+        for &channels in &[1, 2, 4] {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(64, 1.0);
+            synth.process_midi_event([144, 69, 127]);
+            let mut host_buffer: HostBuffer<f32> = HostBuffer::new(0, channels);
+            let inputs: [Vec<f32>; 0] = [];
+            // Every sample should be written, including the silent ones.
+            let mut outputs = vec![vec![f32::NAN; 2048]; channels];
+            synth.process(&mut host_buffer.bind(&inputs, &mut outputs));
+            assert!(outputs.iter().flatten().all(|s| s.is_finite()));
+            for output in outputs.iter().take(2) {
+                assert!(rms(output) > 0.1, "{} outputs", channels);
+            }
+            for output in outputs.iter().skip(2) {
+                assert!(output.iter().all(|&s| s == 0.0), "{} outputs", channels);
+            }
+            rendered.push(outputs);
+        }
+        // The single output is the stereo pair summed, less the centre pan's 3 dB, and the first
+        // two of four are the pair.
+        let (mono, stereo) = (&rendered[0][0], &rendered[1]);
+        for (i, &sample) in mono.iter().enumerate() {
+            let sum = 0.5 * (stereo[0][i] + stereo[1][i]) * std::f32::consts::SQRT_2;
+            assert!((sample - sum).abs() < 1e-6);
+        }
+        assert_eq!(rendered[2][..2], stereo[..]);
+    }
+
+    #[test]
+    fn double_precision_renders_the_same_samples() {
+        let play = |synth: &mut SineSynth| {
+            synth
+                .params
+                .set_parameter(10, Waveform::Saw.to_normalized());
+            synth.params.set_parameter(16, 0.5);
+            synth.params.set_parameter(38, 0.5);
+            synth.params.set_parameter(0, amplitude_to_normalized(0.0));
+            synth.process_midi_event([144, 57, 100]);
+            synth.process_midi_event([144, 64, 90]);
+        };
+        let mut single = instant_synth();
+        play(&mut single);
+        let single = render_outputs(&mut single, 4096, 2);
+
+        let mut double = instant_synth();
+        assert!(double.get_info().f64_precision);
+        play(&mut double);
+        let mut host_buffer: HostBuffer<f64> = HostBuffer::new(0, 2);
+        let inputs: [Vec<f64>; 0] = [];
+        let mut outputs = vec![vec![0.0; 4096]; 2];
+        double.process_f64(&mut host_buffer.bind(&inputs, &mut outputs));
+
+        for (single, double) in single.iter().zip(&outputs) {
+            assert!(rms(single) > 0.05);
+            let narrowed: Vec<f32> = double.iter().map(|&sample| sample as f32).collect();
+            assert_eq!(*single, narrowed);
+        }
+    }
+
+    #[test]
+    fn zero_stereo_width_renders_identical_channels() {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(10, Waveform::Saw.to_normalized());
+        synth.params.set_parameter(16, 0.5); // three unison copies
+        synth.process_midi_event([144, 69, 127]);
+        let outputs = render_outputs(&mut synth, 4096, 2);
+        assert!(rms(&outputs[0]) > 0.1);
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    /// The normalized cross-correlation of two equally long signals at zero lag.
+    fn correlation(a: &[f32], b: &[f32]) -> f64 {
+        let dot = |a: &[f32], b: &[f32]| -> f64 {
+            a.iter()
+                .zip(b)
+                .map(|(&x, &y)| f64::from(x) * f64::from(y))
+                .sum()
+        };
+        dot(a, b) / (dot(a, a) * dot(b, b)).sqrt()
+    }
+
+    #[test]
+    fn full_stereo_width_decorrelates_the_channels() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(64, 1.0);
+        synth.process_midi_event([144, 69, 127]);
+        let outputs = render_outputs(&mut synth, 44100, 2);
+        let (left, right) = (&outputs[0], &outputs[1]);
+        assert!(correlation(left, right) < 0.9);
+
+        // Summed to mono the channels beat against each other rather than cancelling.
+        let sum: Vec<f32> = left.iter().zip(right).map(|(l, r)| 0.5 * (l + r)).collect();
+        assert!(rms(&sum) > 0.6 * rms(left));
+        for window in sum.chunks(4410) {
+            assert!(rms(window) > 0.1 * rms(left));
+        }
+    }
+
+    #[test]
+    fn midi_program_change_selects_a_program() {
+        let mut synth = SineSynth::default();
+        let params = Arc::clone(&synth.params);
+        params.change_preset(3);
+        params.set_parameter(0, 0.2);
+        params.set_parameter(38, 0.4);
+        params.change_preset(0);
+
+        synth.queue_midi_event(10, [0xC0, 3, 0]);
+        render(&mut synth, 64);
+        assert_eq!(params.get_preset_num(), 3);
+        assert_eq!(params.get_parameter(0), 0.2);
+        assert_eq!(params.get_parameter(38), 0.4);
+
+        // Programs past the end select the last one.
+        synth.queue_midi_event(0, [0xC0, 127, 0]);
+        render(&mut synth, 64);
+        assert_eq!(params.get_preset_num(), 15);
+
+        params.set_parameter(62, 0.0);
+        synth.queue_midi_event(0, [0xC0, 3, 0]);
+        render(&mut synth, 64);
+        assert_eq!(params.get_preset_num(), 15);
+        assert_eq!(params.get_parameter(0), 0.5);
+    }
+
+    #[test]
+    fn chunks_restore_saved_state() {
+        let synth = SineSynth::default();
+        let params = &synth.params;
+        params.set_parameter(0, 0.3);
+        params.set_parameter(10, 0.6);
+        params.set_preset_name("Saved".to_string());
+        let preset = params.get_preset_data();
+        params.change_preset(2);
+        params.set_parameter(0, 0.8);
+        let bank = params.get_bank_data();
+
+        params.set_parameter(0, 0.1);
+        params.set_parameter(10, 0.0);
+        params.load_preset_data(&preset);
+        assert_eq!(params.get_parameter(0), 0.3);
+        assert_eq!(params.get_parameter(10), 0.6);
+        assert_eq!(params.get_preset_name(2), "Saved");
+
+        let other = SineSynth::default();
+        other.params.load_bank_data(&bank);
+        assert_eq!(other.params.get_preset_num(), 2);
+        assert_eq!(other.params.get_parameter(0), 0.8);
+        other.params.change_preset(0);
+        assert_eq!(other.params.get_parameter(0), 0.3);
+        assert_eq!(other.params.get_parameter(10), 0.6);
+        assert_eq!(other.params.get_preset_name(0), "Saved");
+    }
+
+    #[test]
+    fn bypass_is_left_out_of_programs() {
+        let params = SineSynth::default().params;
+        params.set_parameter(92, 1.0);
+        params.change_preset(1);
+        assert_eq!(params.display_text(92), "On");
+        let preset = params.get_preset_data();
+        let bank = params.get_bank_data();
+        params.change_preset(0);
+        assert_eq!(params.display_text(92), "On");
+        params.load_preset_data(&preset);
+        params.load_bank_data(&bank);
+        assert_eq!(params.display_text(92), "On");
+
+        let other = SineSynth::default();
+        other.params.load_preset_data(&preset);
+        assert_eq!(other.params.display_text(92), "Off");
+        other.params.load_bank_data(&bank);
+        assert_eq!(other.params.display_text(92), "Off");
+        other.params.change_preset(0);
+        assert_eq!(other.params.display_text(92), "Off");
+    }
+
+    /// Chunks saved by every version of the format so far, which must keep loading.
+    #[test]
+    fn chunk_fixtures_load() {
+        let load = |data: &[u8]| {
+            let synth = SineSynth::default();
+            synth.params.load_preset_data(data);
+            synth
+        };
+        // From when the plugin had only amplitude and attack.
+        let synth = load(include_bytes!("../tests/fixtures/program-v1.chunk"));
+        assert_eq!(synth.params.get_preset_name(0), "Version 1");
+        assert_eq!(synth.params.get_parameter(0), 0.25);
+        assert_eq!(synth.params.get_parameter(1), 0.75);
+        assert_eq!(synth.params.get_parameter(38), 1.0);
+
+        let synth = load(include_bytes!("../tests/fixtures/program-v2.chunk"));
+        assert_eq!(synth.params.get_preset_name(0), "Version 2");
+        assert_eq!(synth.params.get_parameter(0), 0.25);
+        assert_eq!(synth.params.get_parameter(1), 0.75);
+        assert_eq!(synth.params.get_parameter(38), 0.5);
+
+        // As a later build might write it: a parameter this one doesn't have, and a field that
+        // isn't a parameter value at all.
+        let synth = load(include_bytes!(
+            "../tests/fixtures/program-v2-later-fields.chunk"
+        ));
+        assert_eq!(synth.params.get_preset_name(0), "Later");
+        assert_eq!(synth.params.get_parameter(0), 0.25);
+        assert_eq!(synth.params.get_parameter(38), 0.5);
+
+        let defaults = SineSynth::default();
+        let synth = SineSynth::default();
+        synth
+            .params
+            .load_bank_data(include_bytes!("../tests/fixtures/bank-v1.chunk"));
+        assert_eq!(synth.params.get_preset_num(), 1);
+        assert_eq!(synth.params.get_parameter(0), 0.625);
+        assert_eq!(synth.params.get_parameter(1), 0.875);
+        assert_eq!(synth.params.get_preset_name(0), "First");
+        // Slots the bank didn't have keep their factory programs.
+        assert_eq!(synth.params.get_preset_name(2), "Pluck");
+        synth.params.change_preset(0);
+        assert_eq!(synth.params.get_parameter(0), 0.125);
+        assert_eq!(
+            synth.params.get_parameter(2),
+            defaults.params.get_parameter(2)
+        );
+
+        let synth = SineSynth::default();
+        synth
+            .params
+            .load_bank_data(include_bytes!("../tests/fixtures/bank-v2.chunk"));
+        assert_eq!(synth.params.get_preset_name(0), "Pad");
+        assert_eq!(synth.params.get_parameter(0), 0.5);
+        assert_eq!(synth.params.get_parameter(1), 1.0);
+        assert_eq!(synth.params.get_parameter(38), 0.25);
+    }
+
+    #[test]
+    fn unreadable_chunks_change_nothing() {
+        let synth = SineSynth::default();
+        synth.params.set_parameter(0, 0.3);
+        let data = synth.params.get_preset_data();
+        synth.params.load_preset_data(&data[..data.len() - 1]);
+        synth.params.load_preset_data(&[]);
+        synth.params.load_bank_data(&[1, 0, 0]);
+        synth.params.load_bank_data(&data);
+        assert_eq!(synth.params.get_parameter(0), 0.3);
+        assert_eq!(synth.params.get_preset_num(), 0);
+    }
+
+    #[test]
+    fn parameter_text_round_trips() {
+        let synth = SineSynth::default();
+        let defaults = SineSynth::default();
+        let count = synth.get_info().parameters;
+        for index in 0..count {
+            let default = defaults.params.get_parameter(index);
+            let text = synth.params.display_text(index);
+            assert!(synth.params.string_to_parameter(index, text.clone()));
+            let parsed = synth.params.get_parameter(index);
+            assert!(
+                (parsed - default).abs() < 2e-3,
+                "{} {:?}: {} for {}",
+                index,
+                text,
+                parsed,
+                default
+            );
+
+            for step in 0..=10 {
+                synth.params.set_parameter(index, step as f32 / 10.0);
+                let text = synth.params.display_text(index);
+                assert!(synth.params.string_to_parameter(index, text.clone()));
+                assert_eq!(synth.params.display_text(index), text, "{}", index);
+            }
+            synth.params.set_parameter(index, default);
+        }
+    }
+
+    #[test]
+    fn typed_values_accept_units() {
+        let synth = SineSynth::default();
+        let typed = |index: i32, text: &str| {
+            assert!(
+                synth.params.string_to_parameter(index, text.to_string()),
+                "{}",
+                text
+            );
+            synth.params.display_text(index)
+        };
+        assert_eq!(typed(0, "-6"), "-6.0 dB");
+        assert_eq!(typed(0, "-12 dB"), "-12.0 dB");
+        assert_eq!(typed(0, "+20dB"), "6.0 dB");
+        assert_eq!(typed(1, "250ms"), "250 ms");
+        assert_eq!(typed(1, "0.25s"), "250 ms");
+        assert_eq!(typed(1, "2.5 S"), "2.5 s");
+        assert_eq!(typed(3, "50"), "50%");
+        assert_eq!(typed(3, "25 %"), "25%");
+        assert_eq!(typed(9, "omni"), "Omni");
+        assert_eq!(typed(9, "Ch 3"), "Ch 3");
+        assert_eq!(typed(10, "saw"), "Saw");
+        assert_eq!(typed(38, "2.5 kHz"), "2.5 kHz");
+        assert_eq!(typed(38, "440"), "440 Hz");
+        assert_eq!(typed(54, "off"), "Off");
+
+        for &(index, text) in &[(0, "loud"), (1, "ms"), (1, ""), (9, "Ch 17"), (10, "Kazoo")] {
+            let before = synth.params.get_parameter(index);
+            assert!(!synth.params.string_to_parameter(index, text.to_string()));
+            assert_eq!(synth.params.get_parameter(index), before);
+        }
+        assert!(!synth.params.string_to_parameter(-1, "1".to_string()));
+    }
+
+    /// A synth playing a plucked A3 at full velocity.
+    fn pluck_synth(damping: f32) -> SineSynth {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(10, Waveform::Pluck.to_normalized());
+        synth.params.set_parameter(36, damping);
+        synth.process_midi_event([144, 57, 127]);
+        synth
+    }
+
+    #[test]
+    fn pluck_is_in_tune() {
+        let out = render(&mut pluck_synth(0.0), 16384);
+
+        // The strongest component within half a semitone of A3, to the nearest cent.
+        let peak = (-50..=50)
+            .map(|cents| 220.0 * (f64::from(cents) / 1200.0).exp2())
+            .max_by(|a, b| {
+                let level = |freq| magnitude_at(&out, freq, 44100.0);
+                level(*a).partial_cmp(&level(*b)).unwrap()
+            })
+            .unwrap();
+        let cents = cents_between(peak, 220.0);
+        assert!(cents.abs() <= 3.0, "off by {} cents", cents);
+    }
+
+    #[test]
+    fn damping_shortens_decay() {
+        // How many 50 ms chunks pass before the level falls below a tenth of the first one.
+        let decay_chunks = |damping: f32| {
+            let out = render(&mut pluck_synth(damping), 44100 * 3);
+            let levels: Vec<f64> = out.chunks(2205).map(rms).collect();
+            levels.iter().position(|&l| l < levels[0] / 10.0).unwrap()
+        };
+        let decays: Vec<usize> = [0.2, 0.4, 0.6, 0.8]
+            .iter()
+            .map(|&d| decay_chunks(d))
+            .collect();
+        assert!(decays.windows(2).all(|w| w[1] < w[0]), "{:?}", decays);
+    }
+
+    #[test]
+    fn repeated_pluck_reuses_string() {
+        let mut synth = pluck_synth(0.3);
+        render(&mut synth, 4410);
+        synth.process_midi_event([144, 57, 127]);
+        assert_eq!(synth.voices.iter().filter(|v| v.active).count(), 1);
+        let out = render(&mut synth, 4410);
+        assert!(out.iter().all(|s| s.abs() <= 1.0));
+    }
+
+    #[test]
+    fn cutoff_dulls_harmonics() {
+        let render_with = |cutoff: f32| {
+            let mut synth = instant_synth();
+            synth
+                .params
+                .set_parameter(10, Waveform::Saw.to_normalized());
+            synth.params.set_parameter(38, cutoff);
+            synth.process_midi_event([144, 57, 127]);
+            render(&mut synth, 8192)
+        };
+        // 20 Hz × 1000^0.5 is about 632 Hz.
+        let open = render_with(1.0);
+        let closed = render_with(0.5);
+
+        let drop = |freq| magnitude_at(&closed, freq, 44100.0) / magnitude_at(&open, freq, 44100.0);
+        assert!((drop(220.0) - 1.0).abs() < 0.1);
+        assert!(drop(2200.0) < 0.1);
+    }
+
+    #[test]
+    fn cutoff_text() {
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(38), "Open");
+        synth.params.set_parameter(38, 0.5);
+        assert_eq!(synth.params.display_text(38), "632 Hz");
+        synth.params.set_parameter(38, 0.9);
+        assert_eq!(synth.params.display_text(38), "10.0 kHz");
+    }
+
+    /// The amplitude-weighted average frequency of the first 40 harmonics of A3.
+    fn harmonic_centroid(signal: &[f32]) -> f64 {
+        let harmonics: Vec<(f64, f64)> = (1..=40)
+            .map(|k| 220.0 * f64::from(k))
+            .map(|freq| (freq, magnitude_at(signal, freq, 44100.0)))
+            .collect();
+        let total: f64 = harmonics.iter().map(|(_, level)| level).sum();
+        harmonics
+            .iter()
+            .map(|(freq, level)| freq * level)
+            .sum::<f64>()
+            / total
+    }
+
+    /// A synth playing a saw through a dark filter, with the filter envelope settings given
+    /// as (amount, attack, decay, sustain).
+    fn filter_env_synth(amount: f32, attack: f32, decay: f32, sustain: f32) -> SineSynth {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(10, Waveform::Saw.to_normalized());
+        synth.params.set_parameter(38, 0.3);
+        for (index, value) in (40..44).zip(&[amount, attack, decay, sustain]) {
+            synth.params.set_parameter(index, *value);
+        }
+        synth.process_midi_event([144, 57, 127]);
+        synth
+    }
+
+    #[test]
+    fn filter_envelope_darkens_over_decay() {
+        let mut synth = filter_env_synth(1.0, 0.0, 1.0, 0.0);
+        assert_eq!(synth.params.display_text(40), "+6.0 oct");
+        let centroids: Vec<f64> = render(&mut synth, 44100)
+            .chunks(8820)
+            .map(harmonic_centroid)
+            .collect();
+        assert!(centroids.windows(2).all(|w| w[1] < w[0]), "{:?}", centroids);
+    }
+
+    #[test]
+    fn negative_filter_envelope_closes_filter() {
+        let mut synth = filter_env_synth(0.0, 0.0, 1.0, 0.0);
+        synth.params.set_parameter(38, 1.0);
+        let centroids: Vec<f64> = render(&mut synth, 44100)
+            .chunks(8820)
+            .map(harmonic_centroid)
+            .collect();
+        assert!(centroids.windows(2).all(|w| w[1] > w[0]), "{:?}", centroids);
+    }
+
+    #[test]
+    fn zero_filter_env_amount_matches_static_filter() {
+        let mut moving = filter_env_synth(0.5, 0.2, 0.1, 0.0);
+        let mut fixed = filter_env_synth(0.5, 0.0, 0.0, 1.0);
+        assert_eq!(render(&mut moving, 8192), render(&mut fixed, 8192));
+    }
+
+    #[test]
+    fn high_pass_mode_removes_low_notes() {
+        let render_a2 = |mode: FilterMode| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(38, 0.6);
+            synth.params.set_parameter(46, mode.to_normalized());
+            synth.process_midi_event([144, 45, 127]);
+            render(&mut synth, 8192)
+        };
+        let low_pass = rms(&render_a2(FilterMode::LowPass));
+        let high_pass = rms(&render_a2(FilterMode::HighPass));
+        assert!(high_pass < 0.05 * low_pass, "{} vs {}", high_pass, low_pass);
+
+        let synth = SineSynth::default();
+        synth
+            .params
+            .set_parameter(46, FilterMode::Notch.to_normalized());
+        assert_eq!(synth.params.display_text(46), "Notch");
+        // Fully up is only open for the low-pass.
+        synth.params.set_parameter(38, 1.0);
+        assert_ne!(synth.params.display_text(38), "Open");
+    }
+
+    #[test]
+    fn full_key_tracking_keeps_spectral_shape() {
+        // The levels of the first eight harmonics relative to the fundamental, in dB.
+        let shape = |note: u8| {
+            let mut synth = instant_synth();
+            synth
+                .params
+                .set_parameter(10, Waveform::Additive.to_normalized());
+            for index in 28..36 {
+                synth.params.set_parameter(index, 1.0);
+            }
+            synth.params.set_parameter(38, 0.4);
+            synth.params.set_parameter(45, 1.0);
+            synth.process_midi_event([144, note, 127]);
+            let out = render(&mut synth, 16384);
+            let fundamental = midi_pitch_to_freq(note, A4_FREQ);
+            let level = |k: f64| magnitude_at(&out, k * fundamental, 44100.0);
+            // Higher up, the trapezoidal filter's frequency warping steepens the high note's
+            // slope, so only the lower harmonics are compared.
+            (2..=5)
+                .map(|k| 20.0 * (level(f64::from(k)) / level(1.0)).log10())
+                .collect::<Vec<f64>>()
+        };
+        let (c2, c6) = (shape(36), shape(84));
+        for (k, (low, high)) in c2.iter().zip(c6.iter()).enumerate() {
+            assert!(
+                (low - high).abs() < 1.0,
+                "harmonic {}: {} vs {}",
+                k + 2,
+                low,
+                high
+            );
+        }
+        // The filter really is cutting the upper harmonics.
+        assert!(c2[3] < -20.0);
+    }
+
+    /// A synth with a 500ms glide whose notes stop as soon as they are released, so only the
+    /// gliding note is heard.
+    fn gliding_synth() -> SineSynth {
+        let synth = instant_synth();
+        synth.params.set_parameter(4, 0.0);
+        synth.params.set_parameter(54, 500f32.ln() / 2000f32.ln());
+        assert_eq!(synth.params.display_text(54), "500 ms");
+        synth
+    }
+
+    /// The pitch of each 20ms stretch of `out`, in semitones.
+    fn pitch_track(out: &[f32]) -> Vec<f64> {
+        out.chunks(882)
+            .map(|chunk| 69.0 + cents_between(measure_frequency(chunk, 44100.0), 440.0) / 100.0)
+            .collect()
+    }
+
+    #[test]
+    fn glide_slides_up_a_fifth() {
+        let mut synth = gliding_synth();
+        synth.process_midi_event([144, 60, 127]);
+        render(&mut synth, 4410);
+        synth.process_midi_event([144, 67, 127]);
+        synth.process_midi_event([128, 60, 0]);
+        let out = render(&mut synth, 26460);
+
+        let pitches = pitch_track(&out);
+        // Each stretch averages its pitch, so the first is already part way up.
+        assert!(pitches[0] > 60.0 && pitches[0] < 60.5, "{:?}", pitches);
+        assert!(
+            pitches[..25].windows(2).all(|w| w[1] > w[0]),
+            "{:?}",
+            pitches
+        );
+        for &pitch in &pitches[26..] {
+            assert!((pitch - 67.0).abs() < 0.01, "{:?}", pitches);
+        }
+    }
+
+    #[test]
+    fn interrupted_glide_starts_from_current_pitch() {
+        let mut synth = gliding_synth();
+        synth.process_midi_event([144, 60, 127]);
+        render(&mut synth, 4410);
+        synth.process_midi_event([144, 72, 127]);
+        synth.process_midi_event([128, 60, 0]);
+        // Half way through the octave glide.
+        render(&mut synth, 11025);
+        synth.process_midi_event([144, 62, 127]);
+        synth.process_midi_event([128, 72, 0]);
+        let out = render(&mut synth, 26460);
+
+        let pitches = pitch_track(&out);
+        assert!((pitches[0] - 66.0).abs() < 0.5, "{:?}", pitches);
+        assert!(
+            pitches[..24].windows(2).all(|w| w[1] < w[0]),
+            "{:?}",
+            pitches
+        );
+        assert!((pitches[pitches.len() - 1] - 62.0).abs() < 0.01);
+    }
+
+    /// The pitch G4 starts from after going from C4 with `glide_mode`, in mono or poly, with
+    /// the keys overlapping or played apart while C4 is still dying away.
+    fn pitch_after_moving(glide_mode: f32, mono: bool, overlapping: bool) -> f64 {
+        let mut synth = gliding_synth();
+        synth.params.set_parameter(4, 0.5);
+        synth.params.set_parameter(55, if mono { 1.0 } else { 0.0 });
+        synth.params.set_parameter(90, glide_mode);
+        synth.process_midi_event([144, 60, 127]);
+        render(&mut synth, 4410);
+        if overlapping {
+            synth.process_midi_event([144, 67, 127]);
+            synth.process_midi_event([128, 60, 0]);
+        } else {
+            synth.process_midi_event([128, 60, 0]);
+            render(&mut synth, 441);
+            synth.process_midi_event([144, 67, 127]);
+        }
+        starting_pitch(&synth, 67)
+    }
+
+    /// The pitch the voice just started on `note` is at.
+    fn starting_pitch(synth: &SineSynth, note: u8) -> f64 {
+        synth
+            .voices
+            .iter()
+            .find(|voice| voice.active && voice.note == note)
+            .map(Voice::pitch)
+            .unwrap()
+    }
+
+    #[test]
+    fn legato_glide_only_slides_between_overlapping_notes() {
+        let synth = gliding_synth();
+        assert_eq!(synth.params.display_text(90), "Always");
+        synth.params.set_parameter(90, 1.0);
+        assert_eq!(synth.params.display_text(90), "Legato");
+        for &mono in &[false, true] {
+            for &(legato, overlapping, glides) in &[
+                (0.0, true, true),
+                (0.0, false, true),
+                (1.0, true, true),
+                (1.0, false, false),
+            ] {
+                let pitch = pitch_after_moving(legato, mono, overlapping);
+                let expected = if glides { 60.0 } else { 67.0 };
+                assert!(
+                    (pitch - expected).abs() < 0.01,
+                    "mono {} legato {} overlapping {}: {}",
+                    mono,
+                    legato,
+                    overlapping,
+                    pitch
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn glide_never_starts_from_a_note_that_has_finished() {
+        for &mono in &[false, true] {
+            let mut synth = gliding_synth();
+            synth.params.set_parameter(55, if mono { 1.0 } else { 0.0 });
+            synth.process_midi_event([144, 60, 127]);
+            render(&mut synth, 4410);
+            synth.process_midi_event([128, 60, 0]);
+            render(&mut synth, 441);
+            assert!(synth.voices.iter().all(|voice| !voice.active));
+            synth.process_midi_event([144, 67, 127]);
+            assert_eq!(starting_pitch(&synth, 67), 67.0, "mono {}", mono);
+        }
+    }
+
+    #[test]
+    fn glide_starts_from_a_held_key_once_its_note_has_finished() {
+        let mut synth = gliding_synth();
+        synth.process_midi_event([144, 60, 127]);
+        render(&mut synth, 4410);
+        // As if its voice had gone silent with the key still down.
+        let index = synth.last_voice.unwrap();
+        synth.voices[index].active = false;
+        synth.process_midi_event([144, 67, 127]);
+        assert_eq!(starting_pitch(&synth, 67), 60.0);
+    }
+
+    #[test]
+    fn mono_returns_to_held_key_under_each_priority() {
+        for &(priority, while_both_held) in &[
+            (NotePriority::Last, 64),
+            (NotePriority::Highest, 64),
+            (NotePriority::Lowest, 60),
+        ] {
+            let mut synth = instant_synth();
+            // A short decay to half level, so a retriggered attack would show as a bump.
+            synth.params.set_parameter(2, 0.01);
+            synth.params.set_parameter(3, 0.5);
+            synth.params.set_parameter(55, 1.0);
+            synth.params.set_parameter(56, priority.to_normalized());
+            assert_eq!(synth.params.display_text(55), "Mono");
+            synth.process_midi_event([144, 60, 127]);
+            let first = render(&mut synth, 4410);
+            synth.process_midi_event([144, 64, 127]);
+            let both = render(&mut synth, 4410);
+            synth.process_midi_event([128, 64, 0]);
+            let back = render(&mut synth, 4410);
+
+            let pitch =
+                |out: &[f32]| 69.0 + cents_between(measure_frequency(out, 44100.0), 440.0) / 100.0;
+            let settled = &first[2205..];
+            assert!((pitch(settled) - 60.0).abs() < 0.01, "{:?}", priority);
+            assert!(
+                (pitch(&both) - f64::from(while_both_held)).abs() < 0.01,
+                "{:?}",
+                priority
+            );
+            assert!((pitch(&back) - 60.0).abs() < 0.01, "{:?}", priority);
+            // Legato changes never restart the envelope, and only one voice ever plays.
+            for out in &[&both[..], &back[..]] {
+                let ratio = rms(out) / rms(settled);
+                assert!((ratio - 1.0).abs() < 0.01, "{:?}: {}", priority, ratio);
+            }
+        }
+    }
+
+    #[test]
+    fn releasing_top_note_returns_to_held_note() {
+        for &mono in &[0.0, 1.0] {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(4, 0.0);
+            synth.params.set_parameter(55, mono);
+            synth.process_midi_event([144, 60, 127]);
+            render(&mut synth, 4410);
+            synth.process_midi_event([144, 62, 127]);
+            render(&mut synth, 4410);
+            synth.process_midi_event([128, 62, 0]);
+            render(&mut synth, 441);
+            let out = render(&mut synth, 4410);
+            let cents = cents_between(
+                measure_frequency(&out, 44100.0),
+                midi_pitch_to_freq(60, A4_FREQ),
+            );
+            assert!(cents.abs() < 1.0, "mono {}: {} cents", mono, cents);
+        }
+    }
+
+    #[test]
+    fn mono_releases_when_last_key_goes_up() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(4, 0.0);
+        synth.params.set_parameter(55, 1.0);
+        synth.process_midi_event([144, 60, 127]);
+        synth.process_midi_event([144, 64, 127]);
+        synth.process_midi_event([128, 60, 0]);
+        synth.process_midi_event([128, 64, 0]);
+        render(&mut synth, 441);
+        assert!(is_silent(&render(&mut synth, 4410)));
+    }
+
+    #[test]
+    fn full_pool_steals_by_policy() {
+        for &(policy, victim) in &[
+            (StealPolicy::Oldest, Some(48)),
+            (StealPolicy::Quietest, Some(63)),
+            (StealPolicy::Never, None),
+        ] {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(4, 1.0);
+            synth.params.set_parameter(57, policy.to_normalized());
+            assert_eq!(synth.params.display_text(57), policy.name());
+            for i in 0..MAX_VOICES as u8 {
+                synth.process_midi_event([144, 48 + 3 * i, 127]);
+            }
+            render(&mut synth, 4410);
+            // The only voice below full level, releasing slowly.
+            synth.process_midi_event([128, 63, 0]);
+            render(&mut synth, 4410);
+            synth.process_midi_event([144, 100, 127]);
+            render(&mut synth, 882);
+            let out = render(&mut synth, 8192);
+
+            let level = |note: u8| magnitude_at(&out, midi_pitch_to_freq(note, A4_FREQ), 44100.0);
+            let reference = level(69);
+            for i in 0..MAX_VOICES as u8 {
+                let note = 48 + 3 * i;
+                if Some(note) == victim {
+                    assert!(level(note) < 0.01 * reference, "{:?}", policy);
+                } else {
+                    assert!(level(note) > 0.5 * reference, "{:?} lost {}", policy, note);
+                }
+            }
+            if victim.is_some() {
+                assert!(level(100) > 0.5 * reference, "{:?}", policy);
+            } else {
+                assert!(level(100) < 0.01 * reference, "{:?}", policy);
+            }
+        }
+    }
+
+    #[test]
+    fn single_voice_is_stolen_even_with_no_steal_policy() {
+        let mut synth = instant_synth();
+        synth
+            .params
+            .set_parameter(57, StealPolicy::Never.to_normalized());
+        synth.params.set_parameter(58, 0.0);
+        assert_eq!(synth.params.display_text(58), "1");
+        synth.process_midi_event([144, 60, 127]);
+        render(&mut synth, 882);
+        synth.process_midi_event([144, 72, 127]);
+        render(&mut synth, 882);
+        let out = render(&mut synth, 8192);
+        assert!(magnitude_at(&out, midi_pitch_to_freq(72, A4_FREQ), 44100.0) > 0.01);
+        assert!(magnitude_at(&out, midi_pitch_to_freq(60, A4_FREQ), 44100.0) < 0.001);
+    }
+
+    #[test]
+    fn polyphony_limits_sounding_notes() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(58, 1.0 / 15.0);
+        assert_eq!(synth.params.display_text(58), "2");
+        for &note in &[60, 64, 67] {
+            synth.process_midi_event([144, note, 127]);
+        }
+        render(&mut synth, 882);
+        let out = render(&mut synth, 8192);
+
+        let level = |note: u8| magnitude_at(&out, midi_pitch_to_freq(note, A4_FREQ), 44100.0);
+        let sounding = [60, 64, 67].iter().filter(|&&n| level(n) > 0.01).count();
+        assert_eq!(sounding, 2);
+        // The oldest note made way for the newest.
+        assert!(level(60) < 0.001);
+    }
+
+    #[test]
+    fn lowering_polyphony_lets_voices_finish() {
+        let mut synth = instant_synth();
+        for &note in &[60, 64, 67] {
+            synth.process_midi_event([144, note, 127]);
+        }
+        render(&mut synth, 882);
+        synth.params.set_parameter(58, 0.0);
+        let out = render(&mut synth, 8192);
+        for &note in &[60, 64, 67] {
+            assert!(magnitude_at(&out, midi_pitch_to_freq(note, A4_FREQ), 44100.0) > 0.01);
+        }
+
+        // With one voice, each new note takes over from the last.
+        synth.process_midi_event([144, 72, 127]);
+        render(&mut synth, 882);
+        let out = render(&mut synth, 8192);
+        assert!(magnitude_at(&out, midi_pitch_to_freq(72, A4_FREQ), 44100.0) > 0.01);
+        assert!(magnitude_at(&out, midi_pitch_to_freq(60, A4_FREQ), 44100.0) < 0.001);
+    }
+
+    #[test]
+    fn pitch_holds_over_minutes() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 69, 127]);
+        let start = render(&mut synth, 44100);
+        // Rendering five minutes would be slow, so the voice's phase is stepped on by itself,
+        // exactly as rendering would.
+        let increment = 440.0 / 44100.0;
+        let voice = &mut synth.voices[0];
+        for _ in 0..5 * 60 * 44100 {
+            voice.phase = (voice.phase + increment).fract();
+        }
+        assert!((0.0..1.0).contains(&voice.phase));
+        let end = render(&mut synth, 44100);
+
+        let (start, end) = (
+            measure_frequency(&start, 44100.0),
+            measure_frequency(&end, 44100.0),
+        );
+        assert!(
+            cents_between(end, start).abs() < 0.01,
+            "{} vs {}",
+            end,
+            start
+        );
+        assert!(cents_between(start, 440.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn phase_reset_makes_repeated_notes_identical() {
+        // Two overlapping notes, then long enough for both to finish.
+        let play = |synth: &mut SineSynth| {
+            synth.process_midi_event([144, 60, 100]);
+            let mut out = render(synth, 1000);
+            synth.process_midi_event([144, 64, 100]);
+            out.extend(render(synth, 1000));
+            synth.process_midi_event([128, 60, 0]);
+            synth.process_midi_event([128, 64, 0]);
+            out.extend(render(synth, 8820));
+            out
+        };
+        let mut synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(59), "On");
+        assert_eq!(play(&mut synth), play(&mut synth));
+
+        // Off, a new note picks up wherever its voice's oscillator left off, and only resuming
+        // starts them all over.
+        synth.params.set_parameter(59, 0.0);
+        play(&mut synth);
+        synth.process_midi_event([144, 60, 100]);
+        assert_ne!(synth.voices.iter().find(|v| v.active).unwrap().phase, 0.0);
+    }
+
+    #[test]
+    fn amplitude_maps_to_decibels() {
+        assert_eq!(amplitude_db(0.0), f64::NEG_INFINITY);
+        assert_eq!(db_to_gain(amplitude_db(0.0)), 0.0);
+        assert!((amplitude_db(1e-6) - MIN_AMPLITUDE_DB).abs() < 1e-3);
+        assert_eq!(db_to_gain(amplitude_db(0.5)), 0.5);
+        assert!((amplitude_db(1.0) - MAX_AMPLITUDE_DB).abs() < 1e-9);
+        assert!((db_to_gain(amplitude_db(1.0)) - 1.995).abs() < 1e-3);
+        assert!((db_to_gain(amplitude_db(0.75)) - 1.0).abs() < 0.01);
+
+        for &db in &[-59.0, -24.0, -6.0, 0.0, 6.0] {
+            assert!((amplitude_db(amplitude_to_normalized(db)) - db).abs() < 1e-4);
+        }
+        assert_eq!(amplitude_to_normalized(f64::NEG_INFINITY), 0.0);
+        assert_eq!(amplitude_to_normalized(20.0), 1.0);
+    }
+
+    #[test]
+    fn amplitude_text_is_in_decibels() {
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(0), "-6.0 dB");
+        assert_eq!(synth.params.get_parameter_label(0), "dB");
+        synth
+            .params
+            .set_parameter(0, amplitude_to_normalized(-12.0));
+        assert_eq!(synth.params.display_text(0), "-12.0 dB");
+        synth.params.set_parameter(0, 0.0);
+        assert_eq!(synth.params.display_text(0), "-inf dB");
+    }
+
+    #[test]
+    fn attack_maps_exponentially() {
+        assert_eq!(attack_seconds(0.0), 0.0);
+        assert!((attack_seconds(1e-9) - MIN_ATTACK).abs() < 1e-9);
+        assert!((attack_seconds(0.5) - (MIN_ATTACK * MAX_ATTACK).sqrt()).abs() < 1e-9);
+        assert!((attack_seconds(1.0) - MAX_ATTACK).abs() < 1e-9);
+        // Each fifth of the knob multiplies the time by the same factor.
+        let ratio = attack_seconds(0.2) / attack_seconds(0.0001);
+        for step in 1..5 {
+            let value = 0.2 * step as f64;
+            let next = attack_seconds(value + 0.2) / attack_seconds(value + 0.0001);
+            assert!((next - ratio).abs() / ratio < 1e-3);
+        }
+        for &seconds in &[0.0, 0.001, 0.1, 2.5] {
+            let value = f64::from(attack_to_normalized(seconds));
+            assert!((attack_seconds(value) - seconds).abs() <= seconds * 1e-5);
+        }
+    }
+
+    #[test]
+    fn attack_text_switches_units() {
+        let synth = SineSynth::default();
+        assert_eq!(synth.params.display_text(1), "500 ms");
+        assert_eq!(synth.params.get_parameter_text(1), "500");
+        assert_eq!(synth.params.get_parameter_label(1), "ms");
+        synth.params.set_parameter(1, attack_to_normalized(0.0012));
+        assert_eq!(synth.params.display_text(1), "1.2 ms");
+        synth.params.set_parameter(1, attack_to_normalized(1.2));
+        assert_eq!(synth.params.display_text(1), "1.2 s");
+        assert_eq!(synth.params.get_parameter_text(1), "1.2");
+        assert_eq!(synth.params.get_parameter_label(1), "s");
+        synth.params.set_parameter(1, 0.0);
+        assert_eq!(synth.params.display_text(1), "0.0 ms");
+    }
+
+    #[test]
+    fn attack_reaches_full_level_on_time() {
+        let mut synth = SineSynth::default();
+        synth.params.set_parameter(0, amplitude_to_normalized(0.0));
+        synth.params.set_parameter(1, attack_to_normalized(0.1));
+        synth.params.set_parameter(3, 1.0);
+        synth.process_midi_event([144, 69, 127]);
+        let mut level_after = |samples: usize| {
+            render(&mut synth, samples);
+            synth.voices[0].envelope.level()
+        };
+        // The envelope is a straight line, so 63% of a 100 ms attack is 63 ms in.
+        let at_63 = level_after(2778);
+        assert!((at_63 - 0.63).abs() < 1e-3, "{}", at_63);
+        assert!(level_after(1631) < 1.0);
+        assert_eq!(level_after(1), 1.0);
+    }
+
+    #[test]
+    fn amplitude_jump_ramps_smoothly() {
+        // The voice plays the same either way, so the ratio between the two is the gain.
+        let mut synth = instant_synth();
+        // Full amplitude goes past full scale, which the limiter would hold down.
+        synth.params.set_parameter(68, 0.0);
+        synth.params.set_parameter(0, 1.0);
+        synth.process_midi_event([144, 69, 100]);
+        let reference = render(&mut synth, 3205);
+
+        let mut synth = instant_synth();
+        synth.params.set_parameter(68, 0.0);
+        synth.params.set_parameter(0, 0.0);
+        synth.process_midi_event([144, 69, 100]);
+        let mut out = render(&mut synth, 1000);
+        assert!(is_silent(&out));
+        synth.params.set_parameter(0, 1.0);
+        out.extend(render(&mut synth, 2205));
+
+        let gains: Vec<Option<f32>> = out
+            .iter()
+            .zip(&reference)
+            .map(|(&s, &r)| if r.abs() > 0.1 { Some(s / r) } else { None })
+            .collect();
+        for pair in gains.windows(2) {
+            if let [Some(a), Some(b)] = *pair {
+                assert!(b >= a - 1e-6, "{} after {}", b, a);
+                assert!(b - a < 0.01, "step of {}", b - a);
+            }
+        }
+        let last = gains.iter().rev().find_map(|&g| g).unwrap();
+        assert!((last - 1.0).abs() < 1e-4, "{}", last);
+    }
+
+    #[test]
+    fn quiet_blocks_keep_time_without_rendering() {
+        let mut quiet = instant_synth();
+        let mut rendered = instant_synth();
+        assert!(quiet.is_quiet());
+        // An event for a controller nothing uses makes the block render in full.
+        rendered.queue_midi_event(0, [0xB0, 3, 0]);
+        assert!(!rendered.is_quiet());
+        assert!(is_silent(&render(&mut quiet, 1000)));
+        assert!(is_silent(&render(&mut rendered, 1000)));
+        assert!((quiet.vibrato_phase - rendered.vibrato_phase).abs() < 1e-9);
+        assert!((quiet.tremolo_phase - rendered.tremolo_phase).abs() < 1e-9);
+
+        // The next note sounds the same either way.
+        for synth in [&mut quiet, &mut rendered] {
+            synth.process_midi_event([144, 69, 100]);
+        }
+        let (quiet_note, rendered_note) = (render(&mut quiet, 2048), render(&mut rendered, 2048));
+        for (a, b) in quiet_note.iter().zip(&rendered_note) {
+            assert!((a - b).abs() < 1e-6, "{} against {}", a, b);
+        }
+        assert!(!quiet.is_quiet());
+    }
+
+    #[test]
+    fn resume_snaps_smoothed_parameters() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([144, 69, 100]);
+        render(&mut synth, 1000);
+        synth.params.set_parameter(0, 0.0);
+        synth.resume();
+        assert!(is_silent(&render(&mut synth, 64)));
+    }
+
+    #[test]
+    fn blocks_up_to_the_block_size_render() {
+        let mut synth = instant_synth();
+        synth.set_block_size(64);
+        synth.process_midi_event([144, 69, 127]);
+        let long = render(&mut synth, 64);
+        let short = render(&mut synth, 1);
+        assert!(rms(&long) > 0.05 && short[0] != 0.0);
+
+        // Zero takes the limit away again, as if the host had never said.
+        synth.set_block_size(0);
+        assert_eq!(synth.max_block_size, None);
+        assert!(rms(&render(&mut synth, 4096)) > 0.05);
+        synth.set_block_size(-1);
+        assert_eq!(synth.max_block_size, None);
+        synth.set_block_size(512);
+        synth.set_block_size(128);
+        assert_eq!(synth.max_block_size, Some(128));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "65 samples in a block of at most Some(64)")]
+    fn blocks_past_the_block_size_are_caught() {
+        let mut synth = SineSynth::default();
+        synth.set_block_size(64);
+        render(&mut synth, 65);
+    }
+
+    /// A chord of wide, detuned sine unisons with FM, the heaviest load for the lanes.
+    fn unison_chord(lanes: bool) -> SineSynth {
+        let mut synth = instant_synth();
+        synth.lanes = lanes;
+        synth.params.set_parameter(4, 0.05);
+        synth.params.set_parameter(16, 1.0);
+        synth.params.set_parameter(17, 0.7);
+        synth.params.set_parameter(24, 0.3);
+        synth.params.set_parameter(64, 0.8);
+        for &note in &[48, 55, 60, 64, 67, 71, 74, 79] {
+            synth.process_midi_event([144, note, 100]);
+        }
+        synth
+    }
+
+    #[test]
+    fn lanes_match_the_scalar_path() {
+        let mut lanes = unison_chord(true);
+        let mut scalar = unison_chord(false);
+        let mut with_lanes = render_outputs(&mut lanes, 8192, 2);
+        let mut one_at_a_time = render_outputs(&mut scalar, 8192, 2);
+        assert!(rms(&with_lanes[0]) > 0.01);
+        // Releasing partway through a block ends the notes partway through a chunk.
+        for synth in [&mut lanes, &mut scalar] {
+            for &note in &[48, 55, 60, 64, 67, 71, 74, 79] {
+                synth.queue_midi_event(101, [128, note, 0]);
+            }
+        }
+        for (outputs, synth) in [
+            (&mut with_lanes, &mut lanes),
+            (&mut one_at_a_time, &mut scalar),
+        ] {
+            for (output, more) in outputs.iter_mut().zip(render_outputs(synth, 16384, 2)) {
+                output.extend(more);
+            }
+        }
+        assert!(lanes.voices.iter().all(|voice| !voice.active));
+        for (lanes, scalar) in with_lanes.iter().zip(&one_at_a_time) {
+            for (a, b) in lanes.iter().zip(scalar) {
+                assert!((a - b).abs() < 1e-6, "{} against {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn oversampling_cuts_the_aliases_of_a_driven_high_note() {
+        let note = 100;
+        let fundamental = midi_pitch_to_freq(note, A4_FREQ);
+        let aliases_and_level = |oversampling: f32| {
+            let mut synth = instant_synth();
+            synth.params.set_parameter(67, 1.0);
+            synth.params.set_parameter(93, oversampling);
+            synth.process_midi_event([144, note, 127]);
+            render(&mut synth, 4410);
+            let out = render(&mut synth, 8192);
+            // The drive's harmonics above Nyquist, folded back down to where they land.
+            let aliases: f64 = (2..40)
+                .map(|harmonic| harmonic as f64 * fundamental)
+                .filter(|&freq| freq > 22050.0)
+                .map(|freq| {
+                    let folded = freq % 44100.0;
+                    let folded = if folded > 22050.0 {
+                        44100.0 - folded
+                    } else {
+                        folded
+                    };
+                    magnitude_at(&out, folded, 44100.0)
+                })
+                .sum();
+            (aliases, magnitude_at(&out, fundamental, 44100.0))
+        };
+        let (off, off_level) = aliases_and_level(0.0);
+        let (x4, x4_level) = aliases_and_level(1.0);
+        let reduction = 20.0 * (off / x4).log10();
+        assert!(reduction > 20.0, "only {} dB less", reduction);
+        // The note itself comes through at the same level.
+        let change = 20.0 * (x4_level / off_level).log10();
+        assert!(
+            change.abs() < 0.1,
+            "{} dB, aliases down {} dB",
+            change,
+            reduction
+        );
+    }
+
+    #[test]
+    fn latency_follows_oversampling() {
+        let synth = SineSynth::default();
+        assert_eq!(synth.get_info().initial_delay, 0);
+        for &(value, latency) in &[(0.5, 16), (1.0, 24), (0.0, 0)] {
+            synth.params.set_parameter(93, value);
+            assert_eq!(synth.get_info().initial_delay, latency);
+        }
+        assert_eq!(synth.params.display_text(93), "Off");
+        assert_eq!(synth.params.parse_parameter(93, "4x"), Some(1.0));
+    }
+
+    #[test]
+    fn stopping_and_starting_leaves_nothing_behind() {
+        let mut synth = instant_synth();
+        synth.params.set_parameter(4, 1.0);
+        synth.process_midi_event([176, 64, 127]);
+        synth.process_midi_event([144, 69, 127]);
+        synth.process_midi_event([0xD0, 127, 0]);
+        render(&mut synth, 1000);
+        // Played too late in the block to have been heard before the host stopped.
+        synth.queue_midi_event(100, [144, 64, 127]);
+        synth.suspend();
+        synth.resume();
+        let out = render(&mut synth, 4096);
+        assert!(out.iter().all(|sample| sample.is_finite()));
+        assert!(is_silent(&out));
+        assert!(!synth.sustain_pedal);
+        assert_eq!((synth.pressure, synth.pressure_smoothed), (0.0, 0.0));
+        assert_eq!(synth.params.get_parameter(4), 1.0);
+
+        synth.process_midi_event([144, 69, 127]);
+        assert!(rms(&render(&mut synth, 4096)) > 0.05);
+    }
+
+    /// The start phases of `count` notes, each silenced and left to finish before the next.
+    fn start_phases(synth: &mut SineSynth, count: usize) -> Vec<f64> {
+        (0..count)
+            .map(|_| {
+                synth.process_midi_event([144, 60, 100]);
+                let phase = synth.voices.iter().find(|v| v.active).unwrap().phase;
+                synth.process_midi_event([176, 120, 0]);
+                render(synth, 441);
+                phase
+            })
+            .collect()
+    }
+
+    #[test]
+    fn random_phase_sequence_follows_the_seed() {
+        let random_synth = |seed: f32| {
+            let synth = SineSynth::default();
+            synth.params.set_parameter(60, 1.0);
+            synth.params.set_parameter(61, seed);
+            synth
+        };
+        let first = start_phases(&mut random_synth(0.25), 16);
+        assert_eq!(first, start_phases(&mut random_synth(0.25), 16));
+        assert_ne!(first, start_phases(&mut random_synth(0.75), 16));
+
+        // Resuming starts the sequence over.
+        let mut synth = random_synth(0.25);
+        start_phases(&mut synth, 5);
+        synth.resume();
+        assert_eq!(first, start_phases(&mut synth, 16));
+
+        // Off, notes start at zero and the sequence doesn't advance.
+        let mut synth = random_synth(0.25);
+        synth.params.set_parameter(60, 0.0);
+        assert!(start_phases(&mut synth, 4).iter().all(|&p| p == 0.0));
+        synth.params.set_parameter(60, 1.0);
+        assert_eq!(first, start_phases(&mut synth, 16));
+    }
+
+    #[test]
+    fn random_phases_are_uniform() {
+        let mut synth = SineSynth::default();
+        synth.params.set_parameter(60, 1.0);
+        let mut bins = [0; 10];
+        for phase in start_phases(&mut synth, 1000) {
+            assert!((0.0..1.0).contains(&phase));
+            bins[(phase * 10.0) as usize] += 1;
+        }
+        assert!(bins.iter().all(|&n| (70..=130).contains(&n)), "{:?}", bins);
+    }
+
+    #[test]
+    fn random_phase_notes_start_without_a_click() {
+        let mut synth = SineSynth::default();
+        synth.params.set_parameter(1, 0.0);
+        synth.params.set_parameter(60, 1.0);
+        for _ in 0..20 {
+            synth.process_midi_event([144, 69, 127]);
+            let out = render(&mut synth, 441);
+            assert!(out[0].abs() < 0.01, "starts at {}", out[0]);
+            for pair in out.windows(2) {
+                assert!(
+                    (pair[1] - pair[0]).abs() < 0.1,
+                    "jump of {}",
+                    pair[1] - pair[0]
+                );
+            }
+            synth.process_midi_event([176, 120, 0]);
+            render(&mut synth, 441);
+        }
+    }
+
+    #[test]
+    fn notes_on_any_channel_are_played() {
+        for status in &[0x90, 0x91, 0x95, 0x9F] {
+            let mut synth = instant_synth();
+            synth.process_midi_event([*status, 60, 100]);
+            assert!(synth.voices.iter().any(|v| v.active && v.note == 60));
+
+            synth.process_midi_event([*status - 0x10, 60, 0]);
+            assert!(synth.voices.iter().all(|v| !v.active || v.is_released()));
+        }
+    }
+
+    #[test]
+    fn controllers_on_any_channel_are_applied() {
+        let mut synth = instant_synth();
+        synth.process_midi_event([0xB7, 1, 127]);
+        assert_eq!(synth.mod_wheel, 1.0);
+        synth.process_midi_event([0xEC, 0, 0]);
+        assert_eq!(synth.pitch_bend, -1.0);
+    }
+
+    #[test]
+    fn release_has_no_discontinuity() {
+        let mut synth = SineSynth::default();
+        synth.params.set_parameter(1, attack_to_normalized(0.01));
+        synth.process_midi_event([144, 48, 100]);
+        render(&mut synth, 2205);
+        synth.process_midi_event([128, 48, 0]);
+        let tail = render(&mut synth, 4410);
+
+        for pair in tail.windows(2) {
+            assert!(
+                (pair[1] - pair[0]).abs() < 0.02,
+                "jump of {}",
+                pair[1] - pair[0]
+            );
+        }
+        assert!(tail[2210..].iter().all(|&s| s == 0.0));
+        assert!(synth.voices.iter().all(|v| !v.active));
+    }
+
+    #[test]
+    fn release_during_attack_continues_from_current_level() {
+        let mut synth = SineSynth::default();
+        synth.params.set_parameter(1, attack_to_normalized(1.0));
+        synth.process_midi_event([144, 48, 100]);
+        let head = render(&mut synth, 4410);
+        synth.process_midi_event([128, 48, 0]);
+        let tail = render(&mut synth, 4410);
+
+        let peak_before = head.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        let peak_after = tail.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak_after <= peak_before + 1e-3);
+        assert!((tail[0] - head[head.len() - 1]).abs() < 0.02);
+    }
+}
@@ -0,0 +1,80 @@
+//! A peak limiter that keeps the output within full scale.
+
+/// How long the gain takes to recover after a peak, as a one-pole time constant in seconds.
+const RELEASE_TIME: f64 = 0.1;
+
+/// Recovery stops this close to unity and snaps the rest of the way, so the limiter goes back
+/// to leaving the signal untouched.
+const UNITY_SNAP: f64 = 1e-6;
+
+/// A stereo peak limiter with no lookahead, so it adds no latency.
+///
+/// The gain drops at once to whatever brings a peak down to ±1.0 and recovers over
+/// `RELEASE_TIME`. Both channels share it, which keeps the stereo image from shifting. While
+/// the gain is at unity every sample passes through exactly as it came in.
+#[derive(Clone, Copy, Debug)]
+pub struct Limiter {
+    gain: f64,
+    /// How much of the gain reduction is left after each sample of release.
+    release: f64,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: f64) -> Limiter {
+        Limiter {
+            gain: 1.0,
+            release: (-1.0 / (RELEASE_TIME * sample_rate)).exp(),
+        }
+    }
+
+    /// Whether the limiter has fully recovered, and is passing everything untouched.
+    pub fn is_settled(&self) -> bool {
+        self.gain == 1.0
+    }
+
+    /// Limit one frame.
+    pub fn process(&mut self, left: f64, right: f64) -> (f64, f64) {
+        if self.gain < 1.0 {
+            let reduction = (1.0 - self.gain) * self.release;
+            self.gain = if reduction < UNITY_SNAP {
+                1.0
+            } else {
+                1.0 - reduction
+            };
+        }
+        let peak = left.abs().max(right.abs());
+        if peak * self.gain > 1.0 {
+            self.gain = 1.0 / peak;
+        }
+        // The division can land a rounding error over.
+        let limit = |sample: f64| (sample * self.gain).clamp(-1.0, 1.0);
+        (limit(left), limit(right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Limiter;
+
+    #[test]
+    fn quiet_frames_pass_untouched() {
+        let mut limiter = Limiter::new(44100.0);
+        for &(left, right) in &[(0.3, -0.2), (1.0, -1.0), (-0.999, 0.5)] {
+            assert_eq!(limiter.process(left, right), (left, right));
+        }
+    }
+
+    #[test]
+    fn peaks_are_held_to_full_scale_and_released() {
+        let mut limiter = Limiter::new(44100.0);
+        assert_eq!(limiter.process(4.0, -2.0), (1.0, -0.5));
+        // The gain is shared, and still reduced just after the peak.
+        let (left, right) = limiter.process(0.8, 0.8);
+        assert!(left < 0.3 && left == right);
+        // Two seconds later it has recovered completely.
+        for _ in 0..88200 {
+            limiter.process(0.5, 0.5);
+        }
+        assert_eq!(limiter.process(0.8, -0.8), (0.8, -0.8));
+    }
+}
@@ -0,0 +1,450 @@
+//! Decoding of raw three-byte MIDI messages, and of the parameters set by runs of them.
+
+/// Whether the plugin ever sends MIDI back to the host. Messages only come in.
+pub const SENDS_MIDI: bool = false;
+
+/// A channel message, with the status byte split into message type and channel.
+///
+/// Channels are numbered from 0 as they appear on the wire, so MIDI channel 1 is `0`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    /// How hard one key is being pressed, from 0 to 127.
+    PolyPressure {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    /// A request to switch to program number `program`, counted from 0.
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// How hard the keys on a channel are being pressed, from 0 to 127.
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// The 14-bit wheel position, centred on 8192.
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    /// Any message the synth doesn't act on.
+    Unknown,
+}
+
+impl MidiMessage {
+    /// Decode a message from the raw bytes of a MIDI event.
+    ///
+    /// `data[0]` holds the message type in its high nibble and the channel in its low nibble.
+    /// Source: http://www.midimountain.com/midi/midi_status.htm
+    pub fn decode(data: [u8; 3]) -> MidiMessage {
+        let channel = data[0] & 0x0F;
+        match data[0] & 0xF0 {
+            0x80 => MidiMessage::NoteOff {
+                channel,
+                note: data[1],
+                velocity: data[2],
+            },
+            0x90 => MidiMessage::NoteOn {
+                channel,
+                note: data[1],
+                velocity: data[2],
+            },
+            0xA0 => MidiMessage::PolyPressure {
+                channel,
+                note: data[1],
+                pressure: data[2],
+            },
+            0xB0 => MidiMessage::ControlChange {
+                channel,
+                controller: data[1],
+                value: data[2],
+            },
+            // Program Change has a single data byte; the third is padding.
+            0xC0 => MidiMessage::ProgramChange {
+                channel,
+                program: data[1],
+            },
+            // So does Channel Pressure.
+            0xD0 => MidiMessage::ChannelPressure {
+                channel,
+                pressure: data[1],
+            },
+            0xE0 => MidiMessage::PitchBend {
+                channel,
+                value: u16::from(data[2]) << 7 | u16::from(data[1]),
+            },
+            _ => MidiMessage::Unknown,
+        }
+    }
+
+    /// The channel a message was sent on, if it is a channel message.
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyPressure { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(channel),
+            MidiMessage::Unknown => None,
+        }
+    }
+}
+
+/// Parse the raw bytes of a MIDI event, or `None` if they aren't a well-formed message.
+///
+/// The first byte must be a status byte, with its high bit set, and every data byte the
+/// message uses must have its high bit clear; the unused padding after a one-byte message
+/// isn't checked. A NoteOn with zero velocity is a NoteOff by the MIDI spec, so it comes out
+/// as one, with a velocity of zero. System messages parse as `Unknown`.
+pub fn parse(data: [u8; 3]) -> Option<MidiMessage> {
+    let data_bytes = match data[0] & 0xF0 {
+        0x80 | 0x90 | 0xA0 | 0xB0 | 0xE0 => 2,
+        0xC0 | 0xD0 => 1,
+        0xF0 => 0,
+        // A data byte where the status should be, as running status would leave it.
+        _ => return None,
+    };
+    if data[1..=data_bytes].iter().any(|&byte| byte >= 0x80) {
+        return None;
+    }
+    Some(match MidiMessage::decode(data) {
+        MidiMessage::NoteOn {
+            channel,
+            note,
+            velocity: 0,
+        } => MidiMessage::NoteOff {
+            channel,
+            note,
+            velocity: 0,
+        },
+        message => message,
+    })
+}
+
+/// The wheel position from -1.0 (full down) to 1.0 (full up) for a 14-bit pitch bend value.
+///
+/// The wheel centre is 8192, so each direction is scaled separately to reach exactly ±1.0.
+pub fn bend_position(value: u16) -> f64 {
+    let value = i32::from(value) - 8192;
+    if value < 0 {
+        f64::from(value) / 8192.0
+    } else {
+        f64::from(value) / 8191.0
+    }
+}
+
+/// Registered parameter 0,0, the pitch bend range. Its value is in semitones (MSB) and cents
+/// (LSB).
+pub const PITCH_BEND_SENSITIVITY: (u8, u8) = (0, 0);
+
+/// A registered parameter set by Data Entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RpnValue {
+    /// The parameter number, MSB first.
+    pub parameter: (u8, u8),
+    pub msb: u8,
+    pub lsb: u8,
+}
+
+/// Follows the control changes that set Registered Parameter Numbers (RPNs).
+///
+/// CC 101 and 100 select a parameter by the MSB and LSB of its number, in either order, and
+/// CC 6 and 38 then set the MSB and LSB of its value. Data Entry keeps going to the same
+/// parameter until another is selected, so the selection is remembered across notes and any
+/// other controllers in between. Selecting a non-registered parameter with CC 99 or 98 sends
+/// Data Entry there instead, which here means nowhere.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RpnState {
+    /// The MSB and LSB of the selected number, each `None` until it is sent.
+    parameter: (Option<u8>, Option<u8>),
+    /// The value entered so far.
+    msb: u8,
+    lsb: u8,
+}
+
+impl RpnState {
+    /// Take in a control change, returning the registered parameter it sets if it's Data
+    /// Entry for one.
+    ///
+    /// A new MSB starts the LSB over from zero, so a controller that only sends CC 6 sets
+    /// whole units.
+    pub fn control_change(&mut self, controller: u8, value: u8) -> Option<RpnValue> {
+        match controller {
+            101 => self.parameter.0 = Some(value),
+            100 => self.parameter.1 = Some(value),
+            99 | 98 => self.parameter = (None, None),
+            6 => {
+                self.msb = value;
+                self.lsb = 0;
+            }
+            38 => self.lsb = value,
+            _ => return None,
+        }
+        match (controller, self.parameter) {
+            (6, (Some(msb), Some(lsb))) | (38, (Some(msb), Some(lsb))) => Some(RpnValue {
+                parameter: (msb, lsb),
+                msb: self.msb,
+                lsb: self.lsb,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bend_position, parse, MidiMessage, RpnState, RpnValue, PITCH_BEND_SENSITIVITY};
+
+    #[test]
+    fn decodes_notes_on_every_channel() {
+        for channel in 0..16 {
+            assert_eq!(
+                MidiMessage::decode([0x90 | channel, 60, 100]),
+                MidiMessage::NoteOn {
+                    channel,
+                    note: 60,
+                    velocity: 100
+                }
+            );
+            assert_eq!(
+                MidiMessage::decode([0x80 | channel, 60, 0]),
+                MidiMessage::NoteOff {
+                    channel,
+                    note: 60,
+                    velocity: 0
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_pitch_bend_value() {
+        assert_eq!(
+            MidiMessage::decode([0xE3, 0x7F, 0x7F]),
+            MidiMessage::PitchBend {
+                channel: 3,
+                value: 16383
+            }
+        );
+        assert_eq!(
+            MidiMessage::decode([0xE0, 0, 64]),
+            MidiMessage::PitchBend {
+                channel: 0,
+                value: 8192
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_program_change() {
+        assert_eq!(
+            MidiMessage::decode([0xC2, 5, 0]),
+            MidiMessage::ProgramChange {
+                channel: 2,
+                program: 5
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_channel_pressure() {
+        assert_eq!(
+            MidiMessage::decode([0xD4, 90, 0]),
+            MidiMessage::ChannelPressure {
+                channel: 4,
+                pressure: 90
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_poly_pressure() {
+        assert_eq!(
+            MidiMessage::decode([0xA2, 64, 33]),
+            MidiMessage::PolyPressure {
+                channel: 2,
+                note: 64,
+                pressure: 33
+            }
+        );
+    }
+
+    #[test]
+    fn bend_reaches_both_ends_and_centres_exactly() {
+        assert_eq!(bend_position(0), -1.0);
+        assert_eq!(bend_position(8192), 0.0);
+        assert_eq!(bend_position(16383), 1.0);
+        assert_eq!(bend_position(4096), -0.5);
+    }
+
+    #[test]
+    fn unhandled_statuses_are_unknown() {
+        assert_eq!(MidiMessage::decode([0xF2, 60, 10]), MidiMessage::Unknown);
+        assert_eq!(MidiMessage::decode([0xF8, 0, 0]), MidiMessage::Unknown);
+        assert_eq!(MidiMessage::decode([0xF8, 0, 0]).channel(), None);
+        assert_eq!(MidiMessage::decode([0xB5, 1, 0]).channel(), Some(5));
+    }
+
+    #[test]
+    fn parses_every_status_on_every_channel() {
+        for status in 0x80..=0xEF_u8 {
+            let channel = status & 0x0F;
+            let message = parse([status, 64, 100]).unwrap();
+            assert_eq!(message.channel(), Some(channel), "{:#04x}", status);
+            let expected = match status & 0xF0 {
+                0x80 => MidiMessage::NoteOff {
+                    channel,
+                    note: 64,
+                    velocity: 100,
+                },
+                0x90 => MidiMessage::NoteOn {
+                    channel,
+                    note: 64,
+                    velocity: 100,
+                },
+                0xA0 => MidiMessage::PolyPressure {
+                    channel,
+                    note: 64,
+                    pressure: 100,
+                },
+                0xB0 => MidiMessage::ControlChange {
+                    channel,
+                    controller: 64,
+                    value: 100,
+                },
+                0xC0 => MidiMessage::ProgramChange {
+                    channel,
+                    program: 64,
+                },
+                0xD0 => MidiMessage::ChannelPressure {
+                    channel,
+                    pressure: 64,
+                },
+                _ => MidiMessage::PitchBend {
+                    channel,
+                    value: 100 << 7 | 64,
+                },
+            };
+            assert_eq!(message, expected, "{:#04x}", status);
+        }
+        for status in 0xF0..=0xFF {
+            assert_eq!(parse([status, 0, 0]), Some(MidiMessage::Unknown));
+        }
+        for status in 0x00..0x80 {
+            assert_eq!(parse([status, 64, 100]), None, "{:#04x}", status);
+        }
+    }
+
+    #[test]
+    fn data_bytes_with_the_high_bit_are_rejected() {
+        for status in 0x80..=0xEF_u8 {
+            let one_byte = matches!(status & 0xF0, 0xC0 | 0xD0);
+            for high in 0x80..=0xFF {
+                assert_eq!(parse([status, high, 0]), None, "{:#04x}", status);
+                // A one-byte message's padding isn't part of it.
+                assert_eq!(
+                    parse([status, 0, high]).is_some(),
+                    one_byte,
+                    "{:#04x} {:#04x}",
+                    status,
+                    high
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn note_on_without_velocity_is_note_off() {
+        for channel in 0..16 {
+            assert_eq!(
+                parse([0x90 | channel, 60, 0]),
+                Some(MidiMessage::NoteOff {
+                    channel,
+                    note: 60,
+                    velocity: 0
+                })
+            );
+        }
+    }
+
+    /// Feed `state` a run of control changes, returning what the last one set.
+    fn send(state: &mut RpnState, controls: &[(u8, u8)]) -> Option<RpnValue> {
+        controls
+            .iter()
+            .map(|&(controller, value)| state.control_change(controller, value))
+            .last()
+            .flatten()
+    }
+
+    #[test]
+    fn data_entry_sets_the_selected_parameter() {
+        let mut state = RpnState::default();
+        assert_eq!(send(&mut state, &[(101, 0), (100, 0)]), None);
+        let expected = RpnValue {
+            parameter: PITCH_BEND_SENSITIVITY,
+            msb: 12,
+            lsb: 0,
+        };
+        assert_eq!(state.control_change(6, 12), Some(expected));
+        assert_eq!(
+            state.control_change(38, 50),
+            Some(RpnValue {
+                lsb: 50,
+                ..expected
+            })
+        );
+        // The selection stays for the next value, and a new MSB clears the cents.
+        assert_eq!(
+            state.control_change(6, 7),
+            Some(RpnValue { msb: 7, ..expected })
+        );
+    }
+
+    #[test]
+    fn unrelated_controllers_can_come_between() {
+        let mut state = RpnState::default();
+        let controls = [(100, 0), (1, 90), (64, 127), (101, 0), (7, 100), (6, 24)];
+        assert_eq!(
+            send(&mut state, &controls),
+            Some(RpnValue {
+                parameter: PITCH_BEND_SENSITIVITY,
+                msb: 24,
+                lsb: 0
+            })
+        );
+    }
+
+    #[test]
+    fn data_entry_needs_a_registered_parameter() {
+        let mut state = RpnState::default();
+        assert_eq!(state.control_change(6, 12), None);
+        assert_eq!(send(&mut state, &[(101, 0), (6, 12)]), None);
+        // Selecting an NRPN takes Data Entry away from the RPN.
+        assert_eq!(
+            send(&mut state, &[(100, 0), (99, 1), (98, 2), (6, 12)]),
+            None
+        );
+        // The null parameter comes through, and matches nothing.
+        let null = send(&mut state, &[(101, 127), (100, 127), (6, 12)]).unwrap();
+        assert_eq!(null.parameter, (127, 127));
+    }
+}
@@ -0,0 +1,127 @@
+//! MIDI learn: binding controllers to parameters, so any knob on a keyboard can move any
+//! parameter.
+//!
+//! Setting "MIDI Learn" to `Learn` binds the next controller to arrive to the parameter
+//! "Learn Target" names. From then on that controller moves the parameter instead of doing
+//! whatever it would otherwise do.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::params::options;
+
+/// How many parameters "Learn Target" spreads its range over. This is more than there are,
+/// so a saved value still names the same parameter once more are added.
+pub const TARGET_SLOTS: usize = 256;
+
+/// The number of MIDI controllers.
+const CONTROLLERS: usize = 128;
+
+/// The first of the channel mode messages, which share controller numbers with the rest but
+/// switch the synth's state, such as All Notes Off. Learning one would leave no way to stop
+/// stuck notes.
+const FIRST_CHANNEL_MODE: u8 = 120;
+
+/// Whether `controller` can be bound to a parameter.
+pub fn learnable(controller: u8) -> bool {
+    controller < FIRST_CHANNEL_MODE
+}
+
+options! {
+    /// What "MIDI Learn" is doing.
+    pub enum LearnMode {
+        Off => "Off",
+        /// Waiting for a controller to bind to the learn target.
+        Learn => "Learn",
+        /// Dropping every binding. The parameter goes straight back to `Off` once it has.
+        Clear => "Clear",
+    }
+}
+
+/// What a controller's slot holds while it isn't bound.
+const UNBOUND: usize = usize::MAX;
+
+/// Which parameter, by index, each controller is bound to.
+///
+/// Each controller's binding is an atomic of its own, so the audio thread can look one up or
+/// learn one as a controller arrives while the host reads them all to save, and neither
+/// waits for the other.
+#[derive(Debug)]
+pub struct ControlMap {
+    targets: [AtomicUsize; CONTROLLERS],
+}
+
+impl Default for ControlMap {
+    fn default() -> ControlMap {
+        ControlMap {
+            targets: std::array::from_fn(|_| AtomicUsize::new(UNBOUND)),
+        }
+    }
+}
+
+impl ControlMap {
+    /// Bind `controller` to the parameter at `target`, replacing any binding it had.
+    pub fn bind(&self, controller: u8, target: usize) {
+        if let Some(slot) = self.targets.get(usize::from(controller)) {
+            slot.store(target, Ordering::Relaxed);
+        }
+    }
+
+    /// The parameter `controller` is bound to, if it is.
+    pub fn target(&self, controller: u8) -> Option<usize> {
+        let slot = self.targets.get(usize::from(controller))?;
+        Some(slot.load(Ordering::Relaxed)).filter(|&target| target != UNBOUND)
+    }
+
+    pub fn clear(&self) {
+        for slot in self.targets.iter() {
+            slot.store(UNBOUND, Ordering::Relaxed);
+        }
+    }
+
+    /// Every binding, in controller order.
+    pub fn bindings(&self) -> impl Iterator<Item = (u8, usize)> + '_ {
+        (0..CONTROLLERS as u8).filter_map(move |controller| {
+            self.target(controller).map(|target| (controller, target))
+        })
+    }
+}
+
+/// The normalized parameter value for a controller value, from 0.0 at 0 to 1.0 at 127.
+pub fn control_value(value: u8) -> f32 {
+    f32::from(value.min(127)) / 127.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{control_value, ControlMap, LearnMode};
+
+    #[test]
+    fn binding_again_replaces() {
+        let map = ControlMap::default();
+        map.bind(1, 38);
+        map.bind(74, 39);
+        map.bind(1, 0);
+        assert_eq!(map.target(1), Some(0));
+        assert_eq!(map.target(2), None);
+        assert_eq!(map.bindings().collect::<Vec<_>>(), [(1, 0), (74, 39)]);
+        map.clear();
+        assert_eq!(map.bindings().count(), 0);
+        // Out of range controllers have nothing to bind.
+        map.bind(128, 1);
+        assert_eq!(map.target(128), None);
+    }
+
+    #[test]
+    fn values_cover_the_whole_range() {
+        assert_eq!(control_value(0), 0.0);
+        assert_eq!(control_value(127), 1.0);
+        assert_eq!(control_value(64), 64.0 / 127.0);
+    }
+
+    #[test]
+    fn modes_round_trip() {
+        for &mode in LearnMode::ALL.iter() {
+            assert_eq!(LearnMode::from_normalized(mode.to_normalized()), mode);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! MIDI Polyphonic Expression (MPE): each note arrives on a channel of its own, and that
+//! channel's pitch bend, pressure and CC 74 (slide) shape that note alone.
+//!
+//! Channels 1 and 16 are the master channels of the lower and upper zones. Messages on them
+//! are for every note, so their pressure and slide apply on top of each note channel's, and
+//! their pitch bend and other controllers are handled as they are outside MPE.
+
+use crate::denormal::flush;
+
+/// The pitch bend range of a note channel in semitones either way, MPE's default.
+pub const NOTE_BEND_RANGE: f64 = 48.0;
+
+/// How far slide moves a note's cutoff either way from where it would be, in octaves.
+pub const MAX_SLIDE_OCTAVES: f64 = 2.0;
+
+/// The number of MIDI channels.
+const CHANNELS: usize = 16;
+
+/// Whether `channel`, counted from 0, is a zone's master channel.
+pub fn is_master_channel(channel: u8) -> bool {
+    channel == 0 || channel == 15
+}
+
+/// The expression a channel gives its note.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Expression {
+    /// Pitch bend in semitones.
+    pub bend: f64,
+    /// Channel pressure from 0.0 to 1.0.
+    pub pressure: f64,
+    /// Slide from -1.0 to 1.0, centred on a CC 74 value of 64.
+    pub slide: f64,
+}
+
+/// The expression on every channel, as last received and as eased towards that. Channels are
+/// counted from 0, as on the wire.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MpeChannels {
+    received: [Expression; CHANNELS],
+    /// Pressure and slide move in coarse 7-bit steps, so they're smoothed before they're
+    /// heard. Pitch bend is already fine enough, and is taken as it comes.
+    smoothed: [Expression; CHANNELS],
+}
+
+impl MpeChannels {
+    /// Take in a channel's pitch bend, as a wheel position from -1.0 to 1.0.
+    pub fn set_bend(&mut self, channel: u8, position: f64) {
+        self.received[usize::from(channel)].bend = position * NOTE_BEND_RANGE;
+    }
+
+    pub fn set_pressure(&mut self, channel: u8, pressure: u8) {
+        self.received[usize::from(channel)].pressure = f64::from(pressure) / 127.0;
+    }
+
+    pub fn set_slide(&mut self, channel: u8, value: u8) {
+        self.received[usize::from(channel)].slide = (f64::from(value) - 64.0) / 64.0;
+    }
+
+    /// Move the smoothed pressure and slide `amount` of the way to what was received, and
+    /// take up the pitch bend.
+    pub fn ease(&mut self, amount: f64) {
+        for (smoothed, received) in self.smoothed.iter_mut().zip(&self.received) {
+            ease(smoothed, received, amount);
+        }
+    }
+
+    /// The expression a note on `channel` plays with, including the master channels'.
+    #[cfg(test)]
+    pub fn note(&self, channel: u8) -> Expression {
+        self.follow(channel).expression()
+    }
+
+    /// The expression of a note on `channel`, to be eased on its own while nothing new
+    /// arrives, as it would be along with every channel.
+    pub fn follow(&self, channel: u8) -> NoteExpression {
+        let own = usize::from(channel);
+        // A master channel's note takes nothing from the other channels, so they all stand
+        // for its own.
+        let channels = if is_master_channel(channel) {
+            [own; 3]
+        } else {
+            [own, 0, 15]
+        };
+        NoteExpression {
+            master: is_master_channel(channel),
+            received: channels.map(|channel| self.received[channel]),
+            smoothed: channels.map(|channel| self.smoothed[channel]),
+        }
+    }
+}
+
+/// One note's expression: its own channel's and both master channels'.
+#[derive(Clone, Copy, Debug)]
+pub struct NoteExpression {
+    master: bool,
+    received: [Expression; 3],
+    smoothed: [Expression; 3],
+}
+
+impl NoteExpression {
+    /// `MpeChannels::ease` for the channels this note hears.
+    pub fn ease(&mut self, amount: f64) {
+        for (smoothed, received) in self.smoothed.iter_mut().zip(&self.received) {
+            ease(smoothed, received, amount);
+        }
+    }
+
+    /// The expression the note plays with.
+    pub fn expression(&self) -> Expression {
+        let [own, lower, upper] = self.smoothed;
+        if self.master {
+            return own;
+        }
+        Expression {
+            bend: own.bend,
+            pressure: own.pressure.max(lower.pressure).max(upper.pressure),
+            slide: (own.slide + lower.slide + upper.slide).clamp(-1.0, 1.0),
+        }
+    }
+}
+
+/// Move `smoothed` `amount` of the way to `received`, taking the pitch bend straight away.
+fn ease(smoothed: &mut Expression, received: &Expression, amount: f64) {
+    smoothed.bend = received.bend;
+    smoothed.pressure = flush(smoothed.pressure + (received.pressure - smoothed.pressure) * amount);
+    smoothed.slide = flush(smoothed.slide + (received.slide - smoothed.slide) * amount);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MpeChannels, NOTE_BEND_RANGE};
+
+    #[test]
+    fn channels_keep_their_own_expression() {
+        let mut channels = MpeChannels::default();
+        channels.set_bend(1, 0.25);
+        channels.set_pressure(2, 127);
+        channels.set_slide(3, 0);
+        channels.ease(1.0);
+        assert_eq!(channels.note(1).bend, 0.25 * NOTE_BEND_RANGE);
+        assert_eq!(channels.note(1).pressure, 0.0);
+        assert_eq!(channels.note(2).pressure, 1.0);
+        assert_eq!(channels.note(2).bend, 0.0);
+        assert_eq!(channels.note(3).slide, -1.0);
+        assert_eq!(channels.note(4).slide, 0.0);
+    }
+
+    #[test]
+    fn master_channels_reach_every_note() {
+        let mut channels = MpeChannels::default();
+        channels.set_pressure(0, 64);
+        channels.set_pressure(5, 32);
+        channels.set_slide(15, 96);
+        channels.set_slide(5, 127);
+        channels.ease(1.0);
+        assert_eq!(channels.note(5).pressure, 64.0 / 127.0);
+        assert_eq!(channels.note(9).slide, 0.5);
+        assert_eq!(channels.note(5).slide, 1.0);
+        // Master channels don't take from each other.
+        assert_eq!(channels.note(15).pressure, 0.0);
+    }
+
+    #[test]
+    fn pressure_and_slide_ease_in() {
+        let mut channels = MpeChannels::default();
+        channels.set_pressure(3, 127);
+        channels.set_slide(3, 127);
+        channels.set_bend(3, 1.0);
+        channels.ease(0.5);
+        let note = channels.note(3);
+        assert_eq!(note.pressure, 0.5);
+        assert!((note.slide - 0.5 * 63.0 / 64.0).abs() < 1e-12);
+        assert_eq!(note.bend, NOTE_BEND_RANGE);
+    }
+
+    #[test]
+    fn a_followed_note_eases_with_its_channels() {
+        let mut channels = MpeChannels::default();
+        channels.set_pressure(0, 100);
+        channels.set_pressure(4, 50);
+        channels.set_slide(15, 0);
+        channels.set_slide(4, 127);
+        let mut followed = [channels.follow(4), channels.follow(0)];
+        for _ in 0..100 {
+            channels.ease(0.05);
+            for note in followed.iter_mut() {
+                note.ease(0.05);
+            }
+        }
+        assert_eq!(followed[0].expression(), channels.note(4));
+        assert_eq!(followed[1].expression(), channels.note(0));
+    }
+}
@@ -0,0 +1,139 @@
+//! White and pink noise from a small deterministic generator.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseColor {
+    White,
+    Pink,
+}
+
+impl NoiseColor {
+    pub fn name(self) -> &'static str {
+        match self {
+            NoiseColor::White => "White",
+            NoiseColor::Pink => "Pink",
+        }
+    }
+}
+
+/// The generator's starting state. Any non-zero value works; a fixed one makes renders repeat.
+const SEED: u32 = 0x9E37_79B9;
+
+/// Scales the pink filter's output to roughly the level of the white noise feeding it.
+const PINK_GAIN: f64 = 0.25;
+
+/// A noise source cheap and predictable enough to run on the audio thread.
+///
+/// Samples come from a 32-bit xorshift generator, which never allocates or locks. Pink noise is
+/// made by passing the white noise through Paul Kellet's three-pole approximation of a
+/// -3 dB/octave filter.
+pub struct Noise {
+    state: u32,
+    pink: [f64; 3],
+}
+
+impl Default for Noise {
+    fn default() -> Noise {
+        Noise::with_seed(SEED)
+    }
+}
+
+impl Noise {
+    /// A generator starting from `seed`. Zero would never leave zero, so it means the default
+    /// seed instead.
+    pub fn with_seed(seed: u32) -> Noise {
+        Noise {
+            state: if seed == 0 { SEED } else { seed },
+            pink: [0.0; 3],
+        }
+    }
+
+    /// The next noise sample, between -1.0 and 1.0.
+    pub fn next(&mut self, color: NoiseColor) -> f64 {
+        let white = self.white();
+        // The pink filter always runs so switching colour doesn't start it from rest.
+        let b = &mut self.pink;
+        b[0] = 0.99765 * b[0] + white * 0.099_046;
+        b[1] = 0.963 * b[1] + white * 0.296_516_4;
+        b[2] = 0.57 * b[2] + white * 1.052_691_3;
+        match color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                ((b[0] + b[1] + b[2] + white * 0.1848) * PINK_GAIN).clamp(-1.0, 1.0)
+            }
+        }
+    }
+
+    /// A uniformly distributed value in `[0, 1)`.
+    pub fn unit(&mut self) -> f64 {
+        f64::from(self.step()) / 4_294_967_296.0
+    }
+
+    /// A uniformly distributed sample between -1.0 and 1.0.
+    fn white(&mut self) -> f64 {
+        f64::from(self.step()) / f64::from(u32::MAX) * 2.0 - 1.0
+    }
+
+    fn step(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Noise, NoiseColor};
+    use crate::analysis::{octave_power, rms};
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    fn generate(color: NoiseColor, samples: usize) -> Vec<f32> {
+        let mut noise = Noise::default();
+        (0..samples).map(|_| noise.next(color) as f32).collect()
+    }
+
+    fn mean(signal: &[f32]) -> f64 {
+        signal.iter().map(|&s| f64::from(s)).sum::<f64>() / signal.len() as f64
+    }
+
+    #[test]
+    fn white_noise_is_flat() {
+        let noise = generate(NoiseColor::White, 1 << 18);
+        assert!(mean(&noise).abs() < 0.01);
+        assert!(noise.iter().all(|s| s.abs() <= 1.0));
+
+        let noise = &noise[..16384];
+
+        let slope = 10.0
+            * (octave_power(noise, 8000.0, SAMPLE_RATE) / octave_power(noise, 500.0, SAMPLE_RATE))
+                .log10();
+        assert!(slope.abs() < 2.0, "{} dB", slope);
+    }
+
+    #[test]
+    fn pink_noise_falls_3_db_per_octave() {
+        // Pink noise wanders slowly, so its mean needs a long stretch to settle.
+        let noise = generate(NoiseColor::Pink, 1 << 18);
+        assert!(mean(&noise).abs() < 0.05);
+        assert!(rms(&noise) > 0.1);
+
+        let noise = &noise[..16384];
+
+        // Four octaves up, at -3 dB per octave.
+        let slope = 10.0
+            * (octave_power(noise, 8000.0, SAMPLE_RATE) / octave_power(noise, 500.0, SAMPLE_RATE))
+                .log10();
+        assert!((slope + 12.0).abs() < 3.0, "{} dB", slope);
+    }
+
+    #[test]
+    fn sequence_is_reproducible() {
+        assert_eq!(
+            generate(NoiseColor::White, 1024),
+            generate(NoiseColor::White, 1024)
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! The keys currently held down, for choosing which one a monophonic voice plays.
+
+use crate::params::options;
+
+options! {
+    /// Which held key a monophonic voice plays.
+    pub enum NotePriority {
+        /// The most recently pressed key.
+        Last => "Last",
+        Highest => "Highest",
+        Lowest => "Lowest",
+    }
+}
+
+/// The most keys remembered at once. Past this, the oldest key is forgotten.
+pub const MAX_HELD_NOTES: usize = 16;
+
+/// The keys that are down, oldest first.
+///
+/// A key is only ever in the stack once, however many NoteOns it gets, and the stack is a
+/// fixed size so pressing keys never allocates on the audio thread.
+#[derive(Default)]
+pub struct NoteStack {
+    notes: [u8; MAX_HELD_NOTES],
+    len: usize,
+}
+
+impl NoteStack {
+    /// Record a key going down. A key that was already down moves to the top.
+    pub fn push(&mut self, note: u8) {
+        self.remove(note);
+        if self.len == MAX_HELD_NOTES {
+            self.notes.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.notes[self.len] = note;
+        self.len += 1;
+    }
+
+    /// Record a key coming up.
+    pub fn remove(&mut self, note: u8) {
+        if let Some(index) = self.held().iter().position(|&n| n == note) {
+            self.notes.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Whether every key is up.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The keys that are down, oldest first.
+    pub fn held(&self) -> &[u8] {
+        &self.notes[..self.len]
+    }
+
+    /// The key `priority` picks out of those held, or `None` if no key is down.
+    pub fn pick(&self, priority: NotePriority) -> Option<u8> {
+        match priority {
+            NotePriority::Last => self.held().last().copied(),
+            NotePriority::Highest => self.held().iter().max().copied(),
+            NotePriority::Lowest => self.held().iter().min().copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NotePriority, NoteStack, MAX_HELD_NOTES};
+
+    #[test]
+    fn priorities_pick_from_held_keys() {
+        let mut stack = NoteStack::default();
+        assert_eq!(stack.pick(NotePriority::Last), None);
+        for &note in &[64, 60, 67, 62] {
+            stack.push(note);
+        }
+        assert_eq!(stack.pick(NotePriority::Last), Some(62));
+        assert_eq!(stack.pick(NotePriority::Highest), Some(67));
+        assert_eq!(stack.pick(NotePriority::Lowest), Some(60));
+
+        stack.remove(62);
+        stack.remove(60);
+        assert_eq!(stack.pick(NotePriority::Last), Some(67));
+        assert_eq!(stack.pick(NotePriority::Lowest), Some(64));
+    }
+
+    #[test]
+    fn repeated_key_moves_to_the_top() {
+        let mut stack = NoteStack::default();
+        stack.push(60);
+        stack.push(64);
+        stack.push(60);
+        assert_eq!(stack.pick(NotePriority::Last), Some(60));
+        // One release lets go of the key, however many times it was pressed.
+        stack.remove(60);
+        assert_eq!(stack.pick(NotePriority::Last), Some(64));
+    }
+
+    #[test]
+    fn overflow_forgets_the_oldest_key() {
+        let mut stack = NoteStack::default();
+        for note in 0..MAX_HELD_NOTES as u8 + 4 {
+            stack.push(note);
+        }
+        assert_eq!(stack.held().len(), MAX_HELD_NOTES);
+        assert_eq!(stack.pick(NotePriority::Lowest), Some(4));
+        assert_eq!(
+            stack.pick(NotePriority::Last),
+            Some(MAX_HELD_NOTES as u8 + 3)
+        );
+
+        // Releasing a forgotten key changes nothing.
+        stack.remove(0);
+        assert_eq!(stack.held().len(), MAX_HELD_NOTES);
+        for note in 4..MAX_HELD_NOTES as u8 + 4 {
+            stack.remove(note);
+        }
+        assert_eq!(stack.pick(NotePriority::Last), None);
+    }
+
+    #[test]
+    fn priorities_round_trip() {
+        for &priority in NotePriority::ALL.iter() {
+            assert_eq!(
+                NotePriority::from_normalized(priority.to_normalized()),
+                priority
+            );
+        }
+    }
+}
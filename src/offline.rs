@@ -0,0 +1,382 @@
+//! Rendering a MIDI file to a WAV file without a host, for the `render` binary, and driving
+//! the synth a block at a time, for the render tests and the benchmarks.
+//!
+//! The synth is driven as a host drives it: set up, resumed, then processed a block at a time
+//! with each block's events handed over through `process_events`, timed within the block by
+//! their delta frames. A render runs until the last event plus the synth's tail, so the
+//! final release is kept.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Write};
+
+use vst::buffer::SendEventBuffer;
+use vst::event::{Event, MidiEvent, SysExEvent};
+use vst::host::HostBuffer;
+use vst::plugin::{Plugin, PluginParameters};
+
+use crate::params::PARAM_COUNT;
+use crate::smf::{self, Message};
+use crate::SineSynth;
+
+/// How much longer than its events and tail a render runs, in seconds, so the tail is sure
+/// to have reached silence.
+const MARGIN: f64 = 0.1;
+
+/// How many events are handed over at once. A block with more gets several calls.
+const EVENTS_PER_CALL: usize = 64;
+
+/// How a render is set up.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub sample_rate: f32,
+    /// The longest block, in samples. Every block is this long except the last.
+    pub block_size: usize,
+    /// Parameters to set before rendering, as names and values. See `set_by_name`.
+    pub params: Vec<(String, String)>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            sample_rate: 44100.0,
+            block_size: 512,
+            params: Vec::new(),
+        }
+    }
+}
+
+/// Why a render couldn't start.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The input isn't a Standard MIDI File, or is cut short.
+    NotMidi,
+    /// No parameter has this name.
+    UnknownParameter(String),
+    /// The value doesn't make sense for the parameter, which is named first.
+    BadValue(String, String),
+    /// The sample rate or block size is zero or not a number.
+    BadSettings,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotMidi => write!(f, "not a Standard MIDI File"),
+            Error::UnknownParameter(name) => write!(f, "no parameter is called \"{}\"", name),
+            Error::BadValue(name, value) => {
+                write!(f, "\"{}\" isn't a value for {}", value, name)
+            }
+            Error::BadSettings => write!(f, "the sample rate and block size must be positive"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A parameter name as it's compared: lowercase, without spaces, underscores or hyphens, so
+/// `unison_spread`, `unison-spread` and `Unison Spread` all match.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Set the parameter called `name` from `value`. A plain number between 0 and 1 is the
+/// parameter's normalized value, as a host would send it; anything else is read as the text
+/// a user would type into the host, like `2.5 kHz` or `Saw`.
+fn set_by_name(synth: &SineSynth, name: &str, value: &str) -> Result<(), Error> {
+    let wanted = normalize(name);
+    let index = (0..PARAM_COUNT as i32)
+        .find(|&index| normalize(&synth.params.get_parameter_name(index)) == wanted)
+        .ok_or_else(|| Error::UnknownParameter(name.to_string()))?;
+    let bad_value = || Error::BadValue(synth.params.get_parameter_name(index), value.into());
+    match value.trim().parse::<f32>() {
+        Ok(normalized) if (0.0..=1.0).contains(&normalized) => {
+            synth.params.set_parameter(index, normalized);
+            Ok(())
+        }
+        Ok(_) => Err(bad_value()),
+        Err(_) if synth.params.string_to_parameter(index, value.to_string()) => Ok(()),
+        Err(_) => Err(bad_value()),
+    }
+}
+
+/// Render the Standard MIDI File `midi`, returning the left and right outputs.
+pub fn render(midi: &[u8], settings: &Settings) -> Result<[Vec<f32>; 2], Error> {
+    let sample_rate = settings.sample_rate;
+    if !(sample_rate.is_finite() && sample_rate > 0.0) || settings.block_size == 0 {
+        return Err(Error::BadSettings);
+    }
+    let events = smf::parse(midi).ok_or(Error::NotMidi)?;
+
+    let mut synth = SineSynth::default();
+    synth.set_sample_rate(sample_rate);
+    synth.set_block_size(settings.block_size as i64);
+    for (name, value) in &settings.params {
+        set_by_name(&synth, name, value)?;
+    }
+    synth.resume();
+
+    // The tail is read after the parameters are set, since it follows the release.
+    let last = events.last().map_or(0.0, |event| event.seconds);
+    let length = ((last + MARGIN) * f64::from(sample_rate)).ceil() as usize
+        + synth.get_tail_size().max(0) as usize;
+    let at = |seconds: f64| (seconds * f64::from(sample_rate)).round() as usize;
+
+    let mut host_buffer = HostBuffer::new(0, 2);
+    let mut send = SendEventBuffer::new(EVENTS_PER_CALL);
+    let inputs: [Vec<f32>; 0] = [];
+    let mut outputs = [vec![0.0; length], vec![0.0; length]];
+    let mut events = events.iter().peekable();
+    let mut start = 0;
+    while start < length {
+        let end = (start + settings.block_size).min(length);
+        let mut block = Vec::new();
+        while let Some(event) = events.peek() {
+            let time = at(event.seconds);
+            if time >= end {
+                break;
+            }
+            let delta_frames = (time - start) as i32;
+            block.push(match &event.message {
+                Message::Midi(data) => Event::Midi(MidiEvent {
+                    data: *data,
+                    delta_frames,
+                    live: false,
+                    note_length: None,
+                    note_offset: None,
+                    detune: 0,
+                    note_off_velocity: 0,
+                }),
+                Message::SysEx(payload) => Event::SysEx(SysExEvent {
+                    payload,
+                    delta_frames,
+                }),
+            });
+            events.next();
+        }
+        for events in block.chunks(EVENTS_PER_CALL) {
+            send.send_events_to_plugin(events, &mut synth);
+        }
+
+        let (left, right) = outputs.split_at_mut(1);
+        let mut block = [&mut left[0][start..end], &mut right[0][start..end]];
+        synth.process(&mut host_buffer.bind(&inputs, &mut block));
+        start = end;
+    }
+    Ok(outputs)
+}
+
+/// A synth at 44.1 kHz with a stereo output, driven a block at a time as a host drives it.
+///
+/// The golden renders and the benchmarks both go through this, so a benchmark times the
+/// whole `process` call, output writes included.
+pub struct OfflineHost {
+    pub(crate) synth: SineSynth,
+    host_buffer: HostBuffer<f32>,
+    /// The outputs, as long as the largest block, so a block allocates nothing.
+    outputs: Vec<Vec<f32>>,
+}
+
+impl OfflineHost {
+    /// A fresh synth for blocks of up to `block_size`, with `params` set by index before it
+    /// resumes.
+    pub fn new(block_size: usize, params: &[(usize, f32)]) -> OfflineHost {
+        let mut synth = SineSynth::default();
+        synth.set_sample_rate(44100.0);
+        synth.set_block_size(block_size as i64);
+        for &(index, value) in params {
+            synth.params.set_parameter(index as i32, value);
+        }
+        synth.resume();
+        OfflineHost {
+            synth,
+            host_buffer: HostBuffer::new(0, 2),
+            outputs: vec![vec![0.0; block_size]; 2],
+        }
+    }
+
+    /// Queue the MIDI message `data` to arrive `delta_frames` into the next block.
+    pub fn queue_midi(&mut self, delta_frames: usize, data: [u8; 3]) {
+        self.synth.queue_midi_event(delta_frames as i32, data);
+    }
+
+    /// Set the parameter at `index` as the host does, between blocks.
+    pub fn set_parameter(&self, index: usize, value: f32) {
+        self.synth.params.set_parameter(index as i32, value);
+    }
+
+    /// Process a block of `samples`, returning both outputs. Queue its events first.
+    pub fn process(&mut self, samples: usize) -> [&[f32]; 2] {
+        let inputs: [Vec<f32>; 0] = [];
+        let (left, right) = self.outputs.split_at_mut(1);
+        let mut outputs = [&mut left[0][..samples], &mut right[0][..samples]];
+        self.synth
+            .process(&mut self.host_buffer.bind(&inputs, &mut outputs));
+        [&self.outputs[0][..samples], &self.outputs[1][..samples]]
+    }
+}
+
+/// Write `outputs` as a stereo WAV file of 32-bit floats at `sample_rate`.
+pub fn write_wav(
+    out: &mut impl Write,
+    sample_rate: u32,
+    outputs: &[Vec<f32>; 2],
+) -> io::Result<()> {
+    const CHANNELS: u16 = 2;
+    const BYTES_PER_SAMPLE: u16 = 4;
+    // WAVE_FORMAT_IEEE_FLOAT.
+    const FLOAT: u16 = 3;
+    let frames = outputs[0].len().min(outputs[1].len());
+    let data_size = frames * usize::from(CHANNELS * BYTES_PER_SAMPLE);
+    let data_size = u32::try_from(data_size)
+        .ok()
+        .filter(|&size| size <= u32::MAX - 36)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "too long for a WAV file"))?;
+
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_size).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&FLOAT.to_le_bytes());
+    header.extend_from_slice(&CHANNELS.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    let block_align = CHANNELS * BYTES_PER_SAMPLE;
+    header.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&(BYTES_PER_SAMPLE * 8).to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+    out.write_all(&header)?;
+
+    let mut data = Vec::with_capacity(data_size as usize);
+    for (left, right) in outputs[0].iter().zip(&outputs[1]) {
+        data.extend_from_slice(&left.to_le_bytes());
+        data.extend_from_slice(&right.to_le_bytes());
+    }
+    out.write_all(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, write_wav, Error, Settings};
+
+    const ONE_BAR: &[u8] = include_bytes!("../tests/fixtures/one_bar.mid");
+
+    fn u16_at(wav: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([wav[at], wav[at + 1]])
+    }
+
+    fn u32_at(wav: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([wav[at], wav[at + 1], wav[at + 2], wav[at + 3]])
+    }
+
+    #[test]
+    fn renders_a_bar_to_a_float_wav() {
+        let settings = Settings {
+            sample_rate: 48000.0,
+            block_size: 300,
+            params: vec![("amplitude".into(), "0.8".into())],
+        };
+        let outputs = render(ONE_BAR, &settings).unwrap();
+        let mut wav = Vec::new();
+        write_wav(&mut wav, 48000, &outputs).unwrap();
+
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&wav, 16), 16);
+        assert_eq!(u16_at(&wav, 20), 3);
+        assert_eq!(u16_at(&wav, 22), 2);
+        assert_eq!(u32_at(&wav, 24), 48000);
+        assert_eq!(u32_at(&wav, 28), 48000 * 8);
+        assert_eq!(u16_at(&wav, 32), 8);
+        assert_eq!(u16_at(&wav, 34), 32);
+        assert_eq!(&wav[36..40], b"data");
+        let data = &wav[44..];
+        assert_eq!(u32_at(&wav, 40) as usize, data.len());
+
+        // The bar is four beats at 120 BPM, so two seconds and then the release.
+        let frames = data.len() / 8;
+        assert!(frames > 2 * 48000 && frames < 4 * 48000, "{}", frames);
+        let samples: Vec<f32> = data
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert!(samples.iter().all(|sample| sample.is_finite()));
+        let peak = samples
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak > 0.1 && peak <= 1.0, "{}", peak);
+        let rms =
+            (samples.iter().map(|&s| f64::from(s * s)).sum::<f64>() / samples.len() as f64).sqrt();
+        assert!(rms > 0.01, "{}", rms);
+        // It ends in silence, with the whole release kept.
+        assert!(samples[samples.len() - 200..]
+            .iter()
+            .all(|s| s.abs() < 1e-4));
+    }
+
+    #[test]
+    fn the_same_file_renders_the_same_in_any_block_size() {
+        let render_in = |block_size| {
+            let settings = Settings {
+                block_size,
+                ..Settings::default()
+            };
+            render(ONE_BAR, &settings).unwrap()
+        };
+        let whole = render_in(100_000);
+        for &block_size in &[1, 64, 512] {
+            let blocks = render_in(block_size);
+            for (a, b) in whole.iter().zip(&blocks) {
+                assert_eq!(a.len(), b.len());
+                assert!(
+                    a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4),
+                    "{}",
+                    block_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parameters_are_found_by_name_and_checked() {
+        let with = |name: &str, value: &str| Settings {
+            params: vec![(name.into(), value.into())],
+            ..Settings::default()
+        };
+        for &(name, value) in &[
+            ("Amplitude", "0.5"),
+            ("unison_spread", "0.3"),
+            ("UNISON-SPREAD", "0.3"),
+            ("waveform", "Saw"),
+        ] {
+            assert!(
+                render(ONE_BAR, &with(name, value)).is_ok(),
+                "{} {}",
+                name,
+                value
+            );
+        }
+        assert_eq!(
+            render(ONE_BAR, &with("loudness", "0.5")).unwrap_err(),
+            Error::UnknownParameter("loudness".into())
+        );
+        assert!(matches!(
+            render(ONE_BAR, &with("amplitude", "2")),
+            Err(Error::BadValue(..))
+        ));
+        assert!(matches!(
+            render(ONE_BAR, &with("amplitude", "loud")),
+            Err(Error::BadValue(..))
+        ));
+        assert_eq!(
+            render(b"RIFF", &Settings::default()).unwrap_err(),
+            Error::NotMidi
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Oscillator waveforms, evaluated from a phase in `[0, 1)`.
+
+use crate::params::options;
+use crate::sine::{Lookup, Osc};
+
+/// The narrowest pulse allowed, as a fraction of the cycle; the widest is one minus this.
+///
+/// A pulse width of 0% or 100% would be a constant, producing only DC.
+pub const MIN_PULSE_WIDTH: f64 = 0.05;
+
+options! {
+    pub enum Waveform {
+        Sine => "Sine",
+        Triangle => "Triangle",
+        Saw => "Saw",
+        Square => "Square",
+        /// Played from the selected wavetable; see `crate::wavetable`.
+        Wavetable => "Wavetable",
+        /// A sum of the first few harmonics at their own levels.
+        Additive => "Additive",
+        /// A plucked string; see `crate::pluck`.
+        Pluck => "Pluck",
+    }
+}
+
+impl Waveform {
+    /// The band-limited value of the waveform at `phase`.
+    ///
+    /// `increment` is how far the phase advances per sample (frequency / sample rate). The
+    /// jumps in the saw and square are smoothed with PolyBLEP corrections over the samples
+    /// either side of them, which removes most of the aliasing the naive shapes produce.
+    ///
+    /// `pulse_width` is the fraction of the cycle the square spends high; it is ignored by the
+    /// other waveforms.
+    ///
+    /// The table, harmonic levels and string aren't known here, so `Wavetable`, `Additive` and
+    /// `Pluck` play as a sine; voices render them from their own settings instead.
+    pub fn sample(self, phase: f64, increment: f64, pulse_width: f64) -> f64 {
+        let increment = increment.min(0.5);
+        let pulse_width = pulse_width.clamp(MIN_PULSE_WIDTH, 1.0 - MIN_PULSE_WIDTH);
+        let naive = self.naive(phase, pulse_width);
+        match self {
+            Waveform::Sine
+            | Waveform::Triangle
+            | Waveform::Wavetable
+            | Waveform::Additive
+            | Waveform::Pluck => naive,
+            // The saw drops from +1 to -1 halfway through the cycle.
+            Waveform::Saw => naive - poly_blep((phase + 0.5).fract(), increment),
+            // The square rises at the start of the cycle and falls after `pulse_width`.
+            Waveform::Square => {
+                naive + poly_blep(phase, increment)
+                    - poly_blep((phase + 1.0 - pulse_width).fract(), increment)
+            }
+        }
+    }
+
+    /// The step in the waveform when its cycle is cut short at `phase` and restarted, as hard
+    /// sync does.
+    ///
+    /// The step is measured from the value at the very end of a cycle rather than the start,
+    /// because `sample` already smooths the square's own edge at the start of the cycle.
+    pub fn sync_jump(self, phase: f64, pulse_width: f64) -> f64 {
+        let pulse_width = pulse_width.clamp(MIN_PULSE_WIDTH, 1.0 - MIN_PULSE_WIDTH);
+        self.naive(1.0, pulse_width) - self.naive(phase, pulse_width)
+    }
+
+    /// The value of the waveform at `phase`, without any anti-aliasing.
+    ///
+    /// Every waveform starts at zero and rises, so switching between them while a note plays
+    /// carries on from the same point in the cycle.
+    fn naive(self, phase: f64, pulse_width: f64) -> f64 {
+        match self {
+            Waveform::Sine | Waveform::Wavetable | Waveform::Additive | Waveform::Pluck => {
+                Lookup.sine(phase)
+            }
+            Waveform::Triangle => 4.0 * ((phase + 0.75).fract() - 0.5).abs() - 1.0,
+            Waveform::Saw => 2.0 * (phase + 0.5).fract() - 1.0,
+            Waveform::Square => {
+                if phase < pulse_width {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+/// The PolyBLEP residual for a unit step at phase 0, given the distance `t` from it.
+///
+/// This is a two-sample polynomial approximation of the difference between a band-limited
+/// step and a naive one, and is zero away from the discontinuity.
+pub fn poly_blep(t: f64, increment: f64) -> f64 {
+    if t < increment {
+        let t = t / increment;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - increment {
+        let t = (t - 1.0) / increment;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Waveform, MIN_PULSE_WIDTH};
+    use crate::analysis::magnitude_at;
+    use crate::{midi_pitch_to_freq, A4_FREQ};
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    fn cycle(waveform: Waveform) -> Vec<f64> {
+        (0..1000)
+            .map(|i| waveform.sample(i as f64 / 1000.0, 0.001, 0.5))
+            .collect()
+    }
+
+    fn render(note: u8, shape: impl Fn(f64, f64) -> f64) -> Vec<f32> {
+        let increment = midi_pitch_to_freq(note, A4_FREQ) / SAMPLE_RATE;
+        let mut phase = 0.0;
+        (0..8192)
+            .map(|_| {
+                let sample = shape(phase, increment);
+                phase = (phase + increment).fract();
+                sample as f32
+            })
+            .collect()
+    }
+
+    /// The summed amplitude of the harmonics that fold back below Nyquist.
+    fn alias_level(signal: &[f32], note: u8) -> f64 {
+        let fundamental = midi_pitch_to_freq(note, A4_FREQ);
+        let nyquist = SAMPLE_RATE / 2.0;
+        (1..40)
+            .map(|harmonic| harmonic as f64 * fundamental)
+            .filter(|&freq| freq > nyquist)
+            .map(|freq| {
+                // Fold the harmonic back into the audible band.
+                let folded = freq % SAMPLE_RATE;
+                let folded = if folded > nyquist {
+                    SAMPLE_RATE - folded
+                } else {
+                    folded
+                };
+                magnitude_at(signal, folded, SAMPLE_RATE)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn poly_blep_reduces_aliasing() {
+        for &waveform in [Waveform::Saw, Waveform::Square].iter() {
+            let naive = render(96, |phase, _| waveform.naive(phase, 0.5));
+            let band_limited = render(96, |phase, increment| {
+                waveform.sample(phase, increment, 0.5)
+            });
+
+            let reduction =
+                20.0 * (alias_level(&naive, 96) / alias_level(&band_limited, 96)).log10();
+            assert!(
+                reduction > 10.0,
+                "{:?} only reduced by {} dB",
+                waveform,
+                reduction
+            );
+        }
+    }
+
+    #[test]
+    fn sine_is_unaffected() {
+        for i in 0..1000 {
+            let phase = i as f64 / 1000.0;
+            assert_eq!(
+                Waveform::Sine.sample(phase, 0.05, 0.5),
+                Waveform::Sine.naive(phase, 0.5)
+            );
+        }
+    }
+
+    #[test]
+    fn pulse_average_matches_duty_cycle() {
+        for &width in [0.1, 0.25, 0.5, 0.8].iter() {
+            let pulse = render(60, |phase, increment| {
+                Waveform::Square.sample(phase, increment, width)
+            });
+            // Whole cycles only, so the average isn't biased by a partial one.
+            let cycle_len = 44100.0 / midi_pitch_to_freq(60, A4_FREQ);
+            let len = ((pulse.len() as f64 / cycle_len).floor() * cycle_len) as usize;
+            let average = pulse[..len].iter().map(|&s| f64::from(s)).sum::<f64>() / len as f64;
+            assert!(
+                (average - (2.0 * width - 1.0)).abs() < 0.01,
+                "{} gave {}",
+                width,
+                average
+            );
+        }
+    }
+
+    #[test]
+    fn pulse_width_is_limited() {
+        let samples = |width: f64| -> Vec<f64> {
+            (0..1000)
+                .map(|i| Waveform::Square.sample(i as f64 / 1000.0, 0.001, width))
+                .collect()
+        };
+        assert_eq!(samples(0.0), samples(MIN_PULSE_WIDTH));
+        assert_eq!(samples(1.0), samples(1.0 - MIN_PULSE_WIDTH));
+    }
+
+    #[test]
+    fn waveforms_are_bounded_and_audible() {
+        for &waveform in Waveform::ALL.iter() {
+            let samples = cycle(waveform);
+            assert!(samples.iter().all(|s| s.abs() <= 1.0), "{:?}", waveform);
+            assert!(samples.iter().any(|s| s.abs() > 0.9), "{:?}", waveform);
+        }
+    }
+
+    #[test]
+    fn waveforms_rise_from_zero() {
+        assert_eq!(Waveform::Sine.naive(0.0, 0.5), 0.0);
+        assert_eq!(Waveform::Triangle.naive(0.0, 0.5), 0.0);
+        assert_eq!(Waveform::Saw.naive(0.0, 0.5), 0.0);
+        assert_eq!(Waveform::Triangle.naive(0.25, 0.5), 1.0);
+        assert_eq!(Waveform::Saw.naive(0.25, 0.5), 0.5);
+    }
+
+    #[test]
+    fn sync_jump_is_zero_at_cycle_end() {
+        for &waveform in Waveform::ALL.iter() {
+            assert!(waveform.sync_jump(1.0, 0.5).abs() < 1e-12, "{:?}", waveform);
+        }
+        assert_eq!(Waveform::Saw.sync_jump(0.25, 0.5), -0.5);
+    }
+
+    #[test]
+    fn normalized_value_round_trips() {
+        for &waveform in Waveform::ALL.iter() {
+            assert_eq!(
+                Waveform::from_normalized(waveform.to_normalized()),
+                waveform
+            );
+        }
+    }
+}
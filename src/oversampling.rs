@@ -0,0 +1,218 @@
+//! Oversampling: rendering the voices and the drive at a multiple of the host's rate, so the
+//! harmonics hard sync, FM and saturation add above Nyquist are filtered out instead of folding
+//! back down as aliases.
+//!
+//! Each halving of the rate is a linear-phase half-band FIR, so the output is delayed by a
+//! whole number of samples, which the host is told as the plugin's latency.
+
+use crate::params::options;
+use crate::PI;
+
+/// Taps in each half-band filter. With `TAPS - 1` a multiple of 8, both the 2× and 4× delays
+/// come out as whole samples at the host's rate.
+const TAPS: usize = 65;
+
+/// The most samples rendered for every one the host gets.
+pub const MAX_FACTOR: usize = 4;
+
+options! {
+    /// How many times the host's rate the voices render at.
+    pub enum Oversampling {
+        Off => "Off",
+        X2 => "2x",
+        X4 => "4x",
+    }
+}
+
+impl Oversampling {
+    /// Samples rendered for every one the host gets, at most `MAX_FACTOR`.
+    pub fn factor(self) -> usize {
+        match self {
+            Oversampling::Off => 1,
+            Oversampling::X2 => 2,
+            Oversampling::X4 => 4,
+        }
+    }
+
+    /// How many of the host's samples the decimation delays the output by.
+    ///
+    /// Each half-band delays by half its length at the rate it runs at, so the stage from 4×
+    /// to 2× adds half as much as the one from 2× down to the host's rate.
+    pub fn latency(self) -> usize {
+        let last_stage = (TAPS - 1) / 4;
+        match self {
+            Oversampling::Off => 0,
+            Oversampling::X2 => last_stage,
+            Oversampling::X4 => last_stage + last_stage / 2,
+        }
+    }
+}
+
+/// A half-band low-pass that halves the rate of one channel.
+#[derive(Clone, Copy, Debug)]
+struct HalfBand {
+    coefficients: [f64; TAPS],
+    /// The latest `TAPS` inputs, the newest at `write - 1`.
+    history: [f64; TAPS],
+    write: usize,
+}
+
+impl HalfBand {
+    /// A Blackman-windowed sinc cut off at a quarter of the input rate, normalized to unity
+    /// gain at DC. Every other tap but the centre falls on a zero of the sinc, so they're set
+    /// to exactly zero rather than to whatever the sine rounds to.
+    fn new() -> HalfBand {
+        let centre = (TAPS / 2) as f64;
+        let mut coefficients = [0.0; TAPS];
+        for (n, coefficient) in coefficients.iter_mut().enumerate() {
+            let offset = n as f64 - centre;
+            let sinc = if offset == 0.0 {
+                0.5
+            } else if offset % 2.0 == 0.0 {
+                0.0
+            } else {
+                (0.5 * PI * offset).sin() / (PI * offset)
+            };
+            let x = n as f64 / (TAPS - 1) as f64;
+            let window = 0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos();
+            *coefficient = sinc * window;
+        }
+        let sum: f64 = coefficients.iter().sum();
+        coefficients.iter_mut().for_each(|c| *c /= sum);
+        HalfBand {
+            coefficients,
+            history: [0.0; TAPS],
+            write: 0,
+        }
+    }
+
+    fn push(&mut self, input: f64) {
+        self.history[self.write] = input;
+        self.write = (self.write + 1) % TAPS;
+    }
+
+    /// The filter's output with the latest input as its newest sample.
+    fn output(&self) -> f64 {
+        let (older, newer) = self.history.split_at(self.write);
+        // Newest first, so the first coefficient meets the latest input.
+        newer
+            .iter()
+            .chain(older)
+            .rev()
+            .zip(&self.coefficients)
+            .map(|(input, coefficient)| input * coefficient)
+            .sum()
+    }
+
+    /// Filter `input` and keep every other sample of it in `output`, which is half as long.
+    ///
+    /// The kept samples are the ones at the even input positions, which line up with the
+    /// start of each output sample, so the delay is a whole number of output samples.
+    fn halve(&mut self, input: &[f64], output: &mut [f64]) {
+        for (pair, output) in input.chunks_exact(2).zip(output) {
+            self.push(pair[0]);
+            *output = self.output();
+            self.push(pair[1]);
+        }
+    }
+}
+
+/// Brings one channel of oversampled samples back down to the host's rate.
+#[derive(Clone, Copy, Debug)]
+pub struct Decimator {
+    /// From 4× to 2×, then from 2× to the host's rate; 2× only uses the second.
+    stages: [HalfBand; 2],
+}
+
+impl Default for Decimator {
+    fn default() -> Decimator {
+        Decimator {
+            stages: [HalfBand::new(); 2],
+        }
+    }
+}
+
+impl Decimator {
+    /// Whether nothing but silence is left in the filters.
+    pub fn is_silent(&self) -> bool {
+        self.stages
+            .iter()
+            .all(|stage| stage.history.iter().all(|&input| input == 0.0))
+    }
+
+    /// The host-rate sample for `samples`, one host sample's worth at `oversampling`.
+    pub fn process(&mut self, oversampling: Oversampling, samples: &[f64]) -> f64 {
+        let mut output = [0.0; 1];
+        match oversampling {
+            Oversampling::Off => return samples[0],
+            Oversampling::X2 => self.stages[1].halve(samples, &mut output),
+            Oversampling::X4 => {
+                let mut half = [0.0; 2];
+                self.stages[0].halve(samples, &mut half);
+                self.stages[1].halve(&half, &mut output);
+            }
+        }
+        output[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decimator, Oversampling};
+
+    /// The host-rate output for a unit impulse at the start of the first host sample.
+    fn impulse_response(oversampling: Oversampling) -> Vec<f64> {
+        let mut decimator = Decimator::default();
+        let factor = oversampling.factor();
+        (0..64)
+            .map(|n| {
+                let mut samples = [0.0; 4];
+                if n == 0 {
+                    samples[0] = 1.0;
+                }
+                decimator.process(oversampling, &samples[..factor])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn latency_is_the_group_delay() {
+        for &oversampling in Oversampling::ALL.iter() {
+            let response = impulse_response(oversampling);
+            let peak = (0..response.len())
+                .max_by(|&a, &b| response[a].abs().total_cmp(&response[b].abs()))
+                .unwrap();
+            assert_eq!(peak, oversampling.latency(), "{:?}", oversampling);
+        }
+        assert_eq!(Oversampling::X2.latency(), 16);
+        assert_eq!(Oversampling::X4.latency(), 24);
+    }
+
+    #[test]
+    fn passes_dc_and_low_frequencies_at_unity() {
+        for &oversampling in Oversampling::ALL.iter() {
+            let mut decimator = Decimator::default();
+            let factor = oversampling.factor();
+            let mut last = 0.0;
+            for _ in 0..100 {
+                last = decimator.process(oversampling, &[1.0; 4][..factor]);
+            }
+            assert!(
+                (last - 1.0).abs() < 1e-12,
+                "{:?} gave {}",
+                oversampling,
+                last
+            );
+        }
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        for &oversampling in Oversampling::ALL.iter() {
+            assert_eq!(
+                Oversampling::from_normalized(oversampling.to_normalized()),
+                oversampling
+            );
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Changing parameters from inside the plugin, with the host told about each change.
+//!
+//! The host sets parameters through `set_parameter`, and already knows what it set. The
+//! plugin changes them too: the editor's sliders, MIDI learn and controllers bound to
+//! parameters. Those go through a `ParamHandle`, which tells the host with Begin Edit,
+//! Automate and End Edit, so it can record the change as automation and undo it. A MIDI
+//! Program Change replaces the whole program instead, and only asks the host to show it.
+//!
+//! A change made while the host is setting parameters on the same thread came from the host,
+//! however indirectly, so it isn't reported back. That stops a host that answers Automate by
+//! setting the parameter again from going round in circles.
+
+use std::cell::Cell;
+use std::ptr;
+
+use vst::host::OpCode;
+use vst::plugin::{HostCallback, PluginParameters};
+
+use crate::GainEffectParameters;
+
+thread_local! {
+    /// How many calls from the host that set parameters this thread is inside.
+    static FROM_HOST: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Marks the thread as inside a call from the host until dropped, panics included.
+struct FromHost;
+
+impl FromHost {
+    fn enter() -> FromHost {
+        FROM_HOST.with(|depth| depth.set(depth.get() + 1));
+        FromHost
+    }
+}
+
+impl Drop for FromHost {
+    fn drop(&mut self) {
+        FROM_HOST.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Run `set` as the host's own change to the parameters, which the host isn't told about.
+pub fn from_host<R>(set: impl FnOnce() -> R) -> R {
+    let _from_host = FromHost::enter();
+    set()
+}
+
+fn inside_host_call() -> bool {
+    FROM_HOST.with(|depth| depth.get() > 0)
+}
+
+/// The host, for the parameters to call back.
+pub struct HostLink(HostCallback);
+
+// SAFETY: The host's callback is a plain function pointer hosts expect to be called from the
+// audio and editor threads alike, which is all this does with it.
+unsafe impl Send for HostLink {}
+unsafe impl Sync for HostLink {}
+
+impl HostLink {
+    pub fn new(host: HostCallback) -> HostLink {
+        HostLink(host)
+    }
+
+    /// Send `opcode` about parameter `index` to the host, if there is one and it isn't the
+    /// host's own change.
+    fn tell(&self, opcode: OpCode, index: i32, value: f32) {
+        if inside_host_call() {
+            return;
+        }
+        if let Some(callback) = self.0.raw_callback() {
+            callback(
+                self.0.raw_effect(),
+                opcode.into(),
+                index,
+                0,
+                ptr::null_mut(),
+                value,
+            );
+        }
+    }
+
+    /// Ask the host to show the plugin's parameters and program name again.
+    pub fn update_display(&self) {
+        self.tell(OpCode::UpdateDisplay, 0, 0.0);
+    }
+
+    /// Tell the host the plugin's latency is now `samples`. The host reads it from the
+    /// plugin's effect, so it is written there before the host is told to look again.
+    pub fn set_latency(&self, samples: i32) {
+        let effect = self.0.raw_effect();
+        if effect.is_null() {
+            return;
+        }
+        // SAFETY: The effect is the plugin's own, which the host keeps alive for as long as
+        // it calls the plugin, and the host only reads the latency from it.
+        unsafe { (*effect).initialDelay = samples };
+        self.tell(OpCode::IOChanged, 0, 0.0);
+    }
+}
+
+/// One parameter, as the plugin changes it.
+pub struct ParamHandle<'a> {
+    params: &'a GainEffectParameters,
+    index: i32,
+}
+
+impl<'a> ParamHandle<'a> {
+    pub fn new(params: &'a GainEffectParameters, index: usize) -> ParamHandle<'a> {
+        ParamHandle {
+            params,
+            index: index as i32,
+        }
+    }
+
+    fn tell(&self, opcode: OpCode, value: f32) {
+        self.params.host.tell(opcode, self.index, value);
+    }
+
+    /// Start a gesture, like grabbing a slider, that the changes until `end` belong to.
+    pub fn begin(&self) {
+        self.tell(OpCode::BeginEdit, 0.0);
+    }
+
+    /// Set the parameter as `set_parameter` does, and tell the host its new value.
+    pub fn set(&self, value: f32) {
+        self.params.store(self.index, value);
+        self.tell(OpCode::Automate, self.params.get_parameter(self.index));
+    }
+
+    /// End the gesture `begin` started.
+    pub fn end(&self) {
+        self.tell(OpCode::EndEdit, 0.0);
+    }
+
+    /// Set the parameter in a gesture of its own, as a controller does.
+    pub fn change(&self, value: f32) {
+        self.begin();
+        self.set(value);
+        self.end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::os::raw::c_void;
+
+    use vst::api::AEffect;
+    use vst::host::OpCode;
+    use vst::plugin::{HostCallback, PluginParameters};
+
+    use super::from_host;
+    use crate::params::ParamId;
+    use crate::{GainEffectParameters, SineSynth};
+
+    thread_local! {
+        /// What the mock host was told on this thread: the opcode, parameter and value.
+        static TOLD: RefCell<Vec<(i32, i32, f32)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn mock_host(
+        _effect: *mut AEffect,
+        opcode: i32,
+        index: i32,
+        _value: isize,
+        _ptr: *mut c_void,
+        opt: f32,
+    ) -> isize {
+        TOLD.with(|told| told.borrow_mut().push((opcode, index, opt)));
+        // Without a version the vst crate won't make a plugin for the host.
+        match OpCode::from(opcode) {
+            OpCode::Version => 2400,
+            _ => 0,
+        }
+    }
+
+    /// Take what the host has been told since last asked.
+    fn told() -> Vec<(OpCode, i32, f32)> {
+        TOLD.with(|told| told.borrow_mut().drain(..).collect::<Vec<_>>())
+            .into_iter()
+            .map(|(opcode, index, value)| (OpCode::from(opcode), index, value))
+            .collect()
+    }
+
+    fn automated() -> usize {
+        told()
+            .iter()
+            .filter(|(opcode, _, _)| matches!(opcode, OpCode::Automate))
+            .count()
+    }
+
+    fn host() -> HostCallback {
+        HostCallback::wrap(mock_host, std::ptr::null_mut())
+    }
+
+    fn params() -> GainEffectParameters {
+        GainEffectParameters::new(host())
+    }
+
+    #[test]
+    fn the_plugin_s_own_changes_are_reported_as_gestures() {
+        let params = params();
+        params.handle(0).change(0.25);
+        let told = told();
+        assert_eq!(told.len(), 3);
+        assert!(matches!(told[0], (OpCode::BeginEdit, 0, _)));
+        assert!(matches!(told[1], (OpCode::Automate, 0, value) if value == 0.25));
+        assert!(matches!(told[2], (OpCode::EndEdit, 0, _)));
+        assert_eq!(params.get_parameter(0), 0.25);
+
+        // A drag is one gesture however far it goes.
+        let handle = params.handle(38);
+        handle.begin();
+        for step in 0..10 {
+            handle.set(step as f32 / 10.0);
+        }
+        handle.end();
+        assert_eq!(automated(), 10);
+    }
+
+    #[test]
+    fn the_host_s_own_changes_are_not_echoed() {
+        let params = params();
+        params.set_parameter(0, 0.5);
+        assert!(params.string_to_parameter(0, "0.5".to_string()));
+        params.change_preset(2);
+        params.load_bank_data(&params.get_bank_data());
+        assert_eq!(automated(), 0);
+        // Not even what the plugin changes in response.
+        from_host(|| params.handle(1).change(0.3));
+        assert_eq!(automated(), 0);
+        // Once the host's call is over, changes are reported again.
+        params.handle(1).change(0.4);
+        assert_eq!(automated(), 1);
+    }
+
+    #[test]
+    fn reported_values_are_the_stored_ones() {
+        let params = params();
+        params.handle(0).change(7.0);
+        params.handle(0).change(f32::NAN);
+        let values: Vec<f32> = told()
+            .into_iter()
+            .filter(|(opcode, _, _)| matches!(opcode, OpCode::Automate))
+            .map(|(_, _, value)| value)
+            .collect();
+        assert_eq!(values, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn midi_learn_and_program_changes_are_reported() {
+        use vst::plugin::Plugin;
+
+        let mut synth = SineSynth::new(host());
+        let params = &synth.params;
+        params.set_parameter(ParamId::ProgramChange as i32, 1.0);
+        params.set_parameter(ParamId::LearnTarget as i32, 0.0);
+        let learn = params
+            .parse_parameter(ParamId::MidiLearn as i32, "Learn")
+            .unwrap();
+        params.set_parameter(ParamId::MidiLearn as i32, learn as f32);
+        told();
+
+        // Learning switches itself off, which the host sees as a change to "MIDI Learn".
+        synth.process_midi_event([0xB0, 20, 0]);
+        let learned = told();
+        assert!(learned
+            .iter()
+            .any(|&(opcode, index, _)| matches!(opcode, OpCode::Automate)
+                && index == ParamId::MidiLearn as i32));
+        // Then the controller moves the parameter it's bound to, in a gesture of its own.
+        synth.process_midi_event([0xB0, 20, 127]);
+        let moved = told();
+        assert_eq!(moved.len(), 3);
+        assert!(matches!(moved[1], (OpCode::Automate, 0, value) if value == 1.0));
+
+        // A program change asks the host to show the new program once the block is done, as
+        // a whole rather than as an edit to each parameter.
+        synth.process_midi_event([0xC0, 3, 0]);
+        let (mut left, mut right) = (vec![0.0f32; 64], vec![0.0f32; 64]);
+        synth.render(64, [&mut left[..], &mut right[..]], |sample| sample as f32);
+        let switched = told();
+        let told = |wanted: fn(&OpCode) -> bool| {
+            switched
+                .iter()
+                .filter(|(opcode, _, _)| wanted(opcode))
+                .count()
+        };
+        assert_eq!(told(|opcode| matches!(opcode, OpCode::UpdateDisplay)), 1);
+        assert_eq!(told(|opcode| matches!(opcode, OpCode::BeginEdit)), 0);
+        assert_eq!(told(|opcode| matches!(opcode, OpCode::Automate)), 0);
+    }
+
+    #[test]
+    fn a_change_of_latency_is_reported() {
+        use vst::host::HostBuffer;
+
+        let effect = vst::main::<SineSynth>(mock_host);
+        // SAFETY: The effect is a live plugin until it is shut down at the end.
+        let plugin = unsafe { (*effect).get_plugin() };
+        assert_eq!(unsafe { (*effect).initialDelay }, 0);
+        plugin
+            .get_parameter_object()
+            .set_parameter(ParamId::Oversampling as i32, 1.0);
+        told();
+
+        // The latency changes with the first block rendered at the new oversampling.
+        let mut host_buffer: HostBuffer<f32> = HostBuffer::new(0, 2);
+        let inputs: [Vec<f32>; 0] = [];
+        let mut outputs = vec![vec![0.0; 64]; 2];
+        plugin.process(&mut host_buffer.bind(&inputs, &mut outputs));
+        assert_eq!(
+            unsafe { (*effect).initialDelay },
+            plugin.get_info().initial_delay
+        );
+        assert_eq!(unsafe { (*effect).initialDelay }, 24);
+        let io_changed = || {
+            told()
+                .iter()
+                .filter(|(opcode, _, _)| matches!(opcode, OpCode::IOChanged))
+                .count()
+        };
+        assert_eq!(io_changed(), 1);
+        plugin.process(&mut host_buffer.bind(&inputs, &mut outputs));
+        assert_eq!(io_changed(), 0);
+
+        let shutdown = vst::plugin::OpCode::Shutdown.into();
+        unsafe { ((*effect).dispatcher)(effect, shutdown, 0, 0, std::ptr::null_mut(), 0.0) };
+    }
+
+    #[test]
+    fn the_tempo_is_only_asked_for_a_synced_delay() {
+        let effect = vst::main::<SineSynth>(mock_host);
+        // SAFETY: The effect is a live plugin until it is shut down at the end.
+        let plugin = unsafe { (*effect).get_plugin() };
+        let asked_the_time = || {
+            told()
+                .iter()
+                .filter(|(opcode, _, _)| matches!(opcode, OpCode::GetTime))
+                .count()
+        };
+        told();
+        plugin.get_tail_size();
+        assert_eq!(asked_the_time(), 0);
+        plugin
+            .get_parameter_object()
+            .set_parameter(ParamId::DelaySync as i32, 1.0);
+        plugin.get_tail_size();
+        assert_eq!(asked_the_time(), 1);
+
+        let shutdown = vst::plugin::OpCode::Shutdown.into();
+        unsafe { ((*effect).dispatcher)(effect, shutdown, 0, 0, std::ptr::null_mut(), 0.0) };
+    }
+}
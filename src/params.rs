@@ -0,0 +1,1584 @@
+//! Every parameter the host sees, declared once: its index, name, units, default, and how its
+//! value is shown as text and read back from it.
+//!
+//! The values themselves live in `GainEffectParameters`, which maps them onto what the synth
+//! uses. Parameters are only ever added at the end, since hosts and saved chunks from before
+//! version 2 refer to them by index.
+
+use crate::aftertouch::AftertouchDestination;
+use crate::arp::{arp_divisions, ArpMode};
+use crate::chord::ChordType;
+use crate::crusher::{Quantize, MAX_BITS, MAX_HOLD, MIN_BITS};
+use crate::delay::{delay_divisions, MAX_DELAY, MAX_FEEDBACK, MIN_DELAY};
+use crate::envelope::EnvCurve;
+use crate::filter::{FilterMode, MAX_CUTOFF, MIN_CUTOFF};
+use crate::layer::OctaveLayer;
+use crate::lfo::{LfoDestination, LfoShape, SYNC_DIVISIONS};
+use crate::midi_learn::{LearnMode, TARGET_SLOTS};
+use crate::noise::NoiseColor;
+use crate::note_stack::NotePriority;
+use crate::oscillator::{Waveform, MIN_PULSE_WIDTH};
+use crate::oversampling::Oversampling;
+use crate::tilt::MAX_TILT_DB;
+use crate::voice_steal::StealPolicy;
+use crate::wavetable::TABLES;
+use crate::{
+    amplitude_db, amplitude_to_normalized, attack_seconds, attack_to_normalized,
+    GainEffectParameters, ParamValues, Shown, A4_FREQ, FM_RATIOS, MAX_ARP_TEMPO, MAX_BEND_RANGE,
+    MAX_DETUNE, MAX_FILTER_ENV_AMOUNT, MAX_FINE_TUNE, MAX_FM_FINE, MAX_FM_INDEX, MAX_GLIDE,
+    MAX_LEVEL_KEY_TRACK, MAX_LFO_CUTOFF, MAX_LFO_PITCH, MAX_MASTER_TUNE, MAX_OCTAVE_SHIFT,
+    MAX_OSC2_FIXED, MAX_PLUCK_DECAY, MAX_SEMITONE_SHIFT, MAX_SYNC_OFFSET, MAX_UNISON,
+    MAX_UNISON_SPREAD, MAX_VIBRATO_DEPTH, MAX_VOICES, MIN_ARP_TEMPO, MIN_GLIDE, MIN_MASTER_TUNE,
+    MIN_OSC2_FIXED, VEL_CURVE_STEPS,
+};
+
+/// A parameter, by the index the host knows it by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamId {
+    /// The output level, mapped to dB by `amplitude_db`.
+    Amplitude,
+    /// Mapped to seconds by `attack_seconds`.
+    Attack,
+    /// The other envelope times are in seconds, and the sustain is a level.
+    Decay,
+    Sustain,
+    Release,
+    VelocitySens,
+    BendRange,
+    VibratoRate,
+    /// The vibrato depth with the mod wheel fully up.
+    VibratoDepth,
+    /// Omni at 0, then channels 1 to 16 in equal steps.
+    MidiChannel,
+    Waveform,
+    PulseWidth,
+    SubLevel,
+    /// A sine below 0.5, a square above.
+    SubShape,
+    /// Second oscillator detune, centred on 0.5.
+    Detune,
+    OscMix,
+    UnisonVoices,
+    /// How far the outermost unison copies are detuned either way.
+    UnisonSpread,
+    NoiseLevel,
+    /// White below 0.5, pink above.
+    NoiseColor,
+    Table,
+    /// How far to crossfade from the selected table towards the next one.
+    TablePosition,
+    /// Steps through `FM_RATIOS`.
+    FmRatio,
+    /// Fine tuning of the FM ratio, centred on 0.5.
+    FmFine,
+    FmAmount,
+    RingMod,
+    /// Off (following the keyboard) at 0, then a fixed frequency.
+    Osc2Fixed,
+    /// Off below 0.5, on above.
+    Sync,
+    /// The levels of the additive waveform's harmonics, fundamental first.
+    Harmonic1,
+    Harmonic2,
+    Harmonic3,
+    Harmonic4,
+    Harmonic5,
+    Harmonic6,
+    Harmonic7,
+    Harmonic8,
+    Damping,
+    ExciteBrightness,
+    /// The filter is bypassed with the cutoff fully up.
+    Cutoff,
+    Resonance,
+    /// Centred on 0.5, where the filter envelope has no effect.
+    FilterEnvAmount,
+    /// Stored like the amplitude envelope: times in seconds, sustain as a level.
+    FilterAttack,
+    FilterDecay,
+    FilterSustain,
+    FilterRelease,
+    KeyTrack,
+    FilterMode,
+    LfoRate,
+    LfoDepth,
+    LfoDestination,
+    LfoShape,
+    LfoRetrigger,
+    LfoSync,
+    SyncRate,
+    Glide,
+    VoiceMode,
+    NotePriority,
+    VoiceSteal,
+    Polyphony,
+    PhaseReset,
+    RandomPhase,
+    RandomSeed,
+    /// Whether MIDI Program Change messages switch programs.
+    ProgramChange,
+    /// Hard left at 0, centred on 0.5.
+    Pan,
+    /// How far apart the left and right channels are detuned; 0.0 is mono.
+    StereoWidth,
+    TremRate,
+    /// How far the tremolo dips the output level; 0.0 is off.
+    TremDepth,
+    /// How hard the soft clipper is driven; 0.0 bypasses it.
+    Drive,
+    /// Whether the output limiter is on.
+    Limiter,
+    /// The frequency of A4.
+    MasterTune,
+    /// Transposition in whole octaves, centred on 0.5.
+    Octave,
+    /// Transposition in whole semitones, centred on 0.5.
+    Semitone,
+    /// Fine tuning, centred on 0.5.
+    Fine,
+    /// Whether MIDI Tuning Standard messages retune the notes.
+    TuningMessages,
+    /// Whether each MIDI channel's bend, pressure and slide shape only its own notes.
+    Mpe,
+    /// What channel and poly aftertouch modulate.
+    AftertouchDest,
+    /// How much aftertouch modulates it.
+    AftertouchAmount,
+    /// How incoming velocities are reshaped before they're used.
+    VelocityCurve,
+    /// Whether the next controller to arrive is bound to `LearnTarget`.
+    MidiLearn,
+    /// The parameter MIDI learn binds controllers to.
+    LearnTarget,
+    /// Whether held keys are arpeggiated rather than played together.
+    Arp,
+    ArpMode,
+    /// The length of an arpeggiator step, as a note value.
+    ArpRate,
+    /// How much of each step its note sounds for.
+    ArpGate,
+    /// Whether the arpeggiator carries on after the keys are let go.
+    ArpHold,
+    /// The tempo the arpeggiator follows when the host doesn't give one.
+    ArpTempo,
+    /// The chord every key plays.
+    Chord,
+    /// Switched on to learn the held keys as the user chord.
+    ChordLearn,
+    /// Which octaves each voice doubles its first oscillator in.
+    OctaveLayer,
+    LayerLevel,
+    /// Whether glide slides between every note or only between overlapping ones.
+    GlideMode,
+    /// The shape of the amplitude envelope's segments.
+    EnvCurve,
+    /// Fades the output out and stops rendering while on.
+    Bypass,
+    /// How many times the host's rate the voices and the drive render at.
+    Oversampling,
+    /// How many times a second the chorus taps sweep their delays.
+    ChorusRate,
+    /// How far the chorus taps' delays sweep.
+    ChorusDepth,
+    /// How much of the chorus replaces the dry sound, with none leaving it untouched.
+    ChorusMix,
+    /// The delay time in milliseconds, when it isn't synced.
+    DelayTime,
+    /// Whether the delay time follows the host's tempo as a note value.
+    DelaySync,
+    /// The delay time as a note value, when it is synced.
+    DelayNote,
+    /// How much of each echo comes round again.
+    Feedback,
+    /// How much of the echoes replaces the dry sound, with none leaving it untouched.
+    DelayMix,
+    /// Whether the echoes alternate between left and right.
+    PingPong,
+    /// How long the reverb rings, as the size of the room.
+    ReverbSize,
+    /// How quickly the highs die away in the reverb's tail.
+    ReverbDamping,
+    /// How much of the reverb replaces the dry sound, with none leaving it untouched.
+    ReverbMix,
+    /// How many bits each sample is cut down to, with the full 16 leaving it alone.
+    BitDepth,
+    /// How many samples in a row each one taken is held for.
+    Downsample,
+    /// Whether cutting down the bits rounds or truncates.
+    Quantize,
+    /// Tilts the sound darker or brighter around the middle of the spectrum.
+    Brightness,
+    /// How much louder or quieter each octave a note is above middle C plays.
+    LevelKeyTrack,
+}
+
+/// The number of parameters, which is also the number reported to the host.
+/// `LevelKeyTrack` must stay the last variant, or this must move to whichever is.
+pub const PARAM_COUNT: usize = ParamId::LevelKeyTrack as usize + 1;
+
+impl ParamId {
+    /// Whether each program has its own value of it. Bypass is about what the plugin is doing
+    /// right now rather than how it sounds, so it stays as it is through program changes and
+    /// isn't saved.
+    pub fn in_programs(self) -> bool {
+        self != ParamId::Bypass
+    }
+}
+
+pub struct ParamDef {
+    pub id: ParamId,
+    pub name: &'static str,
+    /// The units its text can be shown in, for the host's label.
+    pub units: &'static [&'static str],
+    /// Whether hosts offer it for automation. Internal settings that only make sense saved
+    /// with a project aren't.
+    pub automatable: bool,
+    pub default: f32,
+    /// The text for the value `Shown` holds for the parameter. Some parameters are shown
+    /// differently depending on others, which are live.
+    pub format: fn(&Shown) -> String,
+    /// The normalized value for text typed into the host, or `None` if it doesn't make sense.
+    pub parse: fn(&GainEffectParameters, &str) -> Option<f64>,
+}
+
+/// Every parameter, in `ParamId` order.
+pub static PARAMS: [ParamDef; PARAM_COUNT] = [
+    ParamDef {
+        id: ParamId::Amplitude,
+        name: "Amplitude",
+        units: &["dB"],
+        automatable: true,
+        // Exactly half gain, from `amplitude_to_normalized(CENTRE_AMPLITUDE_DB)`.
+        default: 0.5,
+        format: |shown| format!("{:.1} dB", amplitude_db(shown.value)),
+        parse: |_, text| {
+            parse_number(text, &[("db", 1.0)]).map(|db| f64::from(amplitude_to_normalized(db)))
+        },
+    },
+    ParamDef {
+        id: ParamId::Attack,
+        name: "Attack",
+        units: &["ms", "s"],
+        automatable: true,
+        // Half a second, from `attack_to_normalized(0.5)`.
+        default: 0.787_187_4,
+        format: |shown| {
+            let attack = attack_seconds(f64::from(shown.value));
+            if attack < 0.01 {
+                format!("{:.1} ms", attack * 1000.0)
+            } else if attack < 1.0 {
+                format!("{:.0} ms", attack * 1000.0)
+            } else {
+                format!("{:.1} s", attack)
+            }
+        },
+        parse: |_, text| parse_number(text, TIME).map(|s| f64::from(attack_to_normalized(s))),
+    },
+    ParamDef {
+        id: ParamId::Decay,
+        name: "Decay",
+        units: &["ms"],
+        automatable: true,
+        default: 0.3,
+        format: milliseconds_text,
+        parse: parse_time,
+    },
+    ParamDef {
+        id: ParamId::Sustain,
+        name: "Sustain",
+        units: &["%"],
+        automatable: true,
+        default: 1.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::Release,
+        name: "Release",
+        units: &["ms"],
+        automatable: true,
+        default: 0.05,
+        format: milliseconds_text,
+        parse: parse_time,
+    },
+    ParamDef {
+        id: ParamId::VelocitySens,
+        name: "Velocity Sens",
+        units: &["%"],
+        automatable: true,
+        default: 1.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::BendRange,
+        name: "Bend Range",
+        units: &["st"],
+        automatable: true,
+        // Two semitones.
+        default: 1.0 / (MAX_BEND_RANGE - 1.0),
+        format: |shown| format!("±{} st", shown.bend_range()),
+        parse: |_, text| {
+            let semitones = parse_number(text, &[("st", 1.0)])?;
+            Some(linear(semitones, 1.0, f64::from(MAX_BEND_RANGE)))
+        },
+    },
+    ParamDef {
+        id: ParamId::VibratoRate,
+        name: "Vibrato Rate",
+        units: &["Hz"],
+        automatable: true,
+        default: 0.85,
+        format: |shown| format!("{:.2} Hz", shown.vibrato_rate()),
+        parse: |_, text| Some(exponential(parse_number(text, HERTZ)?, 0.1, 10.0)),
+    },
+    ParamDef {
+        id: ParamId::VibratoDepth,
+        name: "Vibrato Depth",
+        units: &["cents"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("{:.0} cents", shown.vibrato_depth()),
+        parse: |_, text| Some(parse_number(text, CENTS)? / f64::from(MAX_VIBRATO_DEPTH)),
+    },
+    ParamDef {
+        id: ParamId::MidiChannel,
+        name: "MIDI Channel",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: |shown| match shown.midi_channel() {
+            Some(channel) => format!("Ch {}", channel + 1),
+            None => "Omni".to_string(),
+        },
+        parse: |_, text| {
+            if text.eq_ignore_ascii_case("Omni") {
+                return Some(0.0);
+            }
+            let channel = text.trim_start_matches(|c: char| c.is_alphabetic() || c == ' ');
+            let channel = channel
+                .parse::<u8>()
+                .ok()
+                .filter(|c| (1..=16).contains(c))?;
+            Some(f64::from(channel) / 16.0)
+        },
+    },
+    ParamDef {
+        id: ParamId::Waveform,
+        name: "Waveform",
+        units: &[],
+        automatable: true,
+        default: Waveform::Sine.to_normalized(),
+        format: |shown| Waveform::from_normalized(shown.value).name().to_string(),
+        parse: |_, text| named(text, Waveform::ALL, |option| option.name()),
+    },
+    ParamDef {
+        id: ParamId::PulseWidth,
+        name: "Pulse Width",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("{:.0}%", shown.pulse_width() * 100.0),
+        parse: |_, text| {
+            let width = parse_number(text, PERCENT)?;
+            Some(linear(width, MIN_PULSE_WIDTH, 1.0 - MIN_PULSE_WIDTH))
+        },
+    },
+    ParamDef {
+        id: ParamId::SubLevel,
+        name: "Sub Level",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::SubShape,
+        name: "Sub Shape",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: |shown| shown.sub_shape().name().to_string(),
+        parse: |_, text| {
+            named(text, &[Waveform::Sine, Waveform::Square], |shape| {
+                shape.name()
+            })
+        },
+    },
+    ParamDef {
+        id: ParamId::Detune,
+        name: "Detune",
+        units: &["cents", "st"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| {
+            if shown.sync() {
+                format!("+{:.1} st", shown.sync_offset())
+            } else {
+                format!("{:+.0} cents", shown.detune())
+            }
+        },
+        parse: |params, text| {
+            if params.sync() {
+                let semitones = parse_number(text, &[("st", 1.0)])?;
+                Some(linear(semitones, 0.0, MAX_SYNC_OFFSET))
+            } else {
+                Some(linear(parse_number(text, CENTS)?, -MAX_DETUNE, MAX_DETUNE))
+            }
+        },
+    },
+    ParamDef {
+        id: ParamId::OscMix,
+        name: "Osc Mix",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::UnisonVoices,
+        name: "Unison Voices",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: |shown| format!("{}", shown.unison_voices()),
+        parse: |_, text| Some(linear(parse_number(text, &[])?, 1.0, MAX_UNISON as f64)),
+    },
+    ParamDef {
+        id: ParamId::UnisonSpread,
+        name: "Unison Spread",
+        units: &["cents"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("±{:.0} cents", shown.unison_spread()),
+        parse: |_, text| Some(parse_number(text, CENTS)? / MAX_UNISON_SPREAD),
+    },
+    ParamDef {
+        id: ParamId::NoiseLevel,
+        name: "Noise Level",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::NoiseColor,
+        name: "Noise Color",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: |shown| shown.noise_color().name().to_string(),
+        parse: |_, text| {
+            named(text, &[NoiseColor::White, NoiseColor::Pink], |color| {
+                color.name()
+            })
+        },
+    },
+    ParamDef {
+        id: ParamId::Table,
+        name: "Table",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: |shown| TABLES[shown.table()].name.to_string(),
+        parse: |_, text| named(text, &TABLES, |table| table.name),
+    },
+    ParamDef {
+        id: ParamId::TablePosition,
+        name: "Table Position",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::FmRatio,
+        name: "FM Ratio",
+        units: &["x"],
+        automatable: true,
+        // A ratio of 1.
+        default: 1.0 / (FM_RATIOS.len() - 1) as f32,
+        format: |shown| format!("{}x", shown.fm_coarse()),
+        parse: |_, text| {
+            let ratio = parse_number(text, &[("x", 1.0)])?;
+            let nearest = (0..FM_RATIOS.len())
+                .min_by(|&a, &b| {
+                    let distance = |i: usize| (FM_RATIOS[i] - ratio).abs();
+                    distance(a).total_cmp(&distance(b))
+                })
+                .unwrap_or(0);
+            Some(nearest as f64 / (FM_RATIOS.len() - 1) as f64)
+        },
+    },
+    ParamDef {
+        id: ParamId::FmFine,
+        name: "FM Fine",
+        units: &["cents"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("{:+.0} cents", shown.fm_fine()),
+        parse: |_, text| {
+            Some(linear(
+                parse_number(text, CENTS)?,
+                -MAX_FM_FINE,
+                MAX_FM_FINE,
+            ))
+        },
+    },
+    ParamDef {
+        id: ParamId::FmAmount,
+        name: "FM Amount",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: |shown| format!("{:.2}", shown.fm_index()),
+        parse: |_, text| Some(parse_number(text, &[])? / MAX_FM_INDEX),
+    },
+    ParamDef {
+        id: ParamId::RingMod,
+        name: "Ring Mod",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::Osc2Fixed,
+        name: "Osc2 Fixed",
+        units: &["Hz"],
+        automatable: true,
+        default: 0.0,
+        format: |shown| match shown.osc2_fixed() {
+            Some(freq) => format!("{:.0} Hz", freq),
+            None => "Off".to_string(),
+        },
+        parse: |_, text| {
+            if text.eq_ignore_ascii_case("Off") {
+                return Some(0.0);
+            }
+            // Fully down is "Off", so the lowest frequency sits just above it.
+            let freq = parse_number(text, HERTZ)?;
+            Some(exponential(freq, MIN_OSC2_FIXED, MAX_OSC2_FIXED).max(1e-6))
+        },
+    },
+    ParamDef {
+        id: ParamId::Sync,
+        name: "Sync",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    harmonic(ParamId::Harmonic1, "Harmonic 1", 1.0),
+    harmonic(ParamId::Harmonic2, "Harmonic 2", 0.0),
+    harmonic(ParamId::Harmonic3, "Harmonic 3", 0.0),
+    harmonic(ParamId::Harmonic4, "Harmonic 4", 0.0),
+    harmonic(ParamId::Harmonic5, "Harmonic 5", 0.0),
+    harmonic(ParamId::Harmonic6, "Harmonic 6", 0.0),
+    harmonic(ParamId::Harmonic7, "Harmonic 7", 0.0),
+    harmonic(ParamId::Harmonic8, "Harmonic 8", 0.0),
+    ParamDef {
+        id: ParamId::Damping,
+        name: "Damping",
+        units: &["s"],
+        automatable: true,
+        default: 0.3,
+        format: |shown| format!("{:.2} s", shown.pluck_decay()),
+        parse: |_, text| {
+            let decay = parse_number(text, TIME_SECONDS)?;
+            Some((decay / MAX_PLUCK_DECAY).ln() / 0.01f64.ln())
+        },
+    },
+    ParamDef {
+        id: ParamId::ExciteBrightness,
+        name: "Excite Brightness",
+        units: &["%"],
+        automatable: true,
+        default: 0.7,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::Cutoff,
+        name: "Cutoff",
+        units: &["Hz", "kHz"],
+        automatable: true,
+        default: 1.0,
+        format: |shown| {
+            if shown.filter_open() {
+                "Open".to_string()
+            } else if shown.cutoff() >= 1000.0 {
+                format!("{:.1} kHz", shown.cutoff() / 1000.0)
+            } else {
+                format!("{:.0} Hz", shown.cutoff())
+            }
+        },
+        parse: |_, text| {
+            if text.eq_ignore_ascii_case("Open") {
+                return Some(1.0);
+            }
+            Some(exponential(
+                parse_number(text, HERTZ)?,
+                MIN_CUTOFF,
+                MAX_CUTOFF,
+            ))
+        },
+    },
+    ParamDef {
+        id: ParamId::Resonance,
+        name: "Resonance",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::FilterEnvAmount,
+        name: "Env Amount",
+        units: &["oct"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("{:+.1} oct", shown.filter_env_amount()),
+        parse: |_, text| {
+            let octaves = parse_number(text, OCTAVES)?;
+            Some(linear(
+                octaves,
+                -MAX_FILTER_ENV_AMOUNT,
+                MAX_FILTER_ENV_AMOUNT,
+            ))
+        },
+    },
+    ParamDef {
+        id: ParamId::FilterAttack,
+        name: "Filter Attack",
+        units: &["ms"],
+        automatable: true,
+        default: 0.01,
+        format: milliseconds_text,
+        parse: parse_time,
+    },
+    ParamDef {
+        id: ParamId::FilterDecay,
+        name: "Filter Decay",
+        units: &["ms"],
+        automatable: true,
+        default: 0.3,
+        format: milliseconds_text,
+        parse: parse_time,
+    },
+    ParamDef {
+        id: ParamId::FilterSustain,
+        name: "Filter Sustain",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::FilterRelease,
+        name: "Filter Release",
+        units: &["ms"],
+        automatable: true,
+        default: 0.05,
+        format: milliseconds_text,
+        parse: parse_time,
+    },
+    ParamDef {
+        id: ParamId::KeyTrack,
+        name: "Key Track",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: |shown| format!("{:.0}%", shown.key_track() * 100.0),
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::FilterMode,
+        name: "Filter Type",
+        units: &[],
+        automatable: true,
+        default: FilterMode::LowPass.to_normalized(),
+        format: |shown| shown.filter_mode().name().to_string(),
+        parse: |_, text| named(text, FilterMode::ALL, |option| option.name()),
+    },
+    ParamDef {
+        id: ParamId::LfoRate,
+        name: "LFO Rate",
+        units: &["Hz"],
+        automatable: true,
+        default: 0.6,
+        format: |shown| format!("{:.2} Hz", shown.lfo_rate()),
+        parse: |_, text| Some(exponential(parse_number(text, HERTZ)?, 0.1, 20.0)),
+    },
+    ParamDef {
+        id: ParamId::LfoDepth,
+        name: "LFO Depth",
+        units: &["st", "%", "oct"],
+        automatable: true,
+        default: 0.0,
+        // Shown in the units of whatever the LFO is moving.
+        format: |shown| match shown.lfo_destination() {
+            LfoDestination::Pitch => format!("±{:.1} st", shown.lfo_depth() * MAX_LFO_PITCH),
+            LfoDestination::Amplitude => format!("{:.0}%", shown.lfo_depth() * 100.0),
+            LfoDestination::Cutoff => {
+                format!("±{:.1} oct", shown.lfo_depth() * MAX_LFO_CUTOFF)
+            }
+        },
+        parse: |params, text| match params.lfo_destination() {
+            LfoDestination::Pitch => Some(parse_number(text, &[("st", 1.0)])? / MAX_LFO_PITCH),
+            LfoDestination::Amplitude => parse_number(text, PERCENT),
+            LfoDestination::Cutoff => Some(parse_number(text, OCTAVES)? / MAX_LFO_CUTOFF),
+        },
+    },
+    ParamDef {
+        id: ParamId::LfoDestination,
+        name: "LFO Destination",
+        units: &[],
+        automatable: true,
+        default: LfoDestination::Pitch.to_normalized(),
+        format: |shown| shown.lfo_destination().name().to_string(),
+        parse: |_, text| named(text, LfoDestination::ALL, |option| option.name()),
+    },
+    ParamDef {
+        id: ParamId::LfoShape,
+        name: "LFO Shape",
+        units: &[],
+        automatable: true,
+        default: LfoShape::Sine.to_normalized(),
+        format: |shown| shown.lfo_shape().name().to_string(),
+        parse: |_, text| named(text, LfoShape::ALL, |option| option.name()),
+    },
+    ParamDef {
+        id: ParamId::LfoRetrigger,
+        name: "LFO Retrigger",
+        units: &[],
+        automatable: true,
+        default: 1.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::LfoSync,
+        name: "LFO Sync",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::SyncRate,
+        name: "Sync Rate",
+        units: &[],
+        automatable: true,
+        // A quarter note.
+        default: 7.0 / 17.0,
+        format: |shown| shown.sync_division().name.to_string(),
+        parse: |_, text| named(text, &SYNC_DIVISIONS, |division| division.name),
+    },
+    ParamDef {
+        id: ParamId::Glide,
+        name: "Glide",
+        units: &["ms"],
+        automatable: true,
+        default: 0.0,
+        format: |shown| {
+            if shown.glide() == 0.0 {
+                "Off".to_string()
+            } else {
+                format!("{:.0} ms", shown.glide() * 1000.0)
+            }
+        },
+        parse: |_, text| {
+            if text.eq_ignore_ascii_case("Off") {
+                return Some(0.0);
+            }
+            // As with "Osc2 Fixed", fully down is "Off".
+            let glide = parse_number(text, TIME)?;
+            Some(exponential(glide, MIN_GLIDE, MAX_GLIDE).max(1e-6))
+        },
+    },
+    ParamDef {
+        id: ParamId::VoiceMode,
+        name: "Voice Mode",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: |shown| if shown.mono() { "Mono" } else { "Poly" }.to_string(),
+        parse: |_, text| named(text, &["Poly", "Mono"], |mode| mode),
+    },
+    ParamDef {
+        id: ParamId::NotePriority,
+        name: "Note Priority",
+        units: &[],
+        automatable: true,
+        default: NotePriority::Last.to_normalized(),
+        format: |shown| shown.note_priority().name().to_string(),
+        parse: |_, text| named(text, NotePriority::ALL, |option| option.name()),
+    },
+    ParamDef {
+        id: ParamId::VoiceSteal,
+        name: "Voice Steal",
+        units: &[],
+        automatable: true,
+        default: StealPolicy::Oldest.to_normalized(),
+        format: |shown| shown.voice_steal().name().to_string(),
+        parse: |_, text| named(text, StealPolicy::ALL, |option| option.name()),
+    },
+    ParamDef {
+        id: ParamId::Polyphony,
+        name: "Polyphony",
+        units: &[],
+        automatable: true,
+        default: 1.0,
+        format: |shown| format!("{}", shown.polyphony()),
+        parse: |_, text| Some(linear(parse_number(text, &[])?, 1.0, MAX_VOICES as f64)),
+    },
+    ParamDef {
+        id: ParamId::PhaseReset,
+        name: "Phase Reset",
+        units: &[],
+        automatable: true,
+        default: 1.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::RandomPhase,
+        name: "Random Phase",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::RandomSeed,
+        name: "Random Seed",
+        units: &[],
+        automatable: false,
+        default: 0.5,
+        format: |shown| format!("{}", shown.random_seed()),
+        parse: |_, text| Some(parse_number(text, &[])? / f64::from(u32::MAX)),
+    },
+    ParamDef {
+        id: ParamId::ProgramChange,
+        name: "Program Change",
+        units: &[],
+        automatable: true,
+        default: 1.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::Pan,
+        name: "Pan",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        // From -100% (hard left) to +100% (hard right).
+        format: |shown| {
+            let pan = (shown.value.clamp(0.0, 1.0) * 2.0 - 1.0) * 100.0;
+            if pan.abs() < 0.5 {
+                "0%".to_string()
+            } else {
+                format!("{:+.0}%", pan)
+            }
+        },
+        parse: |_, text| Some(linear(parse_number(text, PERCENT)?, -1.0, 1.0)),
+    },
+    ParamDef {
+        id: ParamId::StereoWidth,
+        name: "Stereo Width",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::TremRate,
+        name: "Trem Rate",
+        units: &["Hz"],
+        automatable: true,
+        default: 0.6,
+        format: |shown| format!("{:.2} Hz", shown.tremolo_rate()),
+        parse: |_, text| Some(exponential(parse_number(text, HERTZ)?, 0.1, 20.0)),
+    },
+    ParamDef {
+        id: ParamId::TremDepth,
+        name: "Trem Depth",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::Drive,
+        name: "Drive",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::Limiter,
+        name: "Limiter",
+        units: &[],
+        automatable: true,
+        default: 1.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::MasterTune,
+        name: "Master Tune",
+        units: &["Hz"],
+        automatable: true,
+        default: ((A4_FREQ - MIN_MASTER_TUNE) / (MAX_MASTER_TUNE - MIN_MASTER_TUNE)) as f32,
+        format: |shown| format!("{:.1} Hz", shown.master_tune()),
+        parse: |_, text| {
+            let freq = parse_number(text, HERTZ)?;
+            Some(linear(freq, MIN_MASTER_TUNE, MAX_MASTER_TUNE))
+        },
+    },
+    ParamDef {
+        id: ParamId::Octave,
+        name: "Octave",
+        units: &["oct"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| signed_text(shown.octave(), "oct"),
+        parse: |_, text| {
+            let octaves = parse_number(text, OCTAVES)?.round();
+            let shift = f64::from(MAX_OCTAVE_SHIFT);
+            Some(linear(octaves, -shift, shift))
+        },
+    },
+    ParamDef {
+        id: ParamId::Semitone,
+        name: "Semitone",
+        units: &["st"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| signed_text(shown.semitone(), "st"),
+        parse: |_, text| {
+            let semitones = parse_number(text, &[("st", 1.0)])?.round();
+            let shift = f64::from(MAX_SEMITONE_SHIFT);
+            Some(linear(semitones, -shift, shift))
+        },
+    },
+    ParamDef {
+        id: ParamId::Fine,
+        name: "Fine",
+        units: &["cents"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("{:+.1} cents", shown.fine_tune()),
+        parse: |_, text| {
+            Some(linear(
+                parse_number(text, CENTS)?,
+                -MAX_FINE_TUNE,
+                MAX_FINE_TUNE,
+            ))
+        },
+    },
+    ParamDef {
+        id: ParamId::TuningMessages,
+        name: "MTS Receive",
+        units: &[],
+        automatable: true,
+        default: 1.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::Mpe,
+        name: "MPE",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::AftertouchDest,
+        name: "Aftertouch Dest",
+        units: &[],
+        automatable: true,
+        default: AftertouchDestination::None.to_normalized(),
+        format: |shown| shown.aftertouch_destination().name().to_string(),
+        parse: |_, text| named(text, AftertouchDestination::ALL, |option| option.name()),
+    },
+    ParamDef {
+        id: ParamId::AftertouchAmount,
+        name: "Aftertouch Amount",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::VelocityCurve,
+        name: "Vel Curve",
+        units: &[],
+        automatable: true,
+        default: 0.5,
+        format: |shown| match shown.velocity_curve() {
+            0 => "Linear".to_string(),
+            steps if steps < 0 => format!("Soft {}", -steps),
+            steps => format!("Hard {}", steps),
+        },
+        parse: |_, text| {
+            let text = text.trim().to_ascii_lowercase();
+            let steps = if text == "linear" {
+                0.0
+            } else if let Some(steps) = text.strip_prefix("soft") {
+                -parse_number(steps, &[])?.round()
+            } else {
+                parse_number(text.strip_prefix("hard")?, &[])?.round()
+            };
+            let range = f64::from(VEL_CURVE_STEPS);
+            Some(linear(steps, -range, range))
+        },
+    },
+    ParamDef {
+        id: ParamId::MidiLearn,
+        name: "MIDI Learn",
+        units: &[],
+        automatable: true,
+        default: LearnMode::Off.to_normalized(),
+        format: |shown| shown.learn_mode().name().to_string(),
+        parse: |_, text| named(text, LearnMode::ALL, |mode| mode.name()),
+    },
+    ParamDef {
+        id: ParamId::LearnTarget,
+        name: "Learn Target",
+        units: &[],
+        automatable: true,
+        default: ParamId::Cutoff as usize as f32 / (TARGET_SLOTS - 1) as f32,
+        format: |shown| PARAMS[shown.learn_target()].name.to_string(),
+        parse: |_, text| {
+            let index = PARAMS
+                .iter()
+                .position(|param| param.name.eq_ignore_ascii_case(text.trim()))?;
+            Some(index as f64 / (TARGET_SLOTS - 1) as f64)
+        },
+    },
+    ParamDef {
+        id: ParamId::Arp,
+        name: "Arp",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::ArpMode,
+        name: "Arp Mode",
+        units: &[],
+        automatable: true,
+        default: ArpMode::Up.to_normalized(),
+        format: |shown| shown.arp_mode().name().to_string(),
+        parse: |_, text| named(text, ArpMode::ALL, |mode| mode.name()),
+    },
+    ParamDef {
+        id: ParamId::ArpRate,
+        name: "Arp Rate",
+        units: &[],
+        automatable: true,
+        // A sixteenth note.
+        default: 0.6,
+        format: |shown| shown.arp_division().name.to_string(),
+        parse: |_, text| named(text, arp_divisions(), |division| division.name),
+    },
+    ParamDef {
+        id: ParamId::ArpGate,
+        name: "Arp Gate",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::ArpHold,
+        name: "Arp Hold",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::ArpTempo,
+        name: "Arp BPM",
+        units: &["BPM"],
+        automatable: true,
+        default: ((120.0 - MIN_ARP_TEMPO) / (MAX_ARP_TEMPO - MIN_ARP_TEMPO)) as f32,
+        format: |shown| format!("{:.1} BPM", shown.arp_tempo()),
+        parse: |_, text| {
+            let tempo = parse_number(text, &[("bpm", 1.0)])?;
+            Some(linear(tempo, MIN_ARP_TEMPO, MAX_ARP_TEMPO))
+        },
+    },
+    ParamDef {
+        id: ParamId::Chord,
+        name: "Chord",
+        units: &[],
+        automatable: true,
+        default: ChordType::Off.to_normalized(),
+        format: |shown| shown.chord_type().name().to_string(),
+        parse: |_, text| named(text, ChordType::ALL, |chord| chord.name()),
+    },
+    ParamDef {
+        id: ParamId::ChordLearn,
+        name: "Chord Learn",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::OctaveLayer,
+        name: "Octave Layer",
+        units: &[],
+        automatable: true,
+        default: OctaveLayer::Off.to_normalized(),
+        format: |shown| shown.octave_layer().name().to_string(),
+        parse: |_, text| named(text, OctaveLayer::ALL, |layer| layer.name()),
+    },
+    ParamDef {
+        id: ParamId::LayerLevel,
+        name: "Layer Level",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::GlideMode,
+        name: "Glide Mode",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: |shown| {
+            if shown.legato_glide() {
+                "Legato"
+            } else {
+                "Always"
+            }
+            .to_string()
+        },
+        parse: |_, text| named(text, &["Always", "Legato"], |mode| mode),
+    },
+    ParamDef {
+        id: ParamId::EnvCurve,
+        name: "Env Curve",
+        units: &[],
+        automatable: true,
+        default: EnvCurve::Linear.to_normalized(),
+        format: |shown| shown.env_curve().name().to_string(),
+        parse: |_, text| named(text, EnvCurve::ALL, |curve| curve.name()),
+    },
+    ParamDef {
+        id: ParamId::Bypass,
+        name: "Bypass",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::Oversampling,
+        name: "Oversampling",
+        units: &[],
+        automatable: true,
+        default: Oversampling::Off.to_normalized(),
+        format: |shown| shown.oversampling().name().to_string(),
+        parse: |_, text| named(text, Oversampling::ALL, |oversampling| oversampling.name()),
+    },
+    ParamDef {
+        id: ParamId::ChorusRate,
+        name: "Chorus Rate",
+        units: &["Hz"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("{:.2} Hz", shown.chorus_rate()),
+        parse: |_, text| Some(exponential(parse_number(text, HERTZ)?, 0.1, 5.0)),
+    },
+    ParamDef {
+        id: ParamId::ChorusDepth,
+        name: "Chorus Depth",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::ChorusMix,
+        name: "Chorus Mix",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::DelayTime,
+        name: "Delay Time",
+        units: &["ms", "s"],
+        automatable: true,
+        // About 300 ms.
+        default: 0.75,
+        format: |shown| format!("{:.0} ms", shown.free_delay_time() * 1000.0),
+        parse: |_, text| Some(exponential(parse_number(text, TIME)?, MIN_DELAY, MAX_DELAY)),
+    },
+    ParamDef {
+        id: ParamId::DelaySync,
+        name: "Delay Sync",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::DelayNote,
+        name: "Delay Note",
+        units: &[],
+        automatable: true,
+        // A dotted eighth.
+        default: 0.4,
+        format: |shown| shown.delay_division().name.to_string(),
+        parse: |_, text| named(text, delay_divisions(), |division| division.name),
+    },
+    ParamDef {
+        id: ParamId::Feedback,
+        name: "Feedback",
+        units: &["%"],
+        automatable: true,
+        default: 0.4,
+        format: |shown| format!("{:.0}%", shown.feedback() * 100.0),
+        parse: |_, text| Some(parse_number(text, PERCENT)? / MAX_FEEDBACK),
+    },
+    ParamDef {
+        id: ParamId::DelayMix,
+        name: "Delay Mix",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::PingPong,
+        name: "Ping Pong",
+        units: &[],
+        automatable: true,
+        default: 0.0,
+        format: on_off_text,
+        parse: parse_on_off,
+    },
+    ParamDef {
+        id: ParamId::ReverbSize,
+        name: "Reverb Size",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::ReverbDamping,
+        name: "Reverb Damping",
+        units: &["%"],
+        automatable: true,
+        default: 0.5,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::ReverbMix,
+        name: "Reverb Mix",
+        units: &["%"],
+        automatable: true,
+        default: 0.0,
+        format: percent_text,
+        parse: parse_percent,
+    },
+    ParamDef {
+        id: ParamId::BitDepth,
+        name: "Bit Depth",
+        units: &["bits"],
+        automatable: true,
+        default: 1.0,
+        format: |shown| format!("{:.1} bits", shown.bit_depth()),
+        parse: |_, text| Some(linear(parse_number(text, BITS)?, MIN_BITS, MAX_BITS)),
+    },
+    ParamDef {
+        id: ParamId::Downsample,
+        name: "Downsample",
+        units: &["x"],
+        automatable: true,
+        default: 0.0,
+        format: |shown| format!("{}x", shown.downsample()),
+        parse: |_, text| Some(linear(parse_number(text, TIMES)?, 1.0, MAX_HOLD as f64)),
+    },
+    ParamDef {
+        id: ParamId::Quantize,
+        name: "Quantize",
+        units: &[],
+        automatable: true,
+        default: Quantize::Round.to_normalized(),
+        format: |shown| shown.quantize().name().to_string(),
+        parse: |_, text| named(text, Quantize::ALL, |quantize| quantize.name()),
+    },
+    ParamDef {
+        id: ParamId::Brightness,
+        name: "Brightness",
+        units: &["dB"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("{:+.1} dB", shown.brightness() * MAX_TILT_DB),
+        parse: |_, text| {
+            Some(linear(
+                parse_number(text, &[("db", 1.0)])?,
+                -MAX_TILT_DB,
+                MAX_TILT_DB,
+            ))
+        },
+    },
+    ParamDef {
+        id: ParamId::LevelKeyTrack,
+        name: "Level Key Track",
+        units: &["dB/oct"],
+        automatable: true,
+        default: 0.5,
+        format: |shown| format!("{:+.1} dB/oct", shown.level_key_track()),
+        parse: |_, text| {
+            Some(linear(
+                parse_number(text, DB_PER_OCTAVE)?,
+                -MAX_LEVEL_KEY_TRACK,
+                MAX_LEVEL_KEY_TRACK,
+            ))
+        },
+    },
+];
+
+const fn harmonic(id: ParamId, name: &'static str, default: f32) -> ParamDef {
+    ParamDef {
+        id,
+        name,
+        units: &["%"],
+        automatable: true,
+        default,
+        format: percent_text,
+        parse: parse_percent,
+    }
+}
+
+fn percent_text(shown: &Shown) -> String {
+    format!("{:.0}%", shown.value * 100.0)
+}
+
+/// For times stored directly in seconds.
+fn milliseconds_text(shown: &Shown) -> String {
+    format!("{:.0} ms", shown.value * 1000.0)
+}
+
+fn on_off_text(shown: &Shown) -> String {
+    if shown.value >= 0.5 { "On" } else { "Off" }.to_string()
+}
+
+/// A whole number of `unit`s with its sign, but plain "0" with none.
+fn signed_text(value: i32, unit: &str) -> String {
+    if value == 0 {
+        format!("0 {}", unit)
+    } else {
+        format!("{:+} {}", value, unit)
+    }
+}
+
+fn parse_percent(_: &GainEffectParameters, text: &str) -> Option<f64> {
+    parse_number(text, PERCENT)
+}
+
+fn parse_time(_: &GainEffectParameters, text: &str) -> Option<f64> {
+    parse_number(text, TIME)
+}
+
+fn parse_on_off(_: &GainEffectParameters, text: &str) -> Option<f64> {
+    named(text, &["Off", "On"], |state| state)
+}
+
+/// Units that can be typed after a number, in lower case, with what they scale it by. A bare
+/// number is in the first.
+const PERCENT: &[(&str, f64)] = &[("%", 0.01)];
+const TIME: &[(&str, f64)] = &[("ms", 0.001), ("s", 1.0)];
+const TIME_SECONDS: &[(&str, f64)] = &[("s", 1.0), ("ms", 0.001)];
+const HERTZ: &[(&str, f64)] = &[("hz", 1.0), ("khz", 1000.0)];
+const CENTS: &[(&str, f64)] = &[("cents", 1.0)];
+const OCTAVES: &[(&str, f64)] = &[("oct", 1.0)];
+const BITS: &[(&str, f64)] = &[("bits", 1.0)];
+const TIMES: &[(&str, f64)] = &[("x", 1.0)];
+const DB_PER_OCTAVE: &[(&str, f64)] = &[("db/oct", 1.0), ("db", 1.0)];
+
+/// A number typed into the host, optionally followed by one of `units`.
+fn parse_number(text: &str, units: &[(&str, f64)]) -> Option<f64> {
+    let text = text.trim().trim_start_matches('±').to_ascii_lowercase();
+    let parse = |number: &str| number.trim().parse::<f64>().ok().filter(|n| !n.is_nan());
+    // Stripping a unit that ends a longer one, like the "s" of "ms", doesn't leave a number,
+    // so the longer one still gets its turn.
+    units
+        .iter()
+        .find_map(|&(unit, scale)| Some(parse(text.strip_suffix(unit)?)? * scale))
+        .or_else(|| Some(parse(&text)? * units.first().map_or(1.0, |&(_, scale)| scale)))
+}
+
+/// Where `value` sits from `low` to `high`, as a normalized parameter value.
+fn linear(value: f64, low: f64, high: f64) -> f64 {
+    (value - low) / (high - low)
+}
+
+/// Where `value` sits from `low` to `high` on a logarithmic scale.
+fn exponential(value: f64, low: f64, high: f64) -> f64 {
+    (value / low).ln() / (high / low).ln()
+}
+
+/// Declare the options of a parameter that splits its range evenly between them, each with
+/// the name it's shown by. Along with the enum come `ALL`, every option in declaration order,
+/// and the conversions between an option and the parameter's normalized value.
+macro_rules! options {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$option_meta:meta])* $option:ident => $text:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum $name {
+            $($(#[$option_meta])* $option,)*
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$option),*];
+
+            /// The option a normalized parameter value selects, splitting the range evenly.
+            pub fn from_normalized(value: f32) -> $name {
+                let last = $name::ALL.len() - 1;
+                let index = (value.clamp(0.0, 1.0) * last as f32).round() as usize;
+                $name::ALL[index]
+            }
+
+            /// The normalized parameter value that selects this option.
+            /// `ALL` is in declaration order, so the discriminant is the index.
+            pub const fn to_normalized(self) -> f32 {
+                self as usize as f32 / ($name::ALL.len() - 1) as f32
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($name::$option => $text,)*
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use options;
+
+/// The normalized value selecting the option called `text`, for parameters that split their
+/// range evenly between `options`.
+fn named<T>(text: &str, options: &[T], name: impl Fn(&T) -> &str) -> Option<f64> {
+    let index = options
+        .iter()
+        .position(|option| name(option).eq_ignore_ascii_case(text.trim()))?;
+    Some(index as f64 / (options.len() - 1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use vst::plugin::PluginParameters;
+
+    use super::{ParamId, PARAMS, PARAM_COUNT};
+    use crate::{
+        amplitude_to_normalized, attack_to_normalized, GainEffectParameters, Shown,
+        CENTRE_AMPLITUDE_DB,
+    };
+
+    #[test]
+    fn table_is_in_id_order() {
+        assert_eq!(PARAMS.len(), PARAM_COUNT);
+        for (index, param) in PARAMS.iter().enumerate() {
+            assert_eq!(param.id as usize, index, "{}", param.name);
+        }
+    }
+
+    #[test]
+    fn defaults_match_their_mappings() {
+        let default = |id: ParamId| PARAMS[id as usize].default;
+        assert_eq!(
+            default(ParamId::Amplitude),
+            amplitude_to_normalized(CENTRE_AMPLITUDE_DB)
+        );
+        assert_eq!(default(ParamId::Attack), attack_to_normalized(0.5));
+        assert!(PARAMS
+            .iter()
+            .all(|param| (0.0..=1.0).contains(&param.default)));
+    }
+
+    #[test]
+    fn every_value_has_text() {
+        let params = GainEffectParameters::default();
+        for param in PARAMS.iter() {
+            for &value in &[0.0, 0.37, 1.0, param.default] {
+                let text = (param.format)(&Shown {
+                    params: &params,
+                    id: param.id,
+                    value,
+                });
+                assert!(!text.is_empty(), "{} at {}", param.name, value);
+            }
+        }
+    }
+
+    /// A value's text is the text the parameter shows once set to it, whatever it's set to now.
+    #[test]
+    fn text_is_for_the_value_passed_in() {
+        let params = GainEffectParameters::default();
+        for (index, param) in PARAMS.iter().enumerate() {
+            for &value in &[0.0, 0.37, 1.0] {
+                let text = (param.format)(&Shown {
+                    params: &params,
+                    id: param.id,
+                    value,
+                });
+                let live = params.get_parameter(index as i32);
+                params.set_parameter(index as i32, value);
+                // Some settings only last a moment, like "Clear" for MIDI learn.
+                if params.get_parameter(index as i32) == value {
+                    assert_eq!(text, params.display_text(index as i32), "{}", param.name);
+                }
+                params.set_parameter(index as i32, live);
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Karplus–Strong plucked strings.
+
+use crate::denormal::flush;
+use crate::noise::{Noise, NoiseColor};
+
+/// The highest sample rate the delay line is sized for.
+const MAX_SAMPLE_RATE: f64 = 192_000.0;
+
+/// The lowest pitch a string can play, MIDI note 0.
+const MIN_FREQ: f64 = 8.175_798_915_643_707;
+
+/// Samples in the delay line: a power of two holding a full period of the lowest note at the
+/// highest sample rate, plus room for interpolation.
+const BUFFER_LEN: usize = ((MAX_SAMPLE_RATE / MIN_FREQ) as usize + 3).next_power_of_two();
+
+/// A string modelled as a delay line one period long, fed back through a two-sample average.
+///
+/// The average loses a little of the high frequencies on every pass, so the tone mellows as it
+/// decays the way a real string does. The buffer is allocated once, at its largest size, so
+/// notes of any pitch can be played without allocating on the audio thread.
+pub struct PluckedString {
+    buffer: Vec<f32>,
+    /// Where the next sample will be written.
+    write: usize,
+}
+
+impl Default for PluckedString {
+    fn default() -> PluckedString {
+        PluckedString {
+            buffer: vec![0.0; BUFFER_LEN],
+            write: 0,
+        }
+    }
+}
+
+impl PluckedString {
+    /// Pluck the string by filling one period of the delay line with noise.
+    ///
+    /// `brightness` is the one-pole smoothing coefficient applied to the noise, from just
+    /// above 0.0 (dull) to 1.0 (unfiltered). Anything left in the line from an earlier pluck is
+    /// overwritten, so replucking a ringing string restarts it.
+    pub fn excite(&mut self, period: f64, brightness: f64, noise: &mut Noise) {
+        let len = (period.ceil() as usize + 2).min(BUFFER_LEN - 1);
+        let mut smoothed = 0.0;
+        for age in 1..=len {
+            smoothed += brightness * (noise.next(NoiseColor::White) - smoothed);
+            self.buffer[self.write.wrapping_sub(age) & (BUFFER_LEN - 1)] = smoothed as f32;
+        }
+    }
+
+    /// Produce the next sample of a string with the given period in samples.
+    ///
+    /// `feedback` is the gain on each trip round the loop, which sets how quickly it decays.
+    pub fn next(&mut self, period: f64, feedback: f64) -> f64 {
+        // The average delays the loop by half a sample, so the line is that much shorter.
+        let delay = (period - 0.5).clamp(1.0, (BUFFER_LEN - 3) as f64);
+        let out = flush(feedback * 0.5 * (self.read(delay) + self.read(delay + 1.0)));
+        self.buffer[self.write] = out as f32;
+        self.write = (self.write + 1) & (BUFFER_LEN - 1);
+        out
+    }
+
+    /// The sample written `delay` samples ago, linearly interpolated between whole samples.
+    fn read(&self, delay: f64) -> f64 {
+        let whole = delay.floor();
+        let fraction = delay - whole;
+        let newer = self.write.wrapping_sub(whole as usize) & (BUFFER_LEN - 1);
+        let older = newer.wrapping_sub(1) & (BUFFER_LEN - 1);
+        let (newer, older) = (f64::from(self.buffer[newer]), f64::from(self.buffer[older]));
+        newer + (older - newer) * fraction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PluckedString, BUFFER_LEN, MAX_SAMPLE_RATE, MIN_FREQ};
+    use crate::noise::Noise;
+
+    #[test]
+    fn buffer_holds_lowest_note() {
+        assert!(BUFFER_LEN as f64 >= MAX_SAMPLE_RATE / MIN_FREQ + 2.0);
+    }
+
+    #[test]
+    fn output_stays_in_range() {
+        let mut string = PluckedString::default();
+        let mut noise = Noise::default();
+        for &period in &[2.0, 3.7, 100.25, 30_000.0] {
+            string.excite(period, 1.0, &mut noise);
+            for _ in 0..10_000 {
+                let sample = string.next(period, 0.999);
+                assert!(sample.is_finite() && sample.abs() <= 1.0, "{}", period);
+            }
+        }
+    }
+}
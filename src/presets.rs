@@ -0,0 +1,282 @@
+//! The factory bank: the programs a new instance starts with.
+//!
+//! Each preset lists only the parameters it changes, by `ParamId`, so a parameter added later
+//! starts at its default in every preset instead of shifting the values along. Values are
+//! normalized, as the host sees them.
+
+use crate::lfo::LfoDestination;
+use crate::oscillator::Waveform;
+use crate::params::{ParamId, PARAMS};
+use crate::program::Program;
+
+/// A factory program.
+pub struct Preset {
+    pub name: &'static str,
+    /// The parameters set away from their defaults.
+    pub values: &'static [(ParamId, f32)],
+}
+
+/// The factory programs in slot order. The first is the defaults, which a new instance plays.
+pub const FACTORY: &[Preset] = &[
+    Preset {
+        name: "Init Sine",
+        values: &[],
+    },
+    Preset {
+        name: "Soft Pad",
+        values: &[
+            (ParamId::Waveform, Waveform::Saw.to_normalized()),
+            (ParamId::Attack, 0.89), // 1.5 s
+            (ParamId::Decay, 1.0),
+            (ParamId::Sustain, 0.8),
+            (ParamId::Release, 1.0),
+            (ParamId::UnisonVoices, 0.5),
+            (ParamId::UnisonSpread, 0.3),
+            (ParamId::Cutoff, 0.55),
+            (ParamId::FilterEnvAmount, 0.6),
+            (ParamId::FilterAttack, 1.0),
+            (ParamId::FilterSustain, 0.7),
+        ],
+    },
+    Preset {
+        name: "Pluck",
+        values: &[
+            (ParamId::Waveform, Waveform::Pluck.to_normalized()),
+            (ParamId::Attack, 0.0),
+            (ParamId::Sustain, 0.0),
+            (ParamId::Release, 0.2),
+            (ParamId::Damping, 0.3),
+            (ParamId::ExciteBrightness, 0.8),
+        ],
+    },
+    Preset {
+        name: "Sub Bass",
+        values: &[
+            (ParamId::Attack, 0.21), // 1 ms
+            (ParamId::SubLevel, 0.8),
+            (ParamId::Cutoff, 0.4),
+            (ParamId::Glide, 0.3),
+            (ParamId::VoiceMode, 1.0),
+        ],
+    },
+    Preset {
+        name: "Saw Lead",
+        values: &[
+            (ParamId::Waveform, Waveform::Saw.to_normalized()),
+            (ParamId::Attack, 0.21),
+            (ParamId::Detune, 0.53),
+            (ParamId::OscMix, 0.5),
+            (ParamId::Cutoff, 0.65),
+            (ParamId::Resonance, 0.3),
+            (ParamId::FilterEnvAmount, 0.65),
+            (ParamId::Glide, 0.35),
+            (ParamId::VoiceMode, 1.0),
+            (ParamId::LfoDepth, 0.1),
+            (ParamId::LfoRate, 0.55),
+        ],
+    },
+    Preset {
+        name: "Square Lead",
+        values: &[
+            (ParamId::Waveform, Waveform::Square.to_normalized()),
+            (ParamId::PulseWidth, 0.3),
+            (ParamId::Attack, 0.21),
+            (ParamId::Sustain, 0.7),
+            (ParamId::Cutoff, 0.7),
+            (ParamId::VoiceMode, 1.0),
+        ],
+    },
+    Preset {
+        name: "Brass",
+        values: &[
+            (ParamId::Waveform, Waveform::Saw.to_normalized()),
+            (ParamId::Attack, 0.6), // 35 ms
+            (ParamId::Sustain, 0.8),
+            (ParamId::Release, 0.15),
+            (ParamId::Cutoff, 0.4),
+            (ParamId::FilterEnvAmount, 0.75),
+            (ParamId::FilterAttack, 0.08),
+            (ParamId::FilterDecay, 0.4),
+            (ParamId::FilterSustain, 0.4),
+        ],
+    },
+    Preset {
+        name: "Strings",
+        values: &[
+            (ParamId::Waveform, Waveform::Saw.to_normalized()),
+            (ParamId::Attack, 0.8), // 0.5 s
+            (ParamId::Sustain, 0.9),
+            (ParamId::Release, 0.6),
+            (ParamId::UnisonVoices, 0.5),
+            (ParamId::UnisonSpread, 0.6),
+            (ParamId::Cutoff, 0.6),
+            (ParamId::VibratoDepth, 0.6),
+        ],
+    },
+    Preset {
+        name: "Organ",
+        values: &[
+            (ParamId::Waveform, Waveform::Additive.to_normalized()),
+            (ParamId::Attack, 0.21),
+            (ParamId::Release, 0.05),
+            (ParamId::Harmonic1, 1.0),
+            (ParamId::Harmonic2, 0.7),
+            (ParamId::Harmonic3, 0.5),
+            (ParamId::Harmonic4, 0.4),
+            (ParamId::Harmonic6, 0.3),
+            (ParamId::Harmonic8, 0.2),
+        ],
+    },
+    Preset {
+        name: "FM Bell",
+        values: &[
+            (ParamId::Attack, 0.0),
+            (ParamId::Decay, 1.0),
+            (ParamId::Sustain, 0.0),
+            (ParamId::Release, 1.0),
+            (ParamId::FmRatio, 0.5), // 4x
+            (ParamId::FmFine, 0.55),
+            (ParamId::FmAmount, 0.35),
+        ],
+    },
+    Preset {
+        name: "Wobble Bass",
+        values: &[
+            (ParamId::Waveform, Waveform::Saw.to_normalized()),
+            (ParamId::Attack, 0.21),
+            (ParamId::SubLevel, 0.5),
+            (ParamId::Cutoff, 0.35),
+            (ParamId::Resonance, 0.5),
+            (ParamId::LfoDepth, 0.7),
+            (
+                ParamId::LfoDestination,
+                LfoDestination::Cutoff.to_normalized(),
+            ),
+            (ParamId::LfoSync, 1.0),
+            (ParamId::VoiceMode, 1.0),
+        ],
+    },
+    Preset {
+        name: "Noise Sweep",
+        values: &[
+            (ParamId::Attack, 0.85),
+            (ParamId::Release, 1.0),
+            (ParamId::OscMix, 1.0),
+            (ParamId::NoiseLevel, 0.8),
+            (ParamId::NoiseColor, 1.0),
+            (ParamId::Cutoff, 0.3),
+            (ParamId::Resonance, 0.6),
+            (ParamId::FilterEnvAmount, 0.9),
+            (ParamId::FilterAttack, 1.0),
+            (ParamId::FilterSustain, 1.0),
+        ],
+    },
+    Preset {
+        name: "Ring Bell",
+        values: &[
+            (ParamId::Attack, 0.0),
+            (ParamId::Decay, 0.8),
+            (ParamId::Sustain, 0.0),
+            (ParamId::Release, 0.8),
+            (ParamId::RingMod, 0.8),
+            (ParamId::Detune, 1.0),
+        ],
+    },
+    Preset {
+        name: "Sync Lead",
+        values: &[
+            (ParamId::Waveform, Waveform::Saw.to_normalized()),
+            (ParamId::Attack, 0.21),
+            (ParamId::OscMix, 1.0),
+            (ParamId::Detune, 0.8),
+            (ParamId::Sync, 1.0),
+            (ParamId::Cutoff, 0.75),
+            (ParamId::VoiceMode, 1.0),
+        ],
+    },
+    Preset {
+        name: "Wavetable Pad",
+        values: &[
+            (ParamId::Waveform, Waveform::Wavetable.to_normalized()),
+            (ParamId::TablePosition, 0.3),
+            (ParamId::Attack, 0.85),
+            (ParamId::Decay, 1.0),
+            (ParamId::Sustain, 0.8),
+            (ParamId::Release, 1.0),
+            (ParamId::UnisonVoices, 1.0 / 3.0),
+            (ParamId::RandomPhase, 1.0),
+        ],
+    },
+    Preset {
+        name: "Soft Keys",
+        values: &[
+            (ParamId::Waveform, Waveform::Triangle.to_normalized()),
+            (ParamId::Attack, 0.3),
+            (ParamId::Decay, 0.6),
+            (ParamId::Sustain, 0.3),
+            (ParamId::Release, 0.3),
+            (ParamId::Cutoff, 0.6),
+            (ParamId::KeyTrack, 0.5),
+        ],
+    },
+];
+
+/// The factory programs, given each parameter's name and default value in index order.
+///
+/// Presets are matched to `params` by name, so they can be laid out against a smaller set of
+/// parameters; values for any not in it are dropped.
+pub fn factory_bank(params: &[(&str, f32)]) -> Vec<Program> {
+    FACTORY
+        .iter()
+        .map(|preset| {
+            let mut values: Vec<f32> = params.iter().map(|&(_, default)| default).collect();
+            for &(id, value) in preset.values {
+                let name = PARAMS[id as usize].name;
+                if let Some(index) = params.iter().position(|&(param, _)| param == name) {
+                    values[index] = value;
+                }
+            }
+            Program {
+                name: preset.name.to_string(),
+                values,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{factory_bank, FACTORY};
+    use crate::params::PARAMS;
+    use crate::program::PROGRAM_COUNT;
+
+    #[test]
+    fn presets_fill_the_bank() {
+        assert_eq!(FACTORY.len(), PROGRAM_COUNT);
+        assert!(FACTORY[0].values.is_empty());
+    }
+
+    #[test]
+    fn preset_values_are_in_range_and_set_once() {
+        for preset in FACTORY {
+            for (i, &(id, value)) in preset.values.iter().enumerate() {
+                assert!((0.0..=1.0).contains(&value), "{}: {:?}", preset.name, id);
+                assert!(
+                    preset.values[..i].iter().all(|&(other, _)| other != id),
+                    "{}: {:?} twice",
+                    preset.name,
+                    id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_parameter_gets_a_value() {
+        let params: Vec<(&str, f32)> = PARAMS.iter().map(|param| (param.name, 0.5)).collect();
+        for (program, preset) in factory_bank(&params).iter().zip(FACTORY) {
+            assert_eq!(program.name, preset.name);
+            assert_eq!(program.values.len(), PARAMS.len());
+        }
+    }
+}
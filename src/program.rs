@@ -0,0 +1,180 @@
+//! A bank of programs, each a full set of parameter values the host can switch between.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use vst::util::AtomicFloat;
+
+/// How many programs the host's program list shows.
+pub const PROGRAM_COUNT: usize = 16;
+
+/// A named value for every parameter, in index order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+impl Program {
+    /// A program called "Init" with the given values, as every program starts out.
+    pub fn init(values: Vec<f32>) -> Program {
+        Program {
+            name: "Init".to_string(),
+            values,
+        }
+    }
+}
+
+/// The programs, and which one is selected.
+///
+/// The live parameter values belong to the selected program, so its stored values are only
+/// brought up to date when switching away from it.
+///
+/// The values and the selection are atomics, so the audio thread can switch programs without
+/// waiting on the host. Only the names are behind a lock, and the audio thread never needs
+/// them. One thread switches at a time: the audio thread while it's running, the host
+/// otherwise.
+pub struct ProgramBank {
+    values: Vec<Vec<AtomicFloat>>,
+    names: Mutex<Vec<String>>,
+    current: AtomicUsize,
+    /// Counts the switches, so a reader copying the whole bank can tell whether one happened
+    /// while it was copying.
+    switches: AtomicU32,
+}
+
+impl ProgramBank {
+    /// A bank of `PROGRAM_COUNT` programs all set to `values`, with the first selected.
+    pub fn new(values: Vec<f32>) -> ProgramBank {
+        let program = Program::init(values);
+        ProgramBank {
+            values: (0..PROGRAM_COUNT)
+                .map(|_| atomics(&program.values))
+                .collect(),
+            names: Mutex::new(vec![program.name; PROGRAM_COUNT]),
+            current: AtomicUsize::new(0),
+            switches: AtomicU32::new(0),
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Acquire)
+    }
+
+    fn names(&self) -> MutexGuard<'_, Vec<String>> {
+        // A panic elsewhere while holding the names leaves nothing half changed that matters.
+        self.names.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Every program, with `live` as the selected one's values, copied all from either side
+    /// of any switch made meanwhile.
+    pub fn programs(&self, live: &[AtomicFloat]) -> (Vec<Program>, usize) {
+        let names = self.names().clone();
+        loop {
+            let switches = self.switches.load(Ordering::Acquire);
+            let current = self.current();
+            let programs = names
+                .iter()
+                .zip(&self.values)
+                .enumerate()
+                .map(|(index, (name, values))| Program {
+                    name: name.clone(),
+                    values: if index == current { live } else { values }
+                        .iter()
+                        .map(AtomicFloat::get)
+                        .collect(),
+                })
+                .collect();
+            if self.switches.load(Ordering::Acquire) == switches {
+                return (programs, current);
+            }
+        }
+    }
+
+    /// Replace every program and the selection, as when the host loads a saved bank.
+    pub fn replace(&self, programs: Vec<Program>, current: usize) {
+        for (index, program) in programs.into_iter().enumerate() {
+            self.set(index, program);
+        }
+        self.current.store(current, Ordering::Release);
+        self.switches.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Replace the program at `index`, if there is one.
+    pub fn set(&self, index: usize, program: Program) {
+        if let (Some(name), Some(values)) = (self.names().get_mut(index), self.values.get(index)) {
+            for (value, &new) in values.iter().zip(&program.values) {
+                value.set(new);
+            }
+            *name = program.name;
+        }
+    }
+
+    pub fn name(&self, index: usize) -> Option<String> {
+        self.names().get(index).cloned()
+    }
+
+    pub fn set_name(&self, index: usize, name: String) {
+        if let Some(old) = self.names().get_mut(index) {
+            *old = name;
+        }
+    }
+
+    /// Select the program at `index`, storing `live` as the values of the one being left.
+    ///
+    /// Returns the values to make live, or `None` if `index` is out of range or already
+    /// selected.
+    pub fn switch(&self, index: usize, live: &[AtomicFloat]) -> Option<&[AtomicFloat]> {
+        let current = self.current();
+        if index >= self.values.len() || index == current {
+            return None;
+        }
+        for (stored, value) in self.values[current].iter().zip(live) {
+            stored.set(value.get());
+        }
+        self.current.store(index, Ordering::Release);
+        self.switches.fetch_add(1, Ordering::AcqRel);
+        Some(&self.values[index])
+    }
+}
+
+fn atomics(values: &[f32]) -> Vec<AtomicFloat> {
+    values
+        .iter()
+        .map(|&value| AtomicFloat::new(value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use vst::util::AtomicFloat;
+
+    use super::{atomics, ProgramBank, PROGRAM_COUNT};
+
+    fn values(atomics: &[AtomicFloat]) -> Vec<f32> {
+        atomics.iter().map(AtomicFloat::get).collect()
+    }
+
+    #[test]
+    fn switching_stores_the_program_left() {
+        let bank = ProgramBank::new(vec![0.5, 0.5]);
+        let switched = bank.switch(1, &atomics(&[0.1, 0.2])).map(values);
+        assert_eq!(switched, Some(vec![0.5, 0.5]));
+        let switched = bank.switch(0, &atomics(&[0.3, 0.4])).map(values);
+        assert_eq!(switched, Some(vec![0.1, 0.2]));
+        let (programs, current) = bank.programs(&atomics(&[0.7, 0.8]));
+        assert_eq!(programs[0].values, [0.7, 0.8]);
+        assert_eq!(programs[1].values, [0.3, 0.4]);
+        assert_eq!(current, 0);
+    }
+
+    #[test]
+    fn switching_nowhere_changes_nothing() {
+        let bank = ProgramBank::new(vec![0.5]);
+        assert!(bank.switch(0, &atomics(&[0.9])).is_none());
+        assert!(bank.switch(PROGRAM_COUNT, &atomics(&[0.9])).is_none());
+        let (programs, _) = bank.programs(&atomics(&[0.5]));
+        assert!(programs.iter().all(|program| program.values == [0.5]));
+        assert!(bank.name(PROGRAM_COUNT).is_none());
+    }
+}
@@ -0,0 +1,338 @@
+//! A small Freeverb-style reverb: parallel damped combs into a chain of allpasses, mono in
+//! and stereo out.
+
+use crate::denormal::flush;
+
+/// The comb lengths in samples at 44.1 kHz, from Freeverb. They're chosen to share no
+/// factors, so their echoes don't pile up on the same samples.
+const COMBS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+
+/// The allpass lengths in samples at 44.1 kHz, from Freeverb.
+const ALLPASSES: [usize; 4] = [556, 441, 341, 225];
+
+/// How much longer every line is on the right, so the two sides don't match and the
+/// reverb comes out wide.
+const SPREAD: usize = 23;
+
+/// How much of each pass through an allpass comes round again.
+const ALLPASS_FEEDBACK: f64 = 0.5;
+
+/// The combs' feedback at the smallest size, and how much more the largest adds. It stays
+/// below 1, so the tail always dies away.
+const MIN_FEEDBACK: f64 = 0.7;
+const FEEDBACK_RANGE: f64 = 0.28;
+
+/// The most the combs' lowpass takes off each pass, at full damping.
+const MAX_DAMPING: f64 = 0.4;
+
+/// The input is turned down before the eight combs add up, and the sum brought back up
+/// after, as Freeverb does.
+const INPUT_GAIN: f64 = 0.015;
+const WET_GAIN: f64 = 3.0;
+
+/// A delay line whose output is lowpassed and fed back into it.
+#[derive(Clone, Debug)]
+struct Comb {
+    line: Vec<f64>,
+    position: usize,
+    /// The lowpass's last output.
+    filtered: f64,
+}
+
+impl Comb {
+    fn process(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let output = self.line[self.position];
+        self.filtered = flush(output + (self.filtered - output) * damping);
+        self.line[self.position] = flush(input + self.filtered * feedback);
+        self.position = (self.position + 1) % self.line.len();
+        output
+    }
+}
+
+/// A Schroeder allpass, which smears echoes out without colouring them.
+#[derive(Clone, Debug)]
+struct Allpass {
+    line: Vec<f64>,
+    position: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f64) -> f64 {
+        let delayed = self.line[self.position];
+        self.line[self.position] = flush(input + delayed * ALLPASS_FEEDBACK);
+        self.position = (self.position + 1) % self.line.len();
+        delayed - input
+    }
+}
+
+/// One side's network.
+#[derive(Clone, Debug)]
+struct Side {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Side {
+    /// Lines `extra` samples longer than Freeverb's, scaled to `sample_rate`.
+    fn new(extra: usize, sample_rate: f64) -> Side {
+        let scale = |length: usize| {
+            (((length + extra) as f64 * sample_rate / 44100.0).round() as usize).max(1)
+        };
+        Side {
+            combs: COMBS
+                .iter()
+                .map(|&length| Comb {
+                    line: vec![0.0; scale(length)],
+                    position: 0,
+                    filtered: 0.0,
+                })
+                .collect(),
+            allpasses: ALLPASSES
+                .iter()
+                .map(|&length| Allpass {
+                    line: vec![0.0; scale(length)],
+                    position: 0,
+                })
+                .collect(),
+        }
+    }
+
+    fn clear(&mut self) {
+        for comb in self.combs.iter_mut() {
+            comb.line.fill(0.0);
+            comb.filtered = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut() {
+            allpass.line.fill(0.0);
+        }
+    }
+
+    fn process(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let mut output = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(input, feedback, damping))
+            .sum();
+        for allpass in self.allpasses.iter_mut() {
+            output = allpass.process(output);
+        }
+        output
+    }
+
+    /// Whether nothing is left in any line.
+    fn is_empty(&self) -> bool {
+        let combs = self.combs.iter();
+        combs.clone().all(|comb| comb.filtered == 0.0)
+            && combs
+                .map(|comb| &comb.line)
+                .chain(self.allpasses.iter().map(|allpass| &allpass.line))
+                .all(|line| line.iter().all(|&sample| sample == 0.0))
+    }
+}
+
+/// The combs' feedback for a normalized room size.
+fn feedback(size: f64) -> f64 {
+    MIN_FEEDBACK + FEEDBACK_RANGE * size.clamp(0.0, 1.0)
+}
+
+/// How long the reverb rings on at `size` after its input stops, in seconds, until it is
+/// 60 dB down: the longest comb's echoes dying away, then the allpasses' last smearing.
+pub fn tail(size: f64) -> f64 {
+    let passes = |gain: f64| (0.001f64).ln() / gain.ln();
+    let longest_comb = (COMBS[COMBS.len() - 1] + SPREAD) as f64 / 44100.0;
+    let allpasses = ALLPASSES
+        .iter()
+        .map(|&length| length + SPREAD)
+        .sum::<usize>() as f64
+        / 44100.0;
+    longest_comb * passes(feedback(size)).ceil() + allpasses * passes(ALLPASS_FEEDBACK).ceil()
+}
+
+/// The reverb's two networks, with every line allocated for the sample rate it was made
+/// for, so processing never allocates.
+pub struct Reverb {
+    sides: [Side; 2],
+    /// The size and damping the coefficients were worked out from.
+    settings: (f64, f64),
+    feedback: f64,
+    damping: f64,
+    /// How many samples of silence have gone in since the lines were last checked for having
+    /// emptied.
+    quiet_for: usize,
+    empty: bool,
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f64) -> Reverb {
+        let mut reverb = Reverb {
+            sides: [Side::new(0, sample_rate), Side::new(SPREAD, sample_rate)],
+            settings: (f64::NAN, f64::NAN),
+            feedback: 0.0,
+            damping: 0.0,
+            quiet_for: 0,
+            empty: true,
+        };
+        reverb.set(0.5, 0.5);
+        reverb
+    }
+
+    /// Take the normalized room size and damping, working the coefficients out again only
+    /// if they've changed.
+    pub fn set(&mut self, size: f64, damping: f64) {
+        if (size, damping) != self.settings {
+            self.settings = (size, damping);
+            self.feedback = feedback(size);
+            self.damping = damping.clamp(0.0, 1.0) * MAX_DAMPING;
+        }
+    }
+
+    /// Whether the reverb has nothing left to ring out.
+    pub fn is_silent(&self) -> bool {
+        self.empty
+    }
+
+    /// Empty every line, without reallocating.
+    pub fn reset(&mut self) {
+        if !self.empty {
+            self.sides.iter_mut().for_each(Side::clear);
+            self.empty = true;
+        }
+    }
+
+    /// One stereo frame through the reverb, which hears both channels mixed to mono, with
+    /// `mix` of the wet signal in place of the dry.
+    ///
+    /// Turning the mix down to nothing takes the reverb out, and cuts off whatever tail was
+    /// still ringing so it doesn't pick up again when the mix comes back.
+    pub fn process(&mut self, frame: (f64, f64), mix: f64) -> (f64, f64) {
+        if mix == 0.0 {
+            self.reset();
+            return frame;
+        }
+        let input = 0.5 * (frame.0 + frame.1) * INPUT_GAIN;
+        if input != 0.0 {
+            self.empty = false;
+            self.quiet_for = 0;
+        } else if !self.empty {
+            // Looking through every line is slow, so it's only done once the longest could
+            // have gone round since the input stopped, and again after as long each time.
+            self.quiet_for += 1;
+            let longest = self.sides[1].combs[COMBS.len() - 1].line.len();
+            if self.quiet_for >= longest {
+                self.quiet_for = 0;
+                self.empty = self.sides.iter().all(Side::is_empty);
+            }
+        }
+        let (feedback, damping) = (self.feedback, self.damping);
+        let left = self.sides[0].process(input, feedback, damping) * WET_GAIN;
+        let right = self.sides[1].process(input, feedback, damping) * WET_GAIN;
+        (
+            frame.0 + (left - frame.0) * mix,
+            frame.1 + (right - frame.1) * mix,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tail, Reverb};
+
+    const RATE: f64 = 22050.0;
+
+    /// The reverb's left output for an impulse, long enough for the largest size to die away.
+    fn impulse_response(size: f64) -> Vec<f64> {
+        let mut reverb = Reverb::new(RATE);
+        reverb.set(size, 0.5);
+        (0..(tail(1.0) * 1.2 * RATE) as usize)
+            .map(|i| {
+                let input = if i == 0 { 1.0 } else { 0.0 };
+                reverb.process((input, input), 1.0).0
+            })
+            .collect()
+    }
+
+    /// How long the response takes to fall 60 dB from its loudest, in seconds, measured over
+    /// 10 ms stretches.
+    fn rt60(response: &[f64]) -> f64 {
+        let window = (0.01 * RATE) as usize;
+        let levels: Vec<f64> = response
+            .chunks(window)
+            .map(|chunk| chunk.iter().map(|s| s * s).sum::<f64>().sqrt())
+            .collect();
+        let loudest = levels.iter().cloned().fold(0.0, f64::max);
+        let last_loud = levels
+            .iter()
+            .rposition(|&level| level > loudest * 0.001)
+            .unwrap();
+        (last_loud + 1) as f64 * 0.01
+    }
+
+    #[test]
+    fn larger_rooms_ring_longer_but_within_the_tail() {
+        let mut last = 0.0;
+        for &size in &[0.0, 0.25, 0.5, 0.75, 1.0] {
+            let response = impulse_response(size);
+            let time = rt60(&response);
+            assert!(time > last, "{} s at size {}", time, size);
+            assert!(
+                time <= tail(size),
+                "{} s at size {} past {}",
+                time,
+                size,
+                tail(size)
+            );
+            last = time;
+        }
+    }
+
+    #[test]
+    fn output_is_wide() {
+        let mut reverb = Reverb::new(RATE);
+        let outputs: Vec<(f64, f64)> = (0..RATE as usize)
+            .map(|i| reverb.process(if i == 0 { (1.0, 1.0) } else { (0.0, 0.0) }, 1.0))
+            .collect();
+        assert!(outputs.iter().any(|&(left, right)| left != right));
+    }
+
+    #[test]
+    fn everything_at_maximum_stays_finite() {
+        let mut reverb = Reverb::new(RATE);
+        reverb.set(1.0, 1.0);
+        let mut peak = 0.0f64;
+        for i in 0..10 * RATE as usize {
+            let input = if i % 100 < 50 { 4.0 } else { -4.0 };
+            let (left, right) = reverb.process((input, input), 1.0);
+            assert!(left.is_finite() && right.is_finite());
+            peak = peak.max(left.abs()).max(right.abs());
+        }
+        assert!(peak < 100.0, "{}", peak);
+    }
+
+    #[test]
+    fn no_mix_passes_the_input_through_exactly() {
+        let mut reverb = Reverb::new(RATE);
+        for i in 0..4410 {
+            let frame = ((i as f64).sin(), (i as f64 * 0.3).cos());
+            assert_eq!(reverb.process(frame, 0.0), frame);
+        }
+        reverb.process((1.0, 1.0), 0.5);
+        assert!(!reverb.is_silent());
+        let frame = (0.25, -0.5);
+        assert_eq!(reverb.process(frame, 0.0), frame);
+        assert!(reverb.is_silent());
+    }
+
+    #[test]
+    fn silence_empties_the_lines_in_time() {
+        let mut reverb = Reverb::new(RATE);
+        reverb.set(0.0, 0.5);
+        reverb.process((1.0, 1.0), 1.0);
+        let mut samples = 0;
+        while !reverb.is_silent() {
+            reverb.process((0.0, 0.0), 1.0);
+            samples += 1;
+        }
+        assert!(samples as f64 > tail(0.0) * RATE);
+        assert_eq!(reverb.process((0.0, 0.0), 1.0), (0.0, 0.0));
+    }
+}
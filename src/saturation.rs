@@ -0,0 +1,72 @@
+//! Soft-clipping saturation.
+//!
+//! The clipper bends the waveform symmetrically, so it adds odd harmonics, and the harder it is
+//! driven the further up they reach. Any that land above Nyquist fold back down as aliases
+//! that aren't harmonically related to the note, which is most audible on high notes at full
+//! drive. `Saturator` works on one sample at a time and keeps no state, so an oversampling
+//! wrapper can run it at a higher rate and filter its output without any change here.
+
+/// The input gain at full drive, about 26 dB.
+pub const MAX_DRIVE_GAIN: f64 = 20.0;
+
+/// A `tanh` soft clipper with its input gain and makeup gain worked out in advance.
+#[derive(Clone, Copy, Debug)]
+pub struct Saturator {
+    gain: f64,
+    /// Brings a full-scale input back to a full-scale output, so driving harder squares the
+    /// waveform off rather than making it much louder.
+    makeup: f64,
+}
+
+impl Saturator {
+    /// The clipper for a normalized drive amount, or `None` at zero drive, which should
+    /// bypass the stage rather than run it.
+    ///
+    /// As the drive falls to zero the curve straightens out towards the bypass, so turning it
+    /// down has no jump at the bottom.
+    pub fn new(drive: f64) -> Option<Saturator> {
+        let gain = drive.clamp(0.0, 1.0) * MAX_DRIVE_GAIN;
+        if gain == 0.0 {
+            return None;
+        }
+        Some(Saturator {
+            gain,
+            makeup: 1.0 / gain.tanh(),
+        })
+    }
+
+    /// Clip one sample. Inputs within ±1.0 stay within ±1.0, and anything beyond is held
+    /// there.
+    pub fn process(&self, sample: f64) -> f64 {
+        ((self.gain * sample).tanh() * self.makeup).clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Saturator;
+
+    #[test]
+    fn zero_drive_bypasses() {
+        assert!(Saturator::new(0.0).is_none());
+        assert!(Saturator::new(-1.0).is_none());
+    }
+
+    #[test]
+    fn full_scale_stays_full_scale() {
+        for &drive in &[0.01, 0.5, 1.0] {
+            let saturator = Saturator::new(drive).unwrap();
+            assert!((saturator.process(1.0) - 1.0).abs() < 1e-12);
+            assert!((saturator.process(-1.0) + 1.0).abs() < 1e-12);
+            assert_eq!(saturator.process(5.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn light_drive_is_nearly_linear() {
+        let saturator = Saturator::new(0.001).unwrap();
+        for &sample in &[-0.9, -0.3, 0.2, 0.7] {
+            assert!((saturator.process(sample) - sample).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Sines evaluated from a phase in cycles, either read from a table or computed exactly.
+//!
+//! Every voice takes several sines per sample once unison, FM and the additive harmonics are
+//! counted, and `f64::sin` is the costliest part of that. The oscillators read them from a
+//! table instead; anything that needs them exact, such as rendering other tables, computes them.
+
+use std::sync::OnceLock;
+
+use crate::TAU;
+
+/// Steps in one cycle of the table; a power of two so indices can wrap with a mask.
+///
+/// Interpolating linearly over a step of `TAU / TABLE_SIZE` is out by at most an eighth of its
+/// square, about 3e-7 or -130 dB, comfortably under the -100 dB the oscillators need.
+const TABLE_SIZE: usize = 4096;
+
+/// One cycle, with the first sample repeated at the end so the step after the last index
+/// needs no wrapping.
+type Table = [f64; TABLE_SIZE + 1];
+
+static TABLE_CELL: OnceLock<Box<Table>> = OnceLock::new();
+
+/// The table, built the first time it is asked for.
+fn table() -> &'static Table {
+    TABLE_CELL.get_or_init(|| {
+        let mut table = Box::new([0.0; TABLE_SIZE + 1]);
+        for (i, sample) in table.iter_mut().enumerate() {
+            *sample = (i as f64 / TABLE_SIZE as f64 * TAU).sin();
+        }
+        table
+    })
+}
+
+/// Build the table now, so it doesn't happen on the audio thread the first time a note plays.
+pub fn prepare() {
+    table();
+}
+
+/// A way of evaluating a sine.
+pub trait Osc {
+    /// The sine of `phase` cycles. Any phase works, however many cycles out it is.
+    fn sine(self, phase: f64) -> f64;
+}
+
+/// Linear interpolation in the table, which is what the oscillators play.
+#[derive(Clone, Copy, Debug)]
+pub struct Lookup;
+
+impl Osc for Lookup {
+    fn sine(self, phase: f64) -> f64 {
+        let position = phase * TABLE_SIZE as f64;
+        let whole = position.floor();
+        let t = position - whole;
+        // Masking the two's complement index wraps phases past either end of the cycle back
+        // into it, negative ones included.
+        let index = (whole as i64 as usize) & (TABLE_SIZE - 1);
+        let table = table();
+        table[index] + t * (table[index + 1] - table[index])
+    }
+}
+
+impl Lookup {
+    /// `sine` of each of `phases`, a step at a time across all of them so the steps can be
+    /// vectorized. Each comes out exactly as `sine` gives it.
+    pub fn sines<const N: usize>(self, phases: [f64; N]) -> [f64; N] {
+        let table = table();
+        let position = phases.map(|phase| phase * TABLE_SIZE as f64);
+        let whole = position.map(f64::floor);
+        let mut sines = [0.0; N];
+        for lane in 0..N {
+            let t = position[lane] - whole[lane];
+            let index = (whole[lane] as i64 as usize) & (TABLE_SIZE - 1);
+            sines[lane] = table[index] + t * (table[index + 1] - table[index]);
+        }
+        sines
+    }
+}
+
+/// `f64::sin`, for anything that needs the sine exactly.
+#[derive(Clone, Copy, Debug)]
+pub struct Exact;
+
+impl Osc for Exact {
+    fn sine(self, phase: f64) -> f64 {
+        (phase * TAU).sin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Exact, Lookup, Osc};
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    #[test]
+    fn lookup_is_within_100_db_of_sin() {
+        // An odd number of steps, so most phases fall between the table's samples, sweeping a
+        // few cycles either side of the first.
+        let steps = 1_000_003;
+        let worst = (0..=steps)
+            .map(|i| -3.0 + 6.0 * i as f64 / steps as f64)
+            .map(|phase| (Lookup.sine(phase) - Exact.sine(phase)).abs())
+            .fold(0.0f64, f64::max);
+        let db = 20.0 * worst.log10();
+        assert!(db < -100.0, "out by {} dB", db);
+        assert_eq!(Lookup.sine(0.0), 0.0);
+        assert_eq!(Lookup.sine(0.25), 1.0);
+        assert_eq!(Lookup.sine(-0.25), -1.0);
+
+        let phases = [0.0, 0.1, 0.3, -0.45, 0.77, 1.5, 0.999_999, -2.2];
+        let sines = Lookup.sines(phases);
+        for (&phase, &sine) in phases.iter().zip(&sines) {
+            assert_eq!(sine, Lookup.sine(phase));
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture` to compare the two.
+    #[test]
+    #[ignore]
+    fn lookup_outruns_sin() {
+        fn time(osc: impl Osc + Copy) -> f64 {
+            // Sixteen voices of eight unison copies each, for a second at 44.1 kHz.
+            let mut phases: Vec<f64> = (0..16 * 8).map(|i| i as f64 / 128.0).collect();
+            let increments: Vec<f64> = (0..phases.len())
+                .map(|i| (110.0 + 7.0 * i as f64) / 44100.0)
+                .collect();
+            let start = Instant::now();
+            let mut sum = 0.0;
+            for _ in 0..44100 {
+                for (phase, increment) in phases.iter_mut().zip(&increments) {
+                    sum += osc.sine(black_box(*phase));
+                    *phase = (*phase + increment).fract();
+                }
+            }
+            black_box(sum);
+            start.elapsed().as_secs_f64()
+        }
+        let lookup = time(Lookup);
+        let exact = time(Exact);
+        println!(
+            "128 oscillators for a second: lookup {:.2} ms, sin {:.2} ms ({:.1}x)",
+            lookup * 1000.0,
+            exact * 1000.0,
+            exact / lookup
+        );
+    }
+}
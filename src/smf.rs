@@ -0,0 +1,327 @@
+//! Reading Standard MIDI Files, for rendering them offline.
+//!
+//! A file is a header chunk, `MThd`, then track chunks, `MTrk`. The header gives the format,
+//! the number of tracks and the timing: ticks per quarter note, or with the top bit set, SMPTE
+//! frames per second and ticks per frame. Each track is a run of events, each after a delay in
+//! ticks written as a variable-length number, seven bits a byte with the top bit set on all but
+//! the last. Channel messages can leave out their status byte to repeat the one before, which
+//! is running status. SysEx and meta events carry their own length, and end running status.
+//!
+//! Every track plays at once, whatever the format says, so the events are merged in time. Of
+//! the meta events only Set Tempo matters here; the tempo starts at 120 BPM until one sets it.
+
+use std::cmp::Ordering;
+
+/// The tempo before any Set Tempo, in microseconds per quarter note.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// The meta event types that are read.
+const SET_TEMPO: u8 = 0x51;
+const END_OF_TRACK: u8 = 0x2F;
+
+/// A message in a file, and when it plays.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileEvent {
+    /// Seconds from the start of the file.
+    pub seconds: f64,
+    pub message: Message,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// A channel message, padded with zeros past its data bytes.
+    Midi([u8; 3]),
+    /// A whole SysEx message, from its `0xF0` to its `0xF7`.
+    SysEx(Vec<u8>),
+}
+
+/// How a file measures time.
+#[derive(Clone, Copy, Debug)]
+enum Division {
+    TicksPerQuarter(u16),
+    /// Seconds per tick, fixed whatever the tempo.
+    Smpte(f64),
+}
+
+/// Reads a file from the front, returning `None` from every read that runs off the end.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Some(u32::from_be_bytes(bytes))
+    }
+
+    /// A variable-length number, which is at most four bytes long.
+    fn variable(&mut self) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// A chunk's type and contents.
+    fn chunk(&mut self) -> Option<(&'a [u8], Reader<'a>)> {
+        let kind = self.bytes(4)?;
+        let length = self.u32()? as usize;
+        let data = self.bytes(length)?;
+        Some((kind, Reader { data }))
+    }
+}
+
+/// Every message in the file `data`, in the order they play, or `None` if it isn't a Standard
+/// MIDI File or is cut short.
+pub fn parse(data: &[u8]) -> Option<Vec<FileEvent>> {
+    let mut reader = Reader { data };
+    let (kind, mut header) = reader.chunk()?;
+    if kind != b"MThd" {
+        return None;
+    }
+    let _format = header.u16()?;
+    let tracks = header.u16()?;
+    let division = header.u16()?;
+    let division = if division & 0x8000 == 0 {
+        if division == 0 {
+            return None;
+        }
+        Division::TicksPerQuarter(division)
+    } else {
+        // The frame rate is stored negated; 29.97 fps is written as 29 and means drop frame.
+        let frames = match -i32::from((division >> 8) as u8 as i8) {
+            29 => 29.97,
+            frames if frames > 0 => f64::from(frames),
+            _ => return None,
+        };
+        let ticks = f64::from(division & 0xFF);
+        if ticks == 0.0 {
+            return None;
+        }
+        Division::Smpte(1.0 / (frames * ticks))
+    };
+
+    // Each event with its tick and its place in the file, so events at the same tick keep
+    // their order.
+    let mut events = Vec::new();
+    let mut tempos = Vec::new();
+    let mut read = 0;
+    while read < tracks {
+        let (kind, track) = reader.chunk()?;
+        // Chunks of other types are allowed, and skipped.
+        if kind == b"MTrk" {
+            read_track(track, &mut events, &mut tempos)?;
+            read += 1;
+        }
+    }
+    events.sort_by(|a: &(u64, usize, Message), b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+    tempos.sort_by(|a: &(u64, usize, u32), b| match a.0.cmp(&b.0) {
+        Ordering::Equal => a.1.cmp(&b.1),
+        order => order,
+    });
+
+    // Walk the tempo map alongside the events, adding up the time between them.
+    let mut tempo = DEFAULT_TEMPO;
+    let mut tempos = tempos.into_iter().peekable();
+    let (mut tick, mut seconds) = (0, 0.0);
+    let mut advance = |to: u64, tempo: u32| {
+        let per_tick = match division {
+            Division::TicksPerQuarter(ticks) => f64::from(tempo) / 1e6 / f64::from(ticks),
+            Division::Smpte(per_tick) => per_tick,
+        };
+        seconds += (to - tick) as f64 * per_tick;
+        tick = to;
+        seconds
+    };
+    let mut timed = Vec::with_capacity(events.len());
+    for (at, place, message) in events {
+        while let Some(&(change, change_place, new_tempo)) = tempos.peek() {
+            if (change, change_place) > (at, place) {
+                break;
+            }
+            advance(change, tempo);
+            tempo = new_tempo;
+            tempos.next();
+        }
+        timed.push(FileEvent {
+            seconds: advance(at, tempo),
+            message,
+        });
+    }
+    Some(timed)
+}
+
+/// Add a track's messages to `events` and its tempo changes to `tempos`, each with its tick
+/// and its place in the file.
+fn read_track(
+    mut track: Reader,
+    events: &mut Vec<(u64, usize, Message)>,
+    tempos: &mut Vec<(u64, usize, u32)>,
+) -> Option<()> {
+    let mut tick = 0u64;
+    let mut running = None;
+    while !track.data.is_empty() {
+        tick += u64::from(track.variable()?);
+        let place = events.len() + tempos.len();
+        let mut status = track.u8()?;
+        let first = if status & 0x80 == 0 {
+            // Running status: that was the first data byte.
+            let data = status;
+            status = running?;
+            Some(data)
+        } else {
+            None
+        };
+        match status {
+            0x80..=0xEF => {
+                running = Some(status);
+                let first = match first {
+                    Some(first) => first,
+                    None => track.u8()?,
+                };
+                let second = match status & 0xF0 {
+                    0xC0 | 0xD0 => 0,
+                    _ => track.u8()?,
+                };
+                events.push((tick, place, Message::Midi([status, first, second])));
+            }
+            0xF0 => {
+                running = None;
+                let length = track.variable()? as usize;
+                let mut sysex = vec![0xF0];
+                sysex.extend_from_slice(track.bytes(length)?);
+                events.push((tick, place, Message::SysEx(sysex)));
+            }
+            0xFF => {
+                running = None;
+                let kind = track.u8()?;
+                let length = track.variable()? as usize;
+                let data = track.bytes(length)?;
+                match kind {
+                    SET_TEMPO if length == 3 => {
+                        let tempo = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                        if tempo > 0 {
+                            tempos.push((tick, place, tempo));
+                        }
+                    }
+                    END_OF_TRACK => return Some(()),
+                    _ => (),
+                }
+            }
+            // A SysEx continuation or escaped bytes, which nothing here plays.
+            0xF7 => {
+                running = None;
+                let length = track.variable()? as usize;
+                track.bytes(length)?;
+            }
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, FileEvent, Message};
+
+    /// A file with one track at 96 ticks per quarter holding `track`, which should end with
+    /// End of Track.
+    fn file(track: &[u8]) -> Vec<u8> {
+        let mut data = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk".to_vec();
+        data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        data.extend_from_slice(track);
+        data
+    }
+
+    fn midi(seconds: f64, data: [u8; 3]) -> FileEvent {
+        FileEvent {
+            seconds,
+            message: Message::Midi(data),
+        }
+    }
+
+    #[test]
+    fn notes_are_timed_by_the_tempo() {
+        let data = file(&[
+            // A4 on, then off a quarter note later at the default 120 BPM.
+            0x00, 0x90, 69, 100, //
+            0x60, 0x80, 69, 0, //
+            // Running status, and a delay of 192 ticks written in two bytes.
+            0x81, 0x40, 69, 0, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ]);
+        assert_eq!(
+            parse(&data).unwrap(),
+            [
+                midi(0.0, [0x90, 69, 100]),
+                midi(0.5, [0x80, 69, 0]),
+                midi(1.5, [0x80, 69, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn tempo_changes_apply_from_their_tick() {
+        let data = file(&[
+            0x00, 0x90, 60, 100, //
+            // After a quarter at 120 BPM, switch to 60 BPM.
+            0x60, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, //
+            0x60, 0xC0, 5, // A program change, which has one data byte.
+            0x00, 0xFF, 0x2F, 0x00,
+        ]);
+        let events = parse(&data).unwrap();
+        assert_eq!(
+            events,
+            [midi(0.0, [0x90, 60, 100]), midi(1.5, [0xC0, 5, 0])]
+        );
+    }
+
+    #[test]
+    fn sysex_is_kept_whole() {
+        let data = file(&[
+            0x00, 0xF0, 0x03, 0x7D, 0x01, 0xF7, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ]);
+        let events = parse(&data).unwrap();
+        assert_eq!(
+            events[0].message,
+            Message::SysEx(vec![0xF0, 0x7D, 0x01, 0xF7])
+        );
+    }
+
+    #[test]
+    fn broken_files_are_rejected() {
+        let good = file(&[0x00, 0x90, 60, 100, 0x00, 0xFF, 0x2F, 0x00]);
+        assert!(parse(&good).is_some());
+        for length in 0..good.len() {
+            assert_eq!(parse(&good[..length]), None, "{}", length);
+        }
+        // Running status with nothing to run on.
+        assert_eq!(parse(&file(&[0x00, 60, 100, 0x00, 0xFF, 0x2F, 0x00])), None);
+        assert_eq!(parse(b"RIFF\0\0\0\x06\0\0\0\x01\0\x60"), None);
+    }
+}
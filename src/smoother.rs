@@ -0,0 +1,65 @@
+//! Per-sample smoothing for parameters the host changes in steps.
+
+/// A parameter value that follows its target through a one-pole filter, so a step in the
+/// target becomes a short glide instead of a click or zipper noise.
+///
+/// It starts out with no value and snaps to the first target it sees, so a freshly loaded
+/// project starts at its saved settings rather than gliding in from the defaults.
+#[derive(Clone, Copy, Debug)]
+pub struct SmoothedParam {
+    /// The fraction of the remaining distance covered each sample.
+    coefficient: f64,
+    value: Option<f64>,
+}
+
+impl SmoothedParam {
+    /// A smoother with a time constant of `time` seconds.
+    pub fn new(time: f64, sample_rate: f64) -> SmoothedParam {
+        SmoothedParam {
+            coefficient: 1.0 - (-1.0 / (time * sample_rate)).exp(),
+            value: None,
+        }
+    }
+
+    /// Jump straight to `target`, for when the glide to it wouldn't be heard.
+    pub fn snap(&mut self, target: f64) {
+        self.value = Some(target);
+    }
+
+    /// Move one sample closer to `target` and return the new value.
+    pub fn next(&mut self, target: f64) -> f64 {
+        let value = match self.value {
+            // Close enough counts as arrived, so the value settles exactly and anything
+            // cached from it stops changing.
+            Some(value) if (target - value).abs() > 1e-9 => {
+                value + (target - value) * self.coefficient
+            }
+            _ => target,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmoothedParam;
+
+    #[test]
+    fn first_target_is_taken_immediately() {
+        let mut smoothed = SmoothedParam::new(0.01, 44100.0);
+        assert_eq!(smoothed.next(0.7), 0.7);
+        assert_eq!(smoothed.next(0.7), 0.7);
+    }
+
+    #[test]
+    fn step_becomes_a_settling_glide() {
+        let mut smoothed = SmoothedParam::new(0.01, 44100.0);
+        smoothed.next(0.0);
+        let glide: Vec<f64> = (0..44100).map(|_| smoothed.next(1.0)).collect();
+        assert!(glide.windows(2).all(|w| w[1] >= w[0]));
+        // One time constant covers 1 - 1/e of the distance.
+        assert!((glide[440] - (1.0 - (-1.0f64).exp())).abs() < 0.01);
+        assert_eq!(*glide.last().unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,282 @@
+//! Rendering whole passages offline the way a host would, and checking them against golden
+//! renders saved in `tests/fixtures/golden`.
+//!
+//! A golden render keeps every `GOLDEN_STEP`th sample of each output. When a change to the
+//! sound is meant, the fixtures are brought up to date by running
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test golden
+//! ```
+//!
+//! and the diff in the fixtures shows what changed along with the code.
+//!
+//! The benchmarks in `benches/` drive the synth through the same `OfflineHost`.
+
+use crate::noise::Noise;
+use crate::offline::OfflineHost;
+use crate::params::PARAM_COUNT;
+
+/// The block size the host renders in, small enough that every passage spans several.
+const BLOCK_SIZE: usize = 256;
+
+/// Every how many samples a golden render keeps one.
+const GOLDEN_STEP: usize = 16;
+
+/// How far a sample may be from its golden value. The fixtures are written to six decimal
+/// places, and the maths libraries of different platforms can differ in the last bits.
+const GOLDEN_TOLERANCE: f32 = 1e-5;
+
+/// Render `num_samples` samples of both outputs from a fresh synth at 44.1 kHz.
+///
+/// `params` are set by index before anything renders. `events` are MIDI messages with the
+/// sample each arrives at, in order; each is delivered with the block it falls in, timed
+/// within it as a host would.
+pub fn render_midi(
+    events: &[(usize, [u8; 3])],
+    num_samples: usize,
+    params: &[(usize, f32)],
+) -> Vec<Vec<f32>> {
+    let mut host = OfflineHost::new(BLOCK_SIZE, params);
+    let mut outputs = vec![
+        Vec::with_capacity(num_samples),
+        Vec::with_capacity(num_samples),
+    ];
+    let mut events = events.iter().peekable();
+    let mut start = 0;
+    while start < num_samples {
+        let end = (start + BLOCK_SIZE).min(num_samples);
+        while let Some(&&(time, data)) = events.peek() {
+            if time >= end {
+                break;
+            }
+            host.queue_midi(time.saturating_sub(start), data);
+            events.next();
+        }
+        for (output, block) in outputs.iter_mut().zip(host.process(end - start)) {
+            output.extend_from_slice(block);
+        }
+        start = end;
+    }
+    outputs
+}
+
+/// Check `outputs` against the golden render `name`, or save them as it with `UPDATE_GOLDEN`
+/// set.
+pub fn assert_golden(name: &str, outputs: &[Vec<f32>]) {
+    let path = format!(
+        "{}/tests/fixtures/golden/{}.txt",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let kept: Vec<Vec<f32>> = outputs
+        .iter()
+        .map(|output| output.iter().step_by(GOLDEN_STEP).copied().collect())
+        .collect();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let mut text = format!(
+            "# {}: every {}th sample of each output\n",
+            name, GOLDEN_STEP
+        );
+        for frame in 0..kept[0].len() {
+            let samples: Vec<String> = kept.iter().map(|o| format!("{:.6}", o[frame])).collect();
+            text.push_str(&samples.join(" "));
+            text.push('\n');
+        }
+        std::fs::write(&path, text).unwrap();
+        return;
+    }
+
+    let text = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no golden render at {}; see `crate::testing`", path));
+    let golden: Vec<Vec<f32>> = text
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| line.split(' ').map(|s| s.parse().unwrap()).collect())
+        .collect();
+    assert_eq!(golden.len(), kept[0].len(), "{} has changed length", name);
+    for (frame, golden) in golden.iter().enumerate() {
+        for (channel, &expected) in golden.iter().enumerate() {
+            let sample = kept[channel][frame];
+            assert!(
+                (sample - expected).abs() <= GOLDEN_TOLERANCE,
+                "{} channel {} sample {}: {} rather than {}",
+                name,
+                channel,
+                frame * GOLDEN_STEP,
+                sample,
+                expected
+            );
+        }
+    }
+}
+
+/// Arbitrary host input: MIDI bytes, parameter changes and block sizes drawn from a seeded
+/// generator, so a failure repeats exactly.
+pub struct Fuzzer {
+    random: Noise,
+}
+
+impl Fuzzer {
+    pub fn new(seed: u32) -> Fuzzer {
+        Fuzzer {
+            random: Noise::with_seed(seed),
+        }
+    }
+
+    /// A whole number below `end`.
+    pub fn below(&mut self, end: usize) -> usize {
+        (self.random.unit() * end as f64) as usize
+    }
+
+    /// Whether something with a `chance` in one happens.
+    pub fn chance(&mut self, chance: f64) -> bool {
+        self.random.unit() < chance
+    }
+
+    /// Three bytes that are usually a channel message, but sometimes a system message, a
+    /// stray data byte where the status should be, or data bytes with the high bit set.
+    pub fn midi(&mut self) -> [u8; 3] {
+        let status = if self.chance(0.05) {
+            self.below(256) as u8
+        } else {
+            0x80 | self.below(0x70) as u8
+        };
+        let mut data = [status, self.below(128) as u8, self.below(128) as u8];
+        if self.chance(0.02) {
+            data[1 + self.below(2)] |= 0x80;
+        }
+        data
+    }
+
+    /// A parameter index, now and then one past either end, and a value that is usually in
+    /// range but sometimes well outside it or not a number at all.
+    pub fn parameter(&mut self) -> (i32, f32) {
+        let index = self.below(PARAM_COUNT + 2) as i32 - 1;
+        let value = match self.below(20) {
+            0 => -1.0 - self.random.unit() as f32,
+            1 => 1.0 + self.random.unit() as f32 * 10.0,
+            2 => [f32::NAN, f32::INFINITY, f32::NEG_INFINITY][self.below(3)],
+            3 => [0.0, 1.0][self.below(2)],
+            _ => self.random.unit() as f32,
+        };
+        (index, value)
+    }
+
+    /// A block size up to `max`, with empty and single-sample blocks far more often than
+    /// chance would give them.
+    pub fn block_size(&mut self, max: usize) -> usize {
+        match self.below(8) {
+            0 => 0,
+            1 => 1,
+            _ => self.below(max + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assert_golden, render_midi, Fuzzer, BLOCK_SIZE};
+    use crate::analysis::rms;
+    use crate::SineSynth;
+    use vst::host::HostBuffer;
+    use vst::plugin::{Plugin, PluginParameters};
+
+    #[test]
+    fn events_land_on_their_sample() {
+        // A note starting part way through the second block.
+        let outputs = render_midi(&[(300, [144, 69, 127])], 1024, &[(1, 0.0)]);
+        assert!(outputs[0][..300].iter().all(|&s| s == 0.0));
+        assert!(outputs[0][301] != 0.0);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].len(), 1024);
+    }
+
+    #[test]
+    fn golden_single_note() {
+        let outputs = render_midi(&[(0, [144, 69, 100])], 8192, &[]);
+        assert!(rms(&outputs[0]) > 0.01);
+        assert_golden("single_note", &outputs);
+    }
+
+    #[test]
+    fn golden_chord() {
+        let events = [
+            (0, [144, 60, 100]),
+            (100, [144, 64, 90]),
+            (200, [144, 67, 80]),
+        ];
+        let outputs = render_midi(&events, 8192, &[(10, 1.0 / 6.0)]);
+        assert_golden("chord", &outputs);
+    }
+
+    #[test]
+    fn golden_note_with_release() {
+        let events = [(0, [144, 57, 127]), (4096, [128, 57, 0])];
+        // A short release on a filtered saw, so the tail ends within the render.
+        let params = [(4, 0.1), (10, 2.0 / 6.0), (38, 0.6)];
+        let outputs = render_midi(&events, 16384, &params);
+        let tail = outputs[0][12000..]
+            .iter()
+            .fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(tail < 1e-4, "{}", tail);
+        assert_golden("note_with_release", &outputs);
+    }
+
+    /// Process one block of `samples`, checking every sample is a number within ±4.0.
+    fn process_checked(synth: &mut SineSynth, samples: usize, seed: u32) {
+        let mut host_buffer: HostBuffer<f32> = HostBuffer::new(0, 2);
+        let inputs: [Vec<f32>; 0] = [];
+        let mut outputs = vec![vec![0.0; samples]; 2];
+        synth.process(&mut host_buffer.bind(&inputs, &mut outputs));
+        for sample in outputs.iter().flatten() {
+            assert!(
+                sample.is_finite() && sample.abs() <= 4.0,
+                "seed {}: {}",
+                seed,
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn arbitrary_host_input_stays_finite_and_ends_with_all_notes_off() {
+        for seed in 1..=24 {
+            let mut fuzzer = Fuzzer::new(seed);
+            let mut synth = SineSynth::default();
+            synth.set_sample_rate([22050.0, 44100.0, 96000.0][fuzzer.below(3)]);
+            synth.set_block_size(BLOCK_SIZE as i64);
+            synth.resume();
+            for _ in 0..200 {
+                while fuzzer.chance(0.3) {
+                    let (index, value) = fuzzer.parameter();
+                    synth.params.set_parameter(index, value);
+                }
+                let samples = fuzzer.block_size(BLOCK_SIZE);
+                while fuzzer.chance(0.6) {
+                    let time = fuzzer.below(samples.max(1));
+                    synth.queue_midi_event(time as i32, fuzzer.midi());
+                }
+                process_checked(&mut synth, samples, seed);
+            }
+
+            for channel in 0..16 {
+                synth.queue_midi_event(0, [0xB0 | channel, 123, 0]);
+            }
+            process_checked(&mut synth, 1, seed);
+            assert!(
+                synth.voices.iter().all(|v| !v.active || v.is_released()),
+                "seed {}: a note is still held",
+                seed
+            );
+            // Nothing starts again afterwards, arpeggiated or not.
+            for _ in 0..16 {
+                process_checked(&mut synth, BLOCK_SIZE, seed);
+            }
+            assert!(
+                synth.voices.iter().all(|v| !v.active || v.is_released()),
+                "seed {}: a note started after all notes off",
+                seed
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! A tilt EQ for the "Brightness" parameter: the highs come up as the lows go down, or the
+//! other way round, pivoting around a frequency in the middle that stays where it is.
+
+use crate::denormal::flush;
+use crate::PI;
+
+/// The frequency the tilt pivots around, which it leaves at its level.
+pub const PIVOT: f64 = 800.0;
+
+/// How far the highs go up, and the lows down, at full brightness, in dB. Full darkness is
+/// the same the other way.
+pub const MAX_TILT_DB: f64 = 6.0;
+
+/// A first-order tilt for each channel.
+///
+/// The low shelf cutting and the high shelf boosting by the same amount make a single
+/// first-order section, with its pole and zero either side of `PIVOT`: `G·(s + ω/G) / (s + ωG)`,
+/// through the bilinear transform warped to keep `PIVOT` in place. Swapping the gain for its
+/// reciprocal swaps the pole and zero, so darkening is exactly the inverse of brightening.
+#[derive(Clone, Copy, Debug)]
+pub struct Tilt {
+    /// `b0`, `b1` and `a1`, or `None` when the tilt is flat and left out.
+    coefficients: Option<[f64; 3]>,
+    /// The brightness and sample rate the coefficients were worked out for.
+    settings: (f64, f64),
+    /// The last input and output of each channel.
+    state: [(f64, f64); 2],
+}
+
+impl Default for Tilt {
+    fn default() -> Tilt {
+        Tilt {
+            coefficients: None,
+            settings: (0.0, 0.0),
+            state: [(0.0, 0.0); 2],
+        }
+    }
+}
+
+impl Tilt {
+    /// Take the brightness, from -1 for darkest to 1 for brightest, working the coefficients
+    /// out again only if it or the sample rate has changed.
+    pub fn set(&mut self, brightness: f64, sample_rate: f64) {
+        if (brightness, sample_rate) == self.settings {
+            return;
+        }
+        self.settings = (brightness, sample_rate);
+        if brightness == 0.0 {
+            self.coefficients = None;
+            self.reset();
+            return;
+        }
+        let gain = 10f64.powf(brightness.clamp(-1.0, 1.0) * MAX_TILT_DB / 20.0);
+        let k = (PI * PIVOT / sample_rate).tan();
+        let norm = 1.0 / (1.0 + k * gain);
+        self.coefficients = Some([
+            (gain + k) * norm,
+            (k - gain) * norm,
+            (k * gain - 1.0) * norm,
+        ]);
+    }
+
+    /// Forget the past input and output, for starting over.
+    pub fn reset(&mut self) {
+        self.state = [(0.0, 0.0); 2];
+    }
+
+    /// Whether the filters have nothing left to put out while their input stays at zero.
+    pub fn is_settled(&self) -> bool {
+        self.state == [(0.0, 0.0); 2]
+    }
+
+    /// One stereo frame through the tilt. Flat, the frame comes back exactly as it went in.
+    pub fn process(&mut self, frame: (f64, f64)) -> (f64, f64) {
+        let [b0, b1, a1] = match self.coefficients {
+            Some(coefficients) => coefficients,
+            None => return frame,
+        };
+        let mut filter = |channel: usize, input: f64| {
+            let (last_input, last_output) = self.state[channel];
+            let output = flush(b0 * input + b1 * last_input - a1 * last_output);
+            self.state[channel] = (input, output);
+            output
+        };
+        (filter(0, frame.0), filter(1, frame.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Tilt, MAX_TILT_DB, PIVOT};
+    use crate::analysis::magnitude_at;
+    use crate::TAU;
+
+    /// The tilt's gain in dB for a sine at `freq`, once it has settled.
+    fn gain_db(brightness: f64, freq: f64) -> f64 {
+        let mut tilt = Tilt::default();
+        tilt.set(brightness, 44100.0);
+        let sine = |i: usize| (freq * TAU * i as f64 / 44100.0).sin();
+        let (input, output): (Vec<f32>, Vec<f32>) = (0..44100)
+            .map(|i| (sine(i) as f32, tilt.process((sine(i), 0.0)).0 as f32))
+            .skip(22050)
+            .unzip();
+        20.0 * (magnitude_at(&output, freq, 44100.0) / magnitude_at(&input, freq, 44100.0)).log10()
+    }
+
+    #[test]
+    fn the_pivot_stays_put_and_the_ends_tilt() {
+        for &brightness in &[-1.0, -0.5, 0.5, 1.0] {
+            assert!(gain_db(brightness, PIVOT).abs() < 0.05);
+        }
+        assert!((gain_db(1.0, 20.0) + MAX_TILT_DB).abs() < 0.2);
+        assert!((gain_db(1.0, 20000.0) - MAX_TILT_DB).abs() < 0.2);
+    }
+
+    #[test]
+    fn flat_is_left_out() {
+        let mut tilt = Tilt::default();
+        tilt.set(0.5, 44100.0);
+        tilt.process((1.0, -1.0));
+        tilt.set(0.0, 44100.0);
+        assert!(tilt.is_settled());
+        assert_eq!(tilt.process((0.3, -0.7)), (0.3, -0.7));
+    }
+}
@@ -0,0 +1,621 @@
+//! Tuning tables, and the Scala `.scl` scale files they can be loaded from.
+//!
+//! A scale file is plain text. Lines starting with `!` are comments. The first other line
+//! describes the scale, the next is how many pitches it has, and then come the pitches, one per
+//! line. A pitch with a `.` in it is in cents; any other is a ratio like `3/2`, or a whole
+//! number. Anything after the pitch on its line is ignored. The first degree of the scale is
+//! implied, and the last pitch listed is the period the scale repeats at, usually `2/1`.
+//!
+//! There is no keyboard mapping file: degree 0 goes on middle C at its equal-tempered pitch and
+//! each key plays the next degree up, which is what Scala does without one.
+//!
+//! The table can also be changed note by note with MIDI Tuning Standard (MTS) messages: the
+//! bulk tuning dump, which sets every note, and the single-note tuning change, with or without
+//! a bank number. Each gives a note's frequency as an equal-tempered semitone plus a 14-bit
+//! fraction of a semitone above it, so they're absolute pitches with A4 at 440 Hz.
+//!
+//! The audio thread keeps its own table, since notes start and SysEx arrives there. Tables
+//! loaded with the plugin's state reach it through a `HostTuning`, and the changes SysEx makes
+//! come back the same way, so the scale file can be saved.
+
+use crate::handoff::{handoff, Reader, Writer};
+use crate::{midi_pitch_to_freq, pitch_to_freq, A4_FREQ};
+
+/// The number of MIDI notes.
+const NOTES: usize = 128;
+
+/// The start of a SysEx message carrying a scale file: the manufacturer ID set aside for
+/// non-commercial use, then "SCL".
+const SYSEX_HEADER: &[u8] = &[0xF0, 0x7D, b'S', b'C', b'L'];
+
+/// The note that plays the scale's first degree.
+const BASE_NOTE: i32 = 60;
+
+/// The universal SysEx IDs MTS messages start with.
+const NON_REAL_TIME: u8 = 0x7E;
+const REAL_TIME: u8 = 0x7F;
+
+/// The sub-ID for MTS messages, and the ones understood within it.
+const MTS: u8 = 0x08;
+const BULK_DUMP: u8 = 0x01;
+const NOTE_CHANGE: u8 = 0x02;
+const BANK_NOTE_CHANGE: u8 = 0x07;
+
+/// Whether tuning can be set by SysEx, so the host should pass it on. This includes MTS
+/// single-note tuning changes, which some hosts ask about on their own.
+pub const SYSEX_TUNING: bool = true;
+
+/// The length of a bulk dump's tuning name.
+const NAME_LENGTH: usize = 16;
+
+/// The frequency data that leaves a note as it is.
+const NO_CHANGE: &[u8] = &[0x7F, 0x7F, 0x7F];
+
+/// The longest scale file taken by SysEx, in bytes. The audio thread keeps a copy to hand
+/// over for saving, in space set aside beforehand.
+pub const MAX_SCALE_LENGTH: usize = 16 * 1024;
+
+/// The frequency each MIDI note plays with A4 at `A4_FREQ`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+    frequencies: [f64; NOTES],
+}
+
+impl Default for Tuning {
+    /// Twelve-tone equal temperament.
+    fn default() -> Tuning {
+        let mut frequencies = [0.0; NOTES];
+        for (note, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = midi_pitch_to_freq(note as u8, A4_FREQ);
+        }
+        Tuning { frequencies }
+    }
+}
+
+impl Tuning {
+    /// The tuning a scale file describes, or `None` if it can't be read or its pitches run
+    /// out of range across the keyboard.
+    ///
+    /// It doesn't allocate, so a file arriving by SysEx can be read on the audio thread.
+    pub fn from_scl(text: &str) -> Option<Tuning> {
+        let scale = parse_scl(text)?;
+        let base = midi_pitch_to_freq(BASE_NOTE as u8, A4_FREQ);
+        let degrees = scale.count as i32;
+        let mut frequencies = [0.0; NOTES];
+        // Each degree goes on every key a whole number of periods from its own above middle C.
+        let mut tune = |degree: i32, cents: f64| {
+            let mut note = (BASE_NOTE + degree).rem_euclid(degrees);
+            while note < NOTES as i32 {
+                let periods = (note - BASE_NOTE).div_euclid(degrees);
+                frequencies[note as usize] =
+                    base * ((f64::from(periods) * scale.period + cents) / 1200.0).exp2();
+                note += degrees;
+            }
+        };
+        tune(0, 0.0);
+        for (degree, cents) in (1..degrees).zip(scale.pitches()) {
+            tune(degree, cents);
+        }
+        if !frequencies.iter().all(|f| f.is_normal()) {
+            return None;
+        }
+        Some(Tuning { frequencies })
+    }
+
+    /// The frequency of `note` with A4 tuned to `a4` Hz.
+    pub fn frequency(&self, note: u8, a4: f64) -> f64 {
+        self.frequencies[usize::from(note) % NOTES] * (a4 / A4_FREQ)
+    }
+
+    /// Apply an MTS message, returning whether `payload` was one. A message that is cut
+    /// short, fails its checksum or has a byte outside MIDI's 7 bits changes nothing.
+    ///
+    /// It doesn't allocate, so it can be done on the audio thread as the message arrives.
+    pub fn apply_mts(&mut self, payload: &[u8]) -> bool {
+        let frequencies = &mut self.frequencies;
+        mts_changes(payload, |note, data| {
+            if let (Some(frequency), Some(slot)) = (mts_frequency(data), frequencies.get_mut(note))
+            {
+                *slot = frequency;
+            }
+        })
+    }
+}
+
+/// A tuning change on its way from the audio thread to the host's side of the plugin.
+struct Reported {
+    /// The `HostTuning::loaded` the change was made on top of.
+    loaded: u32,
+    /// The scale file it came from, or empty if there is none to save. It has room for
+    /// `MAX_SCALE_LENGTH` bytes from the start, so filling it doesn't allocate.
+    source: String,
+}
+
+impl Clone for Reported {
+    /// A copy with the same room for a scale file, which a derived clone wouldn't keep.
+    fn clone(&self) -> Reported {
+        let mut source = String::with_capacity(MAX_SCALE_LENGTH);
+        source.push_str(&self.source);
+        Reported {
+            loaded: self.loaded,
+            source,
+        }
+    }
+}
+
+/// The host's side of the tuning: the scale file to save, and the way to load a new table.
+///
+/// Loading counts up `loaded`, and the audio thread reports each change it makes with the
+/// count it had caught up with. A report made before the audio thread had taken the latest
+/// load is one the load has already replaced, so it's dropped here just as the audio thread
+/// will drop the change itself.
+pub struct HostTuning {
+    to_audio: Writer<(u32, Tuning)>,
+    from_audio: Reader<Reported>,
+    loaded: u32,
+    source: Option<String>,
+}
+
+impl HostTuning {
+    /// Take up whatever the audio thread has reported since.
+    fn catch_up(&mut self) {
+        if let Some(reported) = self.from_audio.take() {
+            if reported.loaded == self.loaded {
+                self.source = Some(reported.source.clone()).filter(|source| !source.is_empty());
+            }
+        }
+    }
+
+    /// The scale file to save, or `None` if the tuning isn't one.
+    pub fn source(&mut self) -> Option<&str> {
+        self.catch_up();
+        self.source.as_deref()
+    }
+
+    /// Tune to `tuning`, from the scale file `source`, from the audio thread's next block.
+    pub fn load(&mut self, tuning: Tuning, source: Option<String>) {
+        self.loaded = self.loaded.wrapping_add(1);
+        *self.to_audio.slot() = (self.loaded, tuning);
+        self.to_audio.publish();
+        self.source = source;
+    }
+}
+
+/// The audio thread's side of the tuning: the table notes are tuned from.
+pub struct AudioTuning {
+    from_host: Reader<(u32, Tuning)>,
+    to_host: Writer<Reported>,
+    /// The latest of the host's loads taken.
+    loaded: u32,
+    tuning: Tuning,
+}
+
+impl AudioTuning {
+    /// Take up the tuning the host last loaded, if it's new.
+    pub fn update(&mut self) {
+        if let Some(&mut (loaded, tuning)) = self.from_host.take() {
+            self.loaded = loaded;
+            self.tuning = tuning;
+        }
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    /// Tune to the scale file `text`, returning whether it could be read.
+    pub fn load_scale(&mut self, text: &str) -> bool {
+        if text.len() > MAX_SCALE_LENGTH {
+            return false;
+        }
+        match Tuning::from_scl(text) {
+            Some(tuning) => {
+                self.tuning = tuning;
+                self.report(text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply an MTS message, returning whether `payload` was one.
+    ///
+    /// The table no longer matches any scale file afterwards, so nothing is saved; a song sends
+    /// its tuning messages again each time it plays, like the rest of its MIDI.
+    pub fn apply_mts(&mut self, payload: &[u8]) -> bool {
+        let applied = self.tuning.apply_mts(payload);
+        if applied {
+            self.report("");
+        }
+        applied
+    }
+
+    /// Tell the host's side which scale file the table is now from.
+    fn report(&mut self, source: &str) {
+        let reported = self.to_host.slot();
+        reported.loaded = self.loaded;
+        reported.source.clear();
+        reported.source.push_str(source);
+        self.to_host.publish();
+    }
+}
+
+/// The two sides of the tuning, starting out in equal temperament.
+pub fn tuning_link() -> (HostTuning, AudioTuning) {
+    let (to_audio, from_host) = handoff((0, Tuning::default()));
+    let (to_host, from_audio) = handoff(Reported {
+        loaded: 0,
+        source: String::with_capacity(MAX_SCALE_LENGTH),
+    });
+    let host = HostTuning {
+        to_audio,
+        from_audio,
+        loaded: 0,
+        source: None,
+    };
+    let audio = AudioTuning {
+        from_host,
+        to_host,
+        loaded: 0,
+        tuning: Tuning::default(),
+    };
+    (host, audio)
+}
+
+/// Hand `change` each note an MTS message retunes and the frequency data for it, returning
+/// whether `payload` is a well-formed MTS message. The whole message is checked before any
+/// note is handed over, so a malformed one hands over none.
+fn mts_changes(payload: &[u8], mut change: impl FnMut(usize, &[u8])) -> bool {
+    let payload = payload.strip_suffix(&[0xF7]).unwrap_or(payload);
+    let body = match payload.strip_prefix(&[0xF0]) {
+        Some(body) if body.iter().all(|&byte| byte <= 0x7F) => body,
+        _ => return false,
+    };
+    // After the ID and sub-IDs come a device ID and a tuning program number, which are ignored
+    // since there is only the one table, as is the bank number of a bank note change.
+    match *body {
+        [NON_REAL_TIME, _, MTS, BULK_DUMP, _, ref rest @ ..] => {
+            if rest.len() != NAME_LENGTH + 3 * NOTES + 1 {
+                return false;
+            }
+            // The checksum is every byte after the 0xF0 up to it, exclusive-ored together.
+            let (checked, checksum) = body.split_at(body.len() - 1);
+            if checked.iter().fold(0, |sum, byte| sum ^ byte) != checksum[0] {
+                return false;
+            }
+            let data = &rest[NAME_LENGTH..rest.len() - 1];
+            for (note, data) in data.chunks(3).enumerate() {
+                change(note, data);
+            }
+            true
+        }
+        [REAL_TIME, _, MTS, NOTE_CHANGE, _, count, ref rest @ ..]
+        | [REAL_TIME | NON_REAL_TIME, _, MTS, BANK_NOTE_CHANGE, _, _, count, ref rest @ ..] => {
+            if rest.len() != 4 * usize::from(count) {
+                return false;
+            }
+            for note_change in rest.chunks(4) {
+                change(usize::from(note_change[0]), &note_change[1..]);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The frequency in MTS frequency data, or `None` for `NO_CHANGE`.
+fn mts_frequency(data: &[u8]) -> Option<f64> {
+    if data == NO_CHANGE {
+        return None;
+    }
+    let fraction = f64::from(u16::from(data[1]) << 7 | u16::from(data[2])) / 16384.0;
+    let semitone = f64::from(data[0]) + fraction;
+    Some(pitch_to_freq(semitone, A4_FREQ))
+}
+
+/// The scale file in a SysEx message, if that's what it carries.
+///
+/// The message is `SYSEX_HEADER`, the file as 7-bit ASCII, and the closing `0xF7`. VST 2 gives
+/// plugins no way to open a file, so this is how a host or a MIDI file can send one.
+pub fn sysex_scale(payload: &[u8]) -> Option<&str> {
+    let text = payload.strip_prefix(SYSEX_HEADER)?;
+    let text = text.strip_suffix(&[0xF7]).unwrap_or(text);
+    if !text.is_ascii() {
+        return None;
+    }
+    std::str::from_utf8(text).ok()
+}
+
+/// A scale file that has been checked to be well formed.
+pub struct Scale<'a> {
+    text: &'a str,
+    /// How many pitches the file lists, the period included.
+    count: usize,
+    /// The last pitch, in cents.
+    period: f64,
+}
+
+impl<'a> Scale<'a> {
+    /// The scale's pitches in cents above its first degree, up to and including the period.
+    pub fn pitches(&self) -> impl Iterator<Item = f64> + 'a {
+        pitch_lines(self.text)
+            .take(self.count)
+            .filter_map(|line| parse_pitch(line.split_whitespace().next()?))
+    }
+}
+
+/// The lines of a scale file after the description and the count.
+fn pitch_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().filter(|line| !line.starts_with('!')).skip(2)
+}
+
+/// The scale in a scale file, or `None` if the file is malformed.
+///
+/// The period has to be above the first degree, or the scale wouldn't climb the keyboard.
+pub fn parse_scl(text: &str) -> Option<Scale<'_>> {
+    let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+    // The description isn't needed, but has to be there.
+    lines.next()?;
+    let count: usize = lines.next()?.split_whitespace().next()?.parse().ok()?;
+    if count == 0 {
+        return None;
+    }
+    // Read through once to check every pitch, so the table can be built in a second pass
+    // without anywhere to keep them in between.
+    let mut period = None;
+    let mut listed = 0;
+    for line in pitch_lines(text).take(count) {
+        period = Some(parse_pitch(line.split_whitespace().next()?)?);
+        listed += 1;
+    }
+    let period = period.filter(|&period| listed == count && period > 0.0)?;
+    Some(Scale {
+        text,
+        count,
+        period,
+    })
+}
+
+/// A pitch line's value in cents.
+fn parse_pitch(pitch: &str) -> Option<f64> {
+    let cents = if pitch.contains('.') {
+        pitch.parse().ok()?
+    } else {
+        let (numerator, denominator) = pitch.split_once('/').unwrap_or((pitch, "1"));
+        let numerator: u64 = numerator.parse().ok()?;
+        let denominator: u64 = denominator.parse().ok()?;
+        if numerator == 0 || denominator == 0 {
+            return None;
+        }
+        1200.0 * (numerator as f64 / denominator as f64).log2()
+    };
+    Some(cents).filter(|cents: &f64| cents.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_scl, sysex_scale, tuning_link, Tuning, MAX_SCALE_LENGTH};
+    use crate::{midi_pitch_to_freq, A4_FREQ};
+
+    fn close(a: &[f64], b: &[f64]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-9)
+    }
+
+    /// A bulk tuning dump putting each note `semitones` above its equal-tempered pitch.
+    fn bulk_dump(semitones: f64) -> Vec<u8> {
+        let mut message = vec![0xF0, 0x7E, 0x7F, 0x08, 0x01, 0x00];
+        message.extend_from_slice(b"Shifted tuning  ");
+        for note in 0..128 {
+            let fraction = (semitones * 16384.0) as u16;
+            message.extend_from_slice(&[note, (fraction >> 7) as u8, (fraction & 0x7F) as u8]);
+        }
+        let checksum = message[1..].iter().fold(0, |sum, byte| sum ^ byte);
+        message.extend_from_slice(&[checksum, 0xF7]);
+        message
+    }
+
+    #[test]
+    fn reads_cents_and_ratios() {
+        let text = "Pentatonic\n 5\n 200.0\n 3/2 fifth\n700.\n 5/3\n2\n";
+        let fifth = 1200.0 * 1.5f64.log2();
+        let sixth = 1200.0 * (5.0f64 / 3.0).log2();
+        let pitches: Vec<f64> = parse_scl(text).unwrap().pitches().collect();
+        assert!(close(&pitches, &[200.0, fifth, 700.0, sixth, 1200.0]));
+    }
+
+    #[test]
+    fn skips_comments_and_allows_an_empty_description() {
+        let text = "! quarter.scl\n!\n\n! a comment between lines\n 2\n!\n 600.0\n 2/1\n";
+        let pitches: Vec<f64> = parse_scl(text).unwrap().pitches().collect();
+        assert!(close(&pitches, &[600.0, 1200.0]));
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        for text in &[
+            "",
+            "No count",
+            "Bad count\nmany\n",
+            "Zero\n0\n",
+            "Too few\n3\n100.0\n2/1\n",
+            "Bad ratio\n1\n3/0\n",
+            "Bad cents\n1\n1.2.3\n",
+            "Negative ratio\n1\n-3/2\n",
+            "Nothing there\n1\n\n",
+            "Falling\n1\n-1200.0\n",
+            "Infinite\n1\n1.0e400\n",
+        ] {
+            assert!(parse_scl(text).is_none(), "{:?}", text);
+            assert!(Tuning::from_scl(text).is_none());
+        }
+        // Every step sensible, but too wide for the keyboard.
+        assert!(Tuning::from_scl("Wide\n1\n100000.0\n").is_none());
+    }
+
+    #[test]
+    fn default_is_equal_temperament() {
+        let tuning = Tuning::default();
+        for note in 0..128 {
+            assert_eq!(
+                tuning.frequency(note, A4_FREQ),
+                midi_pitch_to_freq(note, A4_FREQ)
+            );
+        }
+        assert_eq!(tuning.frequency(69, 432.0), 432.0);
+    }
+
+    #[test]
+    fn scales_repeat_at_their_period_from_middle_c() {
+        let text = "Just major\n7\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n";
+        let tuning = Tuning::from_scl(text).unwrap();
+        let c4 = midi_pitch_to_freq(60, A4_FREQ);
+        assert_eq!(tuning.frequency(60, A4_FREQ), c4);
+        assert!((tuning.frequency(62, A4_FREQ) / c4 - 5.0 / 4.0).abs() < 1e-12);
+        assert!((tuning.frequency(67, A4_FREQ) / c4 - 2.0).abs() < 1e-12);
+        assert!((tuning.frequency(59, A4_FREQ) / c4 - 15.0 / 16.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn scales_wider_than_the_keyboard_tune_every_key() {
+        let mut text = String::from("Many\n200\n");
+        for step in 1..=200 {
+            text.push_str(&format!("{}.0\n", step * 6));
+        }
+        let tuning = Tuning::from_scl(&text).unwrap();
+        let c4 = midi_pitch_to_freq(60, A4_FREQ);
+        assert!((tuning.frequency(61, A4_FREQ) / c4 - (6.0f64 / 1200.0).exp2()).abs() < 1e-12);
+        // Below middle C come the top degrees of the period below.
+        let b3 = (-6.0f64 / 1200.0).exp2();
+        assert!((tuning.frequency(59, A4_FREQ) / c4 - b3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn scales_arrive_by_sysex() {
+        let mut message = vec![0xF0, 0x7D, b'S', b'C', b'L'];
+        message.extend_from_slice(b"Half\n1\n600.0\n");
+        message.push(0xF7);
+        assert_eq!(sysex_scale(&message), Some("Half\n1\n600.0\n"));
+
+        // Another manufacturer's message, or one that isn't 7-bit, is left alone.
+        assert_eq!(sysex_scale(&[0xF0, 0x43, b'S', b'C', b'L', 0xF7]), None);
+        assert_eq!(
+            sysex_scale(&[0xF0, 0x7D, b'S', b'C', b'L', 0x80, 0xF7]),
+            None
+        );
+    }
+
+    #[test]
+    fn bulk_dumps_retune_every_note() {
+        let mut tuning = Tuning::from_scl("Half\n1\n600.0\n").unwrap();
+        assert!(tuning.apply_mts(&bulk_dump(0.25)));
+        for note in 0..128 {
+            let expected = midi_pitch_to_freq(note, A4_FREQ) * (0.25f64 / 12.0).exp2();
+            assert!((tuning.frequency(note, A4_FREQ) / expected - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn the_audio_thread_reports_what_sysex_changes() {
+        let (mut host, mut audio) = tuning_link();
+        let text = "Half\n1\n600.0\n";
+        assert!(audio.load_scale(text));
+        assert_eq!(host.source(), Some(text));
+
+        // A retuned scale is no longer the scale file, so there's nothing to save.
+        assert!(audio.apply_mts(&bulk_dump(0.25)));
+        assert_eq!(host.source(), None);
+
+        // Every slot the report goes through has room for a whole scale file.
+        for _ in 0..3 {
+            let reported = audio.to_host.slot();
+            assert!(reported.source.capacity() >= MAX_SCALE_LENGTH);
+            audio.report(text);
+        }
+
+        // Too long to keep without allocating.
+        let long = format!("Long\n1\n600.0\n!{}\n", "-".repeat(MAX_SCALE_LENGTH));
+        assert!(!audio.load_scale(&long));
+    }
+
+    #[test]
+    fn changes_made_before_a_load_arrives_are_dropped() {
+        let (mut host, mut audio) = tuning_link();
+        let loaded = Tuning::from_scl("Half\n1\n600.0\n").unwrap();
+        host.load(loaded, Some("Half\n1\n600.0\n".to_string()));
+        // The audio thread hasn't taken the load yet when this arrives.
+        assert!(audio.apply_mts(&bulk_dump(0.25)));
+        assert_eq!(host.source(), Some("Half\n1\n600.0\n"));
+        audio.update();
+        assert_eq!(*audio.tuning(), loaded);
+    }
+
+    #[test]
+    fn note_changes_retune_only_their_notes() {
+        let mut tuning = Tuning::default();
+        // A4 to half a semitone above A4, C4 to B3, and E4 left alone.
+        let message = [
+            0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 3, 69, 69, 0x40, 0x00, 60, 59, 0x00, 0x00, 64,
+            0x7F, 0x7F, 0x7F, 0xF7,
+        ];
+        assert!(tuning.apply_mts(&message));
+        assert!((tuning.frequency(69, A4_FREQ) - 440.0 * (0.5f64 / 12.0).exp2()).abs() < 1e-9);
+        assert_eq!(
+            tuning.frequency(60, A4_FREQ),
+            midi_pitch_to_freq(59, A4_FREQ)
+        );
+        assert_eq!(
+            tuning.frequency(64, A4_FREQ),
+            midi_pitch_to_freq(64, A4_FREQ)
+        );
+        assert_eq!(
+            tuning.frequency(61, A4_FREQ),
+            midi_pitch_to_freq(61, A4_FREQ)
+        );
+
+        // The bank form, sent either way.
+        for &id in &[0x7E, 0x7F] {
+            let mut tuning = Tuning::default();
+            let message = [
+                0xF0, id, 0x00, 0x08, 0x07, 0x01, 0x00, 1, 60, 61, 0, 0, 0xF7,
+            ];
+            assert!(tuning.apply_mts(&message));
+            assert_eq!(
+                tuning.frequency(60, A4_FREQ),
+                midi_pitch_to_freq(61, A4_FREQ)
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_tuning_messages_change_nothing() {
+        let dump = bulk_dump(0.5);
+        let change = [
+            0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 2, 60, 61, 0, 0, 62, 63, 0, 0, 0xF7,
+        ];
+        for message in &[&dump[..], &change[..]] {
+            // Cut short anywhere. Only the closing 0xF7 can go.
+            for length in 0..message.len() - 1 {
+                let mut tuning = Tuning::default();
+                assert!(!tuning.apply_mts(&message[..length]), "{}", length);
+                assert_eq!(tuning, Tuning::default());
+            }
+            let mut tuning = Tuning::default();
+            assert!(tuning.apply_mts(&message[..message.len() - 1]));
+        }
+
+        let mut bad_checksum = dump.clone();
+        bad_checksum[dump.len() - 2] ^= 1;
+        let mut bad_byte = change;
+        bad_byte[9] = 0x80;
+        let mut long_count = change;
+        long_count[6] = 3;
+        // A non-real-time single-note change isn't part of the standard.
+        let mut non_real_time = change;
+        non_real_time[1] = 0x7E;
+        for message in &[&bad_checksum[..], &bad_byte, &long_count, &non_real_time] {
+            let mut tuning = Tuning::default();
+            assert!(!tuning.apply_mts(message));
+            assert_eq!(tuning, Tuning::default());
+        }
+    }
+}
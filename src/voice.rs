@@ -0,0 +1,928 @@
+//! A single sounding note, and the settings every note shares.
+//!
+//! Nothing here knows about the host: a voice renders into plain slices from what
+//! `RenderContext` gives it, so it can be played and checked on its own.
+
+use std::iter;
+
+use crate::aftertouch::AftertouchDestination;
+use crate::denormal::flush;
+use crate::envelope::{AdsrSettings, Envelope, Stage};
+use crate::filter::{Coefficients, FilterMode, StateVariable};
+use crate::layer::OctaveLayer;
+use crate::mpe::{Expression, MpeChannels, NoteExpression, MAX_SLIDE_OCTAVES};
+use crate::oscillator::{poly_blep, Waveform};
+use crate::pluck::PluckedString;
+use crate::sine::{Lookup, Osc};
+use crate::smoother::SmoothedParam;
+use crate::tuning::Tuning;
+use crate::wavetable::TableMorph;
+use crate::{
+    db_to_gain, midi_pitch_to_freq, semitones_to_ratio, A4_FREQ, HARMONICS, KEY_TRACK_CENTRE,
+    MAX_AFTERTOUCH_CUTOFF, MAX_UNISON, TAU,
+};
+
+/// How many samples a voice renders at a time. A sine's samples across a chunk are worked out
+/// side by side in lanes, enough of them to fill whole vector registers.
+const CHUNK: usize = 8;
+
+/// How far a note's pitch moves, as a fraction of its frequency, before its level key tracking
+/// is worked out again. A hundredth of a semitone moves the level by at most 0.005 dB.
+const LEVEL_TRACK_TOLERANCE: f64 = 5.8e-4;
+
+/// Whether a sine renders in lanes. The `scalar` feature renders it a sample at a time
+/// instead, so the two can be timed against each other.
+const LANES: bool = cfg!(not(feature = "scalar"));
+
+/// Parameter values read once per block and shared by every voice.
+pub struct VoiceSettings {
+    pub sample_rate: f64,
+    /// A fresh smoother for a voice to follow its note's entry in the tuning table with, so a
+    /// change to a sounding note glides instead of jumping.
+    pub retune: SmoothedParam,
+    pub waveform: Waveform,
+    /// The table played when `waveform` is `Wavetable`.
+    pub table: TableMorph,
+    /// The level of each harmonic when `waveform` is `Additive`, scaled so they sum to at most 1.
+    pub harmonics: [f64; HARMONICS],
+    /// The amplitude envelope. Its attack is `Modulation::attack` instead, which is smoothed.
+    pub adsr: AdsrSettings,
+    pub velocity_sens: f64,
+    pub sub_level: f64,
+    pub sub_shape: Waveform,
+    pub octave_layer: OctaveLayer,
+    /// The level of each octave layer copy against the first oscillator.
+    pub layer_level: f64,
+    /// Frequency ratio of the second oscillator to the first.
+    pub osc2_ratio: f64,
+    /// Constant-power gains for the first and second oscillators.
+    pub osc1_gain: f64,
+    pub osc2_gain: f64,
+    /// A fixed second oscillator frequency in Hz, replacing `osc2_ratio` when set.
+    pub osc2_fixed: Option<f64>,
+    /// Blend from the oscillator mix (0.0) to the product of the two oscillators (1.0).
+    pub ring_mod: f64,
+    /// Hard-sync the second oscillator to the first and play it alone.
+    pub sync: bool,
+    /// How many copies of the first oscillator each note plays, from 1 to `MAX_UNISON`.
+    pub unison: usize,
+    /// Frequency ratio of each unison copy to the note's pitch; only the first `unison` are used.
+    pub unison_ratios: [f64; MAX_UNISON],
+    /// Gain applied to the summed unison copies so they never exceed a single oscillator.
+    pub unison_gain: f64,
+    /// Whether a sine may render in lanes, for checking them against the scalar path.
+    #[cfg(test)]
+    pub lanes: bool,
+    /// Frequency ratio of the right channel's first oscillator to the note; the left channel's
+    /// is its reciprocal. Exactly 1.0 at zero stereo width.
+    pub stereo_ratio: f64,
+    pub noise_level: f64,
+    /// How long a plucked string takes to decay by 60 dB, in seconds.
+    pub pluck_decay: f64,
+    /// Frequency ratio of the FM modulator to the note.
+    pub fm_ratio: f64,
+    /// The FM modulation index at full envelope level.
+    pub fm_index: f64,
+    pub filter_mode: FilterMode,
+    /// The resonance `Modulation::filter` was made from, for voices that move the cutoff.
+    pub filter_resonance: f64,
+    pub filter_adsr: AdsrSettings,
+    /// How far the filter envelope moves the cutoff at full level, in octaves.
+    pub filter_env_amount: f64,
+    /// How many octaves the cutoff moves per octave the note is above `KEY_TRACK_CENTRE`.
+    pub key_track: f64,
+    /// How many dB louder the note plays per octave it is above `KEY_TRACK_CENTRE`.
+    pub level_key_track: f64,
+    /// The vibrato's depth in cents at full mod wheel.
+    pub vibrato_depth: f64,
+    pub aftertouch: AftertouchDestination,
+    /// How far aftertouch moves its destination at full pressure, from 0.0 to 1.0.
+    pub aftertouch_amount: f64,
+    /// The fraction of the way a note's poly aftertouch eases to what was received each
+    /// sample.
+    pub pressure_smoothing: f64,
+    /// The same for MPE pressure and slide.
+    pub expression_smoothing: f64,
+}
+
+impl VoiceSettings {
+    /// The main oscillator's waveform at `phase`, reading the wavetable when one is selected.
+    fn oscillator(&self, phase: f64, increment: f64, pulse_width: f64) -> f64 {
+        match self.waveform {
+            Waveform::Wavetable => self.table.sample(phase, increment),
+            Waveform::Additive => self.additive(phase, increment),
+            waveform => waveform.sample(phase, increment, pulse_width),
+        }
+    }
+
+    /// The harmonics summed as sines locked to the fundamental's phase.
+    ///
+    /// Harmonics at or above Nyquist for this note are left out rather than folding back down.
+    fn additive(&self, phase: f64, increment: f64) -> f64 {
+        self.harmonics
+            .iter()
+            .enumerate()
+            .map(|(i, level)| ((i + 1) as f64, level))
+            .take_while(|&(harmonic, _)| harmonic * increment < 0.5)
+            .map(|(harmonic, level)| level * Lookup.sine(harmonic * phase))
+            .sum()
+    }
+
+    /// The first oscillator's unison copies summed at `increment`, moving each of `phases` on
+    /// by a sample.
+    fn unison<'a>(
+        &self,
+        phases: impl Iterator<Item = &'a mut f64>,
+        increment: f64,
+        fm_offset: f64,
+        pulse_width: f64,
+    ) -> f64 {
+        let mut signal = 0.0;
+        for (phase, ratio) in phases.zip(&self.unison_ratios[..self.unison]) {
+            let copy_increment = increment * ratio;
+            let modulated = (*phase + fm_offset).rem_euclid(1.0);
+            signal += self.oscillator(modulated, copy_increment, pulse_width);
+            *phase = (*phase + copy_increment).fract();
+        }
+        signal * self.unison_gain
+    }
+
+    /// Whether the first oscillator renders a chunk at a time in lanes.
+    fn in_lanes(&self) -> bool {
+        #[cfg(test)]
+        let allowed = self.lanes;
+        #[cfg(not(test))]
+        let allowed = true;
+        LANES && allowed && self.waveform == Waveform::Sine
+    }
+
+    /// `unison` for a sine over a chunk, with a sample in each lane. `elapsed` is how far the
+    /// note has moved at each sample since the chunk started, and `total` by its end.
+    ///
+    /// A copy's phase at each sample is worked out from where it started the chunk rather
+    /// than from the sample before, so no lane waits on another and the compiler can
+    /// vectorize them. The table wraps phases past the end of the cycle itself, so they are
+    /// left unwrapped until the chunk ends. Both of these round the last bits of a phase
+    /// differently from `unison`; nothing else differs. The lanes past the end of a short
+    /// chunk are rendered and left unused.
+    fn unison_lanes<'a>(
+        &self,
+        phases: impl Iterator<Item = &'a mut f64>,
+        elapsed: &[f64; CHUNK],
+        total: f64,
+        fm_offsets: &[f64; CHUNK],
+    ) -> [f64; CHUNK] {
+        let mut signal = [0.0; CHUNK];
+        for (phase, &ratio) in phases.zip(&self.unison_ratios[..self.unison]) {
+            let start = *phase;
+            let modulated = lanes(|t| start + ratio * elapsed[t] + fm_offsets[t]);
+            let sines = Lookup.sines(modulated);
+            for t in 0..CHUNK {
+                signal[t] += sines[t];
+            }
+            *phase = (start + ratio * total).fract();
+        }
+        lanes(|t| signal[t] * self.unison_gain)
+    }
+}
+
+/// The gain level key tracking gives a note, with what it was worked out from.
+#[derive(Clone, Copy, Debug)]
+pub struct LevelTrack {
+    increment: f64,
+    amount: f64,
+    gain: f64,
+}
+
+/// Whether the first oscillator's channels part at `settings`.
+///
+/// Width detunes the channels' first oscillators symmetrically rather than offsetting their
+/// phases, so summing them to mono beats slowly instead of cancelling for good. The string and
+/// the synced oscillator have nothing to detune and stay centred.
+fn is_stereo(settings: &VoiceSettings) -> bool {
+    settings.stereo_ratio != 1.0 && settings.waveform != Waveform::Pluck && !settings.sync
+}
+
+/// A lane for each sample of a chunk, each filled in by `value`.
+fn lanes(value: impl Fn(usize) -> f64) -> [f64; CHUNK] {
+    std::array::from_fn(value)
+}
+
+/// A sample of a voice as far as it can be worked out before its oscillators, one after
+/// another, so the oscillators can then render the whole chunk together.
+#[derive(Clone, Copy, Default)]
+struct Step {
+    /// Which of `RenderContext::modulation` the sample follows.
+    modulation: usize,
+    /// The gain aftertouch gives the note.
+    gain: f64,
+    /// How many octaves the note's own slide and pressure move its cutoff.
+    cutoff: f64,
+    /// How far through a cycle the note moves over the sample.
+    increment: f64,
+    /// `increment` for the left channel's first oscillator, which width detunes.
+    left_increment: f64,
+    /// The amplitude envelope's level.
+    level: f64,
+    /// How far FM shifts the first oscillator's phase.
+    fm_offset: f64,
+}
+
+/// What moves every voice alike, worked out a sample at a time at the host's rate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Modulation {
+    /// How far pitch bend, vibrato, the LFO and transposition move every note, as a frequency
+    /// ratio.
+    pub pitch_ratio: f64,
+    /// The smoothed mod wheel from 0.0 to 1.0.
+    pub mod_wheel: f64,
+    /// Where the vibrato is in its cycle, from -1.0 to 1.0. Aftertouch and MPE pressure
+    /// deepen it for their own notes.
+    pub vibrato_sine: f64,
+    /// The smoothed channel pressure from 0.0 to 1.0.
+    pub pressure: f64,
+    /// The frequency of A4 in Hz.
+    pub a4: f64,
+    /// The attack time in seconds.
+    pub attack: f64,
+    /// The filter, or `None` when it is a fully open low-pass and bypassed.
+    pub filter: Option<Coefficients>,
+    /// The cutoff `filter` was made from, for voices that move the cutoff.
+    pub filter_cutoff: f64,
+    /// How far the LFO moves the cutoff, in octaves.
+    pub lfo_cutoff: f64,
+    pub pulse_width: f64,
+    /// This sample of the synth's noise source.
+    pub noise: f64,
+}
+
+/// Everything a voice renders from besides its own note, the same for every voice.
+pub struct RenderContext<'a> {
+    pub settings: &'a VoiceSettings,
+    /// The frequency of each note, with A4 at `A4_FREQ`.
+    pub tuning: &'a Tuning,
+    /// The modulation for each of the host's samples being rendered.
+    pub modulation: &'a [Modulation],
+    /// How many samples a voice renders for each of `modulation`, more than one while
+    /// oversampling.
+    pub oversampling: usize,
+    /// The MPE expression on every channel as the samples start, or `None` without MPE.
+    pub mpe: Option<&'a MpeChannels>,
+}
+
+/// A single sounding note.
+///
+/// `phase` is the oscillator position within the current cycle, kept in `[0, 1)`;
+/// `osc2_phase` and `sub_phase` are the same for the second oscillator and the sub-oscillator,
+/// and `layer_phases` for the octave layer's copies above and below.
+#[derive(Clone, Copy, Default)]
+pub struct Voice {
+    /// The MIDI channel the note arrived on.
+    pub channel: u8,
+    /// The key whose chord the note is part of, which is the note itself without chord memory.
+    pub key: u8,
+    pub note: u8,
+    pub phase: f64,
+    /// Phases of the unison copies after the first, which uses `phase`.
+    pub unison_phases: [f64; MAX_UNISON - 1],
+    /// The right channel's `phase` followed by its `unison_phases`, which only part from the
+    /// left's while the stereo width is above zero.
+    pub right_phases: [f64; MAX_UNISON],
+    pub osc2_phase: f64,
+    /// The step in the second oscillator's waveform at its latest hard sync reset.
+    pub sync_jump: f64,
+    pub sub_phase: f64,
+    pub layer_phases: [f64; 2],
+    /// Phase of the FM modulator.
+    pub fm_phase: f64,
+    /// How far the pitch still is from `note` while gliding, in semitones; zero otherwise.
+    pub glide: f64,
+    /// How many semitones of `glide` are closed each sample.
+    pub glide_step: f64,
+    /// Poly aftertouch on this note's key from 0.0 to 1.0, as last received.
+    pub pressure: f64,
+    /// `pressure` eased over `PRESSURE_SMOOTHING_TIME`.
+    pub pressure_smoothed: f64,
+    /// The note's level key tracking, or `None` until its first sample with some.
+    pub level_track: Option<LevelTrack>,
+    /// Follows the frequency of `note` in the tuning table with A4 at `A4_FREQ`; `None` until
+    /// the note's first sample, so a new note starts in tune.
+    pub tuned: Option<SmoothedParam>,
+    /// The synth's note count when this voice's note started, for finding the oldest.
+    pub started: u64,
+    /// The note (channel, key and velocity) waiting for this voice to finish fading out after
+    /// being stolen from its last note.
+    pub stolen_by: Option<(u8, u8, f64)>,
+    /// The note's velocity from 0.0 to 1.0, after the velocity curve.
+    pub velocity: f64,
+    pub active: bool,
+    /// The key has been let go but the sustain pedal is keeping the note sounding.
+    pub held_by_pedal: bool,
+    pub envelope: Envelope,
+    /// Moves the filter cutoff over the course of the note.
+    pub filter_envelope: Envelope,
+    pub filter: StateVariable,
+    /// The filter for the right channel, which follows `filter` at zero stereo width.
+    pub right_filter: StateVariable,
+}
+
+impl Voice {
+    /// Render a left and right sample into each of `out`, advancing the voice as it goes,
+    /// and return how many it was still sounding for. Once the note has finished, the rest
+    /// are silent.
+    ///
+    /// `string` is this voice's string for the `Pluck` waveform.
+    pub fn render(
+        &mut self,
+        out: &mut [(f64, f64)],
+        context: &RenderContext,
+        string: &mut PluckedString,
+    ) -> usize {
+        let settings = context.settings;
+        let mut expression = context.mpe.map(|mpe| mpe.follow(self.channel));
+        let mut sounding = 0;
+        for (chunk, frames) in out.chunks_mut(CHUNK).enumerate() {
+            if !self.active {
+                frames.fill((0.0, 0.0));
+                continue;
+            }
+            // Everything up to the oscillators, a sample after another, until the chunk ends
+            // or the note does.
+            let mut steps = [Step::default(); CHUNK];
+            let mut count = 0;
+            while count < frames.len() {
+                let index = (chunk * CHUNK + count) / context.oversampling;
+                if let Some(expression) = expression.as_mut() {
+                    expression.ease(settings.expression_smoothing);
+                }
+                let expression = expression.as_ref().map(NoteExpression::expression);
+                let modulation = &context.modulation[index];
+                steps[count] = Step {
+                    modulation: index,
+                    ..self.step(settings, context.tuning, modulation, expression)
+                };
+                count += 1;
+                if !self.envelope.is_active() {
+                    break;
+                }
+            }
+
+            let (signals, rights, osc1) = self.first_oscillator(context, &steps[..count], string);
+            for t in 0..count {
+                let step = &steps[t];
+                let modulation = &context.modulation[step.modulation];
+                let (left, right) =
+                    self.finish_sample(settings, modulation, step, signals[t], rights[t], osc1[t]);
+                frames[t] = (left * step.gain, right * step.gain);
+            }
+            frames[count..].fill((0.0, 0.0));
+            sounding += count;
+        }
+        sounding
+    }
+
+    /// How the note's own pressure and expression move it at `modulation`: the frequency
+    /// ratio it plays at, the gain pressure gives it, and how many octaves they move its
+    /// cutoff. Its poly aftertouch eases on a sample.
+    ///
+    /// `expression` is the note's MPE expression, or `None` without MPE.
+    fn play(
+        &mut self,
+        settings: &VoiceSettings,
+        modulation: &Modulation,
+        expression: Option<Expression>,
+    ) -> (f64, f64, f64) {
+        self.pressure_smoothed = flush(
+            self.pressure_smoothed
+                + (self.pressure - self.pressure_smoothed) * settings.pressure_smoothing,
+        );
+        let amount = settings.aftertouch_amount;
+        // A key is pressed as hard as the harder of its own and the channel's pressure.
+        let pressure = amount * self.pressure_smoothed.max(modulation.pressure);
+        // At zero pressure every destination is exactly as it would be without it.
+        let mod_wheel = modulation.mod_wheel;
+        let mut wheel = mod_wheel;
+        let mut gain = 1.0;
+        let mut cutoff = 0.0;
+        match settings.aftertouch {
+            AftertouchDestination::None => (),
+            AftertouchDestination::Volume => gain = 1.0 - amount + pressure,
+            AftertouchDestination::Vibrato => wheel = (wheel + pressure).min(1.0),
+            AftertouchDestination::Cutoff => cutoff = pressure * MAX_AFTERTOUCH_CUTOFF,
+        }
+        // Whatever aftertouch adds to the mod wheel deepens this note's vibrato alone.
+        let (depth, sine) = (settings.vibrato_depth, modulation.vibrato_sine);
+        let mut cents = (wheel - mod_wheel) * depth * sine;
+        if let Some(expression) = expression {
+            // Pressure deepens this note's vibrato the way the mod wheel does every note's.
+            let pressure = (expression.pressure - wheel).max(0.0);
+            cents += expression.bend * 100.0 + pressure * depth * sine;
+            cutoff += expression.slide * MAX_SLIDE_OCTAVES;
+        }
+        (
+            modulation.pitch_ratio * (cents / 1200.0).exp2(),
+            gain,
+            cutoff,
+        )
+    }
+
+    /// Work out the next sample up to the oscillators, advancing the pitch, the amplitude
+    /// envelope and the FM modulator by one sample.
+    fn step(
+        &mut self,
+        settings: &VoiceSettings,
+        tuning: &Tuning,
+        modulation: &Modulation,
+        expression: Option<Expression>,
+    ) -> Step {
+        let (pitch_ratio, gain, cutoff) = self.play(settings, modulation, expression);
+        let tuned = self
+            .tuned
+            .get_or_insert(settings.retune)
+            .next(tuning.frequency(self.note, A4_FREQ));
+        let mut increment = tuned * (modulation.a4 / A4_FREQ) * pitch_ratio / settings.sample_rate;
+        if self.glide != 0.0 {
+            increment *= semitones_to_ratio(self.glide);
+            // Closing the same number of semitones every sample makes the slide sound even.
+            self.glide = if self.glide.abs() <= self.glide_step {
+                0.0
+            } else {
+                self.glide - self.glide_step.copysign(self.glide)
+            };
+        }
+        let adsr = AdsrSettings {
+            attack: modulation.attack,
+            ..settings.adsr
+        };
+        let level = self.envelope.next(&adsr, settings.sample_rate);
+
+        // The modulator shifts the first oscillator's phase. Its depth follows the envelope, so
+        // the spectrum darkens as the note decays instead of buzzing statically.
+        let fm_offset = settings.fm_index * level * Lookup.sine(self.fm_phase) / TAU;
+        self.fm_phase = (self.fm_phase + increment * settings.fm_ratio).fract();
+
+        let left_increment = if is_stereo(settings) {
+            increment / settings.stereo_ratio
+        } else {
+            increment
+        };
+        Step {
+            modulation: 0,
+            gain,
+            cutoff,
+            increment,
+            left_increment,
+            level,
+            fm_offset,
+        }
+    }
+
+    /// Render the first oscillator for each of `steps`: the left and right samples, with the
+    /// right `None` when it matches the left, and the first copy's phase and increment for
+    /// hard sync.
+    ///
+    /// `string` is this voice's string for the `Pluck` waveform.
+    #[allow(clippy::type_complexity)]
+    fn first_oscillator(
+        &mut self,
+        context: &RenderContext,
+        steps: &[Step],
+        string: &mut PluckedString,
+    ) -> ([f64; CHUNK], [Option<f64>; CHUNK], [(f64, f64); CHUNK]) {
+        let settings = context.settings;
+        let mut signals = [0.0; CHUNK];
+        let mut rights = [None; CHUNK];
+        let mut osc1 = [(0.0, 0.0); CHUNK];
+        let stereo = is_stereo(settings);
+        let ratio = settings.unison_ratios[0];
+        if settings.in_lanes() {
+            let mut elapsed = [0.0; CHUNK];
+            let mut fm_offsets = [0.0; CHUNK];
+            let mut total = 0.0;
+            for (t, step) in steps.iter().enumerate() {
+                elapsed[t] = total;
+                fm_offsets[t] = step.fm_offset;
+                osc1[t] = (
+                    (self.phase + ratio * total).fract(),
+                    step.left_increment * ratio,
+                );
+                total += step.left_increment;
+            }
+            let phases = iter::once(&mut self.phase).chain(self.unison_phases.iter_mut());
+            signals = settings.unison_lanes(phases, &elapsed, total, &fm_offsets);
+            if stereo {
+                // The right channel's increments are the left's at a fixed ratio.
+                let right_ratio = settings.stereo_ratio * settings.stereo_ratio;
+                let elapsed = lanes(|t| elapsed[t] * right_ratio);
+                let phases = self.right_phases.iter_mut();
+                let right =
+                    settings.unison_lanes(phases, &elapsed, total * right_ratio, &fm_offsets);
+                for t in 0..steps.len() {
+                    rights[t] = Some(right[t]);
+                }
+            }
+            return (signals, rights, osc1);
+        }
+
+        for (t, step) in steps.iter().enumerate() {
+            let pulse_width = context.modulation[step.modulation].pulse_width;
+            let copy_increment = step.left_increment * ratio;
+            osc1[t] = (self.phase, copy_increment);
+            signals[t] = if settings.waveform == Waveform::Pluck {
+                // The loop gain that decays by 60 dB over `pluck_decay`, one period at a time.
+                let feedback = 10f64
+                    .powf(-3.0 / (step.increment * settings.sample_rate * settings.pluck_decay));
+                self.phase = (self.phase + copy_increment).fract();
+                string.next(1.0 / step.increment, feedback)
+            } else {
+                let phases = iter::once(&mut self.phase).chain(self.unison_phases.iter_mut());
+                settings.unison(phases, step.left_increment, step.fm_offset, pulse_width)
+            };
+            if stereo {
+                let right_increment = step.increment * settings.stereo_ratio;
+                let phases = self.right_phases.iter_mut();
+                rights[t] =
+                    Some(settings.unison(phases, right_increment, step.fm_offset, pulse_width));
+            }
+        }
+        (signals, rights, osc1)
+    }
+
+    /// Finish a sample from the first oscillator's `signal` and `right`, adding everything
+    /// after it, and advance the rest of the voice by one sample.
+    ///
+    /// `osc1` is the first copy's phase and increment before the sample, for hard sync.
+    fn finish_sample(
+        &mut self,
+        settings: &VoiceSettings,
+        modulation: &Modulation,
+        step: &Step,
+        mut signal: f64,
+        mut right: Option<f64>,
+        osc1: (f64, f64),
+    ) -> (f64, f64) {
+        let (pulse_width, noise) = (modulation.pulse_width, modulation.noise);
+        let (increment, level) = (step.increment, step.level);
+        let (osc1_phase, osc1_increment) = osc1;
+        let per_sample = 1.0 / settings.sample_rate;
+        let osc2_increment = match settings.osc2_fixed {
+            Some(freq) => freq * per_sample,
+            None => increment * settings.osc2_ratio,
+        };
+        if settings.sync {
+            signal = self.synced_osc2(
+                settings,
+                osc1_phase,
+                osc1_increment,
+                osc2_increment,
+                pulse_width,
+            );
+        } else {
+            if settings.osc2_gain > 0.0 || settings.ring_mod > 0.0 {
+                let osc2 = settings.oscillator(self.osc2_phase, osc2_increment, pulse_width);
+                for signal in iter::once(&mut signal).chain(right.as_mut()) {
+                    let mix = *signal * settings.osc1_gain + osc2 * settings.osc2_gain;
+                    *signal = if settings.ring_mod > 0.0 {
+                        mix + (*signal * osc2 - mix) * settings.ring_mod
+                    } else {
+                        mix
+                    };
+                }
+            }
+            self.osc2_phase = (self.osc2_phase + osc2_increment).fract();
+        }
+        if settings.octave_layer != OctaveLayer::Off && settings.layer_level > 0.0 {
+            // The copies follow the bent and gliding pitch, and are scaled back down with the
+            // mix like the sub so the voice stays within ±1.0.
+            let mut layer = 0.0;
+            let mut copies = 0.0;
+            if settings.octave_layer.up() {
+                layer += settings.oscillator(self.layer_phases[0], increment * 2.0, pulse_width);
+                copies += 1.0;
+            }
+            if settings.octave_layer.down() {
+                layer += settings.oscillator(self.layer_phases[1], increment / 2.0, pulse_width);
+                copies += 1.0;
+            }
+            for signal in iter::once(&mut signal).chain(right.as_mut()) {
+                *signal = (*signal + settings.layer_level * layer)
+                    / (1.0 + settings.layer_level * copies);
+            }
+        }
+        if settings.sub_level > 0.0 {
+            let sub = settings
+                .sub_shape
+                .sample(self.sub_phase, increment / 2.0, 0.5);
+            // Scale the mix back down so a full-level sub doesn't push the voice past ±1.0.
+            for signal in iter::once(&mut signal).chain(right.as_mut()) {
+                *signal = (*signal + settings.sub_level * sub) / (1.0 + settings.sub_level);
+            }
+        }
+        if settings.noise_level > 0.0 {
+            for signal in iter::once(&mut signal).chain(right.as_mut()) {
+                *signal = (*signal + settings.noise_level * noise) / (1.0 + settings.noise_level);
+            }
+        }
+        let filter_level = self
+            .filter_envelope
+            .next(&settings.filter_adsr, settings.sample_rate);
+        // Slide and pressure brighten or darken a filter that's there, but don't bring one in.
+        let played = if modulation.filter.is_some() {
+            step.cutoff
+        } else {
+            0.0
+        };
+        let coefficients = if settings.filter_env_amount != 0.0
+            || settings.key_track != 0.0
+            || modulation.lfo_cutoff != 0.0
+            || played != 0.0
+        {
+            // Moving the cutoff by octaves makes the sweep sound even across its range. Key
+            // tracking follows the bent pitch, so bends don't change the tone.
+            let frequency = increment * settings.sample_rate;
+            let octaves = modulation.lfo_cutoff
+                + played
+                + settings.filter_env_amount * filter_level
+                + settings.key_track
+                    * (frequency / midi_pitch_to_freq(KEY_TRACK_CENTRE, modulation.a4)).log2();
+            let cutoff = modulation.filter_cutoff * octaves.exp2();
+            Some(Coefficients::new(
+                cutoff,
+                settings.filter_resonance,
+                settings.sample_rate,
+            ))
+        } else {
+            modulation.filter
+        };
+        if let Some(coefficients) = &coefficients {
+            signal = self
+                .filter
+                .process(signal, coefficients, settings.filter_mode);
+            if let Some(right) = right.as_mut() {
+                *right = self
+                    .right_filter
+                    .process(*right, coefficients, settings.filter_mode);
+            }
+        }
+        let mut alpha = level * self.velocity_gain(settings.velocity_sens);
+        if settings.level_key_track != 0.0 {
+            alpha *= self.level_track_gain(settings, increment, modulation.a4);
+        }
+
+        self.sub_phase = (self.sub_phase + increment / 2.0).fract();
+        self.layer_phases[0] = (self.layer_phases[0] + increment * 2.0).fract();
+        self.layer_phases[1] = (self.layer_phases[1] + increment / 2.0).fract();
+        self.active = self.envelope.is_active();
+
+        // At zero width the right channel keeps in step with the left, so widening it later
+        // spreads the two apart smoothly.
+        let right = match right {
+            Some(right) => right,
+            None => {
+                self.match_right_to_left();
+                self.right_filter = self.filter;
+                signal
+            }
+        };
+        (signal * alpha, right * alpha)
+    }
+
+    /// The gain level key tracking gives the note at `increment`, with A4 at `a4`.
+    ///
+    /// Like the cutoff's, this follows the bent and gliding pitch rather than the key. It is
+    /// only worked out again once the pitch has moved far enough to hear in the level, or the
+    /// amount has changed, so a steady note takes no logarithm or power per sample.
+    fn level_track_gain(&mut self, settings: &VoiceSettings, increment: f64, a4: f64) -> f64 {
+        let amount = settings.level_key_track;
+        if let Some(track) = self.level_track {
+            let moved = (increment / track.increment - 1.0).abs();
+            if track.amount == amount && moved < LEVEL_TRACK_TOLERANCE {
+                return track.gain;
+            }
+        }
+        let frequency = increment * settings.sample_rate;
+        let octaves = (frequency / midi_pitch_to_freq(KEY_TRACK_CENTRE, a4)).log2();
+        let gain = db_to_gain(amount * octaves);
+        self.level_track = Some(LevelTrack {
+            increment,
+            amount,
+            gain,
+        });
+        gain
+    }
+
+    /// Set the right channel's oscillator phases to the left's.
+    fn match_right_to_left(&mut self) {
+        self.right_phases[0] = self.phase;
+        self.right_phases[1..].copy_from_slice(&self.unison_phases);
+    }
+
+    /// Render the second oscillator hard-synced to the first, and advance it by one sample.
+    ///
+    /// Whenever the first oscillator wraps, the second restarts from the exact point within
+    /// the sample where the wrap fell. The step this makes in its waveform is smoothed with a
+    /// PolyBLEP timed from the first oscillator's phase, on the samples either side of it.
+    fn synced_osc2(
+        &mut self,
+        settings: &VoiceSettings,
+        osc1_phase: f64,
+        osc1_increment: f64,
+        osc2_increment: f64,
+        pulse_width: f64,
+    ) -> f64 {
+        let mut osc2 = settings.oscillator(self.osc2_phase, osc2_increment, pulse_width);
+        let samples_to_wrap = (1.0 - osc1_phase) / osc1_increment;
+        if samples_to_wrap <= 1.0 {
+            // The reset falls before the next sample, so the step can be known in advance.
+            let reset_phase = (self.osc2_phase + samples_to_wrap * osc2_increment).fract();
+            self.sync_jump = settings.waveform.sync_jump(reset_phase, pulse_width);
+            self.osc2_phase = ((1.0 - samples_to_wrap) * osc2_increment).fract();
+        } else {
+            self.osc2_phase = (self.osc2_phase + osc2_increment).fract();
+        }
+        osc2 += 0.5 * self.sync_jump * poly_blep(osc1_phase, osc1_increment.min(0.5));
+        osc2
+    }
+
+    /// The level for this note's velocity.
+    ///
+    /// `sensitivity` blends between a fixed level (0.0) and scaling linearly with velocity (1.0).
+    /// Full velocity always plays at full level.
+    fn velocity_gain(&self, sensitivity: f64) -> f64 {
+        1.0 - sensitivity * (1.0 - self.velocity)
+    }
+
+    /// Start every oscillator of a new note from the beginning of its cycle.
+    ///
+    /// The unison copies start at fixed, evenly scattered points instead, so they don't all
+    /// line up on the first cycle but every render of the same notes is identical.
+    pub fn reset_phases(&mut self) {
+        self.phase = 0.0;
+        for (copy, phase) in self.unison_phases.iter_mut().enumerate() {
+            // Successive multiples of the golden ratio fill the cycle evenly.
+            *phase = ((copy + 1) as f64 * 0.618_033_988_749_895).fract();
+        }
+        self.match_right_to_left();
+        self.osc2_phase = 0.0;
+        self.sync_jump = 0.0;
+        self.sub_phase = 0.0;
+        self.layer_phases = [0.0; 2];
+        self.fm_phase = 0.0;
+    }
+
+    /// The pitch the voice is playing right now in semitones, before bends and modulation.
+    pub fn pitch(&self) -> f64 {
+        f64::from(self.note) + self.glide
+    }
+
+    /// Slide to `note` from the pitch `from` over `samples` samples, or go straight there if
+    /// there is nothing to slide from or no time to do it in.
+    pub fn start_glide(&mut self, from: Option<f64>, samples: f64) {
+        self.tuned = None;
+        match from {
+            Some(from) if samples > 0.0 => {
+                self.glide = from - f64::from(self.note);
+                self.glide_step = self.glide.abs() / samples;
+            }
+            _ => self.glide = 0.0,
+        }
+    }
+
+    /// Move the first oscillator and its unison copies on by `offset` of a cycle.
+    pub fn offset_phases(&mut self, offset: f64) {
+        self.phase = (self.phase + offset).fract();
+        for phase in self.unison_phases.iter_mut() {
+            *phase = (*phase + offset).fract();
+        }
+        self.match_right_to_left();
+    }
+
+    /// Start (or restart) both envelopes.
+    pub fn trigger(&mut self) {
+        self.envelope.trigger();
+        self.filter_envelope.trigger();
+    }
+
+    /// Let go of the note; both envelopes move to their release.
+    pub fn release(&mut self) {
+        self.envelope.release();
+        self.filter_envelope.release();
+    }
+
+    /// Fade the note out almost immediately.
+    ///
+    /// Only the amplitude envelope needs to be cut short; the filter carries on releasing
+    /// underneath it.
+    pub fn kill(&mut self) {
+        self.envelope.kill();
+        self.filter_envelope.release();
+    }
+
+    /// Whether the note has been let go; the voice keeps sounding until the release finishes.
+    pub fn is_released(&self) -> bool {
+        self.envelope.stage() == Stage::Release
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Modulation, RenderContext, Voice, VoiceSettings};
+    use crate::analysis::{cents_between, measure_frequency};
+    use crate::envelope::EnvCurve;
+    use crate::pluck::PluckedString;
+    use crate::tuning::Tuning;
+    use crate::{GainEffectParameters, A4_FREQ};
+
+    fn settings() -> VoiceSettings {
+        let mut settings = GainEffectParameters::default().voice_settings(44100.0);
+        settings.adsr.attack = 0.01;
+        settings.adsr.curve = EnvCurve::Linear;
+        settings
+    }
+
+    fn playing(note: u8) -> Voice {
+        let mut voice = Voice {
+            note,
+            velocity: 1.0,
+            active: true,
+            ..Voice::default()
+        };
+        voice.reset_phases();
+        voice.trigger();
+        voice
+    }
+
+    /// The left channel of `samples` samples of `voice`, with nothing modulating it.
+    fn render(voice: &mut Voice, settings: &VoiceSettings, samples: usize) -> Vec<f64> {
+        let modulation = Modulation {
+            pitch_ratio: 1.0,
+            a4: A4_FREQ,
+            attack: settings.adsr.attack,
+            pulse_width: 0.5,
+            ..Modulation::default()
+        };
+        let modulation = vec![modulation; samples];
+        let context = RenderContext {
+            settings,
+            tuning: &Tuning::default(),
+            modulation: &modulation,
+            oversampling: 1,
+            mpe: None,
+        };
+        let mut out = vec![(0.0, 0.0); samples];
+        voice.render(&mut out, &context, &mut PluckedString::default());
+        out.iter().map(|&(left, _)| left).collect()
+    }
+
+    #[test]
+    fn attack_ramps_up_in_a_straight_line() {
+        let settings = settings();
+        let mut voice = playing(69);
+        // 10 ms at 44.1 kHz.
+        let out = render(&mut voice, &settings, 441);
+        for (i, sample) in out.iter().enumerate() {
+            let ramp = (i + 1) as f64 / 441.0;
+            assert!(
+                sample.abs() <= ramp + 1e-9,
+                "{} past {} at {}",
+                sample,
+                ramp,
+                i
+            );
+        }
+        let peak = |samples: &[f64]| samples.iter().fold(0.0f64, |m, s| m.max(s.abs()));
+        // The last cycle of the ramp peaks close to its level, halfway through only half as high.
+        assert!(peak(&out[341..]) > 0.75);
+        assert!((peak(&out[120..220]) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn notes_play_in_tune() {
+        let settings = settings();
+        for &(note, freq) in &[(69, 440.0), (57, 220.0), (81, 880.0)] {
+            let mut voice = playing(note);
+            let out: Vec<f32> = render(&mut voice, &settings, 44100)
+                .iter()
+                .map(|&s| s as f32)
+                .collect();
+            let measured = measure_frequency(&out[4410..], 44100.0);
+            assert!(cents_between(measured, freq).abs() < 0.1, "{}", measured);
+        }
+    }
+
+    #[test]
+    fn silent_without_a_note() {
+        let settings = settings();
+        let mut voice = Voice::default();
+        assert!(render(&mut voice, &settings, 512).iter().all(|&s| s == 0.0));
+
+        // A note that finishes part way through leaves the rest of the block silent.
+        let mut voice = playing(69);
+        render(&mut voice, &settings, 441);
+        voice.kill();
+        let out = render(&mut voice, &settings, 4410);
+        assert!(out[..10].iter().any(|&s| s != 0.0));
+        assert!(!voice.active);
+        assert!(out[4000..].iter().all(|&s| s == 0.0));
+    }
+}
@@ -0,0 +1,90 @@
+//! Choosing which voice a new note takes over when every voice is busy.
+
+use crate::params::options;
+
+options! {
+    pub enum StealPolicy {
+        /// Take over the voice whose note started first.
+        Oldest => "Oldest",
+        /// Take over the voice whose envelope is lowest right now.
+        Quietest => "Quietest",
+        /// Drop the new note, unless the synth is down to one voice; see `SineSynth::steal_voice`.
+        Never => "None",
+    }
+}
+
+/// A busy voice that could be taken over.
+#[derive(Clone, Copy, Debug)]
+pub struct Candidate {
+    pub index: usize,
+    /// When the voice's note started, counted in notes; lower is older.
+    pub started: u64,
+    /// The voice's current envelope level.
+    pub level: f64,
+}
+
+/// The index of the voice `policy` takes over, or `None` if the new note should be dropped.
+///
+/// Ties go to the older voice.
+pub fn pick_victim(
+    policy: StealPolicy,
+    candidates: impl Iterator<Item = Candidate>,
+) -> Option<usize> {
+    let victim = match policy {
+        StealPolicy::Oldest => candidates.min_by_key(|c| c.started),
+        StealPolicy::Quietest => candidates.min_by(|a, b| {
+            a.level
+                .partial_cmp(&b.level)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.started.cmp(&b.started))
+        }),
+        StealPolicy::Never => None,
+    };
+    victim.map(|c| c.index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pick_victim, Candidate, StealPolicy};
+
+    fn candidates() -> Vec<Candidate> {
+        vec![
+            Candidate {
+                index: 0,
+                started: 7,
+                level: 0.2,
+            },
+            Candidate {
+                index: 1,
+                started: 3,
+                level: 0.9,
+            },
+            Candidate {
+                index: 2,
+                started: 5,
+                level: 0.2,
+            },
+        ]
+    }
+
+    #[test]
+    fn policies_pick_their_victim() {
+        let pick = |policy| pick_victim(policy, candidates().into_iter());
+        assert_eq!(pick(StealPolicy::Oldest), Some(1));
+        // Voices 0 and 2 are equally quiet, and 2 is older.
+        assert_eq!(pick(StealPolicy::Quietest), Some(2));
+        assert_eq!(pick(StealPolicy::Never), None);
+    }
+
+    #[test]
+    fn nothing_to_steal_from_no_voices() {
+        assert_eq!(pick_victim(StealPolicy::Oldest, std::iter::empty()), None);
+    }
+
+    #[test]
+    fn policies_round_trip() {
+        for &policy in StealPolicy::ALL.iter() {
+            assert_eq!(StealPolicy::from_normalized(policy.to_normalized()), policy);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Single-cycle wavetables, band-limited per octave.
+//!
+//! Each built-in table is stored as the amplitudes of its harmonics. When the tables are first
+//! used they are rendered into one cycle per octave, each holding only the harmonics that stay
+//! below Nyquist for the notes that octave plays, so high notes don't alias.
+
+use std::sync::OnceLock;
+
+use crate::sine::{Exact, Osc};
+
+/// Samples in one cycle of a table; a power of two so indices can wrap with a mask.
+const TABLE_SIZE: usize = 2048;
+
+/// The most harmonics any built-in table has.
+const MAX_HARMONICS: usize = 64;
+
+/// One table per octave, from all `MAX_HARMONICS` harmonics down to the fundamental alone.
+const LEVELS: usize = 7;
+
+/// A built-in table, described by the amplitude of each harmonic from the fundamental up.
+pub struct TableSpec {
+    pub name: &'static str,
+    harmonics: &'static [f64],
+}
+
+pub const TABLES: [TableSpec; 5] = [
+    TableSpec {
+        name: "Sine",
+        harmonics: &[1.0],
+    },
+    TableSpec {
+        name: "Organ",
+        harmonics: &[1.0, 0.8, 0.6, 0.5, 0.0, 0.4, 0.0, 0.3],
+    },
+    TableSpec {
+        name: "Hollow",
+        harmonics: &[
+            1.0, 0.0, 0.333, 0.0, 0.2, 0.0, 0.143, 0.0, 0.111, 0.0, 0.091, 0.0, 0.077, 0.0, 0.067,
+        ],
+    },
+    TableSpec {
+        name: "Bright",
+        harmonics: &[
+            1.0, 0.5, 0.333, 0.25, 0.2, 0.167, 0.143, 0.125, 0.111, 0.1, 0.091, 0.083, 0.077,
+            0.071, 0.067, 0.063, 0.059, 0.056, 0.053, 0.05, 0.048, 0.045, 0.043, 0.042, 0.04,
+            0.038, 0.037, 0.036, 0.034, 0.033, 0.032, 0.031,
+        ],
+    },
+    // Peaks around the second and ninth harmonics, like the first two formants of an "ah".
+    TableSpec {
+        name: "Vocal",
+        harmonics: &[
+            0.4, 0.9, 1.0, 0.7, 0.4, 0.25, 0.35, 0.6, 0.7, 0.5, 0.3, 0.15, 0.1, 0.12, 0.15, 0.1,
+            0.05, 0.03,
+        ],
+    },
+];
+
+/// The rendered cycles, indexed by table and then by octave level.
+struct Tables {
+    cycles: Vec<Vec<Vec<f32>>>,
+}
+
+static TABLES_CELL: OnceLock<Tables> = OnceLock::new();
+
+/// The rendered tables, built the first time they are asked for.
+fn tables() -> &'static Tables {
+    TABLES_CELL.get_or_init(|| Tables {
+        cycles: TABLES.iter().map(render_levels).collect(),
+    })
+}
+
+/// Render the tables now, so it doesn't happen on the audio thread the first time one plays.
+pub fn prepare() {
+    tables();
+}
+
+/// Render every octave level of a table, all scaled by the same amount so that the full
+/// table peaks at 1.0 and the level doesn't change from one octave to the next.
+fn render_levels(spec: &TableSpec) -> Vec<Vec<f32>> {
+    // The true peak can fall between samples, where interpolation would overshoot it, so it
+    // is found on a finer grid than the table's.
+    let peak = render_cycle(spec.harmonics, 8 * TABLE_SIZE)
+        .iter()
+        .fold(0.0f64, |m, s| m.max(s.abs()));
+    (0..LEVELS)
+        .map(|level| {
+            let harmonics = &spec.harmonics[..spec.harmonics.len().min(MAX_HARMONICS >> level)];
+            render_cycle(harmonics, TABLE_SIZE)
+                .iter()
+                .map(|s| (s / peak) as f32)
+                .collect()
+        })
+        .collect()
+}
+
+fn render_cycle(harmonics: &[f64], samples: usize) -> Vec<f64> {
+    (0..samples)
+        .map(|i| {
+            let phase = i as f64 / samples as f64;
+            harmonics
+                .iter()
+                .enumerate()
+                .map(|(h, amplitude)| amplitude * Exact.sine((h + 1) as f64 * phase))
+                .sum()
+        })
+        .collect()
+}
+
+/// The octave level to play at `increment`, the one with the most harmonics that all stay
+/// below Nyquist.
+fn level_for(increment: f64) -> usize {
+    let below_nyquist = 0.5 / increment.max(f64::MIN_POSITIVE);
+    let mut level = 0;
+    while level + 1 < LEVELS && (MAX_HARMONICS >> level) as f64 > below_nyquist {
+        level += 1;
+    }
+    level
+}
+
+/// Read a cycle at `phase` with cubic (Catmull-Rom) interpolation between its samples.
+fn read(cycle: &[f32], phase: f64) -> f64 {
+    let position = phase * TABLE_SIZE as f64;
+    let index = position.floor() as usize;
+    let t = position - index as f64;
+    let at = |offset: usize| f64::from(cycle[(index + offset) & (TABLE_SIZE - 1)]);
+    let (y0, y1, y2, y3) = (at(TABLE_SIZE - 1), at(0), at(1), at(2));
+    y1 + 0.5
+        * t
+        * (y2 - y0 + t * (2.0 * y0 - 5.0 * y1 + 4.0 * y2 - y3 + t * (3.0 * (y1 - y2) + y3 - y0)))
+}
+
+/// A table selection, crossfading from `table` towards the next one as `position` goes from 0
+/// to 1. The last table crossfades back to the first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TableMorph {
+    pub table: usize,
+    pub position: f64,
+}
+
+impl TableMorph {
+    /// The value of the morphed table at `phase`, for a note advancing `increment` per sample.
+    pub fn sample(&self, phase: f64, increment: f64) -> f64 {
+        let cycles = &tables().cycles;
+        let level = level_for(increment);
+        let from = read(&cycles[self.table][level], phase);
+        if self.position <= 0.0 {
+            return from;
+        }
+        let to = read(&cycles[(self.table + 1) % cycles.len()][level], phase);
+        from + (to - from) * self.position.min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{level_for, TableMorph, MAX_HARMONICS, TABLES};
+    use crate::TAU;
+
+    #[test]
+    fn sine_table_matches_sine() {
+        let sine = TableMorph {
+            table: 0,
+            position: 0.0,
+        };
+        for i in 0..10_000 {
+            let phase = i as f64 / 10_000.0;
+            let error = sine.sample(phase, 0.001) - (phase * TAU).sin();
+            assert!(error.abs() < 1e-6, "{} at {}", error, phase);
+        }
+    }
+
+    #[test]
+    fn morphing_stays_in_range() {
+        for table in 0..TABLES.len() {
+            for step in 0..=10 {
+                let morph = TableMorph {
+                    table,
+                    position: f64::from(step) / 10.0,
+                };
+                for i in 0..1000 {
+                    let sample = morph.sample(i as f64 / 1000.0, 0.001);
+                    assert!(sample.is_finite() && sample.abs() <= 1.0, "{:?}", morph);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn levels_keep_harmonics_below_nyquist() {
+        for &increment in &[0.0001, 0.004, 0.01, 0.03, 0.1, 0.2, 0.4] {
+            let harmonics = (MAX_HARMONICS >> level_for(increment)) as f64;
+            assert!(harmonics * increment < 0.5, "{}", increment);
+        }
+        assert_eq!(level_for(0.001), 0);
+    }
+}
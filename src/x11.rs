@@ -0,0 +1,257 @@
+//! Just enough of Xlib to show the editor: a child window inside the one the host gives,
+//! an image to copy the editor's pixels into, and the mouse events over it.
+//!
+//! The window has its own connection to the X server, so it never touches the host's.
+
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+use std::ptr;
+
+enum Display {}
+enum Visual {}
+enum XImage {}
+type Gc = *mut c_void;
+type XWindow = c_ulong;
+
+/// An `XEvent`, which is a union the size of 24 longs.
+#[repr(C)]
+struct XEvent {
+    data: [c_long; 24],
+}
+
+/// The start of `XButtonEvent`, which `XMotionEvent` shares up to `state`.
+#[repr(C)]
+struct XButtonEvent {
+    kind: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut Display,
+    window: XWindow,
+    root: XWindow,
+    subwindow: XWindow,
+    time: c_ulong,
+    x: c_int,
+    y: c_int,
+    x_root: c_int,
+    y_root: c_int,
+    state: c_uint,
+    button: c_uint,
+}
+
+const BUTTON_PRESS: c_int = 4;
+const BUTTON_RELEASE: c_int = 5;
+const MOTION_NOTIFY: c_int = 6;
+const EXPOSE: c_int = 12;
+
+const BUTTON_PRESS_MASK: c_long = 1 << 2;
+const BUTTON_RELEASE_MASK: c_long = 1 << 3;
+const BUTTON1_MOTION_MASK: c_long = 1 << 8;
+const EXPOSURE_MASK: c_long = 1 << 15;
+
+const Z_PIXMAP: c_int = 2;
+
+/// The left mouse button.
+const BUTTON1: c_uint = 1;
+
+#[link(name = "X11")]
+extern "C" {
+    fn XOpenDisplay(name: *const c_char) -> *mut Display;
+    fn XCloseDisplay(display: *mut Display) -> c_int;
+    fn XDefaultScreen(display: *mut Display) -> c_int;
+    fn XDefaultVisual(display: *mut Display, screen: c_int) -> *mut Visual;
+    fn XDefaultDepth(display: *mut Display, screen: c_int) -> c_int;
+    fn XCreateSimpleWindow(
+        display: *mut Display,
+        parent: XWindow,
+        x: c_int,
+        y: c_int,
+        width: c_uint,
+        height: c_uint,
+        border_width: c_uint,
+        border: c_ulong,
+        background: c_ulong,
+    ) -> XWindow;
+    fn XDestroyWindow(display: *mut Display, window: XWindow) -> c_int;
+    fn XSelectInput(display: *mut Display, window: XWindow, mask: c_long) -> c_int;
+    fn XMapWindow(display: *mut Display, window: XWindow) -> c_int;
+    fn XCreateGC(
+        display: *mut Display,
+        drawable: XWindow,
+        mask: c_ulong,
+        values: *mut c_void,
+    ) -> Gc;
+    fn XFreeGC(display: *mut Display, gc: Gc) -> c_int;
+    fn XCreateImage(
+        display: *mut Display,
+        visual: *mut Visual,
+        depth: c_uint,
+        format: c_int,
+        offset: c_int,
+        data: *mut c_char,
+        width: c_uint,
+        height: c_uint,
+        bitmap_pad: c_int,
+        bytes_per_line: c_int,
+    ) -> *mut XImage;
+    fn XPutImage(
+        display: *mut Display,
+        drawable: XWindow,
+        gc: Gc,
+        image: *mut XImage,
+        src_x: c_int,
+        src_y: c_int,
+        dest_x: c_int,
+        dest_y: c_int,
+        width: c_uint,
+        height: c_uint,
+    ) -> c_int;
+    fn XFree(data: *mut c_void) -> c_int;
+    fn XPending(display: *mut Display) -> c_int;
+    fn XNextEvent(display: *mut Display, event: *mut XEvent) -> c_int;
+    fn XFlush(display: *mut Display) -> c_int;
+}
+
+/// What the mouse did over the window, in pixels from its top left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pointer {
+    Press(i32, i32),
+    Drag(i32, i32),
+    Release,
+}
+
+/// A window showing a fixed-size image of 32-bit `0xRRGGBB` pixels.
+pub struct Window {
+    display: *mut Display,
+    window: XWindow,
+    gc: Gc,
+    image: *mut XImage,
+    /// The pixels the image shows, which it points into; never resized while it exists.
+    pixels: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl Window {
+    /// Open a window of `width` by `height` inside the X window `parent`, or `None` if there's
+    /// no X server or its screen doesn't take 24-bit colour.
+    pub fn open(parent: usize, width: usize, height: usize) -> Option<Window> {
+        // SAFETY: Each call gets the display it was opened with and objects made on it, and
+        // everything made is destroyed before returning on failure.
+        unsafe {
+            let display = XOpenDisplay(ptr::null());
+            if display.is_null() {
+                return None;
+            }
+            let screen = XDefaultScreen(display);
+            if XDefaultDepth(display, screen) != 24 {
+                XCloseDisplay(display);
+                return None;
+            }
+            let window = XCreateSimpleWindow(
+                display,
+                parent as XWindow,
+                0,
+                0,
+                width as c_uint,
+                height as c_uint,
+                0,
+                0,
+                0,
+            );
+            XSelectInput(
+                display,
+                window,
+                EXPOSURE_MASK | BUTTON_PRESS_MASK | BUTTON_RELEASE_MASK | BUTTON1_MOTION_MASK,
+            );
+            let gc = XCreateGC(display, window, 0, ptr::null_mut());
+            let mut pixels = vec![0; width * height];
+            let image = XCreateImage(
+                display,
+                XDefaultVisual(display, screen),
+                24,
+                Z_PIXMAP,
+                0,
+                pixels.as_mut_ptr() as *mut c_char,
+                width as c_uint,
+                height as c_uint,
+                32,
+                0,
+            );
+            if image.is_null() {
+                XFreeGC(display, gc);
+                XDestroyWindow(display, window);
+                XCloseDisplay(display);
+                return None;
+            }
+            XMapWindow(display, window);
+            XFlush(display);
+            Some(Window {
+                display,
+                window,
+                gc,
+                image,
+                pixels,
+                width,
+                height,
+            })
+        }
+    }
+
+    /// Copy `pixels` into the window.
+    pub fn show(&mut self, pixels: &[u32]) {
+        self.pixels.copy_from_slice(pixels);
+        // SAFETY: The image points at `self.pixels`, which is the size it was made for.
+        unsafe {
+            XPutImage(
+                self.display,
+                self.window,
+                self.gc,
+                self.image,
+                0,
+                0,
+                0,
+                0,
+                self.width as c_uint,
+                self.height as c_uint,
+            );
+            XFlush(self.display);
+        }
+    }
+
+    /// Hand every mouse event waiting to `pointer`, returning whether the window needs
+    /// drawing again.
+    pub fn poll(&mut self, mut pointer: impl FnMut(Pointer)) -> bool {
+        let mut exposed = false;
+        // SAFETY: `XNextEvent` fills in a whole `XEvent`, and the button and motion events
+        // start with the fields `XButtonEvent` declares.
+        unsafe {
+            while XPending(self.display) > 0 {
+                let mut event = XEvent { data: [0; 24] };
+                XNextEvent(self.display, &mut event);
+                let button = &*(&event as *const XEvent as *const XButtonEvent);
+                match button.kind {
+                    BUTTON_PRESS if button.button == BUTTON1 => {
+                        pointer(Pointer::Press(button.x, button.y))
+                    }
+                    BUTTON_RELEASE if button.button == BUTTON1 => pointer(Pointer::Release),
+                    MOTION_NOTIFY => pointer(Pointer::Drag(button.x, button.y)),
+                    EXPOSE => exposed = true,
+                    _ => (),
+                }
+            }
+        }
+        exposed
+    }
+}
+
+impl Drop for Window {
+    fn drop(&mut self) {
+        // SAFETY: Everything here was made on this display and is freed once. `XFree` frees
+        // only the image's own structure, leaving the pixels to the `Vec` that owns them.
+        unsafe {
+            XFree(self.image as *mut c_void);
+            XFreeGC(self.display, self.gc);
+            XDestroyWindow(self.display, self.window);
+            XCloseDisplay(self.display);
+        }
+    }
+}
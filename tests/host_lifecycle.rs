@@ -0,0 +1,222 @@
+//! Drives the built plugin through the VST interface as a host does, across the lifecycle
+//! boundaries the tests inside the crate can't reach: loading it from its library, suspending
+//! and resuming at new sample rates, saving and loading its state part way through, and
+//! setting parameters from another thread while it renders.
+//!
+//! Cargo builds the plugin's dynamic library next to the test binaries, so it is loaded from
+//! there like any other plugin. The library also builds as an `rlib` only so that Cargo
+//! rebuilds it before these tests; nothing here links against it.
+
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use vst::buffer::SendEventBuffer;
+use vst::event::MidiEvent;
+use vst::host::{Host, HostBuffer, PluginInstance, PluginLoader};
+use vst::plugin::{Category, Plugin, PluginParameters};
+
+/// The largest block the host sends.
+const BLOCK_SIZE: usize = 512;
+
+/// The loudest any output sample may be.
+const MAX_LEVEL: f32 = 4.0;
+
+/// A host that answers every question with the defaults.
+struct TestHost;
+
+impl Host for TestHost {}
+
+/// Loading runs through a global in the vst crate, so only one plugin loads at a time.
+static LOADING: Mutex<()> = Mutex::new(());
+
+/// Load a fresh instance of the plugin.
+fn load() -> PluginInstance {
+    let _loading = LOADING.lock().unwrap();
+    let mut path = PathBuf::from(std::env::current_exe().unwrap().parent().unwrap());
+    path.push(format!("{}vsttest{}", DLL_PREFIX, DLL_SUFFIX));
+    let host = Arc::new(Mutex::new(TestHost));
+    let mut loader = PluginLoader::load(&path, host)
+        .unwrap_or_else(|_| panic!("couldn't load the plugin from {}", path.display()));
+    loader.instance().unwrap()
+}
+
+/// The parameter object, to hand to another thread.
+struct Knobs(Arc<dyn PluginParameters>);
+
+// SAFETY: The parameters are `Sync`, and a host calls them from whatever thread it likes,
+// which is what sending them checks; the vst crate's trait object only leaves `Send` off.
+unsafe impl Send for Knobs {}
+
+/// A small deterministic generator, so a failure repeats exactly.
+struct Random(u32);
+
+impl Random {
+    fn below(&mut self, end: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        ((u64::from(self.0) * end as u64) >> 32) as usize
+    }
+}
+
+/// The plugin with the buffers a host keeps for it.
+struct Session {
+    plugin: PluginInstance,
+    host_buffer: HostBuffer<f32>,
+    events: SendEventBuffer,
+    inputs: Vec<Vec<f32>>,
+    outputs: Vec<Vec<f32>>,
+    random: Random,
+}
+
+impl Session {
+    fn new(plugin: PluginInstance) -> Session {
+        let info = plugin.get_info();
+        Session {
+            host_buffer: HostBuffer::from_info(&info),
+            events: SendEventBuffer::new(64),
+            inputs: vec![vec![0.0; BLOCK_SIZE]; info.inputs as usize],
+            outputs: vec![vec![0.0; BLOCK_SIZE]; info.outputs as usize],
+            random: Random(0x2545_F491),
+            plugin,
+        }
+    }
+
+    /// Stop, switch to `rate` and start again, as a host does when its settings change.
+    fn restart(&mut self, rate: f32) {
+        self.plugin.suspend();
+        self.plugin.set_sample_rate(rate);
+        self.plugin.set_block_size(BLOCK_SIZE as i64);
+        self.plugin.resume();
+    }
+
+    /// Play `blocks` blocks of random sizes, each with a few random notes, bends and mod
+    /// wheel moves, checking every output sample.
+    fn play(&mut self, blocks: usize) {
+        for _ in 0..blocks {
+            let samples = match self.random.below(8) {
+                0 => 0,
+                1 => 1,
+                _ => self.random.below(BLOCK_SIZE + 1),
+            };
+            let mut events = Vec::new();
+            while self.random.below(3) == 0 {
+                let channel = self.random.below(2) as u8;
+                let status = [0x80, 0x90, 0x90, 0xB0, 0xE0][self.random.below(5)];
+                let first = match status {
+                    // The mod wheel, so nothing else is switched by a controller.
+                    0xB0 => 1,
+                    _ => 36 + self.random.below(48) as u8,
+                };
+                events.push(MidiEvent {
+                    data: [status | channel, first, self.random.below(128) as u8],
+                    delta_frames: self.random.below(samples.max(1)) as i32,
+                    live: true,
+                    note_length: None,
+                    note_offset: None,
+                    detune: 0,
+                    note_off_velocity: 0,
+                });
+            }
+            events.sort_by_key(|event| event.delta_frames);
+            self.events.send_events_to_plugin(&events, &mut self.plugin);
+
+            let inputs: Vec<&[f32]> = self.inputs.iter().map(|input| &input[..samples]).collect();
+            let mut outputs: Vec<&mut [f32]> = self
+                .outputs
+                .iter_mut()
+                .map(|output| &mut output[..samples])
+                .collect();
+            self.plugin
+                .process(&mut self.host_buffer.bind(&inputs, &mut outputs));
+            for sample in outputs.iter().flat_map(|output| output.iter()) {
+                assert!(
+                    sample.is_finite() && sample.abs() <= MAX_LEVEL,
+                    "{}",
+                    sample
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn declared_counts_match_the_plugin() {
+    let mut plugin = load();
+    let info = plugin.get_info();
+    assert!(matches!(info.category, Category::Synth));
+    // It plays in stereo.
+    assert_eq!(info.outputs, 2);
+    assert!(info.preset_chunks);
+
+    let params = plugin.get_parameter_object();
+    for index in 0..info.parameters {
+        assert!(!params.get_parameter_name(index).is_empty(), "{}", index);
+    }
+    assert_eq!(params.get_parameter_name(info.parameters), "");
+    for preset in 0..info.presets {
+        assert!(!params.get_preset_name(preset).is_empty(), "{}", preset);
+    }
+}
+
+#[test]
+fn survives_a_host_session() {
+    let mut session = Session::new(load());
+    let params = session.plugin.get_parameter_object();
+    let count = session.plugin.get_info().parameters;
+    session.plugin.init();
+
+    session.restart(44100.0);
+    session.play(200);
+    // The project is saved part way through and loaded straight back.
+    let bank = params.get_bank_data();
+    let saved: Vec<f32> = (0..count).map(|i| params.get_parameter(i)).collect();
+    params.load_bank_data(&bank);
+    let loaded: Vec<f32> = (0..count).map(|i| params.get_parameter(i)).collect();
+    assert_eq!(loaded, saved);
+    session.play(200);
+
+    // The user turns knobs while it plays at the next rate. The plugin changes its learn
+    // switches back by itself, so those are left alone.
+    session.restart(48000.0);
+    let stop = Arc::new(AtomicBool::new(false));
+    let knobs = {
+        let knobs = Knobs(Arc::clone(&params));
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let params = &knobs.0;
+            let mut random = Random(0x9E37_79B9);
+            let mut turned = 0;
+            while !stop.load(Ordering::Relaxed) || turned < 1000 {
+                let index = random.below(count as usize) as i32;
+                if params.get_parameter_name(index).ends_with("Learn") {
+                    continue;
+                }
+                let value = random.below(1001) as f32 / 1000.0;
+                params.set_parameter(index, value);
+                assert_eq!(params.get_parameter(index), value, "{}", index);
+                turned += 1;
+            }
+        })
+    };
+    session.play(300);
+    stop.store(true, Ordering::Relaxed);
+    knobs.join().unwrap();
+
+    // Then it is reopened at a higher rate with the state saved earlier.
+    session.restart(96000.0);
+    // Bypass isn't saved, so it stays wherever it was turned to.
+    let bypass = (0..count)
+        .find(|&i| params.get_parameter_name(i) == "Bypass")
+        .unwrap();
+    let mut expected = saved;
+    expected[bypass as usize] = params.get_parameter(bypass);
+    params.load_bank_data(&bank);
+    let reloaded: Vec<f32> = (0..count).map(|i| params.get_parameter(i)).collect();
+    assert_eq!(reloaded, expected);
+    session.play(200);
+    session.plugin.suspend();
+}